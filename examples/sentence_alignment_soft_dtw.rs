@@ -8,7 +8,8 @@
 //! This example uses:
 //! - cheap char n-gram hashing to embed sentences into vectors
 //! - cosine distance to build a cost matrix
-//! - `structop::soft_dtw_cost` to compute Soft-DTW on that cost
+//! - `structop::soft_dtw::soft_dtw_alignment` to compute Soft-DTW and its soft path on that cost
+//! - `structop::viz` to show the soft path
 
 use ndarray::Array1;
 
@@ -117,26 +118,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let gamma = 0.5;
-    let sdtw = structop::soft_dtw::soft_dtw_cost(&cost, n, m, gamma)?;
+    let (sdtw, alignment) = structop::soft_dtw::soft_dtw_alignment(&cost, n, m, gamma)?;
 
     println!("Soft-DTW value (gamma={gamma}): {sdtw:.6}");
     println!();
 
-    // For interpretability, show greedy best matches by cost (not the full DTW path).
-    println!("Greedy best sentence matches (by min cost):");
-    for i in 0..n {
-        let mut best_j = 0usize;
-        let mut best = f64::INFINITY;
-        for j in 0..m {
-            let d = cost[i * m + j];
-            if d < best {
-                best = d;
-                best_j = j;
-            }
-        }
-        println!("  ref[{i}] -> noisy[{best_j}]  dist={best:.3}");
+    // The expected alignment: rows are reference sentences, columns noisy ones.
+    println!("Alignment marginals:");
+    print!("{}", structop::viz::ascii_heatmap(&alignment, n, m)?);
+    println!();
+
+    // Cells the soft path passes through with probability above one half.
+    println!("Aligned sentence pairs (marginal >= 0.5):");
+    for p in structop::viz::aligned_pairs(&alignment, n, m, 0.5)? {
+        let (i, j) = (p.i, p.j);
+        println!("  ref[{i}] -> noisy[{j}]  p={:.3}  dist={:.3}", p.weight, cost[i * m + j]);
         println!("    ref : {}", ref_sents[i]);
-        println!("    noisy: {}", noisy_sents[best_j]);
+        println!("    noisy: {}", noisy_sents[j]);
     }
 
     Ok(())
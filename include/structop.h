@@ -0,0 +1,49 @@
+/* C ABI for the structop crate (feature `capi`). See src/capi.rs for details. */
+#ifndef STRUCTOP_H
+#define STRUCTOP_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define STRUCTOP_OK 0
+#define STRUCTOP_ERR_NULL_POINTER 1
+#define STRUCTOP_ERR_INVALID_GAMMA 2
+#define STRUCTOP_ERR_EMPTY_INPUT 3
+#define STRUCTOP_ERR_INVALID_SHAPE 4
+#define STRUCTOP_ERR_TOO_FEW_NODES 5
+#define STRUCTOP_ERR_EDGE_OUT_OF_BOUNDS 6
+#define STRUCTOP_ERR_NOT_DAG_ORDER 7
+#define STRUCTOP_ERR_NO_PATH 8
+#define STRUCTOP_ERR_INVALID_ENDPOINTS 9
+#define STRUCTOP_ERR_INVALID_COST 10
+#define STRUCTOP_ERR_PANIC 99
+
+int32_t structop_soft_dtw(const double *x, size_t n, const double *y, size_t m, double gamma,
+                          double *out_value);
+
+/* out_grad: n doubles (gradient w.r.t. x). */
+int32_t structop_soft_dtw_grad(const double *x, size_t n, const double *y, size_t m, double gamma,
+                               double *out_value, double *out_grad);
+
+/* cost: row-major n*m doubles. */
+int32_t structop_soft_dtw_cost(const double *cost, size_t n, size_t m, double gamma,
+                               double *out_value);
+
+/* out_alignment: row-major n*m doubles. */
+int32_t structop_soft_dtw_alignment(const double *cost, size_t n, size_t m, double gamma,
+                                    double *out_value, double *out_alignment);
+
+/* Edge k is from[k] -> to[k] with cost costs[k]; out_marginals: num_edges doubles. */
+int32_t structop_soft_shortest_path_marginals(size_t n_nodes, const size_t *from, const size_t *to,
+                                              const double *costs, size_t num_edges, double gamma,
+                                              double *out_value, double *out_marginals);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* STRUCTOP_H */
@@ -0,0 +1,166 @@
+//! Soft reachability and absorption probabilities.
+//!
+//! Per-node summaries of the Gibbs path distribution \(p_\gamma(\pi) \propto e^{-C(\pi)/\gamma}\):
+//!
+//! - [`visit_probabilities`]: for every node, \(\mathbb{P}_\gamma(v \in \pi)\) on a [`Graph`]
+//!   (1 at the source and sink, 0 off every source-to-sink path);
+//! - [`absorption`]: paths from a source to a *set* of absorbing sinks (a path stops at the
+//!   first sink it reaches), with the split \(\mathbb{P}_\gamma(\text{absorbed at } k) =
+//!   e^{-(V_k - V_\gamma)/\gamma}\), where \(V_k\) is the soft value of reaching sink `k` and
+//!   \(V_\gamma\) the soft minimum over all sinks, and the visit probabilities under that
+//!   distribution.
+//!
+//! Both are node marginals, so they are also the gradients of the value w.r.t. node costs.
+//!
+//! ```
+//! use structop::absorption::absorption;
+//! use structop::soft_shortest_path::Edge;
+//!
+//! // From node 0, exits 2 and 3; exit 3 is cheaper.
+//! let edges = [
+//!     Edge { from: 0, to: 1, cost: 0.5 },
+//!     Edge { from: 1, to: 2, cost: 1.0 },
+//!     Edge { from: 1, to: 3, cost: 0.2 },
+//! ];
+//! let a = absorption(4, &edges, 0, &[2, 3], 1.0).unwrap();
+//! assert!(a.split[1] > a.split[0]);
+//! assert!((a.split.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+//! assert!((a.visits[1] - 1.0).abs() < 1e-12);
+//! ```
+
+use crate::soft_shortest_path::{self, Edge, Graph};
+
+/// Errors for absorption probabilities.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// At least one sink is required.
+    #[error("no sinks given")]
+    NoSinks,
+    /// A sink is listed twice.
+    #[error("sink {0} is listed twice")]
+    DuplicateSink(usize),
+    /// Error from the soft shortest path (bounds, costs, `gamma`, reachability).
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Result of [`absorption`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Absorption {
+    /// Soft value \(V_\gamma\) over paths to any sink.
+    pub value: f64,
+    /// Probability of being absorbed at each sink, in the order given.
+    pub split: Vec<f64>,
+    /// Probability of visiting each node.
+    pub visits: Vec<f64>,
+}
+
+/// Value and per-node visit probabilities between the graph's source and sink.
+pub fn visit_probabilities(graph: &Graph, gamma: f64) -> Result<(f64, Vec<f64>)> {
+    let (value, _, visits) = graph.node_marginals(gamma, &vec![0.0; graph.n()])?;
+    Ok((value, visits))
+}
+
+/// Absorption split and visit probabilities for paths from `source` to the absorbing
+/// `sinks` of the DAG on `n` nodes (edges in topological order, `from < to`).
+pub fn absorption(
+    n: usize,
+    edges: &[Edge],
+    source: usize,
+    sinks: &[usize],
+    gamma: f64,
+) -> Result<Absorption> {
+    if sinks.is_empty() {
+        return Err(Error::NoSinks);
+    }
+    let mut is_sink = vec![false; n];
+    for &k in sinks {
+        if k >= n {
+            return Err(soft_shortest_path::Error::NodeOutOfBounds { node: k, n }.into());
+        }
+        if std::mem::replace(&mut is_sink[k], true) {
+            return Err(Error::DuplicateSink(k));
+        }
+    }
+    // Sinks absorb: drop their other out-edges and join them to a super-sink at node `n`.
+    let mut extended: Vec<Edge> = edges
+        .iter()
+        .filter(|e| e.from >= n || !is_sink[e.from])
+        .copied()
+        .collect();
+    let first = extended.len();
+    extended.extend(sinks.iter().map(|&k| Edge {
+        from: k,
+        to: n,
+        cost: 0.0,
+    }));
+    let graph = Graph::new(n + 1, extended, source, n)?;
+    let (value, p, mut visits) = graph.node_marginals(gamma, &vec![0.0; n + 1])?;
+    visits.truncate(n);
+    Ok(Absorption {
+        value,
+        split: p[first..].to_vec(),
+        visits,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn e(from: usize, to: usize, cost: f64) -> Edge {
+        Edge { from, to, cost }
+    }
+
+    #[test]
+    fn absorption_matches_path_enumeration() {
+        // Paths from 0: 0-1-2 (absorbed at 2, never reaching 4 through it), 0-1-3-4, 0-3-4,
+        // 0-2 (absorbed).
+        let edges = [
+            e(0, 1, 0.2),
+            e(1, 2, 0.5),
+            e(1, 3, 0.3),
+            e(3, 4, 0.4),
+            e(0, 3, 1.1),
+            e(0, 2, 1.0),
+            e(2, 4, 0.0),
+        ];
+        let gamma = 0.7;
+        let paths: [(f64, &[usize]); 4] = [
+            (0.7, &[0, 1, 2]),
+            (0.9, &[0, 1, 3, 4]),
+            (1.5, &[0, 3, 4]),
+            (1.0, &[0, 2]),
+        ];
+        let z: f64 = paths.iter().map(|(c, _)| (-c / gamma).exp()).sum();
+        let prob = |pred: &dyn Fn(&[usize]) -> bool| -> f64 {
+            paths
+                .iter()
+                .filter(|(_, nodes)| pred(nodes))
+                .map(|(c, _)| (-c / gamma).exp() / z)
+                .sum()
+        };
+        let a = absorption(5, &edges, 0, &[4, 2], gamma).unwrap();
+        assert!((a.value + gamma * z.ln()).abs() < 1e-12);
+        assert!((a.split[0] - prob(&|p| p.ends_with(&[4]))).abs() < 1e-12);
+        assert!((a.split[1] - prob(&|p| p.ends_with(&[2]))).abs() < 1e-12);
+        for (v, q) in a.visits.iter().enumerate() {
+            assert!((q - prob(&|p| p.contains(&v))).abs() < 1e-12, "node {v}");
+        }
+
+        let graph = Graph::new(5, edges[..5].to_vec(), 0, 4).unwrap();
+        let (value, visits) = visit_probabilities(&graph, gamma).unwrap();
+        let (v_ref, p) = graph.edge_marginals(gamma).unwrap();
+        assert_eq!(value, v_ref);
+        assert!((visits[3] - (p[2] + p[4])).abs() < 1e-12 && visits[2] == 0.0);
+
+        assert_eq!(
+            absorption(5, &edges, 0, &[2, 2], gamma),
+            Err(Error::DuplicateSink(2))
+        );
+        assert_eq!(absorption(5, &edges, 0, &[], gamma), Err(Error::NoSinks));
+    }
+}
@@ -0,0 +1,229 @@
+//! Anomaly scores from the residuals of aligning a series to a reference.
+//!
+//! Monitoring against a "golden path" (a reference cycle, a canonical user journey) asks not
+//! just how far a test series is from the reference but *where* it departs from it. Here the
+//! test series is aligned to the reference by a [`SoftDtw`] and read through its expected
+//! alignment: with `w[i, j]` row `i` of the alignment normalized to sum to one,
+//!
+//! - the *warped reference* at test step `i` is `sum_j w[i, j] reference[j]`, the reference
+//!   value the test series is expected to show there;
+//! - the *residual* is `test[i]` minus the warped reference;
+//! - the *local cost* is `sum_j w[i, j] cost[i, j]`, the expected cost paid at step `i`
+//!   (for the squared distance, at least the squared residual).
+//!
+//! The anomaly score aggregates the local costs (see [`Aggregate`]). Warping absorbs
+//! timing differences, so steps that are merely early or late score low while values the
+//! reference never takes score high. Every option of the operator (band, step pattern,
+//! penalties, the [`crate::soft_dtw::TinyGamma`] fallback) applies; small `gamma` gives
+//! the residuals of the hard DTW path.
+//!
+//! ```
+//! use structop::anomaly::ResidualScorer;
+//! use structop::soft_dtw::SoftDtw;
+//!
+//! let reference = [0.0, 1.0, 2.0, 1.0, 0.0];
+//! let test = [0.0, 0.0, 1.0, 2.0, 5.0, 1.0, 0.0];
+//! let res = ResidualScorer::new(SoftDtw::new(0.01)).score(&reference, &test).unwrap();
+//! let worst = res.worst_step();
+//! assert_eq!(worst, 4);
+//! assert!(res.residuals[worst] > 2.5);
+//! ```
+
+use crate::soft_dtw::{self, SoftDtw};
+
+/// Errors for residual scoring.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Error from the underlying Soft-DTW.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// How per-step local costs become one anomaly score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Aggregate {
+    /// Mean local cost: overall deviation, independent of the test length.
+    #[default]
+    Mean,
+    /// Largest local cost: a single bad step is enough.
+    Max,
+    /// Sum of local costs.
+    Sum,
+}
+
+impl Aggregate {
+    fn apply(self, costs: &[f64]) -> f64 {
+        match self {
+            Aggregate::Mean => costs.iter().sum::<f64>() / costs.len() as f64,
+            Aggregate::Max => costs.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::Sum => costs.iter().sum(),
+        }
+    }
+}
+
+/// Per-step residuals of a test series against a reference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Residuals {
+    /// Reference value expected at each test step.
+    pub warped: Vec<f64>,
+    /// `test[i] - warped[i]`.
+    pub residuals: Vec<f64>,
+    /// Expected local cost at each test step.
+    pub costs: Vec<f64>,
+    /// Aggregated local costs.
+    pub score: f64,
+}
+
+impl Residuals {
+    /// Test step with the largest local cost (the first one on ties).
+    pub fn worst_step(&self) -> usize {
+        worst(&self.costs)
+    }
+
+    /// Test steps whose local cost exceeds `threshold`.
+    pub fn flagged(&self, threshold: f64) -> Vec<usize> {
+        (0..self.costs.len())
+            .filter(|&i| self.costs[i] > threshold)
+            .collect()
+    }
+}
+
+fn worst(costs: &[f64]) -> usize {
+    (0..costs.len()).fold(0, |best, i| if costs[i] > costs[best] { i } else { best })
+}
+
+/// Aligns test series to a reference and scores their deviation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResidualScorer {
+    op: SoftDtw,
+    aggregate: Aggregate,
+}
+
+impl ResidualScorer {
+    /// Scorer aligning with `op` and averaging local costs.
+    pub fn new(op: SoftDtw) -> Self {
+        Self {
+            op,
+            aggregate: Aggregate::default(),
+        }
+    }
+
+    /// How local costs are aggregated into the score.
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Self {
+        self.aggregate = aggregate;
+        self
+    }
+
+    /// Residuals of `test` against `reference` under the squared distance.
+    pub fn score(&self, reference: &[f64], test: &[f64]) -> Result<Residuals> {
+        let (n, m) = (test.len(), reference.len());
+        let cost: Vec<f64> = test
+            .iter()
+            .flat_map(|a| reference.iter().map(move |b| (a - b) * (a - b)))
+            .collect();
+        let rows = self.rows(&cost, n, m)?;
+        let warped: Vec<f64> = rows
+            .iter()
+            .map(|w| w.iter().zip(reference).map(|(w, r)| w * r).sum())
+            .collect();
+        let residuals = test.iter().zip(&warped).map(|(t, w)| t - w).collect();
+        let costs = local_costs(&rows, &cost, m);
+        Ok(Residuals {
+            warped,
+            residuals,
+            score: self.aggregate.apply(&costs),
+            costs,
+        })
+    }
+
+    /// Local costs and score for a precomputed row-major cost matrix between `n` test steps
+    /// and `m` reference steps, for references that are not real-valued (categorical
+    /// states, multivariate points).
+    pub fn score_cost(&self, cost: &[f64], n: usize, m: usize) -> Result<(Vec<f64>, f64)> {
+        let rows = self.rows(cost, n, m)?;
+        let costs = local_costs(&rows, cost, m);
+        let score = self.aggregate.apply(&costs);
+        Ok((costs, score))
+    }
+
+    /// Row-normalized expected alignment. Every warping path visits every row, so no row is
+    /// empty.
+    fn rows(&self, cost: &[f64], n: usize, m: usize) -> Result<Vec<Vec<f64>>> {
+        let (_, e) = self.op.alignment(cost, n, m)?;
+        Ok(e.chunks(m)
+            .map(|row| {
+                let mass: f64 = row.iter().sum();
+                row.iter().map(|p| p / mass).collect()
+            })
+            .collect())
+    }
+}
+
+/// Expected cost of each row; cells off every path (cost possibly `+inf`) are skipped.
+fn local_costs(rows: &[Vec<f64>], cost: &[f64], m: usize) -> Vec<f64> {
+    rows.iter()
+        .zip(cost.chunks(m))
+        .map(|(w, c)| {
+            w.iter()
+                .zip(c)
+                .filter(|(w, _)| **w > 0.0)
+                .map(|(w, c)| w * c)
+                .sum()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn residuals_locate_the_deviation_not_the_delay() {
+        let reference = [0.0, 1.0, 3.0, 1.0, 0.0, 0.0];
+        // Same shape, two steps late: warping absorbs it.
+        let late = [0.0, 0.0, 0.0, 1.0, 3.0, 1.0, 0.0, 0.0];
+        let scorer = ResidualScorer::new(SoftDtw::new(0.01));
+        let res = scorer.score(&reference, &late).unwrap();
+        assert!(res.score < 1e-3, "{res:?}");
+        assert!(res.residuals.iter().all(|r| r.abs() < 0.05));
+
+        let mut spiked = late;
+        spiked[6] = -3.0;
+        let res = scorer
+            .aggregate(Aggregate::Max)
+            .score(&reference, &spiked)
+            .unwrap();
+        assert_eq!(res.worst_step(), 6);
+        assert_eq!(res.flagged(1.0), vec![6]);
+        assert!((res.residuals[6] + 3.0).abs() < 0.05, "{res:?}");
+        assert_eq!(res.score, res.costs[6]);
+        assert_eq!(
+            scorer.score(&[], &late),
+            Err(Error::SoftDtw(soft_dtw::Error::EmptyInput))
+        );
+    }
+
+    #[test]
+    fn cost_scores_match_and_rows_are_distributions() {
+        let (reference, test) = ([0.0, 2.0, 1.0], [0.5, 2.0, 2.5, 1.0]);
+        let scorer = ResidualScorer::new(SoftDtw::new(1.0)).aggregate(Aggregate::Sum);
+        let res = scorer.score(&reference, &test).unwrap();
+        let cost: Vec<f64> = test
+            .iter()
+            .flat_map(|a| reference.iter().map(move |b| (a - b) * (a - b)))
+            .collect();
+        let (costs, score) = scorer.score_cost(&cost, 4, 3).unwrap();
+        assert_eq!(costs, res.costs);
+        assert_eq!(score, res.score);
+        for (c, r) in res.costs.iter().zip(&res.residuals) {
+            // Jensen: the expected squared distance bounds the squared residual.
+            assert!(*c >= r * r - 1e-12);
+        }
+        for w in &res.warped {
+            assert!((0.0..=2.0).contains(w));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Apache Arrow adapters for the Soft-DTW operators (feature `arrow`).
+//!
+//! A `Float64Array` is one sequence; a `FixedSizeListArray` of `Float64` is a batch of
+//! equal-length sequences (one per row), the layout Arrow/Parquet pipelines produce for
+//! fixed-width series and embeddings. Rows are borrowed straight from the child values
+//! buffer (respecting array offsets and slices), so no per-row copies are made before
+//! calling the slice-based batch APIs in [`crate::soft_dtw`].
+//!
+//! Nulls are rejected rather than silently skipped: a null row or element has no defined
+//! Soft-DTW value.
+
+use arrow_array::{Array, FixedSizeListArray, Float64Array};
+use arrow_schema::DataType;
+
+use crate::soft_dtw as sdtw;
+
+/// Errors for the Arrow adapters.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The input (or a list's child array) contains nulls.
+    #[error("input contains {0} null values")]
+    ContainsNulls(usize),
+    /// A list's child array is not `Float64`.
+    #[error("expected Float64 values, got {0}")]
+    NotFloat64(DataType),
+    /// Error from the underlying Soft-DTW operator.
+    #[error(transparent)]
+    SoftDtw(#[from] sdtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Borrows the values of a null-free `Float64Array`.
+pub fn values(array: &Float64Array) -> Result<&[f64]> {
+    if array.null_count() > 0 {
+        return Err(Error::ContainsNulls(array.null_count()));
+    }
+    Ok(array.values())
+}
+
+/// Borrows each row of a null-free `FixedSizeListArray<Float64>` as a slice.
+pub fn rows(list: &FixedSizeListArray) -> Result<Vec<&[f64]>> {
+    if list.null_count() > 0 {
+        return Err(Error::ContainsNulls(list.null_count()));
+    }
+    let child = list
+        .values()
+        .as_any()
+        .downcast_ref::<Float64Array>()
+        .ok_or_else(|| Error::NotFloat64(list.values().data_type().clone()))?;
+    let flat = values(child)?;
+    let width = list.value_length() as usize;
+    Ok((0..list.len())
+        .map(|i| {
+            let start = list.value_offset(i) as usize;
+            &flat[start..start + width]
+        })
+        .collect())
+}
+
+/// Soft-DTW value for two sequences given as `Float64Array`s.
+pub fn soft_dtw(x: &Float64Array, y: &Float64Array, gamma: f64) -> Result<f64> {
+    Ok(sdtw::soft_dtw(values(x)?, values(y)?, gamma)?)
+}
+
+/// Soft-DTW values for row pairs of two `FixedSizeListArray<Float64>` batches.
+pub fn soft_dtw_batch(
+    xs: &FixedSizeListArray,
+    ys: &FixedSizeListArray,
+    gamma: f64,
+) -> Result<Float64Array> {
+    let out = sdtw::soft_dtw_batch(&rows(xs)?, &rows(ys)?, gamma)?;
+    Ok(Float64Array::from(out))
+}
+
+/// Soft-DTW divergences for row pairs of two `FixedSizeListArray<Float64>` batches.
+pub fn soft_dtw_divergence_batch(
+    xs: &FixedSizeListArray,
+    ys: &FixedSizeListArray,
+    gamma: f64,
+) -> Result<Float64Array> {
+    let out = sdtw::soft_dtw_divergence_batch(&rows(xs)?, &rows(ys)?, gamma)?;
+    Ok(Float64Array::from(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Int32Array;
+    use arrow_schema::Field;
+    use std::sync::Arc;
+
+    fn batch(flat: Vec<f64>, width: i32) -> FixedSizeListArray {
+        let field = Arc::new(Field::new("item", DataType::Float64, false));
+        FixedSizeListArray::new(field, width, Arc::new(Float64Array::from(flat)), None)
+    }
+
+    #[test]
+    fn batch_matches_slice_api_including_sliced_arrays() {
+        let xs = batch(vec![0.0, 1.0, 2.0, 1.0, 1.0, 0.0, -1.0, 0.5, 0.2], 3);
+        let ys = batch(vec![0.5, 1.5, 1.0, 0.0, 0.0, 0.0, 2.0, 1.0, 0.0], 3);
+        let got = soft_dtw_batch(&xs.slice(1, 2), &ys.slice(1, 2), 0.5).unwrap();
+
+        let expected = sdtw::soft_dtw_batch(
+            &[&[1.0, 1.0, 0.0], &[-1.0, 0.5, 0.2]],
+            &[&[0.0, 0.0, 0.0], &[2.0, 1.0, 0.0]],
+            0.5,
+        )
+        .unwrap();
+        assert_eq!(got.values().to_vec(), expected);
+    }
+
+    #[test]
+    fn rejects_nulls_wrong_types_and_mismatched_batches() {
+        let x = Float64Array::from(vec![Some(1.0), None]);
+        let y = Float64Array::from(vec![1.0]);
+        assert_eq!(soft_dtw(&x, &y, 1.0), Err(Error::ContainsNulls(1)));
+
+        let field = Arc::new(Field::new("item", DataType::Int32, false));
+        let ints = FixedSizeListArray::new(field, 1, Arc::new(Int32Array::from(vec![1])), None);
+        assert_eq!(rows(&ints), Err(Error::NotFloat64(DataType::Int32)));
+
+        let xs = batch(vec![0.0, 1.0, 2.0, 3.0], 2);
+        let ys = batch(vec![0.0, 1.0], 2);
+        assert_eq!(
+            soft_dtw_batch(&xs, &ys, 1.0).unwrap_err(),
+            Error::SoftDtw(sdtw::Error::BatchLengthMismatch { xs: 2, ys: 1 })
+        );
+    }
+}
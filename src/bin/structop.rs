@@ -0,0 +1,368 @@
+//! `structop` command-line tool (feature `cli`): Soft-DTW scoring of series or cost matrices.
+//!
+//! ```text
+//! structop [OPTIONS] <X> <Y>         two series (one value per row; `-` reads stdin)
+//! structop [OPTIONS] --cost <FILE>   a precomputed cost matrix (one row per line)
+//! ```
+//!
+//! Input files are CSV/TSV/whitespace-separated; lines starting with `#` and a leading
+//! non-numeric header row are skipped. Output is JSON by default.
+
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+use structop::soft_dtw;
+
+const USAGE: &str = "\
+usage: structop [OPTIONS] <X> <Y>
+       structop [OPTIONS] --cost <FILE>
+
+Computes the Soft-DTW value of two series (or of a cost matrix).
+
+options:
+  --gamma <G>        smoothing parameter (default 1.0)
+  --column <K>       0-based column to read from series files (default 0)
+  --divergence       also report the Soft-DTW divergence (series input only)
+  --alignment        also report the expected alignment matrix
+  --format <FMT>     json (default) or csv
+  -h, --help         print this help
+
+Series are compared with the squared distance. `-` reads one input from stdin.
+CSV output prints a header row and a value row; with --alignment the matrix follows
+after a blank line.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Input {
+    Series { x: String, y: String },
+    Cost(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Args {
+    input: Input,
+    gamma: f64,
+    column: usize,
+    divergence: bool,
+    alignment: bool,
+    format: Format,
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut gamma = 1.0;
+    let mut column = 0;
+    let mut divergence = false;
+    let mut alignment = false;
+    let mut format = Format::Json;
+    let mut cost = None;
+    let mut positional = Vec::new();
+
+    let mut it = args.into_iter();
+    while let Some(arg) = it.next() {
+        let mut value = |name: &str| it.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--gamma" => {
+                let v = value("--gamma")?;
+                gamma = v.parse().map_err(|_| format!("invalid --gamma: {}", v))?;
+            }
+            "--column" => {
+                let v = value("--column")?;
+                column = v.parse().map_err(|_| format!("invalid --column: {}", v))?;
+            }
+            "--format" => {
+                format = match value("--format")?.as_str() {
+                    "json" => Format::Json,
+                    "csv" => Format::Csv,
+                    other => return Err(format!("unknown --format: {}", other)),
+                }
+            }
+            "--cost" => cost = Some(value("--cost")?),
+            "--divergence" => divergence = true,
+            "--alignment" => alignment = true,
+            s if s.starts_with("--") => return Err(format!("unknown option: {}", s)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let input = match (cost, positional.len()) {
+        (Some(path), 0) => {
+            if divergence {
+                return Err("--divergence needs two series, not a cost matrix".into());
+            }
+            Input::Cost(path)
+        }
+        (None, 2) => {
+            let y = positional.pop().unwrap_or_default();
+            let x = positional.pop().unwrap_or_default();
+            Input::Series { x, y }
+        }
+        _ => return Err("expected two series files or --cost <FILE>".into()),
+    };
+    Ok(Some(Args {
+        input,
+        gamma,
+        column,
+        divergence,
+        alignment,
+        format,
+    }))
+}
+
+fn read_source(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut s = String::new();
+        io::stdin()
+            .read_to_string(&mut s)
+            .map_err(|e| format!("stdin: {}", e))?;
+        Ok(s)
+    } else {
+        std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+/// Parses delimited rows of numbers, skipping comments, blank lines and a header row (the
+/// first non-comment line, if it is not numeric).
+fn parse_rows(text: &str) -> Result<Vec<Vec<f64>>, String> {
+    let mut rows = Vec::new();
+    let mut first = true;
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line
+            .split(|c: char| c == ',' || c == '\t' || c.is_whitespace())
+            .filter(|f| !f.is_empty());
+        match fields.map(str::parse::<f64>).collect::<Result<Vec<_>, _>>() {
+            Ok(row) => rows.push(row),
+            Err(_) if first => {}
+            Err(e) => return Err(format!("line {}: {}", lineno + 1, e)),
+        }
+        first = false;
+    }
+    Ok(rows)
+}
+
+fn series(text: &str, column: usize) -> Result<Vec<f64>, String> {
+    parse_rows(text)?
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            row.get(column)
+                .copied()
+                .ok_or_else(|| format!("row {} has no column {}", i + 1, column))
+        })
+        .collect()
+}
+
+fn matrix(text: &str) -> Result<(Vec<f64>, usize, usize), String> {
+    let rows = parse_rows(text)?;
+    let m = rows.first().map_or(0, Vec::len);
+    if let Some(i) = rows.iter().position(|r| r.len() != m) {
+        return Err(format!(
+            "cost row {} has {} columns, expected {}",
+            i + 1,
+            rows[i].len(),
+            m
+        ));
+    }
+    let n = rows.len();
+    Ok((rows.concat(), n, m))
+}
+
+/// JSON number, or `null` for non-finite values.
+fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".into()
+    }
+}
+
+struct Report {
+    value: f64,
+    divergence: Option<f64>,
+    alignment: Option<(Vec<f64>, usize)>,
+}
+
+fn render(report: &Report, format: Format) -> String {
+    let mut out = String::new();
+    let rows =
+        |e: &[f64], m: usize| -> Vec<Vec<f64>> { e.chunks(m).map(<[f64]>::to_vec).collect() };
+    match format {
+        Format::Json => {
+            let _ = write!(out, "{{\"value\": {}", json_number(report.value));
+            if let Some(d) = report.divergence {
+                let _ = write!(out, ", \"divergence\": {}", json_number(d));
+            }
+            if let Some((e, m)) = &report.alignment {
+                let body: Vec<String> = rows(e, *m)
+                    .iter()
+                    .map(|r| {
+                        let cells: Vec<String> = r.iter().map(|&x| json_number(x)).collect();
+                        format!("[{}]", cells.join(", "))
+                    })
+                    .collect();
+                let _ = write!(out, ", \"alignment\": [{}]", body.join(", "));
+            }
+            out.push_str("}\n");
+        }
+        Format::Csv => {
+            let mut header = vec!["value"];
+            let mut values = vec![report.value.to_string()];
+            if let Some(d) = report.divergence {
+                header.push("divergence");
+                values.push(d.to_string());
+            }
+            let _ = writeln!(out, "{}\n{}", header.join(","), values.join(","));
+            if let Some((e, m)) = &report.alignment {
+                out.push('\n');
+                for r in rows(e, *m) {
+                    let cells: Vec<String> = r.iter().map(f64::to_string).collect();
+                    let _ = writeln!(out, "{}", cells.join(","));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn run(args: &Args) -> Result<String, String> {
+    let err = |e: soft_dtw::Error| e.to_string();
+    let report = match &args.input {
+        Input::Series { x, y } => {
+            let x = series(&read_source(x)?, args.column)?;
+            let y = series(&read_source(y)?, args.column)?;
+            let (value, alignment) = if args.alignment {
+                let cost: Vec<f64> = x
+                    .iter()
+                    .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+                    .collect();
+                let (v, e) = soft_dtw::soft_dtw_alignment(&cost, x.len(), y.len(), args.gamma)
+                    .map_err(err)?;
+                (v, Some((e, y.len())))
+            } else {
+                (soft_dtw::soft_dtw(&x, &y, args.gamma).map_err(err)?, None)
+            };
+            let divergence = if args.divergence {
+                Some(soft_dtw::soft_dtw_divergence(&x, &y, args.gamma).map_err(err)?)
+            } else {
+                None
+            };
+            Report {
+                value,
+                divergence,
+                alignment,
+            }
+        }
+        Input::Cost(path) => {
+            let (cost, n, m) = matrix(&read_source(path)?)?;
+            let (value, alignment) = if args.alignment {
+                let (v, e) = soft_dtw::soft_dtw_alignment(&cost, n, m, args.gamma).map_err(err)?;
+                (v, Some((e, m)))
+            } else {
+                (
+                    soft_dtw::soft_dtw_cost(&cost, n, m, args.gamma).map_err(err)?,
+                    None,
+                )
+            };
+            Report {
+                value,
+                divergence: None,
+                alignment,
+            }
+        }
+    };
+    Ok(render(&report, args.format))
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(None) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Ok(Some(args)) => match run(&args) {
+            Ok(out) => {
+                print!("{}", out);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("structop: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Err(e) => {
+            eprintln!("structop: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Result<Option<Args>, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_options_and_inputs() {
+        let a = args("--gamma 0.5 --divergence a.csv b.tsv --format csv")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            a.input,
+            Input::Series {
+                x: "a.csv".into(),
+                y: "b.tsv".into()
+            }
+        );
+        assert_eq!((a.gamma, a.divergence, a.format), (0.5, true, Format::Csv));
+        assert!(args("--help").unwrap().is_none());
+        assert!(args("only-one.csv").is_err());
+        assert!(args("--cost c.csv --divergence").is_err());
+    }
+
+    #[test]
+    fn parses_headers_comments_and_columns() {
+        let text = "# exported\nt,value\n0,1.5\n1\t2.5\n\n2 3.5\n";
+        assert_eq!(series(text, 1).unwrap(), vec![1.5, 2.5, 3.5]);
+        assert!(series(text, 2).is_err());
+        assert_eq!(
+            series("t,value\nunits,s\n0,1\n", 1).unwrap_err(),
+            "line 2: invalid float literal"
+        );
+        assert!(series("# c\n0,1\nx,2\n", 1).is_err());
+        assert!(matrix("1,2\n3\n").is_err());
+        assert_eq!(
+            matrix("1,2\n3,4\n").unwrap(),
+            (vec![1.0, 2.0, 3.0, 4.0], 2, 2)
+        );
+    }
+
+    #[test]
+    fn json_report_includes_requested_fields() {
+        let report = Report {
+            value: -0.25,
+            divergence: Some(0.0),
+            alignment: Some((vec![1.0, 0.0, 0.0, 1.0], 2)),
+        };
+        assert_eq!(
+            render(&report, Format::Json),
+            "{\"value\": -0.25, \"divergence\": 0, \"alignment\": [[1, 0], [0, 1]]}\n"
+        );
+        assert_eq!(
+            render(&report, Format::Csv),
+            "value,divergence\n-0.25,0\n\n1,0\n0,1\n"
+        );
+    }
+}
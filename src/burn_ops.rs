@@ -0,0 +1,300 @@
+//! `burn` operators with hand-written backward passes (feature `burn`).
+//!
+//! [`StructopBackend`] is a backend extension trait. Its default methods run the crate's
+//! slice-based kernels on host copies of the inputs; any backend opts in with an empty
+//! impl (`impl StructopBackend for MyBackend {}`). For `Autodiff<B, C>` the crate provides
+//! an implementation that registers a custom autodiff node whose backward pass uses the
+//! analytic gradients:
+//!
+//! - Soft-DTW on a cost matrix: \(\partial/\partial C = E\), the expected alignment
+//!   ([`crate::soft_dtw::soft_dtw_alignment`]).
+//! - Soft shortest path: \(\partial V_\gamma/\partial c_e = p_e\), the edge marginals
+//!   ([`crate::soft_shortest_path::soft_shortest_path_edge_marginals`]).
+//!
+//! The checked entry points are [`soft_dtw_cost`] and [`soft_shortest_path`]; they
+//! validate shapes and topology up front and return the crate's errors. Non-finite cost
+//! values are not checked there (that would force a device read); they yield a NaN value.
+
+use burn::backend::autodiff::checkpoint::base::Checkpointer;
+use burn::backend::autodiff::checkpoint::strategy::CheckpointStrategy;
+use burn::backend::autodiff::grads::Gradients;
+use burn::backend::autodiff::ops::{unary, Backward, Ops, OpsKind};
+use burn::backend::Autodiff;
+use burn::tensor::backend::Backend;
+use burn::tensor::ops::FloatTensor;
+use burn::tensor::{Shape, Tensor, TensorData, TensorMetadata, TensorPrimitive};
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+fn to_host<B: Backend>(t: FloatTensor<B>) -> Vec<f64> {
+    Tensor::<B, 1>::from_primitive(TensorPrimitive::Float(t))
+        .into_data()
+        .convert::<f64>()
+        .to_vec::<f64>()
+        .expect("converted tensor data is f64")
+}
+
+fn from_host<B: Backend>(
+    values: Vec<f64>,
+    shape: Vec<usize>,
+    device: &B::Device,
+) -> FloatTensor<B> {
+    let data = TensorData::new(values, shape).convert::<B::FloatElem>();
+    B::float_from_data(data, device)
+}
+
+fn dims2<B: Backend>(t: &FloatTensor<B>) -> (usize, usize) {
+    let dims = t.shape().dims;
+    assert_eq!(dims.len(), 2, "expected a rank-2 cost tensor");
+    (dims[0], dims[1])
+}
+
+fn with_costs(edges: &[(usize, usize)], costs: &[f64]) -> Vec<Edge> {
+    edges
+        .iter()
+        .zip(costs)
+        .map(|(&(from, to), &cost)| Edge { from, to, cost })
+        .collect()
+}
+
+/// Host forward + backward for Soft-DTW: `(value, alignment)`, NaN on invalid values.
+fn soft_dtw_host(cost: &[f64], n: usize, m: usize, gamma: f64) -> (f64, Vec<f64>) {
+    soft_dtw::soft_dtw_alignment(cost, n, m, gamma).unwrap_or((f64::NAN, vec![f64::NAN; n * m]))
+}
+
+/// Host forward + backward for soft shortest path: `(value, marginals)`.
+fn soft_path_host(
+    n: usize,
+    edges: &[(usize, usize)],
+    costs: &[f64],
+    gamma: f64,
+) -> (f64, Vec<f64>) {
+    let es = with_costs(edges, costs);
+    soft_shortest_path::soft_shortest_path_edge_marginals(n, &es, gamma)
+        .unwrap_or((f64::NAN, vec![f64::NAN; edges.len()]))
+}
+
+/// Backend extension with the crate's structured operators.
+///
+/// Default methods compute on the host and are not differentiable by themselves; the
+/// `Autodiff<B, C>` implementation adds the analytic backward pass.
+pub trait StructopBackend: Backend {
+    /// Soft-DTW value of a `[n, m]` cost tensor, as a `[1]` tensor.
+    fn soft_dtw_cost(cost: FloatTensor<Self>, gamma: f64) -> FloatTensor<Self> {
+        let device = Self::float_device(&cost);
+        let (n, m) = dims2::<Self>(&cost);
+        let flat = Self::float_reshape(cost, Shape::new([n * m]));
+        let (value, _) = soft_dtw_host(&to_host::<Self>(flat), n, m, gamma);
+        from_host::<Self>(vec![value], vec![1], &device)
+    }
+
+    /// Soft shortest-path value from node 0 to `n - 1`, as a `[1]` tensor.
+    ///
+    /// `costs` is a `[edges.len()]` tensor of edge costs for the `(from, to)` pairs.
+    fn soft_shortest_path(
+        n: usize,
+        edges: &[(usize, usize)],
+        costs: FloatTensor<Self>,
+        gamma: f64,
+    ) -> FloatTensor<Self> {
+        let device = Self::float_device(&costs);
+        let (value, _) = soft_path_host(n, edges, &to_host::<Self>(costs), gamma);
+        from_host::<Self>(vec![value], vec![1], &device)
+    }
+}
+
+#[derive(Debug)]
+struct SoftDtwBackward;
+
+impl<B: Backend> Backward<B, 1> for SoftDtwBackward {
+    /// Expected alignment `E`, shape `[n, m]`.
+    type State = FloatTensor<B>;
+
+    fn backward(self, ops: Ops<Self::State, 1>, grads: &mut Gradients, _: &mut Checkpointer) {
+        let alignment = ops.state;
+        unary::<B, _>(ops.parents, ops.node, grads, |grad| {
+            let shape = alignment.shape();
+            let grad = B::float_expand(B::float_reshape(grad, Shape::new([1, 1])), shape);
+            B::float_mul(alignment, grad)
+        });
+    }
+}
+
+#[derive(Debug)]
+struct SoftPathBackward;
+
+impl<B: Backend> Backward<B, 1> for SoftPathBackward {
+    /// Edge marginals, shape `[num_edges]`.
+    type State = FloatTensor<B>;
+
+    fn backward(self, ops: Ops<Self::State, 1>, grads: &mut Gradients, _: &mut Checkpointer) {
+        let marginals = ops.state;
+        unary::<B, _>(ops.parents, ops.node, grads, |grad| {
+            let shape = marginals.shape();
+            B::float_mul(marginals, B::float_expand(grad, shape))
+        });
+    }
+}
+
+impl<B: StructopBackend, C: CheckpointStrategy> StructopBackend for Autodiff<B, C> {
+    fn soft_dtw_cost(cost: FloatTensor<Self>, gamma: f64) -> FloatTensor<Self> {
+        match SoftDtwBackward
+            .prepare::<C>([cost.node.clone()])
+            .compute_bound()
+            .stateful()
+        {
+            OpsKind::Tracked(prep) => {
+                let device = B::float_device(&cost.primitive);
+                let (n, m) = dims2::<B>(&cost.primitive);
+                let flat = B::float_reshape(cost.primitive, Shape::new([n * m]));
+                let (value, e) = soft_dtw_host(&to_host::<B>(flat), n, m, gamma);
+                let state = from_host::<B>(e, vec![n, m], &device);
+                prep.finish(state, from_host::<B>(vec![value], vec![1], &device))
+            }
+            OpsKind::UnTracked(prep) => prep.finish(B::soft_dtw_cost(cost.primitive, gamma)),
+        }
+    }
+
+    fn soft_shortest_path(
+        n: usize,
+        edges: &[(usize, usize)],
+        costs: FloatTensor<Self>,
+        gamma: f64,
+    ) -> FloatTensor<Self> {
+        match SoftPathBackward
+            .prepare::<C>([costs.node.clone()])
+            .compute_bound()
+            .stateful()
+        {
+            OpsKind::Tracked(prep) => {
+                let device = B::float_device(&costs.primitive);
+                let (value, p) = soft_path_host(n, edges, &to_host::<B>(costs.primitive), gamma);
+                let state = from_host::<B>(p, vec![edges.len()], &device);
+                prep.finish(state, from_host::<B>(vec![value], vec![1], &device))
+            }
+            OpsKind::UnTracked(prep) => {
+                prep.finish(B::soft_shortest_path(n, edges, costs.primitive, gamma))
+            }
+        }
+    }
+}
+
+/// Soft-DTW value of a `[n, m]` cost tensor (differentiable on `Autodiff` backends).
+pub fn soft_dtw_cost<B: StructopBackend>(
+    cost: Tensor<B, 2>,
+    gamma: f64,
+) -> soft_dtw::Result<Tensor<B, 1>> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(soft_dtw::Error::InvalidGamma(gamma));
+    }
+    let [n, m] = cost.dims();
+    if n == 0 || m == 0 {
+        return Err(soft_dtw::Error::EmptyInput);
+    }
+    let out = B::soft_dtw_cost(cost.into_primitive().tensor(), gamma);
+    Ok(Tensor::from_primitive(TensorPrimitive::Float(out)))
+}
+
+/// Soft shortest-path value for edge costs given as a `[edges.len()]` tensor
+/// (differentiable on `Autodiff` backends).
+pub fn soft_shortest_path<B: StructopBackend>(
+    n: usize,
+    edges: &[(usize, usize)],
+    costs: Tensor<B, 1>,
+    gamma: f64,
+) -> soft_shortest_path::Result<Tensor<B, 1>> {
+    let [len] = costs.dims();
+    if len != edges.len() {
+        return Err(soft_shortest_path::Error::EdgeCostLength {
+            len,
+            expected: edges.len(),
+        });
+    }
+    // Topology (bounds, order, reachability) does not depend on cost values.
+    let probe = with_costs(edges, &vec![0.0; edges.len()]);
+    soft_shortest_path::soft_shortest_path_value(n, &probe, gamma)?;
+    let out = B::soft_shortest_path(n, edges, costs.into_primitive().tensor(), gamma);
+    Ok(Tensor::from_primitive(TensorPrimitive::Float(out)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use burn::backend::NdArray;
+
+    impl StructopBackend for NdArray<f64> {}
+
+    type Ad = Autodiff<NdArray<f64>>;
+
+    #[test]
+    fn soft_dtw_gradient_is_the_expected_alignment() {
+        let device = Default::default();
+        let flat = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let cost = Tensor::<Ad, 1>::from_data(TensorData::new(flat.to_vec(), [6]), &device)
+            .reshape([2, 3])
+            .require_grad();
+        let value = soft_dtw_cost(cost.clone(), 0.5).unwrap();
+        let grads = (value.clone() * 2.0).sum().backward();
+        let grad = to_host::<NdArray<f64>>(
+            cost.grad(&grads)
+                .unwrap()
+                .reshape([6])
+                .into_primitive()
+                .tensor(),
+        );
+
+        let (v, e) = soft_dtw::soft_dtw_alignment(&flat, 2, 3, 0.5).unwrap();
+        let got = value.into_scalar();
+        assert!((got - v).abs() < 1e-12, "value={} expected={}", got, v);
+        for (g, e) in grad.iter().zip(&e) {
+            assert!(
+                (g - 2.0 * e).abs() < 1e-12,
+                "grad={} expected={}",
+                g,
+                2.0 * e
+            );
+        }
+    }
+
+    #[test]
+    fn soft_path_gradient_is_the_edge_marginals() {
+        let device = Default::default();
+        let edges = [(0, 1), (1, 3), (0, 2), (2, 3)];
+        let costs =
+            Tensor::<Ad, 1>::from_data(TensorData::new(vec![1.0, 2.0, 3.0, 4.0], [4]), &device)
+                .require_grad();
+        let value = soft_shortest_path(4, &edges, costs.clone(), 0.5).unwrap();
+        let grads = value.sum().backward();
+        let grad = to_host::<NdArray<f64>>(costs.grad(&grads).unwrap().into_primitive().tensor());
+
+        let es = with_costs(&edges, &[1.0, 2.0, 3.0, 4.0]);
+        let (_, p) = soft_shortest_path::soft_shortest_path_edge_marginals(4, &es, 0.5).unwrap();
+        for (g, p) in grad.iter().zip(&p) {
+            assert!((g - p).abs() < 1e-12, "grad={} marginal={}", g, p);
+        }
+    }
+
+    #[test]
+    fn checked_entry_points_report_errors() {
+        let device = Default::default();
+        let cost = Tensor::<NdArray<f64>, 2>::zeros([2, 2], &device);
+        assert_eq!(
+            soft_dtw_cost(cost, 0.0).unwrap_err(),
+            soft_dtw::Error::InvalidGamma(0.0)
+        );
+        let costs = Tensor::<NdArray<f64>, 1>::zeros([1], &device);
+        assert_eq!(
+            soft_shortest_path(3, &[(0, 1)], costs, 1.0).unwrap_err(),
+            soft_shortest_path::Error::NoPath
+        );
+        let costs = Tensor::<NdArray<f64>, 1>::zeros([2], &device);
+        assert_eq!(
+            soft_shortest_path(3, &[(0, 2)], costs, 1.0).unwrap_err(),
+            soft_shortest_path::Error::EdgeCostLength {
+                len: 2,
+                expected: 1
+            }
+        );
+    }
+}
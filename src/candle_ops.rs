@@ -0,0 +1,266 @@
+//! `candle` custom ops with analytic backward passes (feature `candle`).
+//!
+//! [`SoftDtw`] and [`SoftShortestPath`] implement `candle_core::CustomOp1`:
+//!
+//! - `cpu_fwd` runs the crate's slice-based kernels on `f32`/`f64` CPU storage (any
+//!   strides; `f32` inputs are widened to `f64` internally) and returns a scalar.
+//! - `bwd` returns the analytic gradient scaled by the incoming gradient: the expected
+//!   alignment for Soft-DTW and the edge marginals for the soft shortest path.
+//!
+//! Construct the ops with their checked constructors, then apply them with
+//! `tensor.apply_op1(op)`. GPU storages are not supported (candle reports a missing
+//! kernel); move inputs to the CPU first.
+
+use candle_core::{CpuStorage, CustomOp1, DType, Layout, Shape, Tensor};
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+/// Reads `storage` through `layout` into a row-major `Vec<f64>`.
+fn read_f64(storage: &CpuStorage, layout: &Layout) -> candle_core::Result<Vec<f64>> {
+    fn gather<T: Copy>(data: &[T], layout: &Layout, conv: impl Fn(T) -> f64) -> Vec<f64> {
+        let dims = layout.dims();
+        let stride = layout.stride();
+        let total: usize = dims.iter().product();
+        let mut out = Vec::with_capacity(total);
+        let mut idx = vec![0usize; dims.len()];
+        for _ in 0..total {
+            let off: usize =
+                layout.start_offset() + idx.iter().zip(stride).map(|(i, s)| i * s).sum::<usize>();
+            out.push(conv(data[off]));
+            for d in (0..dims.len()).rev() {
+                idx[d] += 1;
+                if idx[d] < dims[d] {
+                    break;
+                }
+                idx[d] = 0;
+            }
+        }
+        out
+    }
+    match storage {
+        CpuStorage::F64(data) => Ok(gather(data, layout, |x| x)),
+        CpuStorage::F32(data) => Ok(gather(data, layout, f64::from)),
+        _ => candle_core::bail!("expected f32 or f64 input"),
+    }
+}
+
+/// Scalar output storage with the same float type as the input.
+fn scalar_storage(value: f64, like: &CpuStorage) -> CpuStorage {
+    match like {
+        CpuStorage::F32(_) => CpuStorage::F32(vec![value as f32]),
+        _ => CpuStorage::F64(vec![value]),
+    }
+}
+
+/// Soft-DTW of a `[n, m]` cost matrix as a candle custom op (scalar output).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftDtw {
+    gamma: f64,
+}
+
+impl SoftDtw {
+    /// Creates the op, validating \(\gamma\).
+    pub fn new(gamma: f64) -> soft_dtw::Result<Self> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(soft_dtw::Error::InvalidGamma(gamma));
+        }
+        Ok(Self { gamma })
+    }
+
+    /// Smoothing parameter.
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+}
+
+impl CustomOp1 for SoftDtw {
+    fn name(&self) -> &'static str {
+        "structop-soft-dtw"
+    }
+
+    fn cpu_fwd(
+        &self,
+        storage: &CpuStorage,
+        layout: &Layout,
+    ) -> candle_core::Result<(CpuStorage, Shape)> {
+        let (n, m) = layout.shape().dims2()?;
+        let cost = read_f64(storage, layout)?;
+        let v =
+            soft_dtw::soft_dtw_cost(&cost, n, m, self.gamma).map_err(candle_core::Error::wrap)?;
+        Ok((scalar_storage(v, storage), Shape::from(())))
+    }
+
+    fn bwd(
+        &self,
+        arg: &Tensor,
+        _res: &Tensor,
+        grad_res: &Tensor,
+    ) -> candle_core::Result<Option<Tensor>> {
+        let (n, m) = arg.dims2()?;
+        let cost = arg.to_dtype(DType::F64)?.flatten_all()?.to_vec1::<f64>()?;
+        let (_, e) = soft_dtw::soft_dtw_alignment(&cost, n, m, self.gamma)
+            .map_err(candle_core::Error::wrap)?;
+        let e = Tensor::from_vec(e, (n, m), arg.device())?.to_dtype(arg.dtype())?;
+        Ok(Some(e.broadcast_mul(grad_res)?))
+    }
+}
+
+/// Soft shortest-path value over a fixed DAG as a candle custom op.
+///
+/// The op input is a `[edges.len()]` tensor of edge costs for the `(from, to)` pairs given
+/// at construction; the output is the scalar value \(V_\gamma\) from node 0 to `n - 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftShortestPath {
+    n: usize,
+    edges: Vec<(usize, usize)>,
+    gamma: f64,
+}
+
+impl SoftShortestPath {
+    /// Creates the op, validating \(\gamma\) and the graph topology (bounds, topological
+    /// order, and that the sink is reachable).
+    pub fn new(
+        n: usize,
+        edges: Vec<(usize, usize)>,
+        gamma: f64,
+    ) -> soft_shortest_path::Result<Self> {
+        let probe = Self::with_costs(&edges, &vec![0.0; edges.len()]);
+        soft_shortest_path::soft_shortest_path_value(n, &probe, gamma)?;
+        Ok(Self { n, edges, gamma })
+    }
+
+    fn with_costs(edges: &[(usize, usize)], costs: &[f64]) -> Vec<Edge> {
+        edges
+            .iter()
+            .zip(costs)
+            .map(|(&(from, to), &cost)| Edge { from, to, cost })
+            .collect()
+    }
+
+    fn edges_for(&self, costs: &[f64]) -> candle_core::Result<Vec<Edge>> {
+        if costs.len() != self.edges.len() {
+            candle_core::bail!(
+                "expected {} edge costs, got {}",
+                self.edges.len(),
+                costs.len()
+            );
+        }
+        Ok(Self::with_costs(&self.edges, costs))
+    }
+}
+
+impl CustomOp1 for SoftShortestPath {
+    fn name(&self) -> &'static str {
+        "structop-soft-shortest-path"
+    }
+
+    fn cpu_fwd(
+        &self,
+        storage: &CpuStorage,
+        layout: &Layout,
+    ) -> candle_core::Result<(CpuStorage, Shape)> {
+        let costs = read_f64(storage, layout)?;
+        let edges = self.edges_for(&costs)?;
+        let v = soft_shortest_path::soft_shortest_path_value(self.n, &edges, self.gamma)
+            .map_err(candle_core::Error::wrap)?;
+        Ok((scalar_storage(v, storage), Shape::from(())))
+    }
+
+    fn bwd(
+        &self,
+        arg: &Tensor,
+        _res: &Tensor,
+        grad_res: &Tensor,
+    ) -> candle_core::Result<Option<Tensor>> {
+        let costs = arg.to_dtype(DType::F64)?.flatten_all()?.to_vec1::<f64>()?;
+        let edges = self.edges_for(&costs)?;
+        let (_, p) =
+            soft_shortest_path::soft_shortest_path_edge_marginals(self.n, &edges, self.gamma)
+                .map_err(candle_core::Error::wrap)?;
+        let p = Tensor::from_vec(p, arg.shape(), arg.device())?.to_dtype(arg.dtype())?;
+        Ok(Some(p.broadcast_mul(grad_res)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::{Device, Var};
+
+    #[test]
+    fn soft_dtw_op_matches_core_and_backprops_alignment() {
+        let flat = vec![0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let cost = Var::from_tensor(&Tensor::from_vec(flat.clone(), (2, 3), &Device::Cpu).unwrap())
+            .unwrap();
+        let v = cost.apply_op1(SoftDtw::new(0.5).unwrap()).unwrap();
+        let loss = (&v * 3.0).unwrap();
+        let grads = loss.backward().unwrap();
+        let g = grads
+            .get(&cost)
+            .unwrap()
+            .flatten_all()
+            .unwrap()
+            .to_vec1::<f64>()
+            .unwrap();
+
+        let (expected, e) = soft_dtw::soft_dtw_alignment(&flat, 2, 3, 0.5).unwrap();
+        assert!((v.to_scalar::<f64>().unwrap() - expected).abs() < 1e-12);
+        for (g, e) in g.iter().zip(&e) {
+            assert!(
+                (g - 3.0 * e).abs() < 1e-12,
+                "grad={} expected={}",
+                g,
+                3.0 * e
+            );
+        }
+    }
+
+    #[test]
+    fn soft_dtw_op_reads_transposed_views() {
+        let c =
+            Tensor::from_vec(vec![0.1f64, 0.9, 0.4, 0.7, 0.2, 0.8], (2, 3), &Device::Cpu).unwrap();
+        let ct = c.t().unwrap();
+        let v = ct
+            .apply_op1(SoftDtw::new(0.5).unwrap())
+            .unwrap()
+            .to_scalar::<f64>()
+            .unwrap();
+        let flat = ct
+            .contiguous()
+            .unwrap()
+            .flatten_all()
+            .unwrap()
+            .to_vec1::<f64>()
+            .unwrap();
+        let expected = soft_dtw::soft_dtw_cost(&flat, 3, 2, 0.5).unwrap();
+        assert!((v - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn soft_path_op_backprops_edge_marginals() {
+        let edges = vec![(0, 1), (1, 3), (0, 2), (2, 3)];
+        let raw = vec![1.0f64, 2.0, 3.0, 4.0];
+        let costs =
+            Var::from_tensor(&Tensor::from_vec(raw.clone(), 4, &Device::Cpu).unwrap()).unwrap();
+        let op = SoftShortestPath::new(4, edges.clone(), 0.5).unwrap();
+        let v = costs.apply_op1(op).unwrap();
+        let grads = v.backward().unwrap();
+        let g = grads.get(&costs).unwrap().to_vec1::<f64>().unwrap();
+
+        let es = SoftShortestPath::with_costs(&edges, &raw);
+        let (_, p) = soft_shortest_path::soft_shortest_path_edge_marginals(4, &es, 0.5).unwrap();
+        for (g, p) in g.iter().zip(&p) {
+            assert!((g - p).abs() < 1e-12, "grad={} marginal={}", g, p);
+        }
+    }
+
+    #[test]
+    fn constructors_validate() {
+        assert_eq!(SoftDtw::new(-1.0), Err(soft_dtw::Error::InvalidGamma(-1.0)));
+        assert_eq!(
+            SoftShortestPath::new(3, vec![(0, 1)], 1.0),
+            Err(soft_shortest_path::Error::NoPath)
+        );
+    }
+}
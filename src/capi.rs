@@ -0,0 +1,322 @@
+//! Stable C ABI (feature `capi`).
+//!
+//! Every function takes inputs as pointer + length, writes results into caller-allocated
+//! buffers, and returns an `i32` status code (`STRUCTOP_OK` on success, one of the
+//! `STRUCTOP_ERR_*` constants otherwise). Output buffers are only written on success.
+//! Panics are caught at the boundary and reported as [`STRUCTOP_ERR_PANIC`].
+//!
+//! `include/structop.h` declares the same surface for C/C++. Build a shared library with
+//! `cargo rustc --lib --release --features capi --crate-type cdylib`.
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+/// Success.
+pub const STRUCTOP_OK: i32 = 0;
+/// A required pointer argument was null.
+pub const STRUCTOP_ERR_NULL_POINTER: i32 = 1;
+/// \(\gamma\) was not positive and finite.
+pub const STRUCTOP_ERR_INVALID_GAMMA: i32 = 2;
+/// An input sequence or matrix was empty.
+pub const STRUCTOP_ERR_EMPTY_INPUT: i32 = 3;
+/// A buffer length did not match the stated dimensions.
+pub const STRUCTOP_ERR_INVALID_SHAPE: i32 = 4;
+/// The graph has fewer than two nodes.
+pub const STRUCTOP_ERR_TOO_FEW_NODES: i32 = 5;
+/// An edge endpoint was out of bounds.
+pub const STRUCTOP_ERR_EDGE_OUT_OF_BOUNDS: i32 = 6;
+/// An edge violated topological order (`from < to`).
+pub const STRUCTOP_ERR_NOT_DAG_ORDER: i32 = 7;
+/// No path exists from source to sink.
+pub const STRUCTOP_ERR_NO_PATH: i32 = 8;
+/// Source/sink were out of bounds or not ordered `source < sink`.
+pub const STRUCTOP_ERR_INVALID_ENDPOINTS: i32 = 9;
+/// A cost was NaN (or, for an edge, infinite).
+pub const STRUCTOP_ERR_INVALID_COST: i32 = 10;
+/// A Rust panic was caught at the boundary (a bug; please report it).
+pub const STRUCTOP_ERR_PANIC: i32 = 99;
+
+fn dtw_code(e: soft_dtw::Error) -> i32 {
+    match e {
+        soft_dtw::Error::InvalidGamma(_) => STRUCTOP_ERR_INVALID_GAMMA,
+        soft_dtw::Error::EmptyInput => STRUCTOP_ERR_EMPTY_INPUT,
+        soft_dtw::Error::InvalidCostShape { .. }
+        | soft_dtw::Error::TensorRank { .. }
+        | soft_dtw::Error::BatchLengthMismatch { .. }
+        | soft_dtw::Error::DimensionOverflow { .. }
+        | soft_dtw::Error::GradientLengthMismatch { .. }
+        | soft_dtw::Error::InvalidWindow { .. }
+        | soft_dtw::Error::InvalidSubstitutionShape { .. }
+        | soft_dtw::Error::SymbolOutOfRange { .. }
+        | soft_dtw::Error::CheckpointMismatch { .. }
+        | soft_dtw::Error::CheckpointNotStarted { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_dtw::Error::NoWarpingPath { .. } => STRUCTOP_ERR_NO_PATH,
+        soft_dtw::Error::NanCost { .. }
+        | soft_dtw::Error::InvalidPenalty(_)
+        | soft_dtw::Error::InvalidDiagonalPenalty(_)
+        | soft_dtw::Error::InvalidCheckpointRow { .. }
+        | soft_dtw::Error::MissingSample { .. }
+        | soft_dtw::Error::InvalidMissingCost(_) => STRUCTOP_ERR_INVALID_COST,
+    }
+}
+
+fn path_code(e: soft_shortest_path::Error) -> i32 {
+    match e {
+        soft_shortest_path::Error::InvalidGamma(_) => STRUCTOP_ERR_INVALID_GAMMA,
+        soft_shortest_path::Error::TooFewNodes(_) => STRUCTOP_ERR_TOO_FEW_NODES,
+        soft_shortest_path::Error::EdgeOutOfBounds { .. } => STRUCTOP_ERR_EDGE_OUT_OF_BOUNDS,
+        soft_shortest_path::Error::NotDagOrder { .. } => STRUCTOP_ERR_NOT_DAG_ORDER,
+        soft_shortest_path::Error::NonFiniteCost { .. } => STRUCTOP_ERR_INVALID_COST,
+        soft_shortest_path::Error::NoPath => STRUCTOP_ERR_NO_PATH,
+        soft_shortest_path::Error::InvalidEndpoints { .. } => STRUCTOP_ERR_INVALID_ENDPOINTS,
+        soft_shortest_path::Error::GradientLengthMismatch { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_shortest_path::Error::EdgeCostLength { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_shortest_path::Error::TensorRank { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_shortest_path::Error::NodeCostLength { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_shortest_path::Error::NonFiniteNodeCost { .. } => STRUCTOP_ERR_INVALID_COST,
+        soft_shortest_path::Error::NodeOutOfBounds { .. } => STRUCTOP_ERR_INVALID_ENDPOINTS,
+        soft_shortest_path::Error::WarmStartMismatch { .. } => STRUCTOP_ERR_INVALID_SHAPE,
+        soft_shortest_path::Error::InvalidTolerance(_) => STRUCTOP_ERR_INVALID_COST,
+    }
+}
+
+/// Runs `f`, mapping `Err(code)` and panics to status codes.
+fn guard(f: impl FnOnce() -> Result<(), i32>) -> i32 {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => STRUCTOP_OK,
+        Ok(Err(code)) => code,
+        Err(_) => STRUCTOP_ERR_PANIC,
+    }
+}
+
+/// Borrows `len` elements at `ptr`; `len == 0` yields an empty slice.
+///
+/// # Safety
+///
+/// If `len > 0`, `ptr` must be null or valid for `len` reads.
+unsafe fn input<'a, T>(ptr: *const T, len: usize) -> Result<&'a [T], i32> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(STRUCTOP_ERR_NULL_POINTER)
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
+
+/// Writes `values` to `out`.
+///
+/// # Safety
+///
+/// `out` must be null or valid for `values.len()` writes.
+unsafe fn output(out: *mut f64, values: &[f64]) -> Result<(), i32> {
+    if out.is_null() {
+        return Err(STRUCTOP_ERR_NULL_POINTER);
+    }
+    slice::from_raw_parts_mut(out, values.len()).copy_from_slice(values);
+    Ok(())
+}
+
+/// Soft-DTW value of sequences `x[0..n]` and `y[0..m]`, written to `*out_value`.
+///
+/// # Safety
+///
+/// `x` and `y` must be valid for `n` and `m` reads; `out_value` must be valid for one write.
+#[no_mangle]
+pub unsafe extern "C" fn structop_soft_dtw(
+    x: *const f64,
+    n: usize,
+    y: *const f64,
+    m: usize,
+    gamma: f64,
+    out_value: *mut f64,
+) -> i32 {
+    guard(|| {
+        let (x, y) = (input(x, n)?, input(y, m)?);
+        let v = soft_dtw::soft_dtw(x, y, gamma).map_err(dtw_code)?;
+        output(out_value, &[v])
+    })
+}
+
+/// Soft-DTW value and gradient w.r.t. `x`, written to `*out_value` and `out_grad[0..n]`.
+///
+/// # Safety
+///
+/// `x` and `y` must be valid for `n` and `m` reads; `out_value` must be valid for one write
+/// and `out_grad` for `n` writes.
+#[no_mangle]
+pub unsafe extern "C" fn structop_soft_dtw_grad(
+    x: *const f64,
+    n: usize,
+    y: *const f64,
+    m: usize,
+    gamma: f64,
+    out_value: *mut f64,
+    out_grad: *mut f64,
+) -> i32 {
+    guard(|| {
+        let (x, y) = (input(x, n)?, input(y, m)?);
+        let (v, g) = soft_dtw::soft_dtw_grad(x, y, gamma).map_err(dtw_code)?;
+        if out_value.is_null() {
+            return Err(STRUCTOP_ERR_NULL_POINTER);
+        }
+        output(out_grad, &g)?;
+        output(out_value, &[v])
+    })
+}
+
+/// Soft-DTW value of a row-major `n × m` cost matrix, written to `*out_value`.
+///
+/// # Safety
+///
+/// `cost` must be valid for `n * m` reads; `out_value` must be valid for one write.
+#[no_mangle]
+pub unsafe extern "C" fn structop_soft_dtw_cost(
+    cost: *const f64,
+    n: usize,
+    m: usize,
+    gamma: f64,
+    out_value: *mut f64,
+) -> i32 {
+    guard(|| {
+        let len = n.checked_mul(m).ok_or(STRUCTOP_ERR_INVALID_SHAPE)?;
+        let cost = input(cost, len)?;
+        let v = soft_dtw::soft_dtw_cost(cost, n, m, gamma).map_err(dtw_code)?;
+        output(out_value, &[v])
+    })
+}
+
+/// Soft-DTW value and expected alignment (gradient w.r.t. the cost matrix), written to
+/// `*out_value` and row-major `out_alignment[0..n*m]`.
+///
+/// # Safety
+///
+/// `cost` must be valid for `n * m` reads; `out_value` must be valid for one write and
+/// `out_alignment` for `n * m` writes.
+#[no_mangle]
+pub unsafe extern "C" fn structop_soft_dtw_alignment(
+    cost: *const f64,
+    n: usize,
+    m: usize,
+    gamma: f64,
+    out_value: *mut f64,
+    out_alignment: *mut f64,
+) -> i32 {
+    guard(|| {
+        let len = n.checked_mul(m).ok_or(STRUCTOP_ERR_INVALID_SHAPE)?;
+        let cost = input(cost, len)?;
+        let (v, e) = soft_dtw::soft_dtw_alignment(cost, n, m, gamma).map_err(dtw_code)?;
+        if out_value.is_null() {
+            return Err(STRUCTOP_ERR_NULL_POINTER);
+        }
+        output(out_alignment, &e)?;
+        output(out_value, &[v])
+    })
+}
+
+/// Soft shortest-path value from node 0 to `n_nodes - 1` and edge marginals.
+///
+/// Edge `k` goes from `from[k]` to `to[k]` with cost `costs[k]`, for `k < num_edges`.
+/// Results are written to `*out_value` and `out_marginals[0..num_edges]`.
+///
+/// # Safety
+///
+/// `from`, `to` and `costs` must be valid for `num_edges` reads; `out_value` must be valid
+/// for one write and `out_marginals` for `num_edges` writes.
+#[no_mangle]
+pub unsafe extern "C" fn structop_soft_shortest_path_marginals(
+    n_nodes: usize,
+    from: *const usize,
+    to: *const usize,
+    costs: *const f64,
+    num_edges: usize,
+    gamma: f64,
+    out_value: *mut f64,
+    out_marginals: *mut f64,
+) -> i32 {
+    guard(|| {
+        let (from, to) = (input(from, num_edges)?, input(to, num_edges)?);
+        let costs = input(costs, num_edges)?;
+        let edges: Vec<Edge> = (0..num_edges)
+            .map(|k| Edge {
+                from: from[k],
+                to: to[k],
+                cost: costs[k],
+            })
+            .collect();
+        let (v, p) = soft_shortest_path::soft_shortest_path_edge_marginals(n_nodes, &edges, gamma)
+            .map_err(path_code)?;
+        if out_value.is_null() {
+            return Err(STRUCTOP_ERR_NULL_POINTER);
+        }
+        output(out_marginals, &p)?;
+        output(out_value, &[v])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn alignment_matches_rust_api_and_reports_codes() {
+        let cost = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let mut v = 0.0;
+        let mut e = [0.0; 6];
+        let rc = unsafe {
+            structop_soft_dtw_alignment(cost.as_ptr(), 2, 3, 0.5, &mut v, e.as_mut_ptr())
+        };
+        assert_eq!(rc, STRUCTOP_OK);
+        let (v_ref, e_ref) = soft_dtw::soft_dtw_alignment(&cost, 2, 3, 0.5).unwrap();
+        assert_eq!(v, v_ref);
+        assert_eq!(e.to_vec(), e_ref);
+
+        let rc = unsafe { structop_soft_dtw_cost(cost.as_ptr(), 2, 3, -1.0, &mut v) };
+        assert_eq!(rc, STRUCTOP_ERR_INVALID_GAMMA);
+        let rc = unsafe { structop_soft_dtw_cost(ptr::null(), 2, 3, 1.0, &mut v) };
+        assert_eq!(rc, STRUCTOP_ERR_NULL_POINTER);
+        let rc = unsafe { structop_soft_dtw(cost.as_ptr(), 0, cost.as_ptr(), 3, 1.0, &mut v) };
+        assert_eq!(rc, STRUCTOP_ERR_EMPTY_INPUT);
+    }
+
+    #[test]
+    fn shortest_path_marginals_and_errors() {
+        let from = [0usize, 1, 0, 2];
+        let to = [1usize, 3, 2, 3];
+        let costs = [1.0, 2.0, 3.0, 4.0];
+        let mut v = 0.0;
+        let mut p = [0.0; 4];
+        let rc = unsafe {
+            structop_soft_shortest_path_marginals(
+                4,
+                from.as_ptr(),
+                to.as_ptr(),
+                costs.as_ptr(),
+                4,
+                0.5,
+                &mut v,
+                p.as_mut_ptr(),
+            )
+        };
+        assert_eq!(rc, STRUCTOP_OK);
+        assert!((p[0] + p[2] - 1.0).abs() < 1e-12);
+
+        let rc = unsafe {
+            structop_soft_shortest_path_marginals(
+                4,
+                to.as_ptr(),
+                from.as_ptr(),
+                costs.as_ptr(),
+                4,
+                0.5,
+                &mut v,
+                p.as_mut_ptr(),
+            )
+        };
+        assert_eq!(rc, STRUCTOP_ERR_NOT_DAG_ORDER);
+    }
+}
@@ -0,0 +1,193 @@
+//! Euclidean projection onto the capped simplex (constrained sparsemax).
+//!
+//! The capped simplex \(\{p : 0 \le p_i \le u,\ \sum_i p_i = k\}\) bounds every weight by a
+//! capacity \(u\); with \(k = 1\) and \(u \ge 1\) the projection is sparsemax, and with
+//! \(u = 1\) and integer \(k\) it is a sparse relaxation of top-\(k\) selection. The
+//! projection of scores \(z\) is \(p_i = \operatorname{clip}(z_i - \tau, 0, u)\) for the
+//! threshold \(\tau\) making the weights sum to \(k\), found exactly by a sweep over the
+//! sorted breakpoints.
+//!
+//! With \(S\) the *free* coordinates (\(0 < p_i < u\)), the generalized Jacobian is
+//! \(J = \operatorname{diag}(1_S) - 1_S 1_S^\top / |S|\): symmetric, so
+//! [`CappedSimplex::vjp`] also serves as the JVP.
+//!
+//! ```
+//! use structop::capped_simplex::capped_simplex;
+//!
+//! // Sparsemax of three scores, then the same scores with each weight capped at 0.6.
+//! let p = capped_simplex(&[1.0, 0.5, -1.0], 1.0, 1.0).unwrap();
+//! assert_eq!(p.weights, [0.75, 0.25, 0.0]);
+//! let p = capped_simplex(&[1.0, 0.5, -1.0], 0.6, 1.0).unwrap();
+//! assert!((p.weights[0] - 0.6).abs() < 1e-12 && (p.weights[1] - 0.4).abs() < 1e-12);
+//! ```
+
+/// Errors for capped-simplex projections.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The input must be non-empty.
+    #[error("empty input")]
+    EmptyInput,
+    /// A score is not finite.
+    #[error("score {index} is not finite: {value}")]
+    NonFiniteScore {
+        /// Index of the score.
+        index: usize,
+        /// The score.
+        value: f64,
+    },
+    /// The capacity must be positive and finite.
+    #[error("capacity must be positive and finite, got {0}")]
+    InvalidCap(f64),
+    /// The total must be positive and reachable under the capacity.
+    #[error("total {total} must be positive and at most {max}")]
+    InvalidTotal {
+        /// The requested total.
+        total: f64,
+        /// Largest reachable total, `n * cap`.
+        max: f64,
+    },
+    /// A gradient does not match the projection's length.
+    #[error("gradient has length {len}, expected {expected}")]
+    GradientLength {
+        /// Length given.
+        len: usize,
+        /// Length expected.
+        expected: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Result of [`capped_simplex`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CappedSimplex {
+    /// The projected weights.
+    pub weights: Vec<f64>,
+    /// The threshold \(\tau\).
+    pub threshold: f64,
+    free: Vec<bool>,
+}
+
+impl CappedSimplex {
+    /// Product of the generalized Jacobian with `grad` (w.r.t. the weights), giving the
+    /// gradient w.r.t. the scores.
+    pub fn vjp(&self, grad: &[f64]) -> Result<Vec<f64>> {
+        if grad.len() != self.weights.len() {
+            return Err(Error::GradientLength {
+                len: grad.len(),
+                expected: self.weights.len(),
+            });
+        }
+        let count = self.free.iter().filter(|&&f| f).count();
+        if count == 0 {
+            return Ok(vec![0.0; grad.len()]);
+        }
+        let mean = grad
+            .iter()
+            .zip(&self.free)
+            .filter(|(_, &f)| f)
+            .map(|(g, _)| g)
+            .sum::<f64>()
+            / count as f64;
+        Ok(grad
+            .iter()
+            .zip(&self.free)
+            .map(|(g, &f)| if f { g - mean } else { 0.0 })
+            .collect())
+    }
+}
+
+/// Projects `scores` onto \(\{p : 0 \le p_i \le \text{cap},\ \sum_i p_i = \text{total}\}\).
+pub fn capped_simplex(scores: &[f64], cap: f64, total: f64) -> Result<CappedSimplex> {
+    if scores.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    if let Some((index, &value)) = scores.iter().enumerate().find(|(_, v)| !v.is_finite()) {
+        return Err(Error::NonFiniteScore { index, value });
+    }
+    if !(cap > 0.0 && cap.is_finite()) {
+        return Err(Error::InvalidCap(cap));
+    }
+    let max = scores.len() as f64 * cap;
+    if !(total > 0.0 && total <= max) {
+        return Err(Error::InvalidTotal { total, max });
+    }
+
+    // f(tau) = sum clip(z - tau, 0, cap) is piecewise linear and nonincreasing: a weight
+    // becomes free at tau = z - cap (slope -1) and reaches 0 at tau = z (slope +1).
+    let mut breaks: Vec<(f64, f64)> = scores
+        .iter()
+        .flat_map(|&z| [(z - cap, -1.0), (z, 1.0)])
+        .collect();
+    breaks.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let (mut at, mut value, mut slope) = (breaks[0].0, max, 0.0);
+    let mut threshold = at;
+    for &(b, step) in &breaks {
+        let next = value + slope * (b - at);
+        if next <= total {
+            threshold = if slope == 0.0 {
+                at
+            } else {
+                at + (value - total) / -slope
+            };
+            break;
+        }
+        (at, value, slope) = (b, next, slope + step);
+    }
+    let weights: Vec<f64> = scores
+        .iter()
+        .map(|&z| (z - threshold).clamp(0.0, cap))
+        .collect();
+    let free = weights.iter().map(|&p| p > 0.0 && p < cap).collect();
+    Ok(CappedSimplex {
+        weights,
+        threshold,
+        free,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    #[test]
+    fn projection_is_feasible_and_jacobian_matches_finite_differences() {
+        let z = [0.9, -0.3, 1.7, 0.2, 1.1, 0.05];
+        for (cap, total) in [(1.0, 1.0), (0.4, 1.0), (0.5, 2.0), (1.0, 6.0)] {
+            let p = capped_simplex(&z, cap, total).unwrap();
+            assert!((p.weights.iter().sum::<f64>() - total).abs() < 1e-12);
+            assert!(p.weights.iter().all(|&w| (0.0..=cap).contains(&w)));
+            // Optimality: every free weight sits at z - tau, capped ones above, zeros below.
+            for (&w, &s) in p.weights.iter().zip(&z) {
+                if w > 0.0 && w < cap {
+                    assert!((s - p.threshold - w).abs() < 1e-12);
+                } else if w == cap {
+                    assert!(s - p.threshold >= cap - 1e-12);
+                } else {
+                    assert!(s - p.threshold <= 1e-12);
+                }
+            }
+        }
+
+        let p = capped_simplex(&z, 0.5, 2.0).unwrap();
+        let g = [0.3, -1.0, 2.0, 0.5, -0.7, 1.2];
+        let analytic = p.vjp(&g).unwrap();
+        let f = |s: &[f64]| {
+            let w = capped_simplex(s, 0.5, 2.0).unwrap().weights;
+            Ok(w.iter().zip(&g).map(|(w, g)| w * g).sum())
+        };
+        let report = check_gradient(f, &z, &analytic, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+
+        assert_eq!(
+            capped_simplex(&z, 0.1, 1.0),
+            Err(Error::InvalidTotal {
+                total: 1.0,
+                max: 6.0 * 0.1
+            })
+        );
+        assert!(p.vjp(&g[1..]).is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! Soft-DTW barycenters and a nearest-centroid classifier built on them.
+//!
+//! The Soft-DTW barycenter of series \(x_1,\dots,x_K\) minimizes
+//! \(F(z) = \sum_k \operatorname{sdtw}_\gamma(z, x_k)\) over series `z` of a fixed length
+//! (Cuturi & Blondel 2017). Soft-DTW is concave in the cost matrix, so the linearization at
+//! the current `z` (through the expected alignments \(E_k\)) bounds `F` from above; with
+//! squared differences, minimizing that bound has the closed form
+//!
+//! \[ z_i \leftarrow \frac{\sum_k \sum_j E_k[i,j]\, x_k[j]}{\sum_k \sum_j E_k[i,j]}, \]
+//!
+//! a soft version of DBA (Petitjean et al. 2011). Each step decreases `F`, with no step size
+//! to tune. The iteration starts from the medoid (the member with the smallest total
+//! divergence to the others), which also fixes the barycenter's length.
+//!
+//! [`NearestCentroid`] fits one barycenter per class and predicts the class whose
+//! barycenter has the smallest Soft-DTW divergence to a series.
+//!
+//! ```
+//! use structop::centroid::NearestCentroid;
+//!
+//! let bump = |at: usize| -> Vec<f64> {
+//!     (0..12usize).map(|t| if t.abs_diff(at) <= 1 { 1.0 } else { 0.0 }).collect()
+//! };
+//! let ramp = |slope: f64| -> Vec<f64> { (0..12).map(|t| slope * t as f64 / 12.0).collect() };
+//! let train = [bump(3), bump(5), bump(7), ramp(1.0), ramp(1.2), ramp(0.9)];
+//! let series: Vec<&[f64]> = train.iter().map(|s| &s[..]).collect();
+//! let mut clf = NearestCentroid::new(0.1);
+//! clf.fit(&series, &[0, 0, 0, 1, 1, 1]).unwrap();
+//! assert_eq!(clf.predict(&bump(4)).unwrap(), 0);
+//! assert_eq!(clf.predict(&ramp(1.1)).unwrap(), 1);
+//! ```
+
+use crate::soft_dtw::{self, Workspace};
+
+/// Errors for barycenters and the nearest-centroid classifier.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Inputs must be non-empty.
+    #[error("inputs must be non-empty")]
+    EmptyInput,
+    /// There must be one label per training series.
+    #[error("{labels} labels for {series} series")]
+    LabelCountMismatch {
+        /// Number of series.
+        series: usize,
+        /// Number of labels.
+        labels: usize,
+    },
+    /// The classifier has not been fitted.
+    #[error("classifier is not fitted")]
+    NotFitted,
+    /// Error from the underlying Soft-DTW.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Stopping controls for the barycenter iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BarycenterOptions {
+    /// Maximum number of iterations.
+    pub max_iter: usize,
+    /// Stop once no point of the barycenter moves by more than this value.
+    pub tol: f64,
+}
+
+impl Default for BarycenterOptions {
+    fn default() -> Self {
+        Self {
+            max_iter: 100,
+            tol: 1e-6,
+        }
+    }
+}
+
+/// Result of a barycenter solve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DtwBarycenter {
+    /// The barycenter series.
+    pub values: Vec<f64>,
+    /// Objective \(\sum_k \operatorname{sdtw}_\gamma(z, x_k)\) at `values`.
+    pub objective: f64,
+    /// Iterations performed.
+    pub iterations: usize,
+    /// Whether the tolerance was reached within `max_iter`.
+    pub converged: bool,
+}
+
+fn squared_cost(x: &[f64], y: &[f64]) -> Vec<f64> {
+    x.iter()
+        .flat_map(|a| y.iter().map(move |b| (a - b) * (a - b)))
+        .collect()
+}
+
+/// Soft-DTW barycenter of `series` (see the module docs), as long as their medoid.
+pub fn soft_dtw_barycenter(
+    series: &[&[f64]],
+    gamma: f64,
+    opts: BarycenterOptions,
+) -> Result<DtwBarycenter> {
+    if series.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let medoid = medoid(&mut Workspace::new(), series, gamma)?;
+    soft_dtw_barycenter_from(series, series[medoid], gamma, opts)
+}
+
+/// [`soft_dtw_barycenter`] started from `init` instead of the medoid (the barycenter has
+/// `init`'s length), e.g. to warm-start from the solution at a larger `gamma`.
+pub fn soft_dtw_barycenter_from(
+    series: &[&[f64]],
+    init: &[f64],
+    gamma: f64,
+    opts: BarycenterOptions,
+) -> Result<DtwBarycenter> {
+    if series.is_empty() || init.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let mut ws = Workspace::new();
+    let mut z = init.to_vec();
+    let n = z.len();
+    let (mut num, mut den) = (vec![0.0; n], vec![0.0; n]);
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < opts.max_iter {
+        iterations += 1;
+        num.fill(0.0);
+        den.fill(0.0);
+        for x in series {
+            let m = x.len();
+            let (_, e) = ws.soft_dtw_alignment(&squared_cost(&z, x), n, m, gamma)?;
+            for (i, row) in e.chunks(m).enumerate() {
+                num[i] += row.iter().zip(x.iter()).map(|(p, v)| p * v).sum::<f64>();
+                den[i] += row.iter().sum::<f64>();
+            }
+        }
+        let mut change = 0.0f64;
+        for (zi, (a, b)) in z.iter_mut().zip(num.iter().zip(&den)) {
+            let next = a / b;
+            change = change.max((next - *zi).abs());
+            *zi = next;
+        }
+        if change <= opts.tol {
+            converged = true;
+            break;
+        }
+    }
+    let objective = series
+        .iter()
+        .map(|x| ws.soft_dtw(&z, x, gamma))
+        .sum::<soft_dtw::Result<f64>>()?;
+    Ok(DtwBarycenter {
+        values: z,
+        objective,
+        iterations,
+        converged,
+    })
+}
+
+/// Index of the member with the smallest total Soft-DTW divergence to the others (the
+/// first one on ties).
+fn medoid(ws: &mut Workspace, series: &[&[f64]], gamma: f64) -> Result<usize> {
+    let selfs = series
+        .iter()
+        .map(|x| ws.soft_dtw(x, x, gamma))
+        .collect::<soft_dtw::Result<Vec<f64>>>()?;
+    let k = series.len();
+    let mut totals = vec![0.0; k];
+    for a in 0..k {
+        for b in a + 1..k {
+            let d = ws.soft_dtw(series[a], series[b], gamma)? - 0.5 * (selfs[a] + selfs[b]);
+            totals[a] += d;
+            totals[b] += d;
+        }
+    }
+    Ok((0..k).fold(0, |best, i| if totals[i] < totals[best] { i } else { best }))
+}
+
+/// Nearest-centroid classifier over Soft-DTW barycenters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NearestCentroid {
+    gamma: f64,
+    opts: BarycenterOptions,
+    centroids: Vec<(usize, DtwBarycenter)>,
+}
+
+impl NearestCentroid {
+    /// Unfitted classifier with smoothing `gamma` and default barycenter options.
+    pub fn new(gamma: f64) -> Self {
+        Self {
+            gamma,
+            opts: BarycenterOptions::default(),
+            centroids: Vec::new(),
+        }
+    }
+
+    /// Stopping controls for the barycenters.
+    pub fn options(mut self, opts: BarycenterOptions) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Fits one barycenter per distinct label, replacing any previous fit.
+    pub fn fit(&mut self, series: &[&[f64]], labels: &[usize]) -> Result<()> {
+        if series.len() != labels.len() {
+            return Err(Error::LabelCountMismatch {
+                series: series.len(),
+                labels: labels.len(),
+            });
+        }
+        if series.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let mut classes = labels.to_vec();
+        classes.sort_unstable();
+        classes.dedup();
+        self.centroids = classes
+            .into_iter()
+            .map(|label| {
+                let members: Vec<&[f64]> = series
+                    .iter()
+                    .zip(labels)
+                    .filter(|(_, &l)| l == label)
+                    .map(|(s, _)| *s)
+                    .collect();
+                Ok((label, soft_dtw_barycenter(&members, self.gamma, self.opts)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(())
+    }
+
+    /// Fitted `(label, barycenter)` pairs, by increasing label.
+    pub fn centroids(&self) -> &[(usize, DtwBarycenter)] {
+        &self.centroids
+    }
+
+    /// Soft-DTW divergence from `x` to each class barycenter, as `(label, divergence)` by
+    /// increasing label.
+    pub fn scores(&self, x: &[f64]) -> Result<Vec<(usize, f64)>> {
+        if self.centroids.is_empty() {
+            return Err(Error::NotFitted);
+        }
+        let mut ws = Workspace::new();
+        let xx = ws.soft_dtw(x, x, self.gamma)?;
+        self.centroids
+            .iter()
+            .map(|(label, c)| {
+                let z = &c.values;
+                let d =
+                    ws.soft_dtw(x, z, self.gamma)? - 0.5 * (xx + ws.soft_dtw(z, z, self.gamma)?);
+                Ok((*label, d))
+            })
+            .collect()
+    }
+
+    /// Label of the nearest barycenter (the smallest label on ties).
+    pub fn predict(&self, x: &[f64]) -> Result<usize> {
+        let scores = self.scores(x)?;
+        let best = scores
+            .iter()
+            .fold(scores[0], |best, &s| if s.1 < best.1 { s } else { best });
+        Ok(best.0)
+    }
+
+    /// [`predict`](Self::predict) for each of `series`.
+    pub fn predict_batch(&self, series: &[&[f64]]) -> Result<Vec<usize>> {
+        series.iter().map(|x| self.predict(x)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(at: f64, len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|t| (-(t as f64 - at).powi(2) / 2.0).exp())
+            .collect()
+    }
+
+    #[test]
+    fn barycenter_decreases_the_objective_from_the_medoid() {
+        let data = [bump(4.0, 16), bump(7.0, 16), bump(10.0, 16), bump(6.0, 14)];
+        let series: Vec<&[f64]> = data.iter().map(|s| &s[..]).collect();
+        let gamma = 0.1;
+        let start = BarycenterOptions {
+            max_iter: 0,
+            tol: 0.0,
+        };
+        let init = soft_dtw_barycenter(&series, gamma, start).unwrap();
+        assert_eq!(init.values, data[1]);
+        let bar = soft_dtw_barycenter(&series, gamma, BarycenterOptions::default()).unwrap();
+        assert!(bar.converged, "{bar:?}");
+        assert!(bar.objective < init.objective);
+        // Still a single bump, near the middle of the members' peaks.
+        let v = &bar.values;
+        let peak = (0..v.len()).fold(0, |p, i| if v[i] > v[p] { i } else { p });
+        assert!((6..=8).contains(&peak), "{bar:?}");
+        assert!(v[..peak].windows(2).all(|w| w[0] <= w[1]));
+        assert!(v[peak..].windows(2).all(|w| w[0] >= w[1]));
+        assert_eq!(
+            soft_dtw_barycenter(&[], gamma, start),
+            Err(Error::EmptyInput)
+        );
+    }
+
+    #[test]
+    fn classifier_predicts_by_nearest_barycenter() {
+        let wave =
+            |phase: f64| -> Vec<f64> { (0..20).map(|t| (t as f64 * 0.6 + phase).sin()).collect() };
+        let train = [
+            bump(5.0, 20),
+            bump(9.0, 20),
+            bump(13.0, 20),
+            wave(0.0),
+            wave(0.4),
+            wave(0.8),
+        ];
+        let series: Vec<&[f64]> = train.iter().map(|s| &s[..]).collect();
+        let mut clf = NearestCentroid::new(0.1);
+        assert_eq!(clf.predict(&train[0]), Err(Error::NotFitted));
+        assert_eq!(
+            clf.fit(&series, &[3, 3]),
+            Err(Error::LabelCountMismatch {
+                series: 6,
+                labels: 2
+            })
+        );
+        clf.fit(&series, &[3, 3, 3, 8, 8, 8]).unwrap();
+        assert_eq!(clf.centroids().len(), 2);
+        let test = [bump(11.0, 18), wave(0.2), bump(7.0, 20), wave(1.0)];
+        let test: Vec<&[f64]> = test.iter().map(|s| &s[..]).collect();
+        assert_eq!(clf.predict_batch(&test).unwrap(), vec![3, 8, 3, 8]);
+    }
+}
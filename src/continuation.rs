@@ -0,0 +1,205 @@
+//! Gamma continuation: solve along a decreasing smoothing schedule.
+//!
+//! Small \(\gamma\) makes the soft operators close to the hard problems but also makes
+//! their objectives nearly as nonconvex (barycenters, learned alignments) and their
+//! gradients nearly as sparse. The standard recipe is to anneal: solve at a large
+//! \(\gamma\), then repeatedly shrink it, warm-starting each stage from the previous
+//! solution. [`GammaSchedule::run`] is that loop for any problem: the caller supplies one
+//! stage as a closure `(gamma, previous solution) -> (solution, value)` and gets the final
+//! solution with the value of every stage.
+//!
+//! ```
+//! use structop::centroid::{soft_dtw_barycenter, soft_dtw_barycenter_from, BarycenterOptions};
+//! use structop::continuation::GammaSchedule;
+//!
+//! let data = [vec![0.0, 1.0, 3.0, 1.0, 0.0], vec![0.0, 0.0, 1.0, 3.0, 1.0]];
+//! let series: Vec<&[f64]> = data.iter().map(|s| &s[..]).collect();
+//! let opts = BarycenterOptions::default();
+//! let schedule = GammaSchedule::geometric(1.0, 0.01, 5).unwrap();
+//! let init = soft_dtw_barycenter(&series, 1.0, opts).unwrap().values;
+//! let annealed = schedule
+//!     .run(init, |gamma, z| {
+//!         let bar = soft_dtw_barycenter_from(&series, &z, gamma, opts)?;
+//!         Ok::<_, structop::centroid::Error>((bar.values, bar.objective))
+//!     })
+//!     .unwrap();
+//! assert_eq!(annealed.stages.len(), 5);
+//! assert_eq!(annealed.stages[4].gamma, 0.01);
+//! ```
+
+/// Errors for gamma schedules.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A schedule needs at least one stage.
+    #[error("schedule has no stages")]
+    EmptySchedule,
+    /// Every `gamma` must be finite and positive.
+    #[error("gamma must be finite and positive, got {0}")]
+    InvalidGamma(f64),
+    /// `gamma` must not increase along the schedule.
+    #[error("gamma increases at stage {index}")]
+    NotDecreasing {
+        /// The first stage whose `gamma` exceeds the previous one.
+        index: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A validated, non-increasing sequence of smoothing parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GammaSchedule {
+    gammas: Vec<f64>,
+}
+
+/// One stage of a continuation run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stage {
+    /// Smoothing parameter of the stage.
+    pub gamma: f64,
+    /// Value reported by the stage.
+    pub value: f64,
+}
+
+/// Result of [`GammaSchedule::run`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annealed<S> {
+    /// Solution of the last stage.
+    pub solution: S,
+    /// Every stage, in order.
+    pub stages: Vec<Stage>,
+}
+
+impl GammaSchedule {
+    /// Schedule over `gammas`, which must be non-empty, finite, positive and non-increasing.
+    pub fn new(gammas: Vec<f64>) -> Result<Self> {
+        if gammas.is_empty() {
+            return Err(Error::EmptySchedule);
+        }
+        if let Some(&g) = gammas.iter().find(|g| !(**g > 0.0 && g.is_finite())) {
+            return Err(Error::InvalidGamma(g));
+        }
+        if let Some(k) = (1..gammas.len()).find(|&k| gammas[k] > gammas[k - 1]) {
+            return Err(Error::NotDecreasing { index: k });
+        }
+        Ok(Self { gammas })
+    }
+
+    /// `stages` values decreasing geometrically from `start` to `end` (both included; a
+    /// single stage is just `end`).
+    pub fn geometric(start: f64, end: f64, stages: usize) -> Result<Self> {
+        if stages == 0 {
+            return Err(Error::EmptySchedule);
+        }
+        if stages == 1 {
+            return Self::new(vec![end]);
+        }
+        let ratio = (end / start).powf(1.0 / (stages - 1) as f64);
+        let mut gammas: Vec<f64> = (0..stages).map(|k| start * ratio.powi(k as i32)).collect();
+        gammas[stages - 1] = end;
+        Self::new(gammas)
+    }
+
+    /// The smoothing parameters, in order.
+    pub fn gammas(&self) -> &[f64] {
+        &self.gammas
+    }
+
+    /// Runs `stage(gamma, solution)` for each `gamma` in order, feeding each stage the
+    /// previous stage's solution (`init` for the first). Stops at the first error.
+    pub fn run<S, E, F>(&self, init: S, mut stage: F) -> std::result::Result<Annealed<S>, E>
+    where
+        F: FnMut(f64, S) -> std::result::Result<(S, f64), E>,
+    {
+        let mut solution = init;
+        let mut stages = Vec::with_capacity(self.gammas.len());
+        for &gamma in &self.gammas {
+            let (next, value) = stage(gamma, solution)?;
+            solution = next;
+            stages.push(Stage { gamma, value });
+        }
+        Ok(Annealed { solution, stages })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::{soft_shortest_path_value, Edge};
+
+    #[test]
+    fn schedules_are_validated() {
+        let s = GammaSchedule::geometric(1.0, 0.001, 4).unwrap();
+        let expected = [1.0, 0.1, 0.01, 0.001];
+        assert!(s
+            .gammas()
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+        assert_eq!(
+            GammaSchedule::geometric(1.0, 0.5, 1).unwrap().gammas(),
+            &[0.5]
+        );
+        assert_eq!(
+            GammaSchedule::new(vec![1.0, 2.0]),
+            Err(Error::NotDecreasing { index: 1 })
+        );
+        assert_eq!(
+            GammaSchedule::geometric(1.0, 0.0, 3),
+            Err(Error::InvalidGamma(0.0))
+        );
+        assert_eq!(GammaSchedule::new(vec![]), Err(Error::EmptySchedule));
+    }
+
+    #[test]
+    fn annealed_values_approach_the_hard_problem() {
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 2.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 2.5,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 1.0,
+            },
+        ];
+        let schedule = GammaSchedule::geometric(2.0, 1e-3, 6).unwrap();
+        let mut seen = Vec::new();
+        let run = schedule
+            .run(0usize, |gamma, calls| {
+                seen.push(gamma);
+                Ok::<_, crate::soft_shortest_path::Error>((
+                    calls + 1,
+                    soft_shortest_path_value(4, &edges, gamma)?,
+                ))
+            })
+            .unwrap();
+        assert_eq!(run.solution, 6);
+        assert_eq!(seen, schedule.gammas());
+        // The soft minimum rises towards the hard shortest path (3.0) as gamma shrinks.
+        assert!(run.stages.windows(2).all(|w| w[0].value <= w[1].value));
+        assert!((run.stages[5].value - 3.0).abs() < 1e-2);
+
+        let failing = schedule.run((), |gamma, ()| {
+            if gamma < 0.1 {
+                Err("too small")
+            } else {
+                Ok(((), gamma))
+            }
+        });
+        assert_eq!(failing, Err("too small"));
+    }
+}
@@ -0,0 +1,336 @@
+//! Feature-template linear-chain CRFs on top of [`crate::lattice`].
+//!
+//! Each position `t` of a sequence has a sparse feature vector `x_t` (pairs
+//! `(feature, value)`); a weight vector holds one emission weight per (tag, feature) followed
+//! by one transition weight per (tag, tag). A tag sequence \(y\) scores
+//!
+//! \[ s(y) = \sum_t \langle w_{y_t}, x_t\rangle + \sum_{t>0} T_{y_{t-1} y_t}, \qquad
+//!    p(y \mid x) = e^{s(y)} / \textstyle\sum_{y'} e^{s(y')}, \]
+//!
+//! which is the Gibbs distribution of the chain lattice with costs \(-s\) at \(\gamma = 1\).
+//! [`FeatureCrf::nll`] returns \(-\log p(y \mid x)\) with its gradient w.r.t. the weights,
+//! expected minus observed feature counts; [`FeatureCrf::partial_nll`] does the same for
+//! partially labelled sequences, and [`FeatureCrf::decode`] runs Viterbi, optionally under
+//! [`TagConstraints`]. Together with any gradient-based optimizer, that is a classical CRF
+//! trainer.
+//!
+//! ```
+//! use structop::crf::FeatureCrf;
+//!
+//! // Two tags; feature 0 fires on tag-0 tokens, feature 1 on tag-1 tokens.
+//! let crf = FeatureCrf::new(2, 2).unwrap();
+//! let x = vec![vec![(0, 1.0)], vec![(1, 1.0)], vec![(1, 1.0)]];
+//! let mut w = vec![0.0; crf.num_weights()];
+//! for _ in 0..50 {
+//!     let (_, grad) = crf.nll(&w, &x, &[0, 1, 1]).unwrap();
+//!     w.iter_mut().zip(&grad).for_each(|(w, g)| *w -= 0.5 * g);
+//! }
+//! assert_eq!(crf.decode(&w, &x, None).unwrap().1, [0, 1, 1]);
+//! ```
+
+use crate::lattice::{
+    self, lattice_viterbi, partial_label_nll, Constrained, Lattice, TagConstraints,
+};
+
+/// Errors for feature-template CRFs.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A CRF needs at least one tag and one feature.
+    #[error("a CRF needs at least one tag and one feature, got {tags} tags, {features} features")]
+    EmptyModel {
+        /// Number of tags.
+        tags: usize,
+        /// Number of features.
+        features: usize,
+    },
+    /// The weight vector has the wrong length.
+    #[error("weights have length {len}, expected {expected}")]
+    WeightLength {
+        /// The provided length.
+        len: usize,
+        /// [`FeatureCrf::num_weights`].
+        expected: usize,
+    },
+    /// A feature index is out of range.
+    #[error("feature {feature} at position {position} out of range for {features} features")]
+    FeatureOutOfRange {
+        /// Position in the sequence.
+        position: usize,
+        /// The feature index.
+        feature: usize,
+        /// Number of features.
+        features: usize,
+    },
+    /// A label sequence does not match the input length.
+    #[error("{len} labels for a sequence of length {expected}")]
+    LabelLength {
+        /// Number of labels.
+        len: usize,
+        /// Sequence length.
+        expected: usize,
+    },
+    /// Error from the underlying lattice (empty sequences, label ranges, constraints).
+    #[error(transparent)]
+    Lattice(#[from] lattice::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Sparse features of one position: `(feature index, value)` pairs.
+pub type SparseFeatures = Vec<(usize, f64)>;
+
+/// A linear-chain CRF over `tags` tags with `features`-dimensional sparse inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeatureCrf {
+    tags: usize,
+    features: usize,
+}
+
+/// The chain lattice of one scored sequence (costs are negated scores).
+struct Scored {
+    emit: Vec<Vec<f64>>,
+    trans: Vec<f64>,
+}
+
+impl Lattice for Scored {
+    fn steps(&self) -> usize {
+        self.emit.len()
+    }
+
+    fn states(&self, _t: usize) -> usize {
+        self.emit[0].len()
+    }
+
+    fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+        let tags = self.emit[0].len();
+        for to in 0..tags {
+            emit(to, self.trans[from * tags + to] + self.emit[t + 1][to]);
+        }
+    }
+
+    fn initial_cost(&self, s: usize) -> f64 {
+        self.emit[0][s]
+    }
+}
+
+impl FeatureCrf {
+    /// A CRF with `tags` tags and `features` features.
+    pub fn new(tags: usize, features: usize) -> Result<Self> {
+        if tags == 0 || features == 0 {
+            return Err(Error::EmptyModel { tags, features });
+        }
+        Ok(Self { tags, features })
+    }
+
+    /// Number of tags.
+    pub fn tags(&self) -> usize {
+        self.tags
+    }
+
+    /// Number of features.
+    pub fn features(&self) -> usize {
+        self.features
+    }
+
+    /// Length of the weight vector: `tags * features` emission weights (row per tag), then
+    /// `tags * tags` transition weights (row per source tag).
+    pub fn num_weights(&self) -> usize {
+        self.tags * (self.features + self.tags)
+    }
+
+    /// Emission scores `[t][tag]` and transition scores `[from * tags + to]` of a sequence.
+    pub fn scores(
+        &self,
+        weights: &[f64],
+        x: &[SparseFeatures],
+    ) -> Result<(Vec<Vec<f64>>, Vec<f64>)> {
+        let lattice = self.lattice(weights, x)?;
+        let neg = |v: &[f64]| v.iter().map(|c| -c).collect::<Vec<f64>>();
+        Ok((
+            lattice.emit.iter().map(|e| neg(e)).collect(),
+            neg(&lattice.trans),
+        ))
+    }
+
+    fn lattice(&self, weights: &[f64], x: &[SparseFeatures]) -> Result<Scored> {
+        if weights.len() != self.num_weights() {
+            return Err(Error::WeightLength {
+                len: weights.len(),
+                expected: self.num_weights(),
+            });
+        }
+        let (emission, transition) = weights.split_at(self.tags * self.features);
+        let mut emit = Vec::with_capacity(x.len());
+        for (position, feats) in x.iter().enumerate() {
+            let mut costs = vec![0.0; self.tags];
+            for &(feature, value) in feats {
+                if feature >= self.features {
+                    return Err(Error::FeatureOutOfRange {
+                        position,
+                        feature,
+                        features: self.features,
+                    });
+                }
+                for (tag, c) in costs.iter_mut().enumerate() {
+                    *c -= emission[tag * self.features + feature] * value;
+                }
+            }
+            emit.push(costs);
+        }
+        let trans = transition.iter().map(|w| -w).collect();
+        Ok(Scored { emit, trans })
+    }
+
+    /// \(-\log p(y \mid x)\) and its gradient w.r.t. the weights.
+    pub fn nll(
+        &self,
+        weights: &[f64],
+        x: &[SparseFeatures],
+        y: &[usize],
+    ) -> Result<(f64, Vec<f64>)> {
+        if y.len() != x.len() {
+            return Err(Error::LabelLength {
+                len: y.len(),
+                expected: x.len(),
+            });
+        }
+        let labels: Vec<Option<&[usize]>> =
+            y.iter().map(|t| Some(std::slice::from_ref(t))).collect();
+        self.partial_nll(weights, x, &labels)
+    }
+
+    /// Negative log marginal likelihood of partial labels (`labels[t]` the allowed tags at
+    /// position `t`, `None` if unlabelled) and its gradient w.r.t. the weights.
+    pub fn partial_nll(
+        &self,
+        weights: &[f64],
+        x: &[SparseFeatures],
+        labels: &[Option<&[usize]>],
+    ) -> Result<(f64, Vec<f64>)> {
+        let lattice = self.lattice(weights, x)?;
+        let out = partial_label_nll(&lattice, labels, 1.0)?;
+        // Scores are negated costs: d nll / d score = -(cost gradient) = expected - observed.
+        let mut grad = vec![0.0; self.num_weights()];
+        let (emission, transition) = grad.split_at_mut(self.tags * self.features);
+        for (feats, g) in x.iter().zip(&out.state_grads) {
+            for &(feature, value) in feats {
+                for (tag, g) in g.iter().enumerate() {
+                    emission[tag * self.features + feature] -= g * value;
+                }
+            }
+        }
+        for step in &out.transition_grads {
+            for (t, g) in transition.iter_mut().zip(step) {
+                *t -= g;
+            }
+        }
+        Ok((out.nll, grad))
+    }
+
+    /// Viterbi decoding: the best score and tag sequence, under `constraints` if given.
+    pub fn decode(
+        &self,
+        weights: &[f64],
+        x: &[SparseFeatures],
+        constraints: Option<&TagConstraints>,
+    ) -> Result<(f64, Vec<usize>)> {
+        let lattice = self.lattice(weights, x)?;
+        let (cost, path) = match constraints {
+            Some(c) => lattice_viterbi(&Constrained::new(&lattice, c)?)?,
+            None => lattice_viterbi(&lattice)?,
+        };
+        Ok((-cost, path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    fn sequence() -> Vec<SparseFeatures> {
+        vec![
+            vec![(0, 1.0), (2, 0.5)],
+            vec![(1, 1.0)],
+            vec![(1, 0.3), (2, -1.0)],
+            vec![(0, 2.0)],
+        ]
+    }
+
+    #[test]
+    fn nll_gradient_is_expected_minus_observed_counts() {
+        let crf = FeatureCrf::new(3, 3).unwrap();
+        let x = sequence();
+        let w: Vec<f64> = (0..crf.num_weights())
+            .map(|k| ((k * 7 % 11) as f64 - 5.0) / 10.0)
+            .collect();
+        let y = [0, 2, 2, 1];
+        let (nll, grad) = crf.nll(&w, &x, &y).unwrap();
+
+        // Brute force over all 3^4 tag sequences.
+        let (emit, trans) = crf.scores(&w, &x).unwrap();
+        let score = |seq: &[usize]| {
+            let e: f64 = seq.iter().enumerate().map(|(t, &s)| emit[t][s]).sum();
+            e + seq.windows(2).map(|p| trans[p[0] * 3 + p[1]]).sum::<f64>()
+        };
+        let z: f64 = (0..81)
+            .map(|k| [k % 3, k / 3 % 3, k / 9 % 3, k / 27])
+            .map(|seq| score(&seq).exp())
+            .sum();
+        assert!((nll - (z.ln() - score(&y))).abs() < 1e-10);
+
+        let f = |w: &[f64]| Ok(crf.nll(w, &x, &y).unwrap().0);
+        let report = check_gradient(f, &w, &grad, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+
+        let labels: [Option<&[usize]>; 4] = [Some(&[0]), None, Some(&[1, 2]), None];
+        let (partial, grad) = crf.partial_nll(&w, &x, &labels).unwrap();
+        assert!(partial < nll);
+        let f = |w: &[f64]| Ok(crf.partial_nll(w, &x, &labels).unwrap().0);
+        let report = check_gradient(f, &w, &grad, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+    }
+
+    #[test]
+    fn decoding_respects_constraints_and_inputs_are_checked() {
+        // Tags O, B, I; emissions favour starting with I.
+        let crf = FeatureCrf::new(3, 1).unwrap();
+        let x = vec![vec![(0, 1.0)]; 2];
+        let mut w = vec![0.0; crf.num_weights()];
+        w[2] = 1.0;
+        w[1] = 0.5;
+        assert_eq!(crf.decode(&w, &x, None).unwrap().1, [2, 2]);
+        let bio = TagConstraints::bio(1);
+        let (score, path) = crf.decode(&w, &x, Some(&bio)).unwrap();
+        assert_eq!(path, [1, 2]);
+        assert!((score - 1.5).abs() < 1e-12);
+
+        assert_eq!(
+            crf.nll(&w, &[vec![(1, 1.0)]], &[0]),
+            Err(Error::FeatureOutOfRange {
+                position: 0,
+                feature: 1,
+                features: 1
+            })
+        );
+        assert_eq!(
+            crf.nll(&w[1..], &x, &[0, 0]).map(|_| ()),
+            Err(Error::WeightLength {
+                len: 11,
+                expected: 12
+            })
+        );
+        assert_eq!(
+            crf.nll(&w, &x, &[0]).map(|_| ()),
+            Err(Error::LabelLength {
+                len: 1,
+                expected: 2
+            })
+        );
+        assert!(matches!(
+            crf.nll(&w, &[], &[]),
+            Err(Error::Lattice(lattice::Error::EmptyStep { step: 0 }))
+        ));
+    }
+}
@@ -0,0 +1,925 @@
+//! Smoothed elastic distances beyond DTW.
+//!
+//! [`crate::soft_dtw`] sums the cost along a warping path. The measures here are the other
+//! standard elastic distances for time series and trajectories, each with its DP smoothed
+//! the same way (hard `min`/`max` replaced by their log-sum-exp relaxations at
+//! temperature \(\gamma\)), so that the value is differentiable and the gradient is an
+//! expected quantity under a Gibbs distribution over alignments.
+//!
+//! - Discrete Fréchet ([`soft_frechet`]): the bottleneck (largest) cost along a coupled
+//!   traversal rather than the summed cost, via
+//!   \(F_{i,j} = \max_\gamma(c_{i,j}, \min_\gamma(F_{i-1,j}, F_{i,j-1}, F_{i-1,j-1}))\).
+//!   The gradient w.r.t. the cost matrix is a distribution over cells: the probability
+//!   that each cell is the bottleneck.
+//! - Edit distance with Real Penalty ([`soft_erp`], Chen & Ng 2004): an edit distance
+//!   between sequences where matching `x_i` with `y_j` costs `|x_i - y_j|` and skipping a
+//!   point costs its distance to a reference gap value `g`. Hard ERP is a metric; the
+//!   smoothed value is not (it is below the hard one, and below zero on identical inputs).
+//! - Time Warp Edit Distance ([`soft_twed`], Marteau 2009): edits that consume a point of one
+//!   sequence pay the change from the previous point plus a penalty `λ`, matches pay both
+//!   the current and the previous pair's differences, and every step pays a stiffness `ν`
+//!   times its time offset. Timestamps are the indices `1..=n`, with a zero point at time 0
+//!   before each sequence. Hard TWED is a metric.
+//! - Longest common subsequence ([`soft_lcss`], Vlachos et al. 2002): a similarity counting
+//!   the points that can be matched in order within a tolerance `ε`. The smoothed version
+//!   scores a match by \(\sigma((\varepsilon - |x_i - y_j|)/\tau)\) instead of the hard
+//!   threshold and takes a soft maximum over alignments, so `ε` gets a gradient too.
+//! - Move-Split-Merge ([`soft_msm`], Stefan et al. 2013): a match moves `x_i` to `y_j` at
+//!   cost `|x_i - y_j|`; splitting a point into two (or merging two into one) costs `c`,
+//!   plus the distance to the nearer neighbour when the new point is not between them. Hard
+//!   MSM is a metric.
+//!
+//! As \(\gamma \to 0\) each value tends to its hard counterpart ([`discrete_frechet`], ...).
+//!
+//! ```
+//! use structop::elastic::{discrete_frechet, soft_frechet};
+//!
+//! // Two planar trajectories; the cost is the Euclidean distance between points.
+//! let a = [(0.0, 0.0), (1.0, 0.1), (2.0, 0.0)];
+//! let b = [(0.0, 0.5), (1.0, 0.6), (1.5, 0.4), (2.0, 0.5)];
+//! let dist = |p: &(f64, f64), q: &(f64, f64)| (p.0 - q.0).hypot(p.1 - q.1);
+//! let cost: Vec<f64> = a.iter().flat_map(|p| b.iter().map(move |q| dist(p, q))).collect();
+//! let hard = discrete_frechet(&cost, 3, 4).unwrap();
+//! let soft = soft_frechet(&cost, 3, 4, 1e-3).unwrap();
+//! assert!((soft - hard).abs() < 1e-2);
+//! ```
+
+use crate::math::{exp, ln};
+
+/// Errors for elastic distances.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Smoothing parameter \(\gamma\) must be positive and finite.
+    #[error("gamma must be positive and finite, got {0}")]
+    InvalidGamma(f64),
+    /// Inputs must be non-empty.
+    #[error("inputs must be non-empty")]
+    EmptyInput,
+    /// Cost matrix shape mismatch.
+    #[error("cost matrix has length {len}, expected {n}*{m}={expected}")]
+    InvalidCostShape {
+        /// The provided `cost` slice length.
+        len: usize,
+        /// Expected row count.
+        n: usize,
+        /// Expected column count.
+        m: usize,
+        /// `n*m`, included explicitly for readability.
+        expected: usize,
+    },
+    /// A distance parameter is outside its domain: NaN or infinite, or negative where it is a
+    /// cost.
+    #[error("{name} is out of range, got {value}")]
+    InvalidParameter {
+        /// Parameter name.
+        name: &'static str,
+        /// The offending value.
+        value: f64,
+    },
+    /// A cost matrix entry is NaN (the first one in row-major order).
+    #[error("cost[{i}][{j}] is NaN")]
+    NanCost {
+        /// Row index.
+        i: usize,
+        /// Column index.
+        j: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn check_gamma(gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    Ok(())
+}
+
+fn check_cost(cost: &[f64], n: usize, m: usize) -> Result<()> {
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    match n.checked_mul(m) {
+        Some(expected) if expected == cost.len() => {}
+        expected => {
+            return Err(Error::InvalidCostShape {
+                len: cost.len(),
+                n,
+                m,
+                expected: expected.unwrap_or(usize::MAX),
+            })
+        }
+    }
+    match cost.iter().position(|c| c.is_nan()) {
+        Some(k) => Err(Error::NanCost { i: k / m, j: k % m }),
+        None => Ok(()),
+    }
+}
+
+fn check_finite(name: &'static str, value: f64) -> Result<()> {
+    if !value.is_finite() {
+        return Err(Error::InvalidParameter { name, value });
+    }
+    Ok(())
+}
+
+fn check_nonnegative(name: &'static str, value: f64) -> Result<()> {
+    if !(value >= 0.0 && value.is_finite()) {
+        return Err(Error::InvalidParameter { name, value });
+    }
+    Ok(())
+}
+
+/// \(-\gamma \log \sum_k e^{-a_k/\gamma}\) over the candidates; `+inf` when all are, and
+/// the plain minimum for `gamma == 0`.
+fn softmin(gamma: f64, xs: &[f64]) -> f64 {
+    let best = xs.iter().copied().fold(f64::INFINITY, f64::min);
+    if !best.is_finite() || gamma == 0.0 {
+        return best;
+    }
+    let s: f64 = xs.iter().map(|&a| exp(-(a - best) / gamma)).sum();
+    best - gamma * ln(s)
+}
+
+/// \(\gamma \log(e^{a/\gamma} + e^{b/\gamma})\), computed without overflow.
+fn softmax2(gamma: f64, a: f64, b: f64) -> f64 {
+    let hi = a.max(b);
+    if hi.is_infinite() {
+        return hi;
+    }
+    hi + gamma * ln(1.0 + exp(-(a - b).abs() / gamma))
+}
+
+/// Weight of `a` in [`softmax2`], `e^{a/γ} / (e^{a/γ} + e^{b/γ})`.
+fn softmax2_weight(gamma: f64, a: f64, b: f64) -> f64 {
+    if a == f64::INFINITY || b == f64::NEG_INFINITY {
+        1.0
+    } else if a >= b {
+        1.0 / (1.0 + exp((b - a) / gamma))
+    } else {
+        let t = exp((a - b) / gamma);
+        t / (1.0 + t)
+    }
+}
+
+/// Predecessors of cell `(i, j)` in a coupled traversal: up, left and diagonal.
+fn frechet_preds(i: usize, j: usize, m: usize) -> impl Iterator<Item = usize> {
+    let up = (i > 0).then(|| (i - 1) * m + j);
+    let left = (j > 0).then(|| i * m + j - 1);
+    let diag = (i > 0 && j > 0).then(|| (i - 1) * m + j - 1);
+    [up, left, diag].into_iter().flatten()
+}
+
+/// Forward table `F` (row-major `n × m`) and the softmin `S` of each cell's predecessors
+/// (`-inf` at the origin, which has none).
+fn frechet_forward(cost: &[f64], n: usize, m: usize, gamma: f64) -> (Vec<f64>, Vec<f64>) {
+    let mut f = vec![0.0; n * m];
+    let mut s = vec![f64::NEG_INFINITY; n * m];
+    f[0] = cost[0];
+    for i in 0..n {
+        for j in 0..m {
+            if i == 0 && j == 0 {
+                continue;
+            }
+            let mut preds = [f64::INFINITY; 3];
+            for (slot, p) in preds.iter_mut().zip(frechet_preds(i, j, m)) {
+                *slot = f[p];
+            }
+            let k = i * m + j;
+            s[k] = softmin(gamma, &preds);
+            f[k] = softmax2(gamma, cost[k], s[k]);
+        }
+    }
+    (f, s)
+}
+
+/// Discrete Fréchet distance: the smallest, over monotone coupled traversals from `(0, 0)`
+/// to `(n-1, m-1)`, of the largest cost on the traversal. `cost` is row-major `n × m`.
+pub fn discrete_frechet(cost: &[f64], n: usize, m: usize) -> Result<f64> {
+    check_cost(cost, n, m)?;
+    let mut f = vec![0.0; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            let k = i * m + j;
+            let best = frechet_preds(i, j, m)
+                .map(|p| f[p])
+                .fold(f64::INFINITY, f64::min);
+            f[k] = if k == 0 { cost[0] } else { cost[k].max(best) };
+        }
+    }
+    Ok(f[n * m - 1])
+}
+
+/// Soft discrete Fréchet distance at temperature `gamma`; `cost` is row-major `n × m`.
+///
+/// `+inf` entries forbid their cell.
+pub fn soft_frechet(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+    check_gamma(gamma)?;
+    check_cost(cost, n, m)?;
+    let (f, _) = frechet_forward(cost, n, m, gamma);
+    Ok(f[n * m - 1])
+}
+
+/// Soft discrete Fréchet distance and its gradient w.r.t. the cost matrix (row-major
+/// `n × m`). The gradient is nonnegative and sums to one: entry `(i, j)` is the probability
+/// that cell `(i, j)` is the bottleneck of the traversal.
+pub fn soft_frechet_grad(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<(f64, Vec<f64>)> {
+    check_gamma(gamma)?;
+    check_cost(cost, n, m)?;
+    let (f, s) = frechet_forward(cost, n, m, gamma);
+    // Adjoints of F, swept in reverse: each cell splits its adjoint between its own cost
+    // and the softmin of its predecessors, which passes it on with the softmin weights.
+    let mut adj = vec![0.0; n * m];
+    let mut grad = vec![0.0; n * m];
+    adj[n * m - 1] = 1.0;
+    for k in (0..n * m).rev() {
+        let g = adj[k];
+        if g == 0.0 {
+            continue;
+        }
+        let w = softmax2_weight(gamma, cost[k], s[k]);
+        grad[k] = g * w;
+        if !s[k].is_finite() {
+            continue;
+        }
+        for p in frechet_preds(k / m, k % m, m) {
+            adj[p] += g * (1.0 - w) * exp(-(f[p] - s[k]) / gamma);
+        }
+    }
+    Ok((f[n * m - 1], grad))
+}
+
+/// `±1` by sign and `0` at zero: the subgradient of `|v|` that the gradients below use.
+fn sign(v: f64) -> f64 {
+    if v > 0.0 {
+        1.0
+    } else if v < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+/// Moves of the edit DPs, as indices into a cell's move array: consume a point of `x`
+/// only, a point of `y` only, or one of each.
+const UP: usize = 0;
+const LEFT: usize = 1;
+const DIAG: usize = 2;
+
+/// Moves into table cell `(i, j)` that stay in the table, with the predecessor's index
+/// (row-major, width `w`).
+fn edit_preds(i: usize, j: usize, w: usize) -> impl Iterator<Item = (usize, usize)> {
+    let up = (i > 0).then(|| (UP, (i - 1) * w + j));
+    let left = (j > 0).then(|| (LEFT, i * w + j - 1));
+    let diag = (i > 0 && j > 0).then(|| (DIAG, (i - 1) * w + j - 1));
+    [up, left, diag].into_iter().flatten()
+}
+
+/// Forward table `R` of an edit DP, row-major `(n+1) × (m+1)` with `R[0][0] = 0`.
+///
+/// `cost(i, j, mv)` is the cost of move `mv` into table cell `(i, j)` (so the points consumed
+/// are `x[i-1]` and/or `y[j-1]`); it is only called for moves that stay in the table.
+/// `gamma == 0` gives the hard DP.
+fn edit_forward<F>(n: usize, m: usize, gamma: f64, cost: &F) -> Vec<f64>
+where
+    F: Fn(usize, usize, usize) -> f64,
+{
+    let w = m + 1;
+    let mut r = vec![f64::INFINITY; (n + 1) * w];
+    r[0] = 0.0;
+    for i in 0..=n {
+        for j in 0..=m {
+            if i == 0 && j == 0 {
+                continue;
+            }
+            let mut cand = [f64::INFINITY; 3];
+            for (mv, pred) in edit_preds(i, j, w) {
+                cand[mv] = r[pred] + cost(i, j, mv);
+            }
+            r[i * w + j] = softmin(gamma, &cand);
+        }
+    }
+    r
+}
+
+/// Expected number of times each move into each table cell is taken under the Gibbs
+/// distribution over edit paths, `[up, left, diag]` per cell: the gradient of the value
+/// w.r.t. that move's cost.
+fn edit_marginals<F>(r: &[f64], m: usize, gamma: f64, cost: &F) -> Vec<[f64; 3]>
+where
+    F: Fn(usize, usize, usize) -> f64,
+{
+    let w = m + 1;
+    let mut occ = vec![0.0; r.len()];
+    let mut moves = vec![[0.0; 3]; r.len()];
+    occ[r.len() - 1] = 1.0;
+    for k in (1..r.len()).rev() {
+        if occ[k] == 0.0 || !r[k].is_finite() {
+            continue;
+        }
+        let (i, j) = (k / w, k % w);
+        for (mv, pred) in edit_preds(i, j, w) {
+            let p = occ[k] * exp(-(r[pred] + cost(i, j, mv) - r[k]) / gamma);
+            moves[k][mv] = p;
+            occ[pred] += p;
+        }
+    }
+    moves
+}
+
+/// Gradient of a soft ERP value w.r.t. its inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErpGrad {
+    /// W.r.t. each point of `x`.
+    pub x: Vec<f64>,
+    /// W.r.t. each point of `y`.
+    pub y: Vec<f64>,
+    /// W.r.t. the gap value.
+    pub gap: f64,
+}
+
+fn erp_cost<'a>(x: &'a [f64], y: &'a [f64], gap: f64) -> impl Fn(usize, usize, usize) -> f64 + 'a {
+    move |i, j, mv| match mv {
+        UP => (x[i - 1] - gap).abs(),
+        LEFT => (y[j - 1] - gap).abs(),
+        _ => (x[i - 1] - y[j - 1]).abs(),
+    }
+}
+
+/// Edit distance with Real Penalty between `x` and `y` with gap value `gap` (commonly `0`
+/// for z-normalized series). Either sequence may be empty.
+pub fn erp(x: &[f64], y: &[f64], gap: f64) -> Result<f64> {
+    check_finite("gap", gap)?;
+    let r = edit_forward(x.len(), y.len(), 0.0, &erp_cost(x, y, gap));
+    Ok(r[r.len() - 1])
+}
+
+/// Soft ERP at temperature `gamma`.
+pub fn soft_erp(x: &[f64], y: &[f64], gap: f64, gamma: f64) -> Result<f64> {
+    check_gamma(gamma)?;
+    check_finite("gap", gap)?;
+    let r = edit_forward(x.len(), y.len(), gamma, &erp_cost(x, y, gap));
+    Ok(r[r.len() - 1])
+}
+
+/// Soft ERP and its gradient w.r.t. `x`, `y` and the gap value. Where a point coincides with
+/// the value it is compared to, the kink of `|·|` contributes zero.
+pub fn soft_erp_grad(x: &[f64], y: &[f64], gap: f64, gamma: f64) -> Result<(f64, ErpGrad)> {
+    check_gamma(gamma)?;
+    check_finite("gap", gap)?;
+    let (n, m) = (x.len(), y.len());
+    let cost = erp_cost(x, y, gap);
+    let r = edit_forward(n, m, gamma, &cost);
+    let moves = edit_marginals(&r, m, gamma, &cost);
+    let mut grad = ErpGrad {
+        x: vec![0.0; n],
+        y: vec![0.0; m],
+        gap: 0.0,
+    };
+    for (k, &[up, left, diag]) in moves.iter().enumerate() {
+        let (i, j) = (k / (m + 1), k % (m + 1));
+        if up != 0.0 {
+            let s = up * sign(x[i - 1] - gap);
+            grad.x[i - 1] += s;
+            grad.gap -= s;
+        }
+        if left != 0.0 {
+            let s = left * sign(y[j - 1] - gap);
+            grad.y[j - 1] += s;
+            grad.gap -= s;
+        }
+        if diag != 0.0 {
+            let s = diag * sign(x[i - 1] - y[j - 1]);
+            grad.x[i - 1] += s;
+            grad.y[j - 1] -= s;
+        }
+    }
+    Ok((r[r.len() - 1], grad))
+}
+
+/// Gradient of a soft TWED value w.r.t. its inputs and parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwedGrad {
+    /// W.r.t. each point of `x`.
+    pub x: Vec<f64>,
+    /// W.r.t. each point of `y`.
+    pub y: Vec<f64>,
+    /// W.r.t. the stiffness `ν`.
+    pub stiffness: f64,
+    /// W.r.t. the edit penalty `λ`.
+    pub penalty: f64,
+}
+
+/// Point `k` (1-based) of `x`, with the zero point before it at `k = 0`.
+fn padded(x: &[f64], k: usize) -> f64 {
+    if k == 0 {
+        0.0
+    } else {
+        x[k - 1]
+    }
+}
+
+fn twed_cost<'a>(
+    x: &'a [f64],
+    y: &'a [f64],
+    stiffness: f64,
+    penalty: f64,
+) -> impl Fn(usize, usize, usize) -> f64 + 'a {
+    move |i, j, mv| match mv {
+        // Edits need a point of the other sequence already consumed.
+        UP if j == 0 => f64::INFINITY,
+        LEFT if i == 0 => f64::INFINITY,
+        UP => (x[i - 1] - padded(x, i - 1)).abs() + stiffness + penalty,
+        LEFT => (y[j - 1] - padded(y, j - 1)).abs() + stiffness + penalty,
+        _ => {
+            (x[i - 1] - y[j - 1]).abs()
+                + (padded(x, i - 1) - padded(y, j - 1)).abs()
+                + 2.0 * stiffness * i.abs_diff(j) as f64
+        }
+    }
+}
+
+fn check_twed(x: &[f64], y: &[f64], stiffness: f64, penalty: f64) -> Result<()> {
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    check_nonnegative("stiffness", stiffness)?;
+    check_nonnegative("penalty", penalty)
+}
+
+/// Time Warp Edit Distance between non-empty `x` and `y` with stiffness `ν >= 0` and edit
+/// penalty `λ >= 0`.
+pub fn twed(x: &[f64], y: &[f64], stiffness: f64, penalty: f64) -> Result<f64> {
+    check_twed(x, y, stiffness, penalty)?;
+    let r = edit_forward(x.len(), y.len(), 0.0, &twed_cost(x, y, stiffness, penalty));
+    Ok(r[r.len() - 1])
+}
+
+/// Soft TWED at temperature `gamma`.
+pub fn soft_twed(x: &[f64], y: &[f64], stiffness: f64, penalty: f64, gamma: f64) -> Result<f64> {
+    check_gamma(gamma)?;
+    check_twed(x, y, stiffness, penalty)?;
+    let r = edit_forward(
+        x.len(),
+        y.len(),
+        gamma,
+        &twed_cost(x, y, stiffness, penalty),
+    );
+    Ok(r[r.len() - 1])
+}
+
+/// Soft TWED and its gradient w.r.t. `x`, `y`, the stiffness and the penalty. Kinks of `|·|`
+/// contribute zero.
+pub fn soft_twed_grad(
+    x: &[f64],
+    y: &[f64],
+    stiffness: f64,
+    penalty: f64,
+    gamma: f64,
+) -> Result<(f64, TwedGrad)> {
+    check_gamma(gamma)?;
+    check_twed(x, y, stiffness, penalty)?;
+    let (n, m) = (x.len(), y.len());
+    let cost = twed_cost(x, y, stiffness, penalty);
+    let r = edit_forward(n, m, gamma, &cost);
+    let moves = edit_marginals(&r, m, gamma, &cost);
+    let mut grad = TwedGrad {
+        x: vec![0.0; n],
+        y: vec![0.0; m],
+        stiffness: 0.0,
+        penalty: 0.0,
+    };
+    // Adds `w` to the gradient of point `k` (1-based) of a sequence; the zero point is fixed.
+    let add = |g: &mut [f64], k: usize, w: f64| {
+        if k > 0 {
+            g[k - 1] += w;
+        }
+    };
+    for (k, &[up, left, diag]) in moves.iter().enumerate() {
+        let (i, j) = (k / (m + 1), k % (m + 1));
+        if up != 0.0 {
+            let s = up * sign(x[i - 1] - padded(x, i - 1));
+            add(&mut grad.x, i, s);
+            add(&mut grad.x, i - 1, -s);
+            grad.stiffness += up;
+            grad.penalty += up;
+        }
+        if left != 0.0 {
+            let s = left * sign(y[j - 1] - padded(y, j - 1));
+            add(&mut grad.y, j, s);
+            add(&mut grad.y, j - 1, -s);
+            grad.stiffness += left;
+            grad.penalty += left;
+        }
+        if diag != 0.0 {
+            let s = diag * sign(x[i - 1] - y[j - 1]);
+            add(&mut grad.x, i, s);
+            add(&mut grad.y, j, -s);
+            let s = diag * sign(padded(x, i - 1) - padded(y, j - 1));
+            add(&mut grad.x, i - 1, s);
+            add(&mut grad.y, j - 1, -s);
+            grad.stiffness += diag * 2.0 * i.abs_diff(j) as f64;
+        }
+    }
+    Ok((r[r.len() - 1], grad))
+}
+
+fn sigmoid(z: f64) -> f64 {
+    if z >= 0.0 {
+        1.0 / (1.0 + exp(-z))
+    } else {
+        let t = exp(z);
+        t / (1.0 + t)
+    }
+}
+
+/// Gradient of a soft LCSS value w.r.t. its inputs and tolerance.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LcssGrad {
+    /// W.r.t. each point of `x`.
+    pub x: Vec<f64>,
+    /// W.r.t. each point of `y`.
+    pub y: Vec<f64>,
+    /// W.r.t. the tolerance `ε`.
+    pub epsilon: f64,
+}
+
+/// LCSS as an edit DP minimizing minus the number of matches: skipping a point is free and
+/// a match earns `score(i, j)`.
+fn lcss_cost<S>(score: S) -> impl Fn(usize, usize, usize) -> f64
+where
+    S: Fn(usize, usize) -> f64,
+{
+    move |i, j, mv| {
+        if mv == DIAG {
+            -score(i - 1, j - 1)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Length of the longest common subsequence of `x` and `y`, where `x_i` matches `y_j` when
+/// `|x_i - y_j| <= epsilon`. Divide by `min(n, m)` for the usual similarity in `[0, 1]`.
+pub fn lcss(x: &[f64], y: &[f64], epsilon: f64) -> Result<usize> {
+    check_nonnegative("epsilon", epsilon)?;
+    let hit = |i: usize, j: usize| f64::from(u8::from((x[i] - y[j]).abs() <= epsilon));
+    let r = edit_forward(x.len(), y.len(), 0.0, &lcss_cost(hit));
+    Ok(-r[r.len() - 1] as usize)
+}
+
+/// Soft LCSS length: matches scored by a sigmoid of temperature `tau` around the tolerance
+/// `epsilon`, and a soft maximum at temperature `gamma` over alignments. It tends to
+/// [`lcss`] as both temperatures go to zero (away from points exactly `epsilon` apart).
+pub fn soft_lcss(x: &[f64], y: &[f64], epsilon: f64, tau: f64, gamma: f64) -> Result<f64> {
+    check_gamma(gamma)?;
+    check_lcss(epsilon, tau)?;
+    let score = |i: usize, j: usize| sigmoid((epsilon - (x[i] - y[j]).abs()) / tau);
+    let r = edit_forward(x.len(), y.len(), gamma, &lcss_cost(score));
+    Ok(-r[r.len() - 1])
+}
+
+fn check_lcss(epsilon: f64, tau: f64) -> Result<()> {
+    check_nonnegative("epsilon", epsilon)?;
+    if !(tau > 0.0 && tau.is_finite()) {
+        return Err(Error::InvalidParameter {
+            name: "tau",
+            value: tau,
+        });
+    }
+    Ok(())
+}
+
+/// Soft LCSS and its gradient w.r.t. `x`, `y` and `epsilon`.
+pub fn soft_lcss_grad(
+    x: &[f64],
+    y: &[f64],
+    epsilon: f64,
+    tau: f64,
+    gamma: f64,
+) -> Result<(f64, LcssGrad)> {
+    check_gamma(gamma)?;
+    check_lcss(epsilon, tau)?;
+    let (n, m) = (x.len(), y.len());
+    let score = |i: usize, j: usize| sigmoid((epsilon - (x[i] - y[j]).abs()) / tau);
+    let cost = lcss_cost(score);
+    let r = edit_forward(n, m, gamma, &cost);
+    let moves = edit_marginals(&r, m, gamma, &cost);
+    let mut grad = LcssGrad {
+        x: vec![0.0; n],
+        y: vec![0.0; m],
+        epsilon: 0.0,
+    };
+    for (k, mv) in moves.iter().enumerate() {
+        let (i, j) = (k / (m + 1), k % (m + 1));
+        if mv[DIAG] == 0.0 {
+            continue;
+        }
+        // d score / d epsilon = σ(1 - σ) / τ, and |x_i - y_j| enters with the opposite sign.
+        let s = score(i - 1, j - 1);
+        let w = mv[DIAG] * s * (1.0 - s) / tau;
+        let d = sign(x[i - 1] - y[j - 1]);
+        grad.epsilon += w;
+        grad.x[i - 1] -= w * d;
+        grad.y[j - 1] += w * d;
+    }
+    Ok((-r[r.len() - 1], grad))
+}
+
+/// Gradient of a soft MSM value w.r.t. its inputs and split/merge cost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MsmGrad {
+    /// W.r.t. each point of `x`.
+    pub x: Vec<f64>,
+    /// W.r.t. each point of `y`.
+    pub y: Vec<f64>,
+    /// W.r.t. the split/merge cost `c`.
+    pub split_cost: f64,
+}
+
+/// Cost of a split or merge producing `new` next to `a` while the other sequence is at `b`,
+/// and its partial derivatives w.r.t. `(new, a, b)`.
+fn msm_split(new: f64, a: f64, b: f64, c: f64) -> (f64, [f64; 3]) {
+    if (a <= new && new <= b) || (a >= new && new >= b) {
+        (c, [0.0; 3])
+    } else if (new - a).abs() <= (new - b).abs() {
+        let s = sign(new - a);
+        (c + (new - a).abs(), [s, -s, 0.0])
+    } else {
+        let s = sign(new - b);
+        (c + (new - b).abs(), [s, 0.0, -s])
+    }
+}
+
+fn msm_cost<'a>(x: &'a [f64], y: &'a [f64], c: f64) -> impl Fn(usize, usize, usize) -> f64 + 'a {
+    move |i, j, mv| match mv {
+        // Splits and merges act next to a point of the same sequence, so the first points of
+        // both sequences are matched with each other (and the table's border is unreachable).
+        UP if i < 2 || j == 0 => f64::INFINITY,
+        LEFT if j < 2 || i == 0 => f64::INFINITY,
+        UP => msm_split(x[i - 1], x[i - 2], y[j - 1], c).0,
+        LEFT => msm_split(y[j - 1], y[j - 2], x[i - 1], c).0,
+        _ => (x[i - 1] - y[j - 1]).abs(),
+    }
+}
+
+fn check_msm(x: &[f64], y: &[f64], c: f64) -> Result<()> {
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    check_nonnegative("split_cost", c)
+}
+
+/// Move-Split-Merge distance between non-empty `x` and `y` with split/merge cost
+/// `split_cost >= 0`.
+pub fn msm(x: &[f64], y: &[f64], split_cost: f64) -> Result<f64> {
+    check_msm(x, y, split_cost)?;
+    let r = edit_forward(x.len(), y.len(), 0.0, &msm_cost(x, y, split_cost));
+    Ok(r[r.len() - 1])
+}
+
+/// Soft MSM at temperature `gamma`.
+pub fn soft_msm(x: &[f64], y: &[f64], split_cost: f64, gamma: f64) -> Result<f64> {
+    check_gamma(gamma)?;
+    check_msm(x, y, split_cost)?;
+    let r = edit_forward(x.len(), y.len(), gamma, &msm_cost(x, y, split_cost));
+    Ok(r[r.len() - 1])
+}
+
+/// Soft MSM and its gradient w.r.t. `x`, `y` and the split/merge cost. Kinks (a point equal
+/// to a neighbour, or equidistant from both) take one side's derivative.
+pub fn soft_msm_grad(x: &[f64], y: &[f64], split_cost: f64, gamma: f64) -> Result<(f64, MsmGrad)> {
+    check_gamma(gamma)?;
+    check_msm(x, y, split_cost)?;
+    let (n, m) = (x.len(), y.len());
+    let cost = msm_cost(x, y, split_cost);
+    let r = edit_forward(n, m, gamma, &cost);
+    let moves = edit_marginals(&r, m, gamma, &cost);
+    let mut grad = MsmGrad {
+        x: vec![0.0; n],
+        y: vec![0.0; m],
+        split_cost: 0.0,
+    };
+    for (k, &[up, left, diag]) in moves.iter().enumerate() {
+        let (i, j) = (k / (m + 1), k % (m + 1));
+        if up != 0.0 {
+            let (_, [dn, da, db]) = msm_split(x[i - 1], x[i - 2], y[j - 1], split_cost);
+            grad.x[i - 1] += up * dn;
+            grad.x[i - 2] += up * da;
+            grad.y[j - 1] += up * db;
+            grad.split_cost += up;
+        }
+        if left != 0.0 {
+            let (_, [dn, da, db]) = msm_split(y[j - 1], y[j - 2], x[i - 1], split_cost);
+            grad.y[j - 1] += left * dn;
+            grad.y[j - 2] += left * da;
+            grad.x[i - 1] += left * db;
+            grad.split_cost += left;
+        }
+        if diag != 0.0 {
+            let s = diag * sign(x[i - 1] - y[j - 1]);
+            grad.x[i - 1] += s;
+            grad.y[j - 1] -= s;
+        }
+    }
+    Ok((r[r.len() - 1], grad))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    const COST: [f64; 12] = [0.2, 1.0, 2.5, 3.0, 0.9, 0.4, 1.8, 2.2, 2.0, 1.1, 0.3, 0.6];
+
+    #[test]
+    fn frechet_is_the_bottleneck_and_soft_tends_to_it() {
+        // Best traversal (0,0) (1,1) (2,2) (2,3): bottleneck 0.6.
+        assert_eq!(discrete_frechet(&COST, 3, 4), Ok(0.6));
+        let soft = soft_frechet(&COST, 3, 4, 1e-4).unwrap();
+        assert!((soft - 0.6).abs() < 1e-3, "{soft}");
+        // The end cells are on every traversal.
+        assert_eq!(discrete_frechet(&[5.0, 1.0, 1.0, 0.0], 2, 2), Ok(5.0));
+        assert_eq!(
+            soft_frechet(&COST, 4, 4, 0.1),
+            Err(Error::InvalidCostShape {
+                len: 12,
+                n: 4,
+                m: 4,
+                expected: 16
+            })
+        );
+        assert_eq!(
+            soft_frechet(&COST, 3, 4, 0.0),
+            Err(Error::InvalidGamma(0.0))
+        );
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences_and_sums_to_one() {
+        let (v, g) = soft_frechet_grad(&COST, 3, 4, 0.3).unwrap();
+        assert_eq!(soft_frechet(&COST, 3, 4, 0.3), Ok(v));
+        assert!((g.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(g.iter().all(|&p| p >= 0.0));
+        let f = |c: &[f64]| Ok(soft_frechet(c, 3, 4, 0.3).unwrap());
+        let report = check_gradient(f, &COST, &g, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+    }
+
+    #[test]
+    fn erp_is_a_metric_and_soft_erp_tends_to_it() {
+        // Match 1 with 1 and skip 2 (|2 - 0|), or match 2 with 1 and skip 1: both cost 2.
+        assert_eq!(erp(&[1.0, 2.0], &[1.0], 0.0), Ok(2.0));
+        assert_eq!(erp(&[1.0, -2.0], &[], 0.0), Ok(3.0));
+        let seqs: [&[f64]; 3] = [&[0.3, 1.7, -0.4, 2.2], &[0.1, 1.2, 2.9], &[1.0, 0.0]];
+        for a in seqs {
+            assert_eq!(erp(a, a, 0.5), Ok(0.0));
+            for b in seqs {
+                let ab = erp(a, b, 0.5).unwrap();
+                assert_eq!(Ok(ab), erp(b, a, 0.5));
+                for c in seqs {
+                    assert!(ab <= erp(a, c, 0.5).unwrap() + erp(c, b, 0.5).unwrap() + 1e-12);
+                }
+                let soft = soft_erp(a, b, 0.5, 1e-4).unwrap();
+                assert!(soft <= ab && ab - soft < 1e-3, "{soft} vs {ab}");
+            }
+        }
+        assert_eq!(
+            erp(&[1.0], &[1.0], f64::NAN).map_err(|e| e.to_string()),
+            Err("gap is out of range, got NaN".to_string())
+        );
+    }
+
+    #[test]
+    fn erp_gradients_match_finite_differences() {
+        let (x, y, gap) = ([0.3, 1.7, -0.4, 2.2], [0.1, 1.2, 2.9], 0.5);
+        let (v, g) = soft_erp_grad(&x, &y, gap, 0.4).unwrap();
+        assert_eq!(soft_erp(&x, &y, gap, 0.4), Ok(v));
+        let tol = Tolerance::default();
+        let fx = |x: &[f64]| Ok(soft_erp(x, &y, gap, 0.4).unwrap());
+        assert!(check_gradient(fx, &x, &g.x, tol).unwrap().passed());
+        let fy = |y: &[f64]| Ok(soft_erp(&x, y, gap, 0.4).unwrap());
+        assert!(check_gradient(fy, &y, &g.y, tol).unwrap().passed());
+        let fg = |g: &[f64]| Ok(soft_erp(&x, &y, g[0], 0.4).unwrap());
+        assert!(check_gradient(fg, &[gap], &[g.gap], tol).unwrap().passed());
+    }
+
+    #[test]
+    fn twed_is_a_metric_and_soft_twed_tends_to_it() {
+        // Identical lengths and one differing point: the diagonal pays it twice (as the
+        // current pair, then as the previous pair of the next match).
+        assert_eq!(twed(&[1.0, 2.0, 3.0], &[1.0, 2.5, 3.0], 0.1, 1.0), Ok(1.0));
+        let seqs: [&[f64]; 3] = [&[0.3, 1.7, -0.4, 2.2], &[0.1, 1.2, 2.9], &[1.0, 0.0]];
+        for a in seqs {
+            assert_eq!(twed(a, a, 0.2, 0.5), Ok(0.0));
+            for b in seqs {
+                let ab = twed(a, b, 0.2, 0.5).unwrap();
+                assert!((ab - twed(b, a, 0.2, 0.5).unwrap()).abs() < 1e-12);
+                for c in seqs {
+                    let ac = twed(a, c, 0.2, 0.5).unwrap();
+                    assert!(ab <= ac + twed(c, b, 0.2, 0.5).unwrap() + 1e-12);
+                }
+                let soft = soft_twed(a, b, 0.2, 0.5, 1e-4).unwrap();
+                assert!(soft <= ab && ab - soft < 1e-3, "{soft} vs {ab}");
+            }
+        }
+        assert_eq!(
+            twed(&[1.0], &[1.0], -0.1, 1.0),
+            Err(Error::InvalidParameter {
+                name: "stiffness",
+                value: -0.1
+            })
+        );
+        assert_eq!(twed(&[], &[1.0], 0.1, 1.0), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn twed_gradients_match_finite_differences() {
+        let (x, y, nu, lambda) = ([0.3, 1.7, -0.4, 2.2], [0.1, 1.2, 2.9], 0.2, 0.5);
+        let (v, g) = soft_twed_grad(&x, &y, nu, lambda, 0.4).unwrap();
+        assert_eq!(soft_twed(&x, &y, nu, lambda, 0.4), Ok(v));
+        let tol = Tolerance::default();
+        let fx = |x: &[f64]| Ok(soft_twed(x, &y, nu, lambda, 0.4).unwrap());
+        assert!(check_gradient(fx, &x, &g.x, tol).unwrap().passed());
+        let fy = |y: &[f64]| Ok(soft_twed(&x, y, nu, lambda, 0.4).unwrap());
+        assert!(check_gradient(fy, &y, &g.y, tol).unwrap().passed());
+        let fp = |p: &[f64]| Ok(soft_twed(&x, &y, p[0], p[1], 0.4).unwrap());
+        let params = [nu, lambda];
+        let report = check_gradient(fp, &params, &[g.stiffness, g.penalty], tol).unwrap();
+        assert!(report.passed(), "{report:?}");
+    }
+
+    #[test]
+    fn soft_lcss_tends_to_the_match_count() {
+        let (x, y) = ([0.0, 1.0, 5.0, 2.0, 3.0], [0.1, 2.1, 2.9, 7.0]);
+        // 0.0~0.1, 2.0~2.1, 3.0~2.9.
+        assert_eq!(lcss(&x, &y, 0.2), Ok(3));
+        assert_eq!(lcss(&x, &y, 0.0), Ok(0));
+        assert_eq!(lcss(&x, &[], 0.2), Ok(0));
+        let soft = soft_lcss(&x, &y, 0.2, 1e-3, 1e-3).unwrap();
+        assert!(soft >= 3.0 - 1e-9 && soft - 3.0 < 1e-2, "{soft}");
+        assert_eq!(
+            soft_lcss(&x, &y, 0.2, 0.0, 0.1),
+            Err(Error::InvalidParameter {
+                name: "tau",
+                value: 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn lcss_gradients_match_finite_differences() {
+        let (x, y, eps) = ([0.0, 1.0, 5.0, 2.0, 3.0], [0.1, 2.1, 2.9, 7.0], 0.2);
+        let (v, g) = soft_lcss_grad(&x, &y, eps, 0.1, 0.3).unwrap();
+        assert_eq!(soft_lcss(&x, &y, eps, 0.1, 0.3), Ok(v));
+        assert!(g.epsilon > 0.0);
+        let tol = Tolerance::default();
+        let fx = |x: &[f64]| Ok(soft_lcss(x, &y, eps, 0.1, 0.3).unwrap());
+        assert!(check_gradient(fx, &x, &g.x, tol).unwrap().passed());
+        let fy = |y: &[f64]| Ok(soft_lcss(&x, y, eps, 0.1, 0.3).unwrap());
+        assert!(check_gradient(fy, &y, &g.y, tol).unwrap().passed());
+        let fe = |e: &[f64]| Ok(soft_lcss(&x, &y, e[0], 0.1, 0.3).unwrap());
+        assert!(check_gradient(fe, &[eps], &[g.epsilon], tol)
+            .unwrap()
+            .passed());
+    }
+
+    #[test]
+    fn msm_is_a_metric_and_soft_msm_tends_to_it() {
+        // Match 1 with 1, then merge 2 into it: c plus the distance from 2 to 1.
+        assert_eq!(msm(&[1.0, 2.0], &[1.0], 0.5), Ok(1.5));
+        // A point between its neighbour and the other sequence costs only c.
+        assert_eq!(msm(&[1.0, 2.0], &[1.0, 3.0], 0.5), Ok(1.0));
+        let seqs: [&[f64]; 3] = [&[0.3, 1.7, -0.4, 2.2], &[0.1, 1.2, 2.9], &[1.0, 0.0]];
+        for a in seqs {
+            assert_eq!(msm(a, a, 0.5), Ok(0.0));
+            for b in seqs {
+                let ab = msm(a, b, 0.5).unwrap();
+                assert!((ab - msm(b, a, 0.5).unwrap()).abs() < 1e-12);
+                for c in seqs {
+                    assert!(ab <= msm(a, c, 0.5).unwrap() + msm(c, b, 0.5).unwrap() + 1e-12);
+                }
+                let soft = soft_msm(a, b, 0.5, 1e-4).unwrap();
+                assert!(soft <= ab && ab - soft < 1e-3, "{soft} vs {ab}");
+            }
+        }
+        assert_eq!(msm(&[1.0], &[], 0.5), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn msm_gradients_match_finite_differences() {
+        let (x, y, c) = ([0.3, 1.7, -0.4, 2.2], [0.1, 1.2, 2.9], 0.5);
+        let (v, g) = soft_msm_grad(&x, &y, c, 0.4).unwrap();
+        assert_eq!(soft_msm(&x, &y, c, 0.4), Ok(v));
+        let tol = Tolerance::default();
+        let fx = |x: &[f64]| Ok(soft_msm(x, &y, c, 0.4).unwrap());
+        assert!(check_gradient(fx, &x, &g.x, tol).unwrap().passed());
+        let fy = |y: &[f64]| Ok(soft_msm(&x, y, c, 0.4).unwrap());
+        assert!(check_gradient(fy, &y, &g.y, tol).unwrap().passed());
+        let fc = |p: &[f64]| Ok(soft_msm(&x, &y, p[0], 0.4).unwrap());
+        assert!(check_gradient(fc, &[c], &[g.split_cost], tol)
+            .unwrap()
+            .passed());
+    }
+}
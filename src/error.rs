@@ -0,0 +1,173 @@
+//! Crate-level error type.
+//!
+//! Each operator module keeps its own `Error` (so callers can match on the precise failure);
+//! [`Error`] wraps all of them with `From` impls, so code that mixes operators can use one
+//! `Result` and `?` throughout.
+
+use crate::{
+    absorption, anomaly, capped_simplex, centroid, continuation, crf, elastic, fixed_point,
+    gradcheck, hausdorff, implicit_path, invariants, lattice, local_gamma, max_entropy, mbr,
+    metric_learning, motif, nearest_neighbor, ot, path_moments, registration, risk_sensitive, sax,
+    segmental, shape_dtw, soft_dtw, soft_shortest_path, string_kernels, time_expanded, viz, warp2d,
+    znorm,
+};
+
+/// Any error produced by this crate.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Error from [`crate::soft_dtw`].
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+    /// Error from [`crate::soft_shortest_path`].
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+    /// Error from [`crate::absorption`].
+    #[error(transparent)]
+    Absorption(#[from] absorption::Error),
+    /// Error from [`crate::anomaly`].
+    #[error(transparent)]
+    Anomaly(#[from] anomaly::Error),
+    /// Error from [`crate::capped_simplex`].
+    #[error(transparent)]
+    CappedSimplex(#[from] capped_simplex::Error),
+    /// Error from [`crate::centroid`].
+    #[error(transparent)]
+    Centroid(#[from] centroid::Error),
+    /// Error from [`crate::local_gamma`].
+    #[error(transparent)]
+    LocalGamma(#[from] local_gamma::Error),
+    /// Error from [`crate::max_entropy`].
+    #[error(transparent)]
+    MaxEntropy(#[from] max_entropy::Error),
+    /// Error from [`crate::metric_learning`].
+    #[error(transparent)]
+    MetricLearning(#[from] metric_learning::Error),
+    /// Error from [`crate::continuation`].
+    #[error(transparent)]
+    Continuation(#[from] continuation::Error),
+    /// Error from [`crate::motif`].
+    #[error(transparent)]
+    Motif(#[from] motif::Error),
+    /// Error from [`crate::nearest_neighbor`].
+    #[error(transparent)]
+    NearestNeighbor(#[from] nearest_neighbor::Error),
+    /// Error from [`crate::ot`].
+    #[error(transparent)]
+    Ot(#[from] ot::Error),
+    /// Error from [`crate::path_moments`].
+    #[error(transparent)]
+    PathMoments(#[from] path_moments::Error),
+    /// Error from [`crate::registration`].
+    #[error(transparent)]
+    Registration(#[from] registration::Error),
+    /// Error from [`crate::mbr`].
+    #[error(transparent)]
+    Mbr(#[from] mbr::Error),
+    /// Error from [`crate::risk_sensitive`].
+    #[error(transparent)]
+    RiskSensitive(#[from] risk_sensitive::Error),
+    /// Error from [`crate::sax`].
+    #[error(transparent)]
+    Sax(#[from] sax::Error),
+    /// Error from [`crate::segmental`].
+    #[error(transparent)]
+    Segmental(#[from] segmental::Error),
+    /// Error from [`crate::crf`].
+    #[error(transparent)]
+    Crf(#[from] crf::Error),
+    /// Error from [`crate::elastic`].
+    #[error(transparent)]
+    Elastic(#[from] elastic::Error),
+    /// Error from [`crate::shape_dtw`].
+    #[error(transparent)]
+    ShapeDtw(#[from] shape_dtw::Error),
+    /// Error from [`crate::string_kernels`].
+    #[error(transparent)]
+    StringKernels(#[from] string_kernels::Error),
+    /// Error from [`crate::fixed_point`].
+    #[error(transparent)]
+    FixedPoint(#[from] fixed_point::Error),
+    /// Error from [`crate::gradcheck`].
+    #[error(transparent)]
+    Gradcheck(#[from] gradcheck::Error),
+    /// Error from [`crate::hausdorff`].
+    #[error(transparent)]
+    Hausdorff(#[from] hausdorff::Error),
+    /// Error from [`crate::implicit_path`].
+    #[error(transparent)]
+    ImplicitPath(#[from] implicit_path::Error),
+    /// Error from [`crate::invariants`].
+    #[error(transparent)]
+    Invariants(#[from] invariants::Error),
+    /// Error from [`crate::lattice`].
+    #[error(transparent)]
+    Lattice(#[from] lattice::Error),
+    /// Error from [`crate::time_expanded`].
+    #[error(transparent)]
+    TimeExpanded(#[from] time_expanded::Error),
+    /// Error from [`crate::viz`].
+    #[error(transparent)]
+    Viz(#[from] viz::Error),
+    /// Error from [`crate::warp2d`].
+    #[error(transparent)]
+    Warp2d(#[from] warp2d::Error),
+    /// Error from [`crate::znorm`].
+    #[error(transparent)]
+    Znorm(#[from] znorm::Error),
+    /// Error from [`crate::arrow_interop`].
+    #[cfg(feature = "arrow")]
+    #[error(transparent)]
+    Arrow(#[from] crate::arrow_interop::Error),
+    /// Error from [`crate::polars_interop`].
+    #[cfg(feature = "polars")]
+    #[error(transparent)]
+    Polars(#[from] crate::polars_interop::Error),
+    /// Error from [`crate::npy`].
+    #[cfg(feature = "npy")]
+    #[error(transparent)]
+    Npy(#[from] crate::npy::Error),
+    /// Error from [`crate::mmap`].
+    #[cfg(feature = "mmap")]
+    #[error(transparent)]
+    Mmap(#[from] crate::mmap::Error),
+    /// Error from [`crate::graph_json`].
+    #[cfg(feature = "json")]
+    #[error(transparent)]
+    GraphJson(#[from] crate::graph_json::Error),
+}
+
+/// Result type using the crate-level [`Error`].
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::Edge;
+
+    fn mixed(gamma: f64) -> Result<f64> {
+        let d = soft_dtw::soft_dtw(&[0.0, 1.0], &[1.0], gamma)?;
+        let edges = [Edge {
+            from: 0,
+            to: 1,
+            cost: d,
+        }];
+        Ok(soft_shortest_path::soft_shortest_path_value(
+            2, &edges, gamma,
+        )?)
+    }
+
+    #[test]
+    fn module_errors_convert_and_keep_their_message() {
+        assert!(mixed(0.5).is_ok());
+        let err = mixed(-1.0).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::SoftDtw(soft_dtw::Error::InvalidGamma(g)) if g == -1.0
+        ));
+        assert_eq!(
+            err.to_string(),
+            soft_dtw::Error::InvalidGamma(-1.0).to_string()
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Finite-difference gradient checks.
+//!
+//! Compares an analytic gradient against central differences
+//! \((f(x + h e_k) - f(x - h e_k)) / 2h\), coordinate by coordinate. [`check_gradient`] works
+//! for any scalar function of a slice; the wrappers cover the gradients this crate exposes:
+//! the expected alignment (w.r.t. a cost matrix), the sequence gradient of Soft-DTW and the
+//! edge marginals of soft shortest path (w.r.t. edge costs).
+//!
+//! Downstream wrappers (autograd integrations, bindings) can run the same checks against
+//! their own analytic gradients through [`check_gradient`].
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+/// Errors for gradient checks.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The analytic gradient does not have one entry per input coordinate.
+    #[error("analytic gradient has length {analytic}, expected {expected}")]
+    LengthMismatch {
+        /// Length of the analytic gradient.
+        analytic: usize,
+        /// Number of input coordinates.
+        expected: usize,
+    },
+    /// Step and tolerances must be finite, with a positive step and nonnegative tolerances.
+    #[error("invalid tolerance {0:?}")]
+    InvalidTolerance(Tolerance),
+    /// Error from a Soft-DTW evaluation.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+    /// Error from a soft shortest-path evaluation.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Finite-difference step and pass criterion.
+///
+/// Coordinate `k` passes when `|analytic[k] - numeric[k]| <= atol + rtol * |numeric[k]|`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Central-difference step `h`.
+    pub step: f64,
+    /// Absolute tolerance.
+    pub atol: f64,
+    /// Relative tolerance (w.r.t. the numeric derivative).
+    pub rtol: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            step: 1e-6,
+            atol: 1e-7,
+            rtol: 1e-5,
+        }
+    }
+}
+
+/// Outcome of a gradient check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    /// The analytic gradient that was checked.
+    pub analytic: Vec<f64>,
+    /// Central-difference estimates, one per coordinate.
+    pub numeric: Vec<f64>,
+    /// Largest absolute difference between analytic and numeric entries.
+    pub max_abs_error: f64,
+    /// Coordinates that failed the tolerance, in increasing order.
+    pub failures: Vec<usize>,
+}
+
+impl Report {
+    /// Whether every coordinate is within tolerance.
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Checks `analytic` against central differences of `f` at `x`.
+///
+/// `f` is evaluated `2 * x.len()` times on perturbed copies of `x`; errors from `f` are
+/// returned as-is.
+pub fn check_gradient<F>(mut f: F, x: &[f64], analytic: &[f64], tol: Tolerance) -> Result<Report>
+where
+    F: FnMut(&[f64]) -> Result<f64>,
+{
+    let valid = tol.step > 0.0
+        && tol.step.is_finite()
+        && tol.atol >= 0.0
+        && tol.atol.is_finite()
+        && tol.rtol >= 0.0
+        && tol.rtol.is_finite();
+    if !valid {
+        return Err(Error::InvalidTolerance(tol));
+    }
+    if analytic.len() != x.len() {
+        return Err(Error::LengthMismatch {
+            analytic: analytic.len(),
+            expected: x.len(),
+        });
+    }
+
+    let h = tol.step;
+    let mut probe = x.to_vec();
+    let mut numeric = Vec::with_capacity(x.len());
+    let mut max_abs_error = 0.0f64;
+    let mut failures = Vec::new();
+    for k in 0..x.len() {
+        probe[k] = x[k] + h;
+        let up = f(&probe)?;
+        probe[k] = x[k] - h;
+        let dn = f(&probe)?;
+        probe[k] = x[k];
+        let fd = (up - dn) / (2.0 * h);
+        let err = (analytic[k] - fd).abs();
+        max_abs_error = max_abs_error.max(err);
+        // A NaN on either side makes `err` NaN, which fails.
+        if err.is_nan() || err > tol.atol + tol.rtol * fd.abs() {
+            failures.push(k);
+        }
+        numeric.push(fd);
+    }
+    Ok(Report {
+        analytic: analytic.to_vec(),
+        numeric,
+        max_abs_error,
+        failures,
+    })
+}
+
+/// Checks [`soft_dtw::soft_dtw_alignment`] as the gradient of [`soft_dtw::soft_dtw_cost`]
+/// w.r.t. the cost matrix.
+pub fn check_soft_dtw_alignment(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    tol: Tolerance,
+) -> Result<Report> {
+    let (_, e) = soft_dtw::soft_dtw_alignment(cost, n, m, gamma)?;
+    check_gradient(
+        |c| Ok(soft_dtw::soft_dtw_cost(c, n, m, gamma)?),
+        cost,
+        &e,
+        tol,
+    )
+}
+
+/// Checks [`soft_dtw::soft_dtw_grad`] as the gradient of [`soft_dtw::soft_dtw`] w.r.t. `x`.
+pub fn check_soft_dtw_grad(x: &[f64], y: &[f64], gamma: f64, tol: Tolerance) -> Result<Report> {
+    let (_, g) = soft_dtw::soft_dtw_grad(x, y, gamma)?;
+    check_gradient(|x| Ok(soft_dtw::soft_dtw(x, y, gamma)?), x, &g, tol)
+}
+
+/// Checks [`soft_shortest_path::soft_shortest_path_edge_marginals`] as the gradient of
+/// [`soft_shortest_path::soft_shortest_path_value`] w.r.t. the edge costs (in edge order).
+pub fn check_edge_marginals(
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+    tol: Tolerance,
+) -> Result<Report> {
+    let (_, p) = soft_shortest_path::soft_shortest_path_edge_marginals(n, edges, gamma)?;
+    let costs: Vec<f64> = edges.iter().map(|e| e.cost).collect();
+    let mut probe = edges.to_vec();
+    check_gradient(
+        |c| {
+            for (e, &cost) in probe.iter_mut().zip(c) {
+                e.cost = cost;
+            }
+            Ok(soft_shortest_path::soft_shortest_path_value(
+                n, &probe, gamma,
+            )?)
+        },
+        &costs,
+        &p,
+        tol,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_gradients_pass() {
+        let cost = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let r = check_soft_dtw_alignment(&cost, 2, 3, 0.5, Tolerance::default()).unwrap();
+        assert!(r.passed(), "{:?}", r);
+
+        let r =
+            check_soft_dtw_grad(&[0.2, -0.4, 1.1], &[0.0, 0.5], 0.4, Tolerance::default()).unwrap();
+        assert!(r.passed(), "{:?}", r);
+
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 1.5,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 0.2,
+            },
+        ];
+        let r = check_edge_marginals(3, &edges, 0.7, Tolerance::default()).unwrap();
+        assert!(r.passed(), "{:?}", r);
+    }
+
+    #[test]
+    fn detects_wrong_gradients_and_bad_arguments() {
+        let x = [1.0, -2.0];
+        let f = |x: &[f64]| Ok(x[0] * x[0] + 3.0 * x[1]);
+        let r = check_gradient(f, &x, &[2.0, 0.0], Tolerance::default()).unwrap();
+        assert_eq!(r.failures, vec![1]);
+        assert!((r.max_abs_error - 3.0).abs() < 1e-6);
+        assert!((r.numeric[0] - 2.0).abs() < 1e-6);
+
+        assert_eq!(
+            check_gradient(f, &x, &[2.0], Tolerance::default()),
+            Err(Error::LengthMismatch {
+                analytic: 1,
+                expected: 2
+            })
+        );
+        let bad = Tolerance {
+            step: 0.0,
+            ..Tolerance::default()
+        };
+        assert_eq!(
+            check_gradient(f, &x, &[2.0, 3.0], bad),
+            Err(Error::InvalidTolerance(bad))
+        );
+        assert_eq!(
+            check_soft_dtw_grad(&[], &[1.0], 1.0, Tolerance::default()),
+            Err(Error::SoftDtw(soft_dtw::Error::EmptyInput))
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! JSON exchange format for soft shortest-path graphs (feature `json`).
+//!
+//! Input schema:
+//!
+//! ```json
+//! {
+//!   "nodes": ["s", "a", "b", "t"],
+//!   "edges": [
+//!     {"from": "s", "to": "a", "cost": 1.0},
+//!     {"from": "a", "to": "t", "cost": 2.0},
+//!     {"from": "s", "to": "b", "cost": 3.0},
+//!     {"from": "b", "to": "t", "cost": 4.0}
+//!   ],
+//!   "source": "s",
+//!   "sink": "t"
+//! }
+//! ```
+//!
+//! `nodes` is either a node count or a list of unique names (in topological order);
+//! endpoints are given as 0-based indices or, with named nodes, as names. `source` and
+//! `sink` default to the first and last node. Parsing produces a validated
+//! [`Graph`]; unknown fields are rejected.
+//!
+//! The writers emit the same schema (with names when available) and a results document
+//! with the value and per-edge marginals.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::soft_shortest_path::{self, Edge, Graph};
+
+/// Errors for the JSON graph format.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Malformed JSON or schema mismatch.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// A node name was referenced but not declared (or names were used with a node count).
+    #[error("unknown node {0:?}")]
+    UnknownNode(String),
+    /// A node name was declared twice.
+    #[error("duplicate node name {0:?}")]
+    DuplicateNode(String),
+    /// The node names given to a writer are not one per node.
+    #[error("got {len} node names for a graph with {expected} nodes")]
+    NodeNameCount {
+        /// Number of names.
+        len: usize,
+        /// `graph.n()`.
+        expected: usize,
+    },
+    /// The graph failed validation (or the operator failed).
+    #[error(transparent)]
+    Graph(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Nodes {
+    Count(usize),
+    Names(Vec<String>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NodeRef {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EdgeDoc {
+    from: NodeRef,
+    to: NodeRef,
+    cost: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GraphDoc {
+    nodes: Nodes,
+    edges: Vec<EdgeDoc>,
+    source: Option<NodeRef>,
+    sink: Option<NodeRef>,
+}
+
+/// A parsed graph together with its node names, if the document declared any.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedGraph {
+    /// The validated graph.
+    pub graph: Graph,
+    /// Node names by index.
+    pub node_names: Option<Vec<String>>,
+}
+
+/// Parses and validates a graph document.
+pub fn parse_graph(json: &str) -> Result<ParsedGraph> {
+    let doc: GraphDoc = serde_json::from_str(json)?;
+    let (n, names) = match doc.nodes {
+        Nodes::Count(n) => (n, None),
+        Nodes::Names(names) => (names.len(), Some(names)),
+    };
+    let mut index = HashMap::new();
+    for (i, name) in names.iter().flatten().enumerate() {
+        if index.insert(name.as_str(), i).is_some() {
+            return Err(Error::DuplicateNode(name.clone()));
+        }
+    }
+    let resolve = |r: &NodeRef| match r {
+        NodeRef::Index(i) => Ok(*i),
+        NodeRef::Name(s) => index
+            .get(s.as_str())
+            .copied()
+            .ok_or_else(|| Error::UnknownNode(s.clone())),
+    };
+
+    let edges = doc
+        .edges
+        .iter()
+        .map(|e| {
+            Ok(Edge {
+                from: resolve(&e.from)?,
+                to: resolve(&e.to)?,
+                cost: e.cost,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let source = doc.source.as_ref().map_or(Ok(0), resolve)?;
+    let sink = doc.sink.as_ref().map_or(Ok(n.saturating_sub(1)), resolve)?;
+    let graph = Graph::new(n, edges, source, sink)?;
+    Ok(ParsedGraph {
+        graph,
+        node_names: names,
+    })
+}
+
+fn node(i: usize, names: Option<&[String]>) -> Value {
+    match names {
+        Some(names) => json!(names[i]),
+        None => json!(i),
+    }
+}
+
+fn check_names(graph: &Graph, names: Option<&[String]>) -> Result<()> {
+    match names {
+        Some(names) if names.len() != graph.n() => Err(Error::NodeNameCount {
+            len: names.len(),
+            expected: graph.n(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn graph_value(graph: &Graph, names: Option<&[String]>) -> Value {
+    let edges: Vec<Value> = graph
+        .edges()
+        .iter()
+        .map(|e| json!({"from": node(e.from, names), "to": node(e.to, names), "cost": e.cost}))
+        .collect();
+    json!({
+        "nodes": names.map_or(json!(graph.n()), |names| json!(names)),
+        "edges": edges,
+        "source": node(graph.source(), names),
+        "sink": node(graph.sink(), names),
+    })
+}
+
+/// Serializes a graph in the input schema (a node count when `node_names` is `None`).
+///
+/// Fails with [`Error::NodeNameCount`] if `node_names` is not one name per node.
+pub fn graph_to_json(graph: &Graph, node_names: Option<&[String]>) -> Result<String> {
+    check_names(graph, node_names)?;
+    Ok(graph_value(graph, node_names).to_string())
+}
+
+/// Computes the value and edge marginals and serializes them as a results document:
+/// the graph fields plus `"gamma"`, `"value"`, and a `"marginal"` on every edge.
+///
+/// Fails with [`Error::NodeNameCount`] if `node_names` is not one name per node.
+pub fn marginals_to_json(
+    graph: &Graph,
+    node_names: Option<&[String]>,
+    gamma: f64,
+) -> Result<String> {
+    check_names(graph, node_names)?;
+    let (value, marginals) = graph.edge_marginals(gamma)?;
+    let mut doc = graph_value(graph, node_names);
+    for (e, p) in doc["edges"]
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .zip(marginals)
+    {
+        e["marginal"] = json!(p);
+    }
+    doc["gamma"] = json!(gamma);
+    doc["value"] = json!(value);
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIAMOND: &str = r#"{
+        "nodes": ["s", "a", "b", "t"],
+        "edges": [
+            {"from": "s", "to": "a", "cost": 1.0},
+            {"from": "a", "to": "t", "cost": 2.0},
+            {"from": "s", "to": "b", "cost": 3.0},
+            {"from": 2, "to": "t", "cost": 4.0}
+        ]
+    }"#;
+
+    #[test]
+    fn parses_named_graph_and_round_trips() {
+        let parsed = parse_graph(DIAMOND).unwrap();
+        assert_eq!((parsed.graph.source(), parsed.graph.sink()), (0, 3));
+        assert_eq!(
+            parsed.graph.edges()[3],
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 4.0
+            }
+        );
+
+        let names = parsed.node_names.as_deref();
+        let again = parse_graph(&graph_to_json(&parsed.graph, names).unwrap()).unwrap();
+        assert_eq!(again, parsed);
+        let by_index = parse_graph(&graph_to_json(&parsed.graph, None).unwrap()).unwrap();
+        assert_eq!(by_index.graph, parsed.graph);
+    }
+
+    #[test]
+    fn results_document_carries_marginals() {
+        let parsed = parse_graph(DIAMOND).unwrap();
+        let out = marginals_to_json(&parsed.graph, parsed.node_names.as_deref(), 0.5).unwrap();
+        let doc: Value = serde_json::from_str(&out).unwrap();
+        let (v, p) = parsed.graph.edge_marginals(0.5).unwrap();
+        assert_eq!(doc["value"].as_f64().unwrap(), v);
+        assert_eq!(doc["edges"][2]["marginal"].as_f64().unwrap(), p[2]);
+        assert_eq!(doc["edges"][2]["from"], "s");
+    }
+
+    #[test]
+    fn rejects_bad_documents() {
+        assert!(matches!(
+            parse_graph(r#"{"nodes": ["s", "t"], "edges": [{"from": "s", "to": "x", "cost": 1}]}"#),
+            Err(Error::UnknownNode(name)) if name == "x"
+        ));
+        assert!(matches!(
+            parse_graph(r#"{"nodes": ["s", "s"], "edges": []}"#),
+            Err(Error::DuplicateNode(_))
+        ));
+        assert!(matches!(
+            parse_graph(r#"{"nodes": 2, "edges": [], "extra": 1}"#),
+            Err(Error::Json(_))
+        ));
+        assert!(matches!(
+            parse_graph(r#"{"nodes": 3, "edges": [{"from": 2, "to": 1, "cost": 1}]}"#),
+            Err(Error::Graph(soft_shortest_path::Error::NotDagOrder { .. }))
+        ));
+
+        let graph = parse_graph(DIAMOND).unwrap().graph;
+        let names = ["s".to_string(), "t".to_string()];
+        assert!(matches!(
+            graph_to_json(&graph, Some(&names)),
+            Err(Error::NodeNameCount {
+                len: 2,
+                expected: 4
+            })
+        ));
+        assert!(matches!(
+            marginals_to_json(&graph, Some(&names), 0.5),
+            Err(Error::NodeNameCount { .. })
+        ));
+    }
+}
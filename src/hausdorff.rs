@@ -0,0 +1,177 @@
+//! Soft Hausdorff distance between point sets.
+//!
+//! The Hausdorff distance between finite sets \(X\) and \(Y\) is
+//! \(\max\big(\max_i \min_j d_{ij},\ \max_j \min_i d_{ij}\big)\) with \(d_{ij} =
+//! \|x_i - y_j\|\): the worst distance from a point of either set to the other set. Unlike
+//! optimal transport it has no mass constraints, so sets of different sizes, or with many
+//! points in one place, compare by their geometry alone.
+//!
+//! The soft version replaces every `min` by \(\operatorname{softmin}_\gamma(a) = -\gamma
+//! \log \sum_k e^{-a_k/\gamma}\) and every `max` by \(\operatorname{softmax}_\gamma(a) =
+//! \gamma \log \sum_k e^{a_k/\gamma}\); it is smooth in the coordinates away from coincident
+//! points and tends to the Hausdorff distance as \(\gamma \to 0\).
+//!
+//! ```
+//! use structop::hausdorff::soft_hausdorff;
+//!
+//! let x = [0.0, 0.0, 1.0, 0.0];
+//! let y = [0.0, 0.0, 3.0, 0.0];
+//! let h = soft_hausdorff(&x, &y, 2, 1e-3).unwrap();
+//! assert!((h.value - 2.0).abs() < 1e-2);
+//! ```
+
+/// Errors for the soft Hausdorff distance.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Smoothing parameter \(\gamma\) must be positive and finite.
+    #[error("gamma must be positive and finite, got {0}")]
+    InvalidGamma(f64),
+    /// A point set is empty, has non-finite coordinates, or its length is not a multiple of
+    /// the dimension.
+    #[error("point set {index} must be non-empty and finite, with length a multiple of {dim}")]
+    InvalidPointSet {
+        /// Index of the offending set (0 = `x`, 1 = `y`).
+        index: usize,
+        /// Point dimension.
+        dim: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Result of [`soft_hausdorff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftHausdorff {
+    /// Soft Hausdorff distance.
+    pub value: f64,
+    /// Gradient w.r.t. the coordinates of `x`, same layout.
+    pub grad_x: Vec<f64>,
+    /// Gradient w.r.t. the coordinates of `y`, same layout.
+    pub grad_y: Vec<f64>,
+}
+
+/// Soft minimum (`sign = 1`) or maximum (`sign = -1`) of `a`, writing its gradient (softmin
+/// or softmax weights) into `weights`.
+fn smooth_extreme(a: &[f64], gamma: f64, sign: f64, weights: &mut [f64]) -> f64 {
+    let best = a.iter().map(|&v| sign * v).fold(f64::INFINITY, f64::min);
+    let mut sum = 0.0;
+    for (w, &v) in weights.iter_mut().zip(a) {
+        *w = (-(sign * v - best) / gamma).exp();
+        sum += *w;
+    }
+    weights.iter_mut().for_each(|w| *w /= sum);
+    sign * (best - gamma * sum.ln())
+}
+
+fn validate_points(points: &[f64], dim: usize, index: usize) -> Result<usize> {
+    if dim == 0 || points.is_empty() || points.len() % dim != 0 {
+        return Err(Error::InvalidPointSet { index, dim });
+    }
+    if points.iter().any(|v| !v.is_finite()) {
+        return Err(Error::InvalidPointSet { index, dim });
+    }
+    Ok(points.len() / dim)
+}
+
+/// Soft Hausdorff distance between the point sets `x` and `y` (row-major, `dim` coordinates
+/// per point), with gradients w.r.t. both (zero through pairs of coincident points).
+pub fn soft_hausdorff(x: &[f64], y: &[f64], dim: usize, gamma: f64) -> Result<SoftHausdorff> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    let n = validate_points(x, dim, 0)?;
+    let m = validate_points(y, dim, 1)?;
+    let mut d = vec![0.0; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            let sq: f64 = x[i * dim..(i + 1) * dim]
+                .iter()
+                .zip(&y[j * dim..(j + 1) * dim])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            d[i * m + j] = sq.sqrt();
+        }
+    }
+    let mut d_t = vec![0.0; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            d_t[j * n + i] = d[i * m + j];
+        }
+    }
+
+    // Directed terms: softmax over one set of the softmin distance to the other, with the
+    // softmin weights kept per row (of `d` or `d_t`).
+    let directed = |d: &[f64], rows: usize, cols: usize| {
+        let mut near = vec![0.0; rows * cols];
+        let reach: Vec<f64> = (0..rows)
+            .map(|r| {
+                let slice = &d[r * cols..(r + 1) * cols];
+                smooth_extreme(slice, gamma, 1.0, &mut near[r * cols..(r + 1) * cols])
+            })
+            .collect();
+        let mut far = vec![0.0; rows];
+        let value = smooth_extreme(&reach, gamma, -1.0, &mut far);
+        (value, near, far)
+    };
+    let (h_xy, near_xy, far_x) = directed(&d, n, m);
+    let (h_yx, near_yx, far_y) = directed(&d_t, m, n);
+    let mut top = [0.0; 2];
+    let value = smooth_extreme(&[h_xy, h_yx], gamma, -1.0, &mut top);
+
+    let mut grad_x = vec![0.0; x.len()];
+    let mut grad_y = vec![0.0; y.len()];
+    for i in 0..n {
+        for j in 0..m {
+            let dij = d[i * m + j];
+            if dij == 0.0 {
+                continue;
+            }
+            let g = top[0] * far_x[i] * near_xy[i * m + j] + top[1] * far_y[j] * near_yx[j * n + i];
+            for k in 0..dim {
+                let step = g * (x[i * dim + k] - y[j * dim + k]) / dij;
+                grad_x[i * dim + k] += step;
+                grad_y[j * dim + k] -= step;
+            }
+        }
+    }
+    Ok(SoftHausdorff {
+        value,
+        grad_x,
+        grad_y,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    #[test]
+    fn tends_to_hausdorff_and_matches_finite_differences() {
+        // x is a square; y adds a far point, which sets the Hausdorff distance.
+        let x = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+        let y = [0.1, 0.0, 1.0, 0.9, 4.0, 1.0];
+        let hard = 3.0;
+        let h = soft_hausdorff(&x, &y, 2, 1e-4).unwrap();
+        assert!((h.value - hard).abs() < 1e-3, "{}", h.value);
+
+        let gamma = 0.3;
+        let h = soft_hausdorff(&x, &y, 2, gamma).unwrap();
+        let f = |p: &[f64]| Ok(soft_hausdorff(p, &y, 2, gamma).unwrap().value);
+        let report = check_gradient(f, &x, &h.grad_x, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+        let f = |p: &[f64]| Ok(soft_hausdorff(&x, p, 2, gamma).unwrap().value);
+        let report = check_gradient(f, &y, &h.grad_y, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+
+        assert_eq!(
+            soft_hausdorff(&x, &y[..5], 2, gamma),
+            Err(Error::InvalidPointSet { index: 1, dim: 2 })
+        );
+        assert_eq!(
+            soft_hausdorff(&x, &y, 2, 0.0),
+            Err(Error::InvalidGamma(0.0))
+        );
+    }
+}
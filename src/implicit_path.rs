@@ -0,0 +1,361 @@
+//! Soft shortest path on implicit DAGs.
+//!
+//! [`crate::soft_shortest_path`] takes the DAG as an edge list. For very large graphs that
+//! are defined by a rule (edit lattices, grid graphs, state spaces), materializing that list
+//! dominates the cost, so the functions here take the out-edges of a node from a
+//! `successors(u)` callback instead, along with a topological order of the nodes. Only
+//! `O(n)` potentials are stored; the callback is called once per node reachable from the
+//! source for the value, and once more per node on a source-to-sink path for the marginals.
+//!
+//! Nodes are `0..n` with `n = order.len()`, and `order` must list every node once, with
+//! every edge going from a node to a later one. Results are those of
+//! [`crate::soft_shortest_path`] on the same graph.
+//!
+//! ```
+//! use structop::implicit_path::implicit_value;
+//!
+//! // A chain 0 -> 1 -> ... -> 9 with skip edges, never stored.
+//! let order: Vec<usize> = (0..10).collect();
+//! let successors = |u: usize| {
+//!     [(u + 1, 1.0), (u + 2, 1.5)].into_iter().filter(|&(v, _)| v < 10)
+//! };
+//! let v = implicit_value(&order, 0, 9, 0.1, successors).unwrap();
+//! assert!(v < 9.0);
+//! ```
+
+use crate::math::{exp, ln};
+
+/// Errors for implicit-graph operators.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Smoothing parameter \(\gamma\) must be positive and finite.
+    #[error("gamma must be positive and finite, got {0}")]
+    InvalidGamma(f64),
+    /// `order` repeats a node or lists one outside `0..order.len()`.
+    #[error("order lists node {node} twice or out of range for n={n}")]
+    InvalidOrder {
+        /// The offending node.
+        node: usize,
+        /// Number of nodes.
+        n: usize,
+    },
+    /// Source and sink must be nodes, with the source before the sink in `order`.
+    #[error("invalid endpoints: source={source_node}, sink={sink_node} for n={n}")]
+    InvalidEndpoints {
+        /// Requested source node.
+        source_node: usize,
+        /// Requested sink node.
+        sink_node: usize,
+        /// Number of nodes.
+        n: usize,
+    },
+    /// A successor is out of range or not after its predecessor in `order`.
+    #[error("edge {from}->{to} does not go forward in the topological order")]
+    NotTopological {
+        /// Source endpoint.
+        from: usize,
+        /// Destination endpoint.
+        to: usize,
+    },
+    /// An edge cost is NaN or infinite.
+    #[error("edge {from}->{to} has non-finite cost {cost}")]
+    NonFiniteCost {
+        /// Source endpoint.
+        from: usize,
+        /// Destination endpoint.
+        to: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// No path exists from source to sink.
+    #[error("no path exists from source to sink")]
+    NoPath,
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// An edge with its marginal probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeMarginal {
+    /// Source node.
+    pub from: usize,
+    /// Destination node.
+    pub to: usize,
+    /// Edge cost, as returned by the callback.
+    pub cost: f64,
+    /// Probability that a path uses the edge.
+    pub prob: f64,
+}
+
+/// Streaming softmin: candidates are pushed one at a time, rescaling the running sum
+/// whenever the minimum improves.
+#[derive(Debug, Clone, Copy)]
+struct Softmin {
+    best: f64,
+    sum: f64,
+}
+
+impl Softmin {
+    const EMPTY: Self = Self {
+        best: f64::INFINITY,
+        sum: 0.0,
+    };
+
+    fn push(&mut self, a: f64, gamma: f64) {
+        if a == f64::INFINITY {
+            return;
+        }
+        if a < self.best {
+            self.sum = self.sum * exp(-(self.best - a) / gamma) + 1.0;
+            self.best = a;
+        } else {
+            self.sum += exp(-(a - self.best) / gamma);
+        }
+    }
+
+    fn value(self, gamma: f64) -> f64 {
+        if self.best.is_finite() {
+            self.best - gamma * ln(self.sum)
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+/// Position of each node in `order`, after checking that it is a permutation.
+fn ranks(order: &[usize], source: usize, sink: usize, gamma: f64) -> Result<Vec<usize>> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    let n = order.len();
+    let mut rank = vec![usize::MAX; n];
+    for (r, &u) in order.iter().enumerate() {
+        if u >= n || rank[u] != usize::MAX {
+            return Err(Error::InvalidOrder { node: u, n });
+        }
+        rank[u] = r;
+    }
+    if source >= n || sink >= n || rank[source] >= rank[sink] {
+        return Err(Error::InvalidEndpoints {
+            source_node: source,
+            sink_node: sink,
+            n,
+        });
+    }
+    Ok(rank)
+}
+
+fn check_edge(rank: &[usize], from: usize, to: usize, cost: f64) -> Result<()> {
+    if to >= rank.len() || rank[to] <= rank[from] {
+        return Err(Error::NotTopological { from, to });
+    }
+    if !cost.is_finite() {
+        return Err(Error::NonFiniteCost { from, to, cost });
+    }
+    Ok(())
+}
+
+/// Forward potentials (soft shortest from the source) by pushing along out-edges.
+fn forward<S, I>(
+    order: &[usize],
+    rank: &[usize],
+    source: usize,
+    sink: usize,
+    gamma: f64,
+    successors: &mut S,
+) -> Result<Vec<f64>>
+where
+    S: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, f64)>,
+{
+    let mut acc = vec![Softmin::EMPTY; order.len()];
+    let mut fwd = vec![f64::INFINITY; order.len()];
+    acc[source].push(0.0, gamma);
+    for &u in &order[rank[source]..rank[sink]] {
+        fwd[u] = acc[u].value(gamma);
+        if !fwd[u].is_finite() {
+            continue;
+        }
+        for (v, c) in successors(u) {
+            check_edge(rank, u, v, c)?;
+            acc[v].push(fwd[u] + c, gamma);
+        }
+    }
+    fwd[sink] = acc[sink].value(gamma);
+    Ok(fwd)
+}
+
+/// Soft shortest-path value from `source` to `sink`.
+pub fn implicit_value<S, I>(
+    order: &[usize],
+    source: usize,
+    sink: usize,
+    gamma: f64,
+    mut successors: S,
+) -> Result<f64>
+where
+    S: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, f64)>,
+{
+    let rank = ranks(order, source, sink, gamma)?;
+    let fwd = forward(order, &rank, source, sink, gamma, &mut successors)?;
+    match fwd[sink] {
+        v if v.is_finite() => Ok(v),
+        _ => Err(Error::NoPath),
+    }
+}
+
+/// Soft shortest-path value and the marginals of the edges on some source-to-sink path
+/// (every other edge has marginal zero), grouped by source node in topological order and
+/// in callback order within a node.
+pub fn implicit_edge_marginals<S, I>(
+    order: &[usize],
+    source: usize,
+    sink: usize,
+    gamma: f64,
+    mut successors: S,
+) -> Result<(f64, Vec<EdgeMarginal>)>
+where
+    S: FnMut(usize) -> I,
+    I: IntoIterator<Item = (usize, f64)>,
+{
+    let rank = ranks(order, source, sink, gamma)?;
+    let fwd = forward(order, &rank, source, sink, gamma, &mut successors)?;
+    let value = fwd[sink];
+    if !value.is_finite() {
+        return Err(Error::NoPath);
+    }
+    // Backward potentials (soft shortest to the sink), pulled along out-edges in reverse
+    // order; the marginals come out node by node, in reverse.
+    let mut bwd = vec![f64::INFINITY; order.len()];
+    bwd[sink] = 0.0;
+    let mut edges = Vec::new();
+    let mut starts = Vec::new();
+    for &u in order[rank[source]..rank[sink]].iter().rev() {
+        if !fwd[u].is_finite() {
+            continue;
+        }
+        let mut acc = Softmin::EMPTY;
+        starts.push(edges.len());
+        for (v, c) in successors(u) {
+            check_edge(&rank, u, v, c)?;
+            acc.push(c + bwd[v], gamma);
+            let prob = exp(-(fwd[u] + c + bwd[v] - value) / gamma);
+            if prob > 0.0 {
+                edges.push(EdgeMarginal {
+                    from: u,
+                    to: v,
+                    cost: c,
+                    prob,
+                });
+            }
+        }
+        bwd[u] = acc.value(gamma);
+    }
+    starts.push(edges.len());
+    let out = starts
+        .windows(2)
+        .rev()
+        .flat_map(|w| edges[w[0]..w[1]].iter().copied())
+        .collect();
+    Ok((value, out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::{soft_shortest_path_edge_marginals, Edge};
+
+    /// Edit-style lattice on a `k × k` grid: right, down and diagonal moves.
+    fn grid_edges(k: usize) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        for i in 0..k {
+            for j in 0..k {
+                let u = i * k + j;
+                let cost = ((i * 7 + j * 3) % 5) as f64 * 0.3;
+                if j + 1 < k {
+                    edges.push(Edge {
+                        from: u,
+                        to: u + 1,
+                        cost: cost + 0.5,
+                    });
+                }
+                if i + 1 < k {
+                    edges.push(Edge {
+                        from: u,
+                        to: u + k,
+                        cost: cost + 0.4,
+                    });
+                }
+                if i + 1 < k && j + 1 < k {
+                    edges.push(Edge {
+                        from: u,
+                        to: u + k + 1,
+                        cost,
+                    });
+                }
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn matches_the_explicit_graph_under_a_relabelling() {
+        let k = 5;
+        let edges = grid_edges(k);
+        let n = k * k;
+        let (v, p) = soft_shortest_path_edge_marginals(n, &edges, 0.3).unwrap();
+
+        // Relabel node u as `n - 1 - u`; the reversed order is still topological.
+        let relabel = |u: usize| n - 1 - u;
+        let order: Vec<usize> = (0..n).map(relabel).collect();
+        let successors = |u: usize| {
+            edges
+                .iter()
+                .filter(move |e| e.from == relabel(u))
+                .map(move |e| (relabel(e.to), e.cost))
+        };
+        let (src, dst) = (relabel(0), relabel(n - 1));
+        let v2 = implicit_value(&order, src, dst, 0.3, successors).unwrap();
+        let (v3, marg) = implicit_edge_marginals(&order, src, dst, 0.3, successors).unwrap();
+        assert!((v - v2).abs() < 1e-12 && v2 == v3);
+        assert_eq!(marg.len(), edges.len());
+        for (m, (e, &pe)) in marg.iter().zip(edges.iter().zip(&p)) {
+            assert_eq!((m.from, m.to), (relabel(e.from), relabel(e.to)));
+            assert!((m.prob - pe).abs() < 1e-12, "{} vs {}", m.prob, pe);
+        }
+    }
+
+    #[test]
+    fn invalid_inputs_are_reported() {
+        let none = |_: usize| std::iter::empty::<(usize, f64)>();
+        assert_eq!(implicit_value(&[0, 1], 0, 1, 0.3, none), Err(Error::NoPath));
+        assert_eq!(
+            implicit_value(&[0, 0], 0, 1, 0.3, none),
+            Err(Error::InvalidOrder { node: 0, n: 2 })
+        );
+        assert_eq!(
+            implicit_value(&[1, 0], 0, 1, 0.3, none),
+            Err(Error::InvalidEndpoints {
+                source_node: 0,
+                sink_node: 1,
+                n: 2
+            })
+        );
+        let back = |u: usize| std::iter::once((u.saturating_sub(1), 1.0));
+        assert_eq!(
+            implicit_value(&[0, 1], 0, 1, 0.3, back),
+            Err(Error::NotTopological { from: 0, to: 0 })
+        );
+        let nan = |_: usize| std::iter::once((1, f64::NAN));
+        assert!(matches!(
+            implicit_edge_marginals(&[0, 1], 0, 1, 0.3, nan),
+            Err(Error::NonFiniteCost { from: 0, to: 1, cost }) if cost.is_nan()
+        ));
+        assert_eq!(
+            implicit_value(&[0, 1], 0, 1, 0.0, none),
+            Err(Error::InvalidGamma(0.0))
+        );
+    }
+}
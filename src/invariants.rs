@@ -0,0 +1,268 @@
+//! Invariant checks for property testing.
+//!
+//! These are the oracles the crate's own tests rely on, exposed so downstream integrations
+//! (autograd wrappers, bindings, GPU ports) can fuzz their outputs against the same
+//! mathematical facts:
+//!
+//! - marginals and expected alignments are probabilities, in \([0,1]\);
+//! - edge marginals form a unit flow: source outflow and sink inflow are 1, and every other
+//!   node conserves flow;
+//! - soft values bracket the hard DP: \(\mathrm{hard} - \gamma \log N \le \mathrm{soft} \le
+//!   \mathrm{hard}\), with \(N\) the number of paths (Soft-DTW uses the per-cell bound
+//!   \((n+m)\,\gamma \log 3\));
+//! - the Soft-DTW divergence is nonnegative.
+//!
+//! Each check returns `Ok(())` or the first violation found, with the offending numbers.
+//! Tolerances are absolute.
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+/// Errors for invariant checks: a violated invariant, or a failed operator call.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A probability lies outside \([0,1]\) (or is NaN).
+    #[error("entry {index} = {value} is not a probability")]
+    NotProbability {
+        /// Index of the entry.
+        index: usize,
+        /// Its value.
+        value: f64,
+    },
+    /// Edge marginals do not form a unit flow at `node`.
+    #[error("flow at node {node} is {flow}, expected {expected}")]
+    FlowImbalance {
+        /// Node where the flow balance fails.
+        node: usize,
+        /// Outflow minus inflow (outflow for the source, inflow for the sink).
+        flow: f64,
+        /// Expected flow (1 at the source and sink, 0 elsewhere).
+        expected: f64,
+    },
+    /// The soft value is outside `[lower, upper]`.
+    #[error("soft value {soft} is outside [{lower}, {upper}]")]
+    ValueOutOfBounds {
+        /// The soft value.
+        soft: f64,
+        /// Lower bound (hard value minus the entropic slack).
+        lower: f64,
+        /// Upper bound (the hard value).
+        upper: f64,
+    },
+    /// The Soft-DTW divergence is negative.
+    #[error("soft-DTW divergence is negative: {0}")]
+    NegativeDivergence(f64),
+    /// Marginals do not have one entry per edge.
+    #[error("got {marginals} marginals for {edges} edges")]
+    LengthMismatch {
+        /// Number of marginals.
+        marginals: usize,
+        /// Number of edges.
+        edges: usize,
+    },
+    /// Error from a Soft-DTW evaluation.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+    /// Error from a soft shortest-path evaluation.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Hard DTW value (min-plus DP) of a row-major `n × m` cost matrix.
+pub fn hard_dtw(cost: &[f64], n: usize, m: usize) -> Result<f64> {
+    // Reuse the soft kernel's shape checks; gamma is irrelevant here.
+    soft_dtw::validate_cost(cost, n, m, 1.0)?;
+    let w = m + 1;
+    let mut r = vec![f64::INFINITY; (n + 1) * w];
+    r[0] = 0.0;
+    for i in 1..=n {
+        for j in 1..=m {
+            let best = r[(i - 1) * w + j]
+                .min(r[i * w + j - 1])
+                .min(r[(i - 1) * w + j - 1]);
+            r[i * w + j] = cost[(i - 1) * m + (j - 1)] + best;
+        }
+    }
+    Ok(r[n * w + m])
+}
+
+/// Hard shortest-path value from node 0 to node `n-1`, and the natural log of the number
+/// of such paths.
+pub fn hard_shortest_path(n: usize, edges: &[Edge]) -> Result<(f64, f64)> {
+    // Validates the graph and rejects unreachable sinks.
+    soft_shortest_path::soft_shortest_path_value(n, edges, 1.0)?;
+    let mut order: Vec<&Edge> = edges.iter().collect();
+    order.sort_by_key(|e| e.from);
+    let mut dist = vec![f64::INFINITY; n];
+    let mut log_paths = vec![f64::NEG_INFINITY; n];
+    dist[0] = 0.0;
+    log_paths[0] = 0.0;
+    for e in order {
+        dist[e.to] = dist[e.to].min(dist[e.from] + e.cost);
+        let (a, b) = (log_paths[e.to], log_paths[e.from]);
+        let hi = a.max(b);
+        if hi > f64::NEG_INFINITY {
+            log_paths[e.to] = hi + ((a - hi).exp() + (b - hi).exp()).ln();
+        }
+    }
+    Ok((dist[n - 1], log_paths[n - 1]))
+}
+
+/// Checks that every entry lies in `[-tol, 1 + tol]`.
+pub fn check_probabilities(values: &[f64], tol: f64) -> Result<()> {
+    match values
+        .iter()
+        .position(|&v| !(-tol..=1.0 + tol).contains(&v))
+    {
+        Some(index) => Err(Error::NotProbability {
+            index,
+            value: values[index],
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `marginals` (one per edge) are probabilities forming a unit flow from node 0
+/// to node `n-1`.
+pub fn check_unit_flow(n: usize, edges: &[Edge], marginals: &[f64], tol: f64) -> Result<()> {
+    if marginals.len() != edges.len() {
+        return Err(Error::LengthMismatch {
+            marginals: marginals.len(),
+            edges: edges.len(),
+        });
+    }
+    check_probabilities(marginals, tol)?;
+    let mut outflow = vec![0.0; n];
+    let mut inflow = vec![0.0; n];
+    for (e, &p) in edges.iter().zip(marginals) {
+        outflow[e.from] += p;
+        inflow[e.to] += p;
+    }
+    for node in 0..n {
+        let (flow, expected) = if node == 0 {
+            (outflow[0], 1.0)
+        } else if node == n - 1 {
+            (inflow[node], 1.0)
+        } else {
+            (outflow[node] - inflow[node], 0.0)
+        };
+        if (flow - expected).abs() > tol || flow.is_nan() {
+            return Err(Error::FlowImbalance {
+                node,
+                flow,
+                expected,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_bracket(soft: f64, hard: f64, slack: f64, tol: f64) -> Result<()> {
+    let (lower, upper) = (hard - slack, hard);
+    if soft < lower - tol || soft > upper + tol || soft.is_nan() {
+        return Err(Error::ValueOutOfBounds { soft, lower, upper });
+    }
+    Ok(())
+}
+
+/// Checks the Soft-DTW value of a cost matrix against the hard DTW value:
+/// `hard - (n+m) γ ln 3 <= soft <= hard`.
+pub fn check_soft_dtw_bounds(cost: &[f64], n: usize, m: usize, gamma: f64, tol: f64) -> Result<()> {
+    let soft = soft_dtw::soft_dtw_cost(cost, n, m, gamma)?;
+    let hard = hard_dtw(cost, n, m)?;
+    let slack = (n + m) as f64 * gamma * 3.0f64.ln();
+    check_bracket(soft, hard, slack, tol)
+}
+
+/// Checks the soft shortest-path value against the hard one:
+/// `hard - γ ln(#paths) <= soft <= hard`.
+pub fn check_soft_shortest_path_bounds(
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+    tol: f64,
+) -> Result<()> {
+    let soft = soft_shortest_path::soft_shortest_path_value(n, edges, gamma)?;
+    let (hard, log_paths) = hard_shortest_path(n, edges)?;
+    check_bracket(soft, hard, gamma * log_paths, tol)
+}
+
+/// Checks that the Soft-DTW divergence of `x` and `y` is at least `-tol`.
+pub fn check_divergence_nonnegative(x: &[f64], y: &[f64], gamma: f64, tol: f64) -> Result<()> {
+    let d = soft_dtw::soft_dtw_divergence(x, y, gamma)?;
+    if d < -tol || d.is_nan() {
+        return Err(Error::NegativeDivergence(d));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn edge(from: usize, to: usize, cost: f64) -> Edge {
+        Edge { from, to, cost }
+    }
+
+    #[test]
+    fn hard_oracles_and_violations() {
+        let edges = [
+            edge(0, 1, 1.0),
+            edge(1, 3, 2.0),
+            edge(0, 2, 3.0),
+            edge(2, 3, 4.0),
+        ];
+        let (hard, log_paths) = hard_shortest_path(4, &edges).unwrap();
+        assert_eq!(hard, 3.0);
+        assert!((log_paths - 2.0f64.ln()).abs() < 1e-15);
+        assert_eq!(hard_dtw(&[1.0, 5.0, 5.0, 1.0], 2, 2).unwrap(), 2.0);
+
+        assert_eq!(
+            check_probabilities(&[0.5, 1.5], 1e-12),
+            Err(Error::NotProbability {
+                index: 1,
+                value: 1.5
+            })
+        );
+        assert_eq!(
+            check_unit_flow(4, &edges, &[0.5, 0.5, 0.5, 0.25], 1e-12),
+            Err(Error::FlowImbalance {
+                node: 2,
+                flow: -0.25,
+                expected: 0.0
+            })
+        );
+        assert!(matches!(
+            check_unit_flow(4, &edges, &[1.0], 1e-12),
+            Err(Error::LengthMismatch { .. })
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn operators_satisfy_invariants_on_random_dags(
+            costs in prop::collection::vec(0.0f64..5.0, 10),
+            gamma in 0.05f64..3.0
+        ) {
+            // All forward edges of a 5-node DAG.
+            let pairs = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+            let edges: Vec<Edge> = pairs
+                .iter()
+                .zip(&costs)
+                .map(|(&(from, to), &cost)| edge(from, to, cost))
+                .collect();
+            let (_, p) = soft_shortest_path::soft_shortest_path_edge_marginals(5, &edges, gamma).unwrap();
+            prop_assert_eq!(check_unit_flow(5, &edges, &p, 1e-9), Ok(()));
+            prop_assert_eq!(check_soft_shortest_path_bounds(5, &edges, gamma, 1e-9), Ok(()));
+
+            let (_, e) = soft_dtw::soft_dtw_alignment(&costs, 2, 5, gamma).unwrap();
+            prop_assert_eq!(check_probabilities(&e, 1e-9), Ok(()));
+            prop_assert_eq!(check_soft_dtw_bounds(&costs, 2, 5, gamma, 1e-9), Ok(()));
+            prop_assert_eq!(check_divergence_nonnegative(&costs[..4], &costs[4..], gamma, 1e-9), Ok(()));
+        }
+    }
+}
@@ -0,0 +1,980 @@
+//! Soft dynamic programming over user-defined lattices.
+//!
+//! A lattice is a layered DAG: step `t` has `states(t)` states, a path picks one state per
+//! step, and only the transitions the lattice lists between consecutive steps are allowed.
+//! Paths pay an initial cost for their first state, a cost per transition and a final cost
+//! for their last state. This sits between the hard-coded DTW grid of [`crate::soft_dtw`]
+//! and the explicit edge lists of [`crate::soft_shortest_path`]: the DP is layered, so only
+//! the states are stored, and transitions are enumerated on demand by the [`Lattice`]
+//! implementation, twice per call (forward and backward).
+//!
+//! With \(C(\pi)\) the total cost of path \(\pi\), the value is
+//! \(V_\gamma = -\gamma \log \sum_\pi \exp(-C(\pi)/\gamma)\), and the marginal probability of
+//! a transition (or state) under the Gibbs distribution over paths is the gradient of
+//! \(V_\gamma\) w.r.t. its cost.
+//!
+//! ```
+//! use structop::lattice::{soft_lattice_marginals, Lattice};
+//!
+//! /// Two states per step; switching state costs 1.
+//! struct Switches(usize);
+//!
+//! impl Lattice for Switches {
+//!     fn steps(&self) -> usize {
+//!         self.0
+//!     }
+//!     fn states(&self, _t: usize) -> usize {
+//!         2
+//!     }
+//!     fn transitions(&self, _t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+//!         emit(from, 0.0);
+//!         emit(1 - from, 1.0);
+//!     }
+//! }
+//!
+//! let m = soft_lattice_marginals(&Switches(3), 0.5).unwrap();
+//! let stay: f64 = m.transitions[0].iter().filter(|t| t.from == t.to).map(|t| t.prob).sum();
+//! assert!(stay > 0.5);
+//! ```
+//!
+//! [`lattice_viterbi`] decodes the hard minimum-cost path. Chain lattices whose states are
+//! tags (linear-chain CRFs) take declarative [`TagConstraints`] (BIO, BILOU or custom) through
+//! the [`Constrained`] adapter, which both decoding and marginals respect, and
+//! [`partial_label_nll`] trains them from partial annotation.
+
+use crate::math::{exp, ln};
+
+/// Errors for lattice operators.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Smoothing parameter \(\gamma\) must be positive and finite.
+    #[error("gamma must be positive and finite, got {0}")]
+    InvalidGamma(f64),
+    /// The lattice has no steps, or a step with no states.
+    #[error("lattice step {step} has no states")]
+    EmptyStep {
+        /// The empty step (0 when the lattice has no steps at all).
+        step: usize,
+    },
+    /// A transition targets a state that does not exist at the next step.
+    #[error("transition {from}->{to} out of step {step} targets one of {states} states")]
+    StateOutOfRange {
+        /// Step of the source state.
+        step: usize,
+        /// Source state.
+        from: usize,
+        /// Target state at `step + 1`.
+        to: usize,
+        /// Number of states at `step + 1`.
+        states: usize,
+    },
+    /// A cost is NaN or `-inf` (`+inf` is allowed and forbids the transition or state).
+    #[error("invalid cost {cost} at step {step}")]
+    InvalidCost {
+        /// Step the cost belongs to (of the source state, for a transition).
+        step: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// No path has finite cost.
+    #[error("no path through the lattice has finite cost")]
+    NoPath,
+    /// A constrained lattice step does not have one state per tag.
+    #[error("step {step} has {states} states, constraints cover {tags} tags")]
+    TagCountMismatch {
+        /// The step.
+        step: usize,
+        /// States at that step.
+        states: usize,
+        /// Tags in the constraints.
+        tags: usize,
+    },
+    /// Partial labels must cover every step.
+    #[error("labels cover {len} steps, lattice has {steps}")]
+    LabelLengthMismatch {
+        /// Number of label entries.
+        len: usize,
+        /// Number of lattice steps.
+        steps: usize,
+    },
+    /// An allowed label is not a state of its step.
+    #[error("label {state} at step {step} out of range for {states} states")]
+    LabelOutOfRange {
+        /// The step.
+        step: usize,
+        /// The label.
+        state: usize,
+        /// States at that step.
+        states: usize,
+    },
+    /// A tag index is out of range for the constraints.
+    #[error("tag {tag} out of range for {tags} tags")]
+    TagOutOfRange {
+        /// The tag.
+        tag: usize,
+        /// Number of tags.
+        tags: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A layered DAG of states with costed transitions between consecutive steps.
+///
+/// Implementations must enumerate the same transitions, in the same order, every time
+/// [`Lattice::transitions`] is called with the same arguments.
+pub trait Lattice {
+    /// Number of steps; a path visits one state per step.
+    fn steps(&self) -> usize;
+
+    /// Number of states at step `t`.
+    fn states(&self, t: usize) -> usize;
+
+    /// Calls `emit(to, cost)` for each allowed transition from state `from` at step `t` to
+    /// state `to` at step `t + 1`. Called for `t < steps() - 1` only.
+    fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64));
+
+    /// Cost of starting in state `s` at step 0.
+    fn initial_cost(&self, s: usize) -> f64 {
+        let _ = s;
+        0.0
+    }
+
+    /// Cost of ending in state `s` at the last step.
+    fn final_cost(&self, s: usize) -> f64 {
+        let _ = s;
+        0.0
+    }
+}
+
+/// One transition with its marginal probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    /// Source state at step `t`.
+    pub from: usize,
+    /// Target state at step `t + 1`.
+    pub to: usize,
+    /// Probability that a path uses the transition.
+    pub prob: f64,
+}
+
+/// Output of [`soft_lattice_marginals`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatticeMarginals {
+    /// Soft value \(V_\gamma\).
+    pub value: f64,
+    /// `states[t][s]`: probability that a path visits state `s` at step `t` (the gradient
+    /// w.r.t. the initial costs for `t = 0` and the final costs for the last step).
+    pub states: Vec<Vec<f64>>,
+    /// `transitions[t]`: the transitions out of step `t`, in enumeration order (by source
+    /// state, then as emitted), with their probabilities (the gradient w.r.t. their costs).
+    pub transitions: Vec<Vec<Transition>>,
+}
+
+fn check_cost(step: usize, cost: f64) -> Result<()> {
+    if cost.is_nan() || cost == f64::NEG_INFINITY {
+        return Err(Error::InvalidCost { step, cost });
+    }
+    Ok(())
+}
+
+/// Transitions out of step `t` as `(from, to, cost)`, validated.
+fn step_edges<L: Lattice + ?Sized>(lattice: &L, t: usize) -> Result<Vec<(usize, usize, f64)>> {
+    let next = lattice.states(t + 1);
+    let mut edges = Vec::new();
+    for from in 0..lattice.states(t) {
+        lattice.transitions(t, from, &mut |to, cost| edges.push((from, to, cost)));
+    }
+    for &(from, to, cost) in &edges {
+        if to >= next {
+            return Err(Error::StateOutOfRange {
+                step: t,
+                from,
+                to,
+                states: next,
+            });
+        }
+        check_cost(t, cost)?;
+    }
+    Ok(edges)
+}
+
+/// `out[k] = softmin_γ` over the candidates `(k, a)`.
+fn softmin_into<I>(gamma: f64, out: &mut [f64], candidates: I)
+where
+    I: Iterator<Item = (usize, f64)> + Clone,
+{
+    let mut best = vec![f64::INFINITY; out.len()];
+    for (k, a) in candidates.clone() {
+        best[k] = best[k].min(a);
+    }
+    let mut sum = vec![0.0; out.len()];
+    for (k, a) in candidates {
+        if best[k].is_finite() {
+            sum[k] += exp(-(a - best[k]) / gamma);
+        }
+    }
+    for ((o, b), s) in out.iter_mut().zip(best).zip(sum) {
+        *o = if b.is_finite() {
+            b - gamma * ln(s)
+        } else {
+            f64::INFINITY
+        };
+    }
+}
+
+fn validate<L: Lattice + ?Sized>(lattice: &L, gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if lattice.steps() == 0 {
+        return Err(Error::EmptyStep { step: 0 });
+    }
+    match (0..lattice.steps()).find(|&t| lattice.states(t) == 0) {
+        Some(step) => Err(Error::EmptyStep { step }),
+        None => Ok(()),
+    }
+}
+
+/// Forward potentials `alpha[t][s]` (soft cost of the paths from step 0 to `(t, s)`,
+/// including the initial cost) and the value.
+fn forward<L: Lattice + ?Sized>(lattice: &L, gamma: f64) -> Result<(Vec<Vec<f64>>, f64)> {
+    let steps = lattice.steps();
+    let mut first = Vec::with_capacity(lattice.states(0));
+    for s in 0..lattice.states(0) {
+        let c = lattice.initial_cost(s);
+        check_cost(0, c)?;
+        first.push(c);
+    }
+    let mut alpha = vec![first];
+    for t in 0..steps - 1 {
+        let edges = step_edges(lattice, t)?;
+        let mut next = vec![0.0; lattice.states(t + 1)];
+        let prev = &alpha[t];
+        softmin_into(
+            gamma,
+            &mut next,
+            edges.iter().map(|&(f, to, c)| (to, prev[f] + c)),
+        );
+        alpha.push(next);
+    }
+    let last = &alpha[steps - 1];
+    let mut finals = Vec::with_capacity(last.len());
+    for (s, &a) in last.iter().enumerate() {
+        let c = lattice.final_cost(s);
+        check_cost(steps - 1, c)?;
+        finals.push((0, a + c));
+    }
+    let mut value = [0.0];
+    softmin_into(gamma, &mut value, finals.into_iter());
+    if !value[0].is_finite() {
+        return Err(Error::NoPath);
+    }
+    Ok((alpha, value[0]))
+}
+
+/// Soft value of a lattice.
+pub fn soft_lattice_value<L: Lattice + ?Sized>(lattice: &L, gamma: f64) -> Result<f64> {
+    validate(lattice, gamma)?;
+    Ok(forward(lattice, gamma)?.1)
+}
+
+/// Soft value with state and transition marginals, by a forward and a backward pass.
+pub fn soft_lattice_marginals<L: Lattice + ?Sized>(
+    lattice: &L,
+    gamma: f64,
+) -> Result<LatticeMarginals> {
+    validate(lattice, gamma)?;
+    let (alpha, value) = forward(lattice, gamma)?;
+    let steps = lattice.steps();
+    // beta[t][s]: soft cost of the paths from (t, s) to the end, including the final cost.
+    let mut beta = vec![Vec::new(); steps];
+    beta[steps - 1] = (0..lattice.states(steps - 1))
+        .map(|s| lattice.final_cost(s))
+        .collect();
+    let mut transitions = vec![Vec::new(); steps - 1];
+    for t in (0..steps - 1).rev() {
+        let edges = step_edges(lattice, t)?;
+        let mut cur = vec![0.0; lattice.states(t)];
+        let next = &beta[t + 1];
+        softmin_into(
+            gamma,
+            &mut cur,
+            edges.iter().map(|&(f, to, c)| (f, c + next[to])),
+        );
+        transitions[t] = edges
+            .iter()
+            .map(|&(from, to, c)| Transition {
+                from,
+                to,
+                prob: exp(-(alpha[t][from] + c + next[to] - value) / gamma),
+            })
+            .collect();
+        beta[t] = cur;
+    }
+    let states = alpha
+        .iter()
+        .zip(&beta)
+        .map(|(a, b)| {
+            a.iter()
+                .zip(b)
+                .map(|(&a, &b)| exp(-(a + b - value) / gamma))
+                .collect()
+        })
+        .collect();
+    Ok(LatticeMarginals {
+        value,
+        states,
+        transitions,
+    })
+}
+
+/// Hard minimum-cost path (Viterbi): its cost and the state visited at each step (ties go
+/// to the lowest state).
+pub fn lattice_viterbi<L: Lattice + ?Sized>(lattice: &L) -> Result<(f64, Vec<usize>)> {
+    validate(lattice, 1.0)?;
+    let steps = lattice.steps();
+    let mut best = Vec::with_capacity(lattice.states(0));
+    for s in 0..lattice.states(0) {
+        let c = lattice.initial_cost(s);
+        check_cost(0, c)?;
+        best.push(c);
+    }
+    let mut back: Vec<Vec<usize>> = Vec::with_capacity(steps - 1);
+    for t in 0..steps - 1 {
+        let mut next = vec![f64::INFINITY; lattice.states(t + 1)];
+        let mut arg = vec![0; next.len()];
+        for (from, to, c) in step_edges(lattice, t)? {
+            let a = best[from] + c;
+            if a < next[to] || (a == next[to] && from < arg[to]) {
+                next[to] = a;
+                arg[to] = from;
+            }
+        }
+        back.push(arg);
+        best = next;
+    }
+    let mut end = (f64::INFINITY, 0);
+    for (s, &a) in best.iter().enumerate() {
+        let c = lattice.final_cost(s);
+        check_cost(steps - 1, c)?;
+        if a + c < end.0 {
+            end = (a + c, s);
+        }
+    }
+    if !end.0.is_finite() {
+        return Err(Error::NoPath);
+    }
+    let mut path = vec![end.1; steps];
+    for t in (0..steps - 1).rev() {
+        path[t] = back[t][path[t + 1]];
+    }
+    Ok((end.0, path))
+}
+
+/// Declarative constraints for chain lattices whose states are tags (the same `tags`
+/// states at every step): disallowed transitions and the tags a path may start and end in.
+///
+/// [`TagConstraints::bio`] and [`TagConstraints::bilou`] build the usual span-tagging
+/// schemes, so decoding and marginals never produce invalid span structure (an `I` without
+/// its `B`, say). Apply them with [`Constrained`], which works with every lattice operator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagConstraints {
+    tags: usize,
+    allowed: Vec<bool>,
+    start: Vec<bool>,
+    end: Vec<bool>,
+}
+
+impl TagConstraints {
+    /// No constraints over `tags` tags.
+    pub fn new(tags: usize) -> Self {
+        Self {
+            tags,
+            allowed: vec![true; tags * tags],
+            start: vec![true; tags],
+            end: vec![true; tags],
+        }
+    }
+
+    /// BIO over `types` span types: tag 0 is `O`, `1 + 2k` is `B-k` and `2 + 2k` is `I-k`.
+    /// `I-k` may only follow `B-k` or `I-k`, and no path starts with an `I` tag.
+    pub fn bio(types: usize) -> Self {
+        let mut c = Self::new(1 + 2 * types);
+        for k in 0..types {
+            let inside = 2 + 2 * k;
+            for from in 0..c.tags {
+                c.allowed[from * c.tags + inside] = from == inside || from == inside - 1;
+            }
+            c.start[inside] = false;
+        }
+        c
+    }
+
+    /// BILOU over `types` span types: tag 0 is `O`, and `B-k`, `I-k`, `L-k`, `U-k` are
+    /// `1 + 4k` to `4 + 4k`. An open span (`B-k` or `I-k`) continues with `I-k` or closes
+    /// with `L-k`; anything else (`O`, `B-*`, `U-*`) follows `O`, `L-*` or `U-*`. Paths start
+    /// outside a span and end with it closed.
+    pub fn bilou(types: usize) -> Self {
+        let mut c = Self::new(1 + 4 * types);
+        let open = |t: usize| t > 0 && (t - 1) % 4 < 2;
+        let begins = |t: usize| t == 0 || (t - 1) % 4 == 0 || (t - 1) % 4 == 3;
+        let kind = |t: usize| (t - 1) / 4;
+        for from in 0..c.tags {
+            for to in 0..c.tags {
+                c.allowed[from * c.tags + to] = if open(from) {
+                    to > 0 && kind(to) == kind(from) && matches!((to - 1) % 4, 1 | 2)
+                } else {
+                    begins(to)
+                };
+            }
+            c.start[from] = begins(from);
+            c.end[from] = !open(from);
+        }
+        c
+    }
+
+    /// Number of tags.
+    pub fn tags(&self) -> usize {
+        self.tags
+    }
+
+    /// Forbids the transition `from -> to`.
+    pub fn forbid(mut self, from: usize, to: usize) -> Result<Self> {
+        self.check(from)?;
+        self.check(to)?;
+        self.allowed[from * self.tags + to] = false;
+        Ok(self)
+    }
+
+    /// Restricts the first step to `tags`.
+    pub fn start_tags(mut self, tags: &[usize]) -> Result<Self> {
+        self.start = self.mask(tags)?;
+        Ok(self)
+    }
+
+    /// Restricts the last step to `tags`.
+    pub fn end_tags(mut self, tags: &[usize]) -> Result<Self> {
+        self.end = self.mask(tags)?;
+        Ok(self)
+    }
+
+    /// Whether `from -> to` is allowed.
+    pub fn allows(&self, from: usize, to: usize) -> bool {
+        self.allowed[from * self.tags + to]
+    }
+
+    /// Whether a path may start in `tag`.
+    pub fn allows_start(&self, tag: usize) -> bool {
+        self.start[tag]
+    }
+
+    /// Whether a path may end in `tag`.
+    pub fn allows_end(&self, tag: usize) -> bool {
+        self.end[tag]
+    }
+
+    fn check(&self, tag: usize) -> Result<()> {
+        if tag >= self.tags {
+            return Err(Error::TagOutOfRange {
+                tag,
+                tags: self.tags,
+            });
+        }
+        Ok(())
+    }
+
+    fn mask(&self, tags: &[usize]) -> Result<Vec<bool>> {
+        let mut mask = vec![false; self.tags];
+        for &t in tags {
+            self.check(t)?;
+            mask[t] = true;
+        }
+        Ok(mask)
+    }
+}
+
+/// A lattice with [`TagConstraints`] applied: disallowed transitions are not emitted and
+/// disallowed start/end tags get infinite initial/final cost.
+#[derive(Debug, Clone, Copy)]
+pub struct Constrained<'a, L: ?Sized> {
+    lattice: &'a L,
+    constraints: &'a TagConstraints,
+}
+
+impl<'a, L: Lattice + ?Sized> Constrained<'a, L> {
+    /// Applies `constraints` to `lattice`, whose every step must have one state per tag.
+    pub fn new(lattice: &'a L, constraints: &'a TagConstraints) -> Result<Self> {
+        let tags = constraints.tags;
+        if let Some(step) = (0..lattice.steps()).find(|&t| lattice.states(t) != tags) {
+            return Err(Error::TagCountMismatch {
+                step,
+                states: lattice.states(step),
+                tags,
+            });
+        }
+        Ok(Self {
+            lattice,
+            constraints,
+        })
+    }
+}
+
+impl<L: Lattice + ?Sized> Lattice for Constrained<'_, L> {
+    fn steps(&self) -> usize {
+        self.lattice.steps()
+    }
+
+    fn states(&self, t: usize) -> usize {
+        self.lattice.states(t)
+    }
+
+    fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+        let c = self.constraints;
+        self.lattice.transitions(t, from, &mut |to, cost| {
+            // Out-of-range targets are passed through for the operators to report.
+            if to >= c.tags || c.allows(from, to) {
+                emit(to, cost);
+            }
+        });
+    }
+
+    fn initial_cost(&self, s: usize) -> f64 {
+        match self.constraints.allows_start(s) {
+            true => self.lattice.initial_cost(s),
+            false => f64::INFINITY,
+        }
+    }
+
+    fn final_cost(&self, s: usize) -> f64 {
+        match self.constraints.allows_end(s) {
+            true => self.lattice.final_cost(s),
+            false => f64::INFINITY,
+        }
+    }
+}
+
+/// Negative log marginal likelihood of partial labels and its gradients; see
+/// [`partial_label_nll`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialLikelihood {
+    /// \(-\log \sum_{\pi \text{ consistent}} p_\gamma(\pi)\).
+    pub nll: f64,
+    /// `state_grads[t][s]`: gradient w.r.t. a cost on state `s` at step `t` (emission
+    /// costs; the initial and final costs at the first and last step).
+    pub state_grads: Vec<Vec<f64>>,
+    /// `transition_grads[t][k]`: gradient w.r.t. the cost of the `k`-th transition out of
+    /// step `t`, in the enumeration order of [`LatticeMarginals::transitions`].
+    pub transition_grads: Vec<Vec<f64>>,
+}
+
+/// A lattice restricted to the states allowed by partial labels.
+struct Observed<'a, L: ?Sized> {
+    lattice: &'a L,
+    allowed: Vec<Option<Vec<bool>>>,
+}
+
+impl<L: Lattice + ?Sized> Observed<'_, L> {
+    fn allows(&self, t: usize, s: usize) -> bool {
+        self.allowed[t]
+            .as_ref()
+            .map_or(true, |mask| mask.get(s) == Some(&true))
+    }
+}
+
+impl<L: Lattice + ?Sized> Lattice for Observed<'_, L> {
+    fn steps(&self) -> usize {
+        self.lattice.steps()
+    }
+
+    fn states(&self, t: usize) -> usize {
+        self.lattice.states(t)
+    }
+
+    fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+        // Disallowed targets stay in the enumeration at infinite cost, so transitions line
+        // up with the unconstrained lattice.
+        self.lattice.transitions(t, from, &mut |to, cost| {
+            emit(
+                to,
+                if self.allows(t + 1, to) {
+                    cost
+                } else {
+                    f64::INFINITY
+                },
+            )
+        });
+    }
+
+    fn initial_cost(&self, s: usize) -> f64 {
+        match self.allows(0, s) {
+            true => self.lattice.initial_cost(s),
+            false => f64::INFINITY,
+        }
+    }
+
+    fn final_cost(&self, s: usize) -> f64 {
+        self.lattice.final_cost(s)
+    }
+}
+
+/// Negative log marginal likelihood of partial labels under the Gibbs distribution
+/// \(p_\gamma(\pi) \propto e^{-C(\pi)/\gamma}\) over the lattice's paths.
+///
+/// `labels[t]` is the set of states allowed at step `t`, or `None` for an unlabelled step.
+/// The likelihood sums over every path consistent with the labels, so a fully labelled
+/// sequence gives the usual CRF likelihood and a fully unlabelled one gives 0. With \(V\)
+/// the soft value and \(V^{\text{obs}}\) that of the constrained lattice, the loss is
+/// \((V^{\text{obs}} - V)/\gamma\), and its gradient w.r.t. any cost is the difference of
+/// the two marginals divided by \(\gamma\): learning from partial annotation takes one
+/// constrained and one free forward/backward pass.
+pub fn partial_label_nll<L: Lattice + ?Sized>(
+    lattice: &L,
+    labels: &[Option<&[usize]>],
+    gamma: f64,
+) -> Result<PartialLikelihood> {
+    validate(lattice, gamma)?;
+    if labels.len() != lattice.steps() {
+        return Err(Error::LabelLengthMismatch {
+            len: labels.len(),
+            steps: lattice.steps(),
+        });
+    }
+    let mut allowed = Vec::with_capacity(labels.len());
+    for (step, label) in labels.iter().enumerate() {
+        let states = lattice.states(step);
+        allowed.push(match label {
+            None => None,
+            Some(set) => {
+                let mut mask = vec![false; states];
+                for &state in set.iter() {
+                    if state >= states {
+                        return Err(Error::LabelOutOfRange {
+                            step,
+                            state,
+                            states,
+                        });
+                    }
+                    mask[state] = true;
+                }
+                Some(mask)
+            }
+        });
+    }
+    let free = soft_lattice_marginals(lattice, gamma)?;
+    let observed = soft_lattice_marginals(&Observed { lattice, allowed }, gamma)?;
+    let diff = |a: &f64, b: &f64| (a - b) / gamma;
+    Ok(PartialLikelihood {
+        nll: (observed.value - free.value) / gamma,
+        state_grads: observed
+            .states
+            .iter()
+            .zip(&free.states)
+            .map(|(o, f)| o.iter().zip(f).map(|(o, f)| diff(o, f)).collect())
+            .collect(),
+        transition_grads: observed
+            .transitions
+            .iter()
+            .zip(&free.transitions)
+            .map(|(o, f)| {
+                o.iter()
+                    .zip(f)
+                    .map(|(o, f)| diff(&o.prob, &f.prob))
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::{soft_shortest_path_edge_marginals, Edge};
+
+    /// Fully connected lattice with explicit costs, `costs[t][from][to]`.
+    struct Dense {
+        init: Vec<f64>,
+        costs: Vec<Vec<Vec<f64>>>,
+    }
+
+    impl Lattice for Dense {
+        fn steps(&self) -> usize {
+            self.costs.len() + 1
+        }
+        fn states(&self, t: usize) -> usize {
+            if t == 0 {
+                self.init.len()
+            } else {
+                self.costs[t - 1][0].len()
+            }
+        }
+        fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+            for (to, &c) in self.costs[t][from].iter().enumerate() {
+                emit(to, c);
+            }
+        }
+        fn initial_cost(&self, s: usize) -> f64 {
+            self.init[s]
+        }
+    }
+
+    fn dense() -> Dense {
+        Dense {
+            init: vec![0.3, 1.1],
+            costs: vec![
+                vec![vec![0.2, 1.5, 0.7], vec![0.9, 0.1, f64::INFINITY]],
+                vec![vec![0.4, 0.6], vec![1.2, 0.3], vec![0.8, 0.5]],
+            ],
+        }
+    }
+
+    #[test]
+    fn marginals_match_the_equivalent_graph() {
+        let lat = dense();
+        let m = soft_lattice_marginals(&lat, 0.4).unwrap();
+        assert_eq!(soft_lattice_value(&lat, 0.4), Ok(m.value));
+
+        // Nodes: source 0, states (1..=2), (3..=5), (6..=7), sink 8; the forbidden
+        // transition is dropped.
+        let offset = [1, 3, 6];
+        let mut edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 0.3,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 1.1,
+            },
+        ];
+        for (t, layer) in lat.costs.iter().enumerate() {
+            for (f, row) in layer.iter().enumerate() {
+                for (to, &cost) in row.iter().enumerate().filter(|(_, c)| c.is_finite()) {
+                    edges.push(Edge {
+                        from: offset[t] + f,
+                        to: offset[t + 1] + to,
+                        cost,
+                    });
+                }
+            }
+        }
+        edges.push(Edge {
+            from: 6,
+            to: 8,
+            cost: 0.0,
+        });
+        edges.push(Edge {
+            from: 7,
+            to: 8,
+            cost: 0.0,
+        });
+        let (v, p) = soft_shortest_path_edge_marginals(9, &edges, 0.4).unwrap();
+        assert!((v - m.value).abs() < 1e-12);
+        assert!((p[0] - m.states[0][0]).abs() < 1e-12);
+        let flat: Vec<f64> = m
+            .transitions
+            .iter()
+            .flatten()
+            .filter(|t| t.prob > 0.0)
+            .map(|t| t.prob)
+            .collect();
+        for (a, b) in flat.iter().zip(&p[2..]) {
+            assert!((a - b).abs() < 1e-12, "{} vs {}", a, b);
+        }
+        for layer in &m.states {
+            assert!((layer.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn transition_marginals_are_the_cost_gradient() {
+        let base = dense();
+        let m = soft_lattice_marginals(&base, 0.4).unwrap();
+        let x = [base.costs[0][0][1], base.costs[1][2][1]];
+        let analytic = [m.transitions[0][1].prob, m.transitions[1][5].prob];
+        let report = crate::gradcheck::check_gradient(
+            |x| {
+                let mut lat = dense();
+                lat.costs[0][0][1] = x[0];
+                lat.costs[1][2][1] = x[1];
+                Ok(soft_lattice_value(&lat, 0.4).unwrap())
+            },
+            &x,
+            &analytic,
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+    }
+
+    /// Linear-chain CRF costs: `emit[t][s]` per state, `trans[from][to]` per transition.
+    struct Chain {
+        emit: Vec<Vec<f64>>,
+        trans: Vec<Vec<f64>>,
+    }
+
+    impl Lattice for Chain {
+        fn steps(&self) -> usize {
+            self.emit.len()
+        }
+        fn states(&self, _t: usize) -> usize {
+            self.trans.len()
+        }
+        fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+            for (to, &c) in self.trans[from].iter().enumerate() {
+                emit(to, c + self.emit[t + 1][to]);
+            }
+        }
+        fn initial_cost(&self, s: usize) -> f64 {
+            self.emit[0][s]
+        }
+    }
+
+    #[test]
+    fn bio_constraints_mask_viterbi_and_marginals() {
+        // Tags O, B-0, I-0; the emissions alone prefer I-0 first and O -> I-0 later.
+        let chain = Chain {
+            emit: vec![
+                vec![1.0, 0.8, 0.0],
+                vec![0.0, 1.0, 0.9],
+                vec![1.0, 1.0, 0.0],
+            ],
+            trans: vec![vec![0.0; 3]; 3],
+        };
+        let (_, free) = lattice_viterbi(&chain).unwrap();
+        assert_eq!(free, [2, 0, 2]);
+        let bio = TagConstraints::bio(1);
+        let lat = Constrained::new(&chain, &bio).unwrap();
+        let (cost, path) = lattice_viterbi(&lat).unwrap();
+        assert_eq!(path, [1, 2, 2]);
+        assert!((cost - 1.7).abs() < 1e-12);
+
+        let m = soft_lattice_marginals(&lat, 0.5).unwrap();
+        assert_eq!(m.states[0][2], 0.0);
+        for layer in &m.transitions {
+            assert!(layer.iter().all(|t| bio.allows(t.from, t.to)));
+            assert!((layer.iter().map(|t| t.prob).sum::<f64>() - 1.0).abs() < 1e-12);
+        }
+        // The soft value tends to the Viterbi cost.
+        let v = soft_lattice_value(&lat, 1e-3).unwrap();
+        assert!((v - cost).abs() < 1e-2);
+
+        let bilou = TagConstraints::bilou(1);
+        assert!(bilou.allows(1, 3) && !bilou.allows(1, 0) && bilou.allows(3, 4));
+        assert!(!bilou.allows_start(2) && !bilou.allows_end(1) && bilou.allows_end(4));
+        assert_eq!(
+            Constrained::new(&chain, &bilou).map(|_| ()),
+            Err(Error::TagCountMismatch {
+                step: 0,
+                states: 3,
+                tags: 5
+            })
+        );
+        assert_eq!(
+            TagConstraints::new(3).forbid(0, 3),
+            Err(Error::TagOutOfRange { tag: 3, tags: 3 })
+        );
+        let forced = TagConstraints::new(3).end_tags(&[0]).unwrap();
+        let (_, path) = lattice_viterbi(&Constrained::new(&chain, &forced).unwrap()).unwrap();
+        assert_eq!(path[2], 0);
+    }
+
+    #[test]
+    fn partial_label_likelihood_matches_enumeration_and_gradients() {
+        let emit = vec![
+            vec![0.2, 1.0, 0.5],
+            vec![0.7, 0.1, 0.4],
+            vec![0.3, 0.6, 0.0],
+        ];
+        let trans = vec![
+            vec![0.0, 0.5, 1.0],
+            vec![0.3, 0.0, 0.2],
+            vec![0.9, 0.4, 0.0],
+        ];
+        let gamma = 0.6;
+        let labels: [Option<&[usize]>; 3] = [Some(&[0, 1]), None, Some(&[2])];
+        let chain = Chain {
+            emit: emit.clone(),
+            trans: trans.clone(),
+        };
+        let out = partial_label_nll(&chain, &labels, gamma).unwrap();
+
+        let (mut all, mut consistent) = (0.0, 0.0);
+        for a in 0..3 {
+            for b in 0..3 {
+                for c in 0..3 {
+                    let cost = emit[0][a] + trans[a][b] + emit[1][b] + trans[b][c] + emit[2][c];
+                    let w = (-cost / gamma).exp();
+                    all += w;
+                    if a < 2 && c == 2 {
+                        consistent += w;
+                    }
+                }
+            }
+        }
+        assert!((out.nll + (consistent / all).ln()).abs() < 1e-12);
+
+        let flat: Vec<f64> = emit.concat();
+        let grads: Vec<f64> = out.state_grads.concat();
+        let report = crate::gradcheck::check_gradient(
+            |x| {
+                let chain = Chain {
+                    emit: x.chunks(3).map(|r| r.to_vec()).collect(),
+                    trans: trans.clone(),
+                };
+                Ok(partial_label_nll(&chain, &labels, gamma).unwrap().nll)
+            },
+            &flat,
+            &grads,
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(out.transition_grads[1].len(), 9);
+
+        let unlabelled = partial_label_nll(&chain, &[None, None, None], gamma).unwrap();
+        assert!(unlabelled.nll.abs() < 1e-12);
+        assert_eq!(
+            partial_label_nll(&chain, &[None, Some(&[3]), None], gamma),
+            Err(Error::LabelOutOfRange {
+                step: 1,
+                state: 3,
+                states: 3
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_lattices_are_reported() {
+        let mut lat = dense();
+        lat.costs[1][0][1] = f64::NAN;
+        assert!(matches!(
+            soft_lattice_value(&lat, 0.4),
+            Err(Error::InvalidCost { step: 1, cost }) if cost.is_nan()
+        ));
+        assert_eq!(
+            soft_lattice_value(&dense(), 0.0),
+            Err(Error::InvalidGamma(0.0))
+        );
+        let mut lat = dense();
+        lat.init = vec![f64::INFINITY; 2];
+        assert_eq!(soft_lattice_marginals(&lat, 0.4), Err(Error::NoPath));
+        // Step 1 has one state, but state 1 emits a transition to a second one.
+        let lat = Dense {
+            init: vec![0.0, 0.0],
+            costs: vec![vec![vec![0.0], vec![0.0, 0.0]]],
+        };
+        assert_eq!(
+            soft_lattice_value(&lat, 0.4),
+            Err(Error::StateOutOfRange {
+                step: 0,
+                from: 1,
+                to: 1,
+                states: 1
+            })
+        );
+    }
+}
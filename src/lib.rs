@@ -8,12 +8,81 @@
 //! - Numeric code is deterministic (no RNG in core ops).
 //! - Parameters that control smoothing (e.g. \(\gamma\)) are explicit and validated.
 
+pub mod absorption;
+pub mod anomaly;
+#[cfg(feature = "arrow")]
+pub mod arrow_interop;
+#[cfg(feature = "burn")]
+pub mod burn_ops;
+#[cfg(feature = "candle")]
+pub mod candle_ops;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod capped_simplex;
+pub mod centroid;
+pub mod continuation;
+pub mod crf;
+pub mod elastic;
+mod error;
+pub mod fixed_point;
+pub mod gradcheck;
+#[cfg(feature = "json")]
+pub mod graph_json;
+pub mod hausdorff;
+pub mod implicit_path;
+pub mod invariants;
+pub mod lattice;
+pub mod local_gamma;
+mod math;
+pub mod max_entropy;
+pub mod mbr;
+pub mod metric_learning;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+pub mod motif;
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra_interop;
+#[cfg(feature = "ndarray")]
+pub mod ndarray_interop;
+pub mod nearest_neighbor;
+#[cfg(feature = "npy")]
+pub mod npy;
+pub mod ot;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod path_moments;
+#[cfg(feature = "polars")]
+pub mod polars_interop;
+pub mod prelude;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod registration;
+pub mod resources;
+pub mod risk_sensitive;
+pub mod sax;
+pub mod segmental;
+pub mod shape_dtw;
+pub mod simplify;
 pub mod soft_dtw;
+pub mod soft_dtw_f32;
 pub mod soft_shortest_path;
+pub mod string_kernels;
+#[cfg(feature = "tch")]
+pub mod tch_ops;
+pub mod time_expanded;
+pub mod validation;
+pub mod viz;
+pub mod warp2d;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod znorm;
 
 /// Re-export commonly-used operators at crate root for examples.
 pub use soft_dtw::*;
 
+// Shadows the glob's `soft_dtw::{Error, Result}`; module errors convert via `From`.
+pub use error::{Error, Result};
+
 #[cfg(test)]
 mod tests {
     // Keep this module for crate-level smoke tests if needed.
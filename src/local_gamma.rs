@@ -0,0 +1,238 @@
+//! Soft-DTW with a smoothing parameter per cell.
+//!
+//! A single \(\gamma\) trades smoothness for fidelity everywhere at once. Here every cell
+//! `(i, j)` of the alignment lattice has its own \(\gamma_{ij}\), used by the soft-min that
+//! enters it: \(R_{ij} = C_{ij} + \operatorname{softmin}_{\gamma_{ij}}(R_{i-1,j},
+//! R_{i,j-1}, R_{i-1,j-1})\). Large values smooth over noisy regions; small ones keep
+//! near-hard decisions around reliable anchors. With a constant \(\gamma\) this is
+//! [`crate::soft_dtw::soft_dtw_alignment`].
+//!
+//! Besides the expected alignment (the gradient w.r.t. the costs), [`soft_dtw_local_gamma`]
+//! returns the gradient w.r.t. every \(\gamma_{ij}\): the alignment mass at the cell times
+//! \(\partial \operatorname{softmin}_\gamma / \partial \gamma = (s - \sum_k p_k x_k) /
+//! \gamma\), with \(s\) the soft-min and \(p\) its weights over the candidates \(x\).
+//!
+//! ```
+//! use structop::local_gamma::soft_dtw_local_gamma;
+//!
+//! let cost = [0.0, 1.0, 1.0, 0.0];
+//! // Smooth at the start, nearly hard at the end.
+//! let out = soft_dtw_local_gamma(&cost, 2, 2, &[1.0, 1.0, 1.0, 0.01]).unwrap();
+//! assert!(out.alignment[3] == 1.0 && out.value.abs() < 1e-12);
+//! ```
+
+use crate::math::{exp, ln};
+use crate::soft_dtw;
+
+/// Errors for per-cell smoothing.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The smoothing matrix does not match the cost matrix.
+    #[error("gamma matrix has length {len}, expected {expected}")]
+    GammaShape {
+        /// Length given.
+        len: usize,
+        /// `n * m`.
+        expected: usize,
+    },
+    /// A cell's smoothing parameter is not positive and finite.
+    #[error("gamma[{i}][{j}] must be positive and finite, got {gamma}")]
+    InvalidGamma {
+        /// Row index.
+        i: usize,
+        /// Column index.
+        j: usize,
+        /// The value.
+        gamma: f64,
+    },
+    /// Error from the cost-matrix checks of [`crate::soft_dtw`].
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Output of [`soft_dtw_local_gamma`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalGammaAlignment {
+    /// Soft-DTW value.
+    pub value: f64,
+    /// Expected alignment, row-major `n × m` (the gradient w.r.t. the costs).
+    pub alignment: Vec<f64>,
+    /// Gradient of the value w.r.t. each \(\gamma_{ij}\), row-major `n × m`.
+    pub grad_gamma: Vec<f64>,
+}
+
+/// Soft-min of `x` at `gamma` and its weights (zero for `+inf` candidates), `+inf` with
+/// zero weights if every candidate is `+inf`.
+fn softmin(gamma: f64, x: [f64; 3]) -> (f64, [f64; 3]) {
+    let top = x
+        .iter()
+        .map(|&v| -v / gamma)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if !top.is_finite() {
+        return (f64::INFINITY, [0.0; 3]);
+    }
+    let sum: f64 = x.iter().map(|&v| exp(-v / gamma - top)).sum();
+    let s = -gamma * (top + ln(sum));
+    (
+        s,
+        x.map(|v| {
+            if v.is_finite() {
+                exp((s - v) / gamma)
+            } else {
+                0.0
+            }
+        }),
+    )
+}
+
+/// Soft-DTW value, expected alignment and gradient w.r.t. the smoothing of an `n × m`
+/// row-major cost matrix, with `gamma[i * m + j]` the smoothing of cell `(i, j)`.
+pub fn soft_dtw_local_gamma(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: &[f64],
+) -> Result<LocalGammaAlignment> {
+    soft_dtw::validate_cost(cost, n, m, 1.0)?;
+    if gamma.len() != n * m {
+        return Err(Error::GammaShape {
+            len: gamma.len(),
+            expected: n * m,
+        });
+    }
+    solve(cost, n, m, |i, j| gamma[i * m + j])
+}
+
+/// [`soft_dtw_local_gamma`] with the smoothing of cell `(i, j)` given by `gamma(i, j)`
+/// (called once per cell), e.g. a per-region lookup.
+pub fn soft_dtw_local_gamma_with<F>(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: F,
+) -> Result<LocalGammaAlignment>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    soft_dtw::validate_cost(cost, n, m, 1.0)?;
+    solve(cost, n, m, gamma)
+}
+
+/// [`soft_dtw_local_gamma_with`] on a cost matrix already validated by the caller.
+fn solve<F>(cost: &[f64], n: usize, m: usize, gamma: F) -> Result<LocalGammaAlignment>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    let mut g = Vec::with_capacity(n * m);
+    for i in 0..n {
+        for j in 0..m {
+            let v = gamma(i, j);
+            if v <= 0.0 || !v.is_finite() {
+                return Err(Error::InvalidGamma { i, j, gamma: v });
+            }
+            g.push(v);
+        }
+    }
+
+    // 1-based table as in the scalar kernels; `weights` keeps each cell's soft-min weights
+    // over (up, left, diagonal) for the backward pass.
+    let w = m + 1;
+    let mut r = vec![f64::INFINITY; (n + 1) * w];
+    r[0] = 0.0;
+    let mut weights = vec![[0.0; 3]; n * m];
+    for i in 1..=n {
+        for j in 1..=m {
+            let k = (i - 1) * m + j - 1;
+            let x = [r[(i - 1) * w + j], r[i * w + j - 1], r[(i - 1) * w + j - 1]];
+            let (s, p) = softmin(g[k], x);
+            r[i * w + j] = cost[k] + s;
+            weights[k] = p;
+        }
+    }
+
+    let mut alignment = vec![0.0; n * m];
+    let mut grad_gamma = vec![0.0; n * m];
+    alignment[n * m - 1] = 1.0;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let k = i * m + j;
+            if k + 1 < n * m {
+                let mut acc = 0.0;
+                if i + 1 < n {
+                    acc += alignment[k + m] * weights[k + m][0];
+                }
+                if j + 1 < m {
+                    acc += alignment[k + 1] * weights[k + 1][1];
+                }
+                if i + 1 < n && j + 1 < m {
+                    acc += alignment[k + m + 1] * weights[k + m + 1][2];
+                }
+                alignment[k] = acc;
+            }
+            if alignment[k] != 0.0 {
+                let x = [r[i * w + j + 1], r[(i + 1) * w + j], r[i * w + j]];
+                let s = r[(i + 1) * w + j + 1] - cost[k];
+                let mean: f64 = x
+                    .iter()
+                    .zip(&weights[k])
+                    .filter(|(_, &p)| p > 0.0)
+                    .map(|(v, p)| p * v)
+                    .sum();
+                grad_gamma[k] = alignment[k] * (s - mean) / g[k];
+            }
+        }
+    }
+    Ok(LocalGammaAlignment {
+        value: r[n * w + m],
+        alignment,
+        grad_gamma,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    #[test]
+    fn constant_gamma_matches_soft_dtw_and_gradients_check() {
+        let (n, m) = (4, 5);
+        let cost: Vec<f64> = (0..n * m).map(|k| ((k * 7) % 5) as f64 * 0.3).collect();
+        let out = soft_dtw_local_gamma(&cost, n, m, &vec![0.4; n * m]).unwrap();
+        let (v, e) = soft_dtw::soft_dtw_alignment(&cost, n, m, 0.4).unwrap();
+        assert!((out.value - v).abs() < 1e-12);
+        assert!(out
+            .alignment
+            .iter()
+            .zip(&e)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let gamma: Vec<f64> = (0..n * m).map(|k| 0.1 + (k % 3) as f64 * 0.4).collect();
+        let out = soft_dtw_local_gamma(&cost, n, m, &gamma).unwrap();
+        let f = |g: &[f64]| Ok(soft_dtw_local_gamma(&cost, n, m, g).unwrap().value);
+        let report = check_gradient(f, &gamma, &out.grad_gamma, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+        let f = |c: &[f64]| Ok(soft_dtw_local_gamma(c, n, m, &gamma).unwrap().value);
+        let report = check_gradient(f, &cost, &out.alignment, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+
+        assert_eq!(
+            soft_dtw_local_gamma(&cost, n, m, &gamma[1..]),
+            Err(Error::GammaShape {
+                len: n * m - 1,
+                expected: n * m
+            })
+        );
+        assert_eq!(
+            soft_dtw_local_gamma_with(&cost, n, m, |i, j| if (i, j) == (2, 1) { 0.0 } else { 1.0 }),
+            Err(Error::InvalidGamma {
+                i: 2,
+                j: 1,
+                gamma: 0.0
+            })
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! `exp`/`ln` and summation used by the softmin kernels.
+//!
+//! By default these are `f64::exp` / `f64::ln`. With the `fast-math` feature they are
+//! branch-light polynomial approximations (no table lookups, no libm calls) that inline
+//! into the DP loops, where transcendental calls dominate the profile:
+//!
+//! - [`exp_approx`]: range reduction \(x = k \ln 2 + r\), \(|r| \le \tfrac12 \ln 2\), and a
+//!   degree-11 Taylor polynomial for \(e^r\). Relative error below `1e-14` for
+//!   \(x \in [-708, 709]\); returns `0` below that range (no subnormal results) and `+inf`
+//!   above it.
+//! - [`ln_approx`]: \(x = 2^e m\) with \(m \in [\sqrt{1/2}, \sqrt 2)\) and
+//!   \(\ln m = 2\,\operatorname{atanh}\frac{m-1}{m+1}\) summed to the \(s^{19}\) term.
+//!   Absolute error below `1e-15 · max(1, |ln x|)` for positive normal `x`; other inputs
+//!   (zero, negative, subnormal, non-finite) defer to `f64::ln`.
+//!
+//! Both are deterministic, so results stay reproducible across runs; they differ from the
+//! default build only at the level of the bounds above.
+//!
+//! [`DoubleDouble`] carries the DP potentials of the extended-precision mode: a value is the
+//! unevaluated sum `hi + lo`, giving about 106 significand bits from error-free
+//! transformations (`two_sum`, and `two_prod` via `mul_add`).
+
+use std::f64::consts::LN_2;
+
+/// `exp` as used by the kernels.
+#[inline]
+pub(crate) fn exp(x: f64) -> f64 {
+    if cfg!(feature = "fast-math") {
+        exp_approx(x)
+    } else {
+        x.exp()
+    }
+}
+
+/// `ln` as used by the kernels.
+#[inline]
+pub(crate) fn ln(x: f64) -> f64 {
+    if cfg!(feature = "fast-math") {
+        ln_approx(x)
+    } else {
+        x.ln()
+    }
+}
+
+/// Neumaier (improved Kahan) compensated sum.
+///
+/// The error is bounded independently of the number of terms (about `2ε Σ|x|` rather than
+/// `nε Σ|x|`), which matters for log-sum-exp over nodes with thousands of incoming edges.
+#[inline]
+pub(crate) fn compensated_sum<I: IntoIterator<Item = f64>>(xs: I) -> f64 {
+    let mut sum = 0.0;
+    let mut comp = 0.0;
+    for x in xs {
+        let t = sum + x;
+        comp += if sum.abs() >= x.abs() {
+            (sum - t) + x
+        } else {
+            (x - t) + sum
+        };
+        sum = t;
+    }
+    sum + comp
+}
+
+/// Double-double number `hi + lo`, normalized so that `|lo| <= ulp(hi) / 2`.
+///
+/// Non-finite values are kept with `lo == 0`, so `+inf` (an unreachable DP cell) propagates
+/// as in plain `f64` arithmetic instead of turning into NaN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct DoubleDouble {
+    pub(crate) hi: f64,
+    pub(crate) lo: f64,
+}
+
+/// `a + b = s + e` exactly.
+#[inline]
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// [`two_sum`] for `|a| >= |b|`.
+#[inline]
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+impl DoubleDouble {
+    pub(crate) const INFINITY: Self = Self {
+        hi: f64::INFINITY,
+        lo: 0.0,
+    };
+
+    #[inline]
+    fn normalized(hi: f64, lo: f64) -> Self {
+        if !hi.is_finite() {
+            return Self { hi, lo: 0.0 };
+        }
+        let (hi, lo) = quick_two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    /// Nearest `f64`.
+    #[inline]
+    pub(crate) fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+
+    #[inline]
+    pub(crate) fn is_finite(self) -> bool {
+        self.hi.is_finite()
+    }
+
+    /// `self * b`.
+    #[inline]
+    pub(crate) fn mul_f64(self, b: f64) -> Self {
+        let p = self.hi * b;
+        if !p.is_finite() {
+            return Self { hi: p, lo: 0.0 };
+        }
+        let e = self.hi.mul_add(b, -p) + self.lo * b;
+        Self::normalized(p, e)
+    }
+
+    /// `self / b`.
+    #[inline]
+    pub(crate) fn div_f64(self, b: f64) -> Self {
+        let q1 = self.hi / b;
+        if !q1.is_finite() {
+            return Self { hi: q1, lo: 0.0 };
+        }
+        let p1 = q1 * b;
+        let p2 = q1.mul_add(b, -p1);
+        let (s, e) = two_sum(self.hi, -p1);
+        let q2 = (s + (e - p2 + self.lo)) / b;
+        Self::normalized(q1, q2)
+    }
+}
+
+impl From<f64> for DoubleDouble {
+    #[inline]
+    fn from(hi: f64) -> Self {
+        Self { hi, lo: 0.0 }
+    }
+}
+
+impl std::ops::Add for DoubleDouble {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, o: Self) -> Self {
+        let (s, e) = two_sum(self.hi, o.hi);
+        if !s.is_finite() {
+            return Self { hi: s, lo: 0.0 };
+        }
+        let (t, f) = two_sum(self.lo, o.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        Self::normalized(s, e + f)
+    }
+}
+
+impl std::ops::Neg for DoubleDouble {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        Self {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl std::ops::Sub for DoubleDouble {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, o: Self) -> Self {
+        self + -o
+    }
+}
+
+/// Soft minimum \(-\gamma \log \sum_k e^{-a_k/\gamma}\) of double-double candidates.
+///
+/// Only the shifted exponents `(a_min - a_k)/γ <= 0` go through `f64` `exp`; the shift
+/// itself stays in double-double, so the result is accurate to about `γ ε` rather than
+/// `|a| ε`. Returns `+inf` when every candidate is `+inf` (or there are none).
+pub(crate) fn softmin_dd(gamma: f64, candidates: &[DoubleDouble]) -> DoubleDouble {
+    let best = candidates
+        .iter()
+        .copied()
+        .filter(|a| a.is_finite())
+        .min_by(|a, b| a.hi.total_cmp(&b.hi).then(a.lo.total_cmp(&b.lo)));
+    let Some(best) = best else {
+        return DoubleDouble::INFINITY;
+    };
+    let s = compensated_sum(
+        candidates
+            .iter()
+            .filter(|a| a.is_finite())
+            .map(|&a| exp(-(a - best).div_f64(gamma).to_f64())),
+    );
+    best + DoubleDouble::from(-gamma * ln(s))
+}
+
+/// Polynomial `exp`; see the module docs for the error bound.
+#[inline]
+pub(crate) fn exp_approx(x: f64) -> f64 {
+    // ln 2 split (fdlibm's constants) so that k * LN2_HI is exact for |k| < 2^11.
+    const LN2_HI: f64 = 0.693_147_180_369_123_8;
+    const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+    if x.is_nan() {
+        return x;
+    }
+    if x > 709.0 {
+        return f64::INFINITY;
+    }
+    if x < -708.0 {
+        return 0.0;
+    }
+    let k = (x * std::f64::consts::LOG2_E).round();
+    let r = (x - k * LN2_HI) - k * LN2_LO;
+    // Horner form of sum_{i<=11} r^i / i!.
+    let mut p = 1.0 / 39_916_800.0;
+    for c in [
+        1.0 / 3_628_800.0,
+        1.0 / 362_880.0,
+        1.0 / 40_320.0,
+        1.0 / 5_040.0,
+        1.0 / 720.0,
+        1.0 / 120.0,
+        1.0 / 24.0,
+        1.0 / 6.0,
+        0.5,
+        1.0,
+        1.0,
+    ] {
+        p = p * r + c;
+    }
+    // k is in [-1022, 1023] here, so 2^k is a normal number.
+    p * f64::from_bits(((k as i64 + 1023) as u64) << 52)
+}
+
+/// Polynomial `ln`; see the module docs for the error bound.
+#[inline]
+pub(crate) fn ln_approx(x: f64) -> f64 {
+    if !(x.is_normal() && x > 0.0) {
+        return x.ln();
+    }
+    let bits = x.to_bits();
+    let mut e = ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut m = f64::from_bits((bits & ((1u64 << 52) - 1)) | (1023u64 << 52));
+    if m > std::f64::consts::SQRT_2 {
+        m *= 0.5;
+        e += 1;
+    }
+    let s = (m - 1.0) / (m + 1.0);
+    let s2 = s * s;
+    // 2 * (s + s^3/3 + ... + s^19/19), Horner in s^2.
+    let mut p = 1.0 / 19.0;
+    for c in [
+        1.0 / 17.0,
+        1.0 / 15.0,
+        1.0 / 13.0,
+        1.0 / 11.0,
+        1.0 / 9.0,
+        1.0 / 7.0,
+        1.0 / 5.0,
+        1.0 / 3.0,
+        1.0,
+    ] {
+        p = p * s2 + c;
+    }
+    e as f64 * LN_2 + 2.0 * s * p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approximations_meet_documented_bounds() {
+        let mut x = -708.0;
+        while x <= 709.0 {
+            let (got, want) = (exp_approx(x), x.exp());
+            assert!(
+                ((got - want) / want).abs() < 1e-14,
+                "exp({}) = {} vs {}",
+                x,
+                got,
+                want
+            );
+            x += 0.37;
+        }
+        assert_eq!(exp_approx(-800.0), 0.0);
+        assert_eq!(exp_approx(800.0), f64::INFINITY);
+        assert_eq!(exp_approx(0.0), 1.0);
+        assert!(exp_approx(f64::NAN).is_nan());
+
+        for k in -300..300 {
+            for frac in [1.0, 1.2, 1.41, 1.42, 1.7, 1.99] {
+                let x = frac * 2f64.powi(k);
+                let (got, want) = (ln_approx(x), x.ln());
+                assert!(
+                    (got - want).abs() < 1e-15 * want.abs().max(1.0),
+                    "ln({}) = {} vs {}",
+                    x,
+                    got,
+                    want
+                );
+            }
+        }
+        assert_eq!(ln_approx(1.0), 0.0);
+        assert_eq!(ln_approx(0.0), f64::NEG_INFINITY);
+        assert!(ln_approx(-1.0).is_nan());
+    }
+
+    #[test]
+    fn double_double_keeps_low_order_bits() {
+        let big = DoubleDouble::from(1e16);
+        let x = big + DoubleDouble::from(1.0) - big;
+        assert_eq!(x.to_f64(), 1.0);
+        let third = DoubleDouble::from(1.0).div_f64(3.0);
+        let back = third.mul_f64(3.0) - DoubleDouble::from(1.0);
+        assert!(back.to_f64().abs() < 1e-30, "{:?}", back);
+        let inf = DoubleDouble::INFINITY + DoubleDouble::from(1.0);
+        assert_eq!(inf, DoubleDouble::INFINITY);
+        assert_eq!(softmin_dd(1.0, &[inf]), DoubleDouble::INFINITY);
+        // Two equal candidates: a - γ ln 2, with `a` far larger than the correction.
+        let a = DoubleDouble::from(1e8);
+        let got = softmin_dd(1e-3, &[a, a]) - a;
+        assert!((got.to_f64() + 1e-3 * 2f64.ln()).abs() < 1e-18);
+    }
+
+    #[test]
+    fn compensated_sum_keeps_small_terms() {
+        let xs = std::iter::once(1.0).chain(std::iter::repeat(1e-16).take(100_000));
+        assert_eq!(xs.clone().sum::<f64>(), 1.0);
+        assert!((compensated_sum(xs) - (1.0 + 1e-11)).abs() < 1e-16);
+        assert_eq!(compensated_sum([]), 0.0);
+    }
+}
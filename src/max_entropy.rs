@@ -0,0 +1,234 @@
+//! Maximum-entropy path distributions with an expected-cost constraint.
+//!
+//! Among all distributions over source-to-sink paths whose expected cost is \(\bar c\), the
+//! one of maximum entropy is the Gibbs distribution \(p(\pi) \propto e^{-C(\pi)/\gamma}\) for
+//! the \(\gamma\) (the inverse of the dual multiplier) at which
+//! \(\mathbb{E}_\gamma[C] = \sum_e p_e c_e = \bar c\). Since
+//! \(\frac{d}{d\gamma}\mathbb{E}_\gamma[C] = \operatorname{Var}_\gamma[C]/\gamma^2 \ge 0\),
+//! the expected cost rises monotonically from the shortest-path cost (\(\gamma \to 0\)) to the
+//! mean over all paths (\(\gamma \to \infty\)), and [`solve_expected_cost`] finds \(\gamma\)
+//! by bisection on \(\log\gamma\) inside a bracket. The resulting marginals spend exactly the
+//! requested exploration budget above the optimum.
+//!
+//! ```
+//! use structop::max_entropy::{solve_expected_cost, MaxEntOptions};
+//! use structop::soft_shortest_path::{Edge, Graph};
+//!
+//! let edges = vec![
+//!     Edge { from: 0, to: 1, cost: 1.0 },
+//!     Edge { from: 0, to: 1, cost: 3.0 },
+//! ];
+//! let graph = Graph::new(2, edges, 0, 1).unwrap();
+//! let sol = solve_expected_cost(&graph, 1.5, MaxEntOptions::default()).unwrap();
+//! assert!((sol.expected_cost - 1.5).abs() < 1e-6);
+//! assert!((sol.marginals[1] - 0.25).abs() < 1e-6);
+//! ```
+
+use crate::soft_shortest_path::{self, Graph};
+
+/// Errors for the expected-cost solver.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The bracket must satisfy `0 < gamma_min < gamma_max < inf`, and the tolerance must be
+    /// finite and positive.
+    #[error("invalid options: bracket [{gamma_min}, {gamma_max}], tol {tol}")]
+    InvalidOptions {
+        /// Lower end of the bracket.
+        gamma_min: f64,
+        /// Upper end of the bracket.
+        gamma_max: f64,
+        /// Tolerance on the expected cost.
+        tol: f64,
+    },
+    /// The target expected cost is not reachable inside the bracket.
+    #[error("target expected cost {target} outside achievable range [{min}, {max}]")]
+    TargetOutOfRange {
+        /// The requested expected cost.
+        target: f64,
+        /// Expected cost at `gamma_min`.
+        min: f64,
+        /// Expected cost at `gamma_max`.
+        max: f64,
+    },
+    /// Error from the soft shortest path.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Options for [`solve_expected_cost`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaxEntOptions {
+    /// Smallest `gamma` searched.
+    pub gamma_min: f64,
+    /// Largest `gamma` searched.
+    pub gamma_max: f64,
+    /// Absolute tolerance on the expected cost.
+    pub tol: f64,
+    /// Maximum number of bisection steps.
+    pub max_iter: usize,
+}
+
+impl Default for MaxEntOptions {
+    fn default() -> Self {
+        Self {
+            gamma_min: 1e-4,
+            gamma_max: 1e4,
+            tol: 1e-9,
+            max_iter: 200,
+        }
+    }
+}
+
+/// Result of [`solve_expected_cost`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxEntPath {
+    /// The temperature found.
+    pub gamma: f64,
+    /// Soft shortest-path value \(V_\gamma\) at that temperature.
+    pub value: f64,
+    /// Expected path cost \(\sum_e p_e c_e\).
+    pub expected_cost: f64,
+    /// Edge marginals (indexed like [`Graph::edges`]).
+    pub marginals: Vec<f64>,
+    /// Bisection steps taken.
+    pub iterations: usize,
+}
+
+fn evaluate(graph: &Graph, gamma: f64) -> Result<(f64, f64, Vec<f64>)> {
+    let (value, p) = graph.edge_marginals(gamma)?;
+    let cost = graph.edges().iter().zip(&p).map(|(e, p)| p * e.cost).sum();
+    Ok((value, cost, p))
+}
+
+/// Temperature whose Gibbs path distribution on `graph` has expected cost `target`, with its
+/// marginals. Stops when the expected cost is within `tol` of the target or the bracket has
+/// collapsed to floating-point resolution.
+pub fn solve_expected_cost(graph: &Graph, target: f64, opts: MaxEntOptions) -> Result<MaxEntPath> {
+    let MaxEntOptions {
+        gamma_min,
+        gamma_max,
+        tol,
+        max_iter,
+    } = opts;
+    let bracket = gamma_min > 0.0 && gamma_min < gamma_max && gamma_max.is_finite();
+    if !(bracket && tol > 0.0 && tol.is_finite()) {
+        return Err(Error::InvalidOptions {
+            gamma_min,
+            gamma_max,
+            tol,
+        });
+    }
+    let lo = evaluate(graph, gamma_min)?;
+    let hi = evaluate(graph, gamma_max)?;
+    if !(target >= lo.1 - tol && target <= hi.1 + tol) {
+        return Err(Error::TargetOutOfRange {
+            target,
+            min: lo.1,
+            max: hi.1,
+        });
+    }
+    let solution = |gamma: f64, (value, expected_cost, marginals), iterations| MaxEntPath {
+        gamma,
+        value,
+        expected_cost,
+        marginals,
+        iterations,
+    };
+    if (lo.1 - target).abs() <= tol {
+        return Ok(solution(gamma_min, lo, 0));
+    }
+    if (hi.1 - target).abs() <= tol {
+        return Ok(solution(gamma_max, hi, 0));
+    }
+    let (mut a, mut b) = (gamma_min.ln(), gamma_max.ln());
+    let mut iterations = 0;
+    loop {
+        iterations += 1;
+        let mid = 0.5 * (a + b);
+        let eval = evaluate(graph, mid.exp())?;
+        let gap = eval.1 - target;
+        if gap.abs() <= tol || mid <= a || mid >= b || iterations >= max_iter {
+            return Ok(solution(mid.exp(), eval, iterations));
+        }
+        if gap < 0.0 {
+            a = mid;
+        } else {
+            b = mid;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::Edge;
+
+    #[test]
+    fn matches_the_closed_form_on_parallel_routes() {
+        // Three routes of costs 1, 2, 4 through an intermediate node each.
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 0.5,
+            },
+            Edge {
+                from: 1,
+                to: 4,
+                cost: 0.5,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 1.0,
+            },
+            Edge {
+                from: 2,
+                to: 4,
+                cost: 1.0,
+            },
+            Edge {
+                from: 0,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 2.0,
+            },
+        ];
+        let graph = Graph::new(5, edges, 0, 4).unwrap();
+        let sol = solve_expected_cost(&graph, 2.0, MaxEntOptions::default()).unwrap();
+        let w: Vec<f64> = [1.0, 2.0, 4.0_f64]
+            .iter()
+            .map(|c| (-c / sol.gamma).exp())
+            .collect();
+        let z: f64 = w.iter().sum();
+        let mean = (w[0] + 2.0 * w[1] + 4.0 * w[2]) / z;
+        assert!((sol.expected_cost - 2.0).abs() < 1e-9);
+        assert!((mean - 2.0).abs() < 1e-9);
+        assert!((sol.marginals[4] - w[2] / z).abs() < 1e-9);
+
+        // The mean over all paths is 7/3; anything above is unreachable.
+        match solve_expected_cost(&graph, 2.5, MaxEntOptions::default()) {
+            Err(Error::TargetOutOfRange { max, .. }) => assert!((max - 7.0 / 3.0).abs() < 1e-3),
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(
+            solve_expected_cost(
+                &graph,
+                2.0,
+                MaxEntOptions {
+                    gamma_min: 1.0,
+                    gamma_max: 0.5,
+                    ..MaxEntOptions::default()
+                }
+            ),
+            Err(Error::InvalidOptions { .. })
+        ));
+    }
+}
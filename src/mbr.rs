@@ -0,0 +1,348 @@
+//! Minimum Bayes-risk (MBR) decoding from posteriors.
+//!
+//! The hard shortest path (or Viterbi path) is the single most probable structure under the
+//! Gibbs distribution; under a loss that gives partial credit, the decode minimizing the
+//! *expected* loss is often better. For a local loss, which charges `false_positive` for each
+//! predicted item (edge or lattice state) missing from the reference and `false_negative`
+//! for each reference item the prediction misses, the expected loss of a prediction
+//! \(\hat\pi\) given item posteriors \(p\) is
+//!
+//! \[ \mathbb{E}[L(\hat\pi, \pi)] = \sum_{e \in \hat\pi} \big((1 - p_e)\,\mathrm{fp}_e
+//!    - p_e\,\mathrm{fn}_e\big) + \sum_e p_e\,\mathrm{fn}_e, \]
+//!
+//! a sum over the chosen items plus a constant, so the exact MBR decode is a hard shortest
+//! path under the per-item risks. With `fp = fn = 1` ([`LocalLoss::HAMMING`]) the loss is the
+//! size of the symmetric difference.
+//!
+//! - [`mbr_path`]: over the edges of a [`Graph`], from its edge marginals;
+//! - [`mbr_lattice`]: over the states of a [`Lattice`], from its state marginals (paths may
+//!   only use transitions and endpoints of finite cost).
+//!
+//! ```
+//! use structop::mbr::{mbr_path, LocalLoss};
+//! use structop::soft_shortest_path::{Edge, Graph};
+//!
+//! let e = |from, to, cost| Edge { from, to, cost };
+//! let edges = vec![e(0, 1, 0.0), e(1, 2, 0.0), e(0, 2, 0.1)];
+//! let graph = Graph::new(3, edges, 0, 2).unwrap();
+//! let (_, p) = graph.edge_marginals(1.0).unwrap();
+//! let mbr = mbr_path(&graph, &p, |_| LocalLoss::HAMMING).unwrap();
+//! assert_eq!(mbr.edges, [0, 1]);
+//! ```
+
+use crate::lattice::{self, Lattice, LatticeMarginals};
+use crate::soft_shortest_path::{self, Graph};
+
+/// Errors for MBR decoding.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The posteriors do not match the structure (edges, or states of a step).
+    #[error("posteriors have length {len}, expected {expected}")]
+    MarginalLength {
+        /// Length given.
+        len: usize,
+        /// Length expected.
+        expected: usize,
+    },
+    /// The loss or posterior of an edge is not finite.
+    #[error("edge {0} has a non-finite loss or posterior")]
+    InvalidEdgeRisk(usize),
+    /// The loss or posterior of a lattice state is not finite.
+    #[error("state {state} at step {step} has a non-finite loss or posterior")]
+    InvalidStateRisk {
+        /// The step.
+        step: usize,
+        /// The state.
+        state: usize,
+    },
+    /// Error from the soft shortest path.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+    /// Error from the lattice.
+    #[error(transparent)]
+    Lattice(#[from] lattice::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Loss charged for one item (edge or lattice state).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalLoss {
+    /// Charged when the prediction uses the item and the reference does not.
+    pub false_positive: f64,
+    /// Charged when the reference uses the item and the prediction does not.
+    pub false_negative: f64,
+}
+
+impl LocalLoss {
+    /// Hamming loss: the number of items in exactly one of prediction and reference.
+    pub const HAMMING: Self = Self {
+        false_positive: 1.0,
+        false_negative: 1.0,
+    };
+
+    /// Expected loss of choosing an item with posterior `p`, less its constant part.
+    fn risk(self, p: f64) -> f64 {
+        (1.0 - p) * self.false_positive - p * self.false_negative
+    }
+}
+
+/// Result of [`mbr_path`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MbrPath {
+    /// Expected loss of the decoded path.
+    pub expected_loss: f64,
+    /// Indices into [`Graph::edges`] of the decoded path, from source to sink.
+    pub edges: Vec<usize>,
+}
+
+/// Source-to-sink path of `graph` minimizing the expected local loss under the edge
+/// posteriors `marginals` (ties go to the earliest edge).
+pub fn mbr_path<F>(graph: &Graph, marginals: &[f64], loss: F) -> Result<MbrPath>
+where
+    F: Fn(usize) -> LocalLoss,
+{
+    let edges = graph.edges();
+    if marginals.len() != edges.len() {
+        return Err(Error::MarginalLength {
+            len: marginals.len(),
+            expected: edges.len(),
+        });
+    }
+    let mut risk = Vec::with_capacity(edges.len());
+    let mut constant = 0.0;
+    for (k, &p) in marginals.iter().enumerate() {
+        let l = loss(k);
+        let r = l.risk(p);
+        if !(r.is_finite() && p.is_finite()) {
+            return Err(Error::InvalidEdgeRisk(k));
+        }
+        risk.push(r);
+        constant += p * l.false_negative;
+    }
+    let (s, t) = (graph.source(), graph.sink());
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by_key(|&k| edges[k].from);
+    let mut dist = vec![f64::INFINITY; graph.n()];
+    let mut pred = vec![usize::MAX; graph.n()];
+    dist[s] = 0.0;
+    for k in order {
+        let e = edges[k];
+        if e.to > t {
+            continue;
+        }
+        let d = dist[e.from] + risk[k];
+        if d < dist[e.to] {
+            dist[e.to] = d;
+            pred[e.to] = k;
+        }
+    }
+    if !dist[t].is_finite() {
+        return Err(soft_shortest_path::Error::NoPath.into());
+    }
+    let mut path = Vec::new();
+    let mut v = t;
+    while v != s {
+        path.push(pred[v]);
+        v = edges[pred[v]].from;
+    }
+    path.reverse();
+    Ok(MbrPath {
+        expected_loss: dist[t] + constant,
+        edges: path,
+    })
+}
+
+/// `lattice` with every finite cost replaced by the risk of the state it enters.
+struct Risk<'a, L: ?Sized> {
+    lattice: &'a L,
+    risk: Vec<Vec<f64>>,
+}
+
+impl<L: Lattice + ?Sized> Lattice for Risk<'_, L> {
+    fn steps(&self) -> usize {
+        self.lattice.steps()
+    }
+
+    fn states(&self, t: usize) -> usize {
+        self.lattice.states(t)
+    }
+
+    fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+        let next = &self.risk[t + 1];
+        self.lattice.transitions(t, from, &mut |to, cost| {
+            if cost.is_finite() {
+                // Out-of-range targets are passed through for the decoder to report.
+                emit(to, next.get(to).copied().unwrap_or(0.0));
+            } else if cost.is_nan() {
+                emit(to, cost);
+            }
+        });
+    }
+
+    fn initial_cost(&self, s: usize) -> f64 {
+        let c = self.lattice.initial_cost(s);
+        if c.is_finite() {
+            self.risk[0][s]
+        } else {
+            c
+        }
+    }
+
+    fn final_cost(&self, s: usize) -> f64 {
+        let c = self.lattice.final_cost(s);
+        if c.is_finite() {
+            0.0
+        } else {
+            c
+        }
+    }
+}
+
+/// State sequence through `lattice` minimizing the expected local loss under the state
+/// posteriors of `marginals` (from [`lattice::soft_lattice_marginals`]), with `loss(t, s)`
+/// the loss of state `s` at step `t`; returns the expected loss and the states.
+pub fn mbr_lattice<L, F>(
+    lattice: &L,
+    marginals: &LatticeMarginals,
+    loss: F,
+) -> Result<(f64, Vec<usize>)>
+where
+    L: Lattice + ?Sized,
+    F: Fn(usize, usize) -> LocalLoss,
+{
+    if marginals.states.len() != lattice.steps() {
+        return Err(Error::MarginalLength {
+            len: marginals.states.len(),
+            expected: lattice.steps(),
+        });
+    }
+    let mut risk = Vec::with_capacity(lattice.steps());
+    let mut constant = 0.0;
+    for (step, q) in marginals.states.iter().enumerate() {
+        if q.len() != lattice.states(step) {
+            return Err(Error::MarginalLength {
+                len: q.len(),
+                expected: lattice.states(step),
+            });
+        }
+        let mut r = Vec::with_capacity(q.len());
+        for (state, &p) in q.iter().enumerate() {
+            let l = loss(step, state);
+            let v = l.risk(p);
+            if !(v.is_finite() && p.is_finite()) {
+                return Err(Error::InvalidStateRisk { step, state });
+            }
+            r.push(v);
+            constant += p * l.false_negative;
+        }
+        risk.push(r);
+    }
+    let (cost, path) = lattice::lattice_viterbi(&Risk { lattice, risk })?;
+    Ok((cost + constant, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::soft_lattice_marginals;
+    use crate::soft_shortest_path::Edge;
+
+    #[test]
+    fn path_minimizes_expected_hamming_over_all_paths() {
+        let e = |from, to, cost| Edge { from, to, cost };
+        // Two parallel middle edges split the mass of the path through node 1.
+        let edges = vec![e(0, 1, 0.3), e(1, 2, 0.3), e(1, 2, 0.3), e(0, 2, 0.5)];
+        let paths: [&[usize]; 3] = [&[0, 1], &[0, 2], &[3]];
+        let graph = Graph::new(3, edges.clone(), 0, 2).unwrap();
+        let gamma = 1.0;
+        let (_, p) = graph.edge_marginals(gamma).unwrap();
+        let cost = |path: &[usize]| path.iter().map(|&k| edges[k].cost).sum::<f64>();
+        let z: f64 = paths.iter().map(|q| (-cost(q) / gamma).exp()).sum();
+        let hamming = |a: &[usize], b: &[usize]| {
+            (a.iter().filter(|k| !b.contains(k)).count()
+                + b.iter().filter(|k| !a.contains(k)).count()) as f64
+        };
+        let expected = |a: &[usize]| -> f64 {
+            paths
+                .iter()
+                .map(|b| (-cost(b) / gamma).exp() / z * hamming(a, b))
+                .sum()
+        };
+        let mbr = mbr_path(&graph, &p, |_| LocalLoss::HAMMING).unwrap();
+        let best = paths
+            .iter()
+            .map(|a| expected(a))
+            .fold(f64::INFINITY, f64::min);
+        assert!((mbr.expected_loss - best).abs() < 1e-12);
+        assert!((expected(&mbr.edges) - best).abs() < 1e-12);
+        // The Viterbi path is the direct edge, but the shared first edge is more certain.
+        assert_eq!(mbr.edges, [0, 1]);
+
+        assert_eq!(
+            mbr_path(&graph, &p[1..], |_| LocalLoss::HAMMING),
+            Err(Error::MarginalLength {
+                len: 3,
+                expected: 4
+            })
+        );
+    }
+
+    /// Three tags; tag 2 may not follow tag 0.
+    struct Chain;
+
+    impl Lattice for Chain {
+        fn steps(&self) -> usize {
+            3
+        }
+        fn states(&self, _t: usize) -> usize {
+            3
+        }
+        fn transitions(&self, t: usize, from: usize, emit: &mut dyn FnMut(usize, f64)) {
+            for to in 0..3 {
+                let cost = if (from, to) == (0, 2) {
+                    f64::INFINITY
+                } else {
+                    0.1 * ((t + 2 * from + 3 * to) % 4) as f64
+                };
+                emit(to, cost);
+            }
+        }
+        fn initial_cost(&self, s: usize) -> f64 {
+            [0.2, 0.0, 0.5][s]
+        }
+    }
+
+    #[test]
+    fn lattice_matches_brute_force_over_allowed_sequences() {
+        let m = soft_lattice_marginals(&Chain, 0.3).unwrap();
+        // Only false positives: the expected number of mismatched positions.
+        let loss = |_, _| LocalLoss {
+            false_positive: 1.0,
+            false_negative: 0.0,
+        };
+        let (value, path) = mbr_lattice(&Chain, &m, loss).unwrap();
+        let mut best = f64::INFINITY;
+        for code in 0..27 {
+            let seq = [code / 9, code / 3 % 3, code % 3];
+            if seq.windows(2).any(|w| w == [0, 2]) {
+                continue;
+            }
+            let v: f64 = seq
+                .iter()
+                .enumerate()
+                .map(|(t, &s)| 1.0 - m.states[t][s])
+                .sum();
+            best = best.min(v);
+        }
+        assert!((value - best).abs() < 1e-12);
+        let v: f64 = path
+            .iter()
+            .enumerate()
+            .map(|(t, &s)| 1.0 - m.states[t][s])
+            .sum();
+        assert!((v - best).abs() < 1e-12);
+        assert!(!path.windows(2).any(|w| w == [0, 2]));
+    }
+}
@@ -0,0 +1,239 @@
+//! Metric-learning losses over the Soft-DTW divergence.
+//!
+//! Each loss compares sequences through \(D(x, y) = \operatorname{sdtw}_\gamma(x, y) -
+//! \tfrac12\operatorname{sdtw}_\gamma(x, x) - \tfrac12\operatorname{sdtw}_\gamma(y, y)\)
+//! (nonnegative, zero on identical inputs) and returns its value with the gradient w.r.t.
+//! every sequence involved, so an encoder producing the sequences can be trained by
+//! backpropagating them:
+//!
+//! - [`triplet_loss`]: \(\max(0, D(a, p) - D(a, n) + \text{margin})\);
+//! - [`contrastive_loss`]: \(D(x, y)\) for similar pairs, \(\max(0, \text{margin} - D(x, y))\)
+//!   for dissimilar ones;
+//! - [`info_nce_loss`]: cross-entropy of picking the positive among the positive and
+//!   caller-provided negatives, with logits \(-D / \tau\).
+//!
+//! Hinges use the subgradient 0 at the kink, so inactive terms contribute no gradient.
+//!
+//! ```
+//! use structop::metric_learning::triplet_loss;
+//!
+//! let anchor = [0.0, 1.0, 2.0, 1.0];
+//! let positive = [0.0, 1.0, 1.0, 2.0, 1.0];
+//! let negative = [2.0, 1.0, 0.0];
+//! let loss = triplet_loss(&anchor, &positive, &negative, 20.0, 0.1).unwrap();
+//! assert!(loss.value > 0.0);
+//! assert_eq!(loss.grads[2].len(), 3);
+//! ```
+
+use crate::soft_dtw::{self, soft_dtw_divergence_grad};
+
+/// Errors for metric-learning losses.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A margin must be finite and nonnegative; a temperature finite and positive.
+    #[error("{name} is out of range, got {value}")]
+    InvalidParameter {
+        /// Parameter name.
+        name: &'static str,
+        /// The offending value.
+        value: f64,
+    },
+    /// InfoNCE needs at least one negative.
+    #[error("at least one negative is required")]
+    NoNegatives,
+    /// Error from the underlying Soft-DTW.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A loss value and its gradients, one per input sequence in argument order (for
+/// [`info_nce_loss`]: anchor, positive, then each negative).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loss {
+    /// Loss value.
+    pub value: f64,
+    /// Gradient w.r.t. each input sequence.
+    pub grads: Vec<Vec<f64>>,
+}
+
+fn check_margin(margin: f64) -> Result<()> {
+    if !(margin >= 0.0 && margin.is_finite()) {
+        return Err(Error::InvalidParameter {
+            name: "margin",
+            value: margin,
+        });
+    }
+    Ok(())
+}
+
+/// Adds `scale` times `g` into `out`.
+fn axpy(out: &mut [f64], scale: f64, g: &[f64]) {
+    for (o, g) in out.iter_mut().zip(g) {
+        *o += scale * g;
+    }
+}
+
+/// Triplet loss \(\max(0, D(a, p) - D(a, n) + \text{margin})\).
+pub fn triplet_loss(
+    anchor: &[f64],
+    positive: &[f64],
+    negative: &[f64],
+    margin: f64,
+    gamma: f64,
+) -> Result<Loss> {
+    check_margin(margin)?;
+    let (dp, gap, gp) = soft_dtw_divergence_grad(anchor, positive, gamma)?;
+    let (dn, gan, gn) = soft_dtw_divergence_grad(anchor, negative, gamma)?;
+    let value = dp - dn + margin;
+    let mut grads = vec![
+        vec![0.0; anchor.len()],
+        vec![0.0; positive.len()],
+        vec![0.0; negative.len()],
+    ];
+    if value > 0.0 {
+        axpy(&mut grads[0], 1.0, &gap);
+        axpy(&mut grads[0], -1.0, &gan);
+        axpy(&mut grads[1], 1.0, &gp);
+        axpy(&mut grads[2], -1.0, &gn);
+    }
+    Ok(Loss {
+        value: value.max(0.0),
+        grads,
+    })
+}
+
+/// Contrastive loss: \(D(x, y)\) if `similar`, else \(\max(0, \text{margin} - D(x, y))\).
+pub fn contrastive_loss(
+    x: &[f64],
+    y: &[f64],
+    similar: bool,
+    margin: f64,
+    gamma: f64,
+) -> Result<Loss> {
+    check_margin(margin)?;
+    let (d, gx, gy) = soft_dtw_divergence_grad(x, y, gamma)?;
+    let (value, scale) = match similar {
+        true => (d, 1.0),
+        false if margin > d => (margin - d, -1.0),
+        false => (0.0, 0.0),
+    };
+    let grads = [gx, gy]
+        .into_iter()
+        .map(|g| g.into_iter().map(|v| scale * v).collect())
+        .collect();
+    Ok(Loss { value, grads })
+}
+
+/// InfoNCE loss \(-\log\big(e^{-D(a, p)/\tau} / (e^{-D(a, p)/\tau} + \sum_k e^{-D(a,
+/// n_k)/\tau})\big)\) with temperature `tau`.
+pub fn info_nce_loss(
+    anchor: &[f64],
+    positive: &[f64],
+    negatives: &[&[f64]],
+    tau: f64,
+    gamma: f64,
+) -> Result<Loss> {
+    if !(tau > 0.0 && tau.is_finite()) {
+        return Err(Error::InvalidParameter {
+            name: "tau",
+            value: tau,
+        });
+    }
+    if negatives.is_empty() {
+        return Err(Error::NoNegatives);
+    }
+    let others = std::iter::once(positive).chain(negatives.iter().copied());
+    let terms = others
+        .map(|y| soft_dtw_divergence_grad(anchor, y, gamma))
+        .collect::<soft_dtw::Result<Vec<_>>>()?;
+    let logits: Vec<f64> = terms.iter().map(|(d, _, _)| -d / tau).collect();
+    let top = logits.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let lse = top + logits.iter().map(|s| (s - top).exp()).sum::<f64>().ln();
+    let mut grads = vec![vec![0.0; anchor.len()]];
+    for (k, (_, ga, gy)) in terms.iter().enumerate() {
+        // dL/dD_k = -(p_k - [k == 0]) / tau.
+        let p = (logits[k] - lse).exp();
+        let w = -(p - if k == 0 { 1.0 } else { 0.0 }) / tau;
+        axpy(&mut grads[0], w, ga);
+        grads.push(gy.iter().map(|g| w * g).collect());
+    }
+    Ok(Loss {
+        value: lse - logits[0],
+        grads,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    /// Checks `grads[k]` against finite differences of `f` in input `k`.
+    fn check_all(inputs: &[&[f64]], grads: &[Vec<f64>], f: impl Fn(&[Vec<f64>]) -> f64) {
+        for k in 0..inputs.len() {
+            let g = |v: &[f64]| {
+                let mut args: Vec<Vec<f64>> = inputs.iter().map(|s| s.to_vec()).collect();
+                args[k] = v.to_vec();
+                Ok(f(&args))
+            };
+            let report = check_gradient(g, inputs[k], &grads[k], Tolerance::default()).unwrap();
+            assert!(report.passed(), "input {k}: {report:?}");
+        }
+    }
+
+    #[test]
+    fn triplet_and_contrastive_gradients_match_finite_differences() {
+        let (a, p, n) = (
+            [0.0, 1.0, 2.0, 1.0],
+            [0.2, 1.1, 1.6, 1.9, 0.7],
+            [1.5, 0.4, -0.3],
+        );
+        let loss = triplet_loss(&a, &p, &n, 6.0, 0.5).unwrap();
+        assert!(loss.value > 0.0);
+        check_all(&[&a, &p, &n], &loss.grads, |v| {
+            triplet_loss(&v[0], &v[1], &v[2], 6.0, 0.5).unwrap().value
+        });
+        let inactive = triplet_loss(&a, &p, &n, 0.0, 0.5).unwrap();
+        assert_eq!(inactive.value, 0.0);
+        assert!(inactive.grads.iter().flatten().all(|&g| g == 0.0));
+
+        for similar in [true, false] {
+            let loss = contrastive_loss(&a, &n, similar, 20.0, 0.5).unwrap();
+            assert!(loss.value > 0.0);
+            check_all(&[&a, &n], &loss.grads, |v| {
+                contrastive_loss(&v[0], &v[1], similar, 20.0, 0.5)
+                    .unwrap()
+                    .value
+            });
+        }
+        assert_eq!(
+            contrastive_loss(&a, &n, false, -1.0, 0.5),
+            Err(Error::InvalidParameter {
+                name: "margin",
+                value: -1.0
+            })
+        );
+    }
+
+    #[test]
+    fn info_nce_gradients_match_finite_differences() {
+        let a = [0.0, 1.0, 2.0, 1.0];
+        let p = [0.1, 0.9, 2.2, 0.8];
+        let n1 = [2.0, 0.0, 1.0];
+        let n2 = [1.0, 1.0, 1.0, 0.5, 0.0];
+        let loss = info_nce_loss(&a, &p, &[&n1, &n2], 2.0, 0.3).unwrap();
+        assert_eq!(loss.grads.len(), 4);
+        check_all(&[&a, &p, &n1, &n2], &loss.grads, |v| {
+            info_nce_loss(&v[0], &v[1], &[&v[2], &v[3]], 2.0, 0.3)
+                .unwrap()
+                .value
+        });
+        assert_eq!(
+            info_nce_loss(&a, &p, &[], 2.0, 0.3),
+            Err(Error::NoNegatives)
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Memory-mapped cost matrices (feature `mmap`).
+//!
+//! A precomputed `n × m` cost matrix too large to hold in RAM can be stored as raw `f64`
+//! values (row-major, native byte order, optionally after a fixed-size header) and mapped
+//! read-only: [`MappedCost`] checks the length and alignment once and then hands the kernels
+//! a borrowed slice, so pages are read on demand rather than loaded up front. The DP table
+//! and any alignment output are still allocated in memory (see [`crate::resources`]).
+//!
+//! ```no_run
+//! use structop::mmap::MappedCost;
+//! use structop::soft_dtw::SoftDtw;
+//!
+//! // SAFETY: nothing else writes to the file while it is mapped.
+//! let cost = unsafe { MappedCost::open("cost.f64", 0, 200_000, 500) }.unwrap();
+//! let value = cost.soft_dtw(&SoftDtw::new(1.0).band(50)).unwrap();
+//! ```
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::soft_dtw::{self, SoftDtw};
+
+/// Errors for memory-mapped inputs.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// The mapped bytes after the header do not hold exactly `n * m` values.
+    #[error("mapping has {len} bytes after the header, expected {expected}")]
+    LengthMismatch {
+        /// Bytes after the header.
+        len: usize,
+        /// `n * m * 8`.
+        expected: usize,
+    },
+    /// The first value is not aligned for `f64`.
+    #[error("data at offset {offset} is not aligned for f64")]
+    Misaligned {
+        /// Byte offset of the first value.
+        offset: usize,
+    },
+    /// `n * m * 8` (or the header offset plus it) overflows `usize`.
+    #[error("a {n} x {m} cost matrix does not fit in the address space")]
+    DimensionOverflow {
+        /// Rows.
+        n: usize,
+        /// Columns.
+        m: usize,
+    },
+    /// Opening or mapping the file failed.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Error from the Soft-DTW operator applied to the mapping.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A read-only, validated `n × m` row-major `f64` cost matrix backed by a memory map.
+#[derive(Debug)]
+pub struct MappedCost {
+    map: Mmap,
+    offset: usize,
+    n: usize,
+    m: usize,
+}
+
+impl MappedCost {
+    /// Maps the file at `path` read-only, with the values starting `offset` bytes in.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated (by this or any other process) while the
+    /// mapping is alive; see [`memmap2::Mmap::map`].
+    pub unsafe fn open(path: impl AsRef<Path>, offset: usize, n: usize, m: usize) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::from_mmap(Mmap::map(&file)?, offset, n, m)
+    }
+
+    /// Wraps an existing mapping, with the values starting `offset` bytes in.
+    pub fn from_mmap(map: Mmap, offset: usize, n: usize, m: usize) -> Result<Self> {
+        let expected = n
+            .checked_mul(m)
+            .and_then(|len| len.checked_mul(std::mem::size_of::<f64>()))
+            .filter(|&bytes| offset.checked_add(bytes).is_some())
+            .ok_or(Error::DimensionOverflow { n, m })?;
+        let len = map.len().saturating_sub(offset);
+        if offset > map.len() || len != expected {
+            return Err(Error::LengthMismatch { len, expected });
+        }
+        if (map.as_ptr() as usize + offset) % std::mem::align_of::<f64>() != 0 {
+            return Err(Error::Misaligned { offset });
+        }
+        Ok(Self { map, offset, n, m })
+    }
+
+    /// Rows.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Columns.
+    pub fn m(&self) -> usize {
+        self.m
+    }
+
+    /// The values as a row-major slice, borrowed from the mapping.
+    pub fn as_slice(&self) -> &[f64] {
+        let data = &self.map[self.offset..];
+        // SAFETY: `from_mmap` checked that `data` is aligned for `f64` and holds exactly
+        // `n * m` of them, every bit pattern is a valid `f64`, and the mapping is read-only
+        // and outlives the borrow.
+        unsafe { std::slice::from_raw_parts(data.as_ptr().cast::<f64>(), self.n * self.m) }
+    }
+
+    /// [`SoftDtw::compute_cost`] on the mapped matrix.
+    pub fn soft_dtw(&self, op: &SoftDtw) -> Result<f64> {
+        Ok(op.compute_cost(self.as_slice(), self.n, self.m)?)
+    }
+
+    /// [`SoftDtw::alignment`] on the mapped matrix.
+    pub fn alignment(&self, op: &SoftDtw) -> Result<(f64, Vec<f64>)> {
+        Ok(op.alignment(self.as_slice(), self.n, self.m)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn mapped_matrix_matches_in_memory_and_checks_layout() {
+        let (n, m) = (5, 4);
+        let cost: Vec<f64> = (0..n * m).map(|k| ((k * 7) % 5) as f64 * 0.3).collect();
+        let path = std::env::temp_dir().join(format!("structop-mmap-{}.f64", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"HEADER!\n").unwrap();
+        for v in &cost {
+            file.write_all(&v.to_ne_bytes()).unwrap();
+        }
+        drop(file);
+
+        // SAFETY: the test owns the file and does not modify it while mapped.
+        let mapped = unsafe { MappedCost::open(&path, 8, n, m) }.unwrap();
+        assert_eq!(mapped.as_slice(), &cost[..]);
+        let op = SoftDtw::new(0.5);
+        assert_eq!(
+            mapped.alignment(&op).unwrap(),
+            op.alignment(&cost, n, m).unwrap()
+        );
+
+        let map = || unsafe { Mmap::map(&File::open(&path).unwrap()) }.unwrap();
+        assert!(matches!(
+            MappedCost::from_mmap(map(), 8, n, m + 1),
+            Err(Error::LengthMismatch {
+                len: 160,
+                expected: 200
+            })
+        ));
+        assert!(matches!(
+            MappedCost::from_mmap(map(), 4, n, m),
+            Err(Error::LengthMismatch { .. })
+        ));
+        assert!(matches!(
+            MappedCost::from_mmap(map(), 8, usize::MAX, 2),
+            Err(Error::DimensionOverflow { .. })
+        ));
+        drop(mapped);
+        std::fs::remove_file(&path).unwrap();
+
+        // A 4-byte header: the length fits, but the values are not 8-byte aligned.
+        let path = std::env::temp_dir().join(format!("structop-mmap-{}.odd", std::process::id()));
+        let mut file = File::create(&path).unwrap();
+        file.write_all(b"HDR\n").unwrap();
+        for v in &cost {
+            file.write_all(&v.to_ne_bytes()).unwrap();
+        }
+        drop(file);
+        // SAFETY: as above.
+        let odd = unsafe { Mmap::map(&File::open(&path).unwrap()) }.unwrap();
+        assert!(matches!(
+            MappedCost::from_mmap(odd, 4, n, m),
+            Err(Error::Misaligned { offset: 4 })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
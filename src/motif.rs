@@ -0,0 +1,276 @@
+//! Motifs and discords: the most similar window pairs and the most isolated windows.
+//!
+//! Every window `series[k..k + window]` is compared with every other window by (soft) DTW,
+//! using the distance profiles of [`crate::soft_dtw::soft_dtw_profile`] /
+//! [`crate::soft_dtw::dtw_profile`]. Windows that start within the exclusion zone of each
+//! other overlap too much to count (a window trivially matches its own shifts), so:
+//!
+//! - the *matrix profile* of window `i` is its distance to the nearest window starting more
+//!   than `exclusion` positions away;
+//! - *motifs* are the closest window pairs, picked greedily so that no two reported windows
+//!   fall within each other's exclusion zone;
+//! - *discords* are the windows with the largest matrix-profile values, picked the same way.
+//!
+//! The cost is `O(W² · window · |window|)` for `W` windows, so this is meant for series of
+//! moderate length.
+//!
+//! ```
+//! use structop::motif::{Measure, MotifSearch};
+//!
+//! let mut series: Vec<f64> = (0..40).map(|t| (t as f64 * 1.7).sin() * 0.5).collect();
+//! series[5..10].copy_from_slice(&[0.0, 4.0, 8.0, 4.0, 0.0]);
+//! series[25..30].copy_from_slice(&[0.0, 4.0, 8.0, 4.0, 0.0]);
+//! let search = MotifSearch::new(5, Measure::Dtw);
+//! let top = search.motifs(&series, 1).unwrap();
+//! assert_eq!((top[0].first, top[0].second), (5, 25));
+//! ```
+
+use crate::soft_dtw::{self, dtw_profile, soft_dtw_profile, Workspace};
+
+/// Errors for motif search.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Error from the window distances.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Distance between windows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    /// Soft-DTW divergence at smoothing `gamma`. The raw Soft-DTW value is not used: it is
+    /// lower for flat windows (more near-optimal paths), which would rank any two quiet
+    /// stretches above a true repeat; the divergence is zero on identical windows.
+    SoftDtw {
+        /// Smoothing parameter.
+        gamma: f64,
+    },
+    /// Hard DTW.
+    Dtw,
+}
+
+/// A pair of similar windows, by start position (`first < second`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Motif {
+    /// Start of the earlier window.
+    pub first: usize,
+    /// Start of the later window.
+    pub second: usize,
+    /// Distance between the two windows.
+    pub distance: f64,
+}
+
+/// An isolated window, by start position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Discord {
+    /// Start of the window.
+    pub index: usize,
+    /// Distance to its nearest non-overlapping window.
+    pub distance: f64,
+}
+
+/// Nearest non-overlapping window of every window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixProfile {
+    /// Distance to the nearest window (`+inf` if every other window is excluded).
+    pub distances: Vec<f64>,
+    /// Start of the nearest window.
+    pub neighbors: Vec<Option<usize>>,
+}
+
+/// Motif and discord search over the windows of one series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotifSearch {
+    window: usize,
+    exclusion: Option<usize>,
+    measure: Measure,
+}
+
+impl MotifSearch {
+    /// Search over windows of `window` points compared by `measure`, with the default
+    /// exclusion zone `window / 2`.
+    pub fn new(window: usize, measure: Measure) -> Self {
+        Self {
+            window,
+            exclusion: None,
+            measure,
+        }
+    }
+
+    /// Windows starting at most `exclusion` positions apart are not compared, and not both
+    /// reported.
+    pub fn exclusion(mut self, exclusion: usize) -> Self {
+        self.exclusion = Some(exclusion);
+        self
+    }
+
+    fn zone(&self) -> usize {
+        self.exclusion.unwrap_or(self.window / 2)
+    }
+
+    /// Distances between every pair of windows that are far enough apart, as
+    /// `(first, second, distance)` with `second > first + exclusion`.
+    pub fn pairs(&self, series: &[f64]) -> Result<Vec<(usize, usize, f64)>> {
+        let w = self.window;
+        if w == 0 || w > series.len() {
+            return Err(soft_dtw::Error::InvalidWindow {
+                window: w,
+                len: series.len(),
+            }
+            .into());
+        }
+        let count = series.len() - w + 1;
+        let skip = self.zone() + 1;
+        let selfs = match self.measure {
+            Measure::SoftDtw { gamma } => {
+                let mut ws = Workspace::new();
+                let windows = series.windows(w);
+                windows
+                    .map(|x| ws.soft_dtw(x, x, gamma))
+                    .collect::<soft_dtw::Result<_>>()?
+            }
+            Measure::Dtw => vec![0.0; count],
+        };
+        let mut out = Vec::new();
+        for i in 0..count.saturating_sub(skip) {
+            let query = &series[i..i + w];
+            let rest = &series[i + skip..];
+            let profile = match self.measure {
+                Measure::SoftDtw { gamma } => soft_dtw_profile(query, rest, w, gamma)?,
+                Measure::Dtw => dtw_profile(query, rest, w)?,
+            };
+            out.extend(
+                profile
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, d)| (i, i + skip + k, d - 0.5 * (selfs[i] + selfs[i + skip + k]))),
+            );
+        }
+        Ok(out)
+    }
+
+    /// Matrix profile: each window's nearest non-overlapping window.
+    pub fn matrix_profile(&self, series: &[f64]) -> Result<MatrixProfile> {
+        let pairs = self.pairs(series)?;
+        let count = series.len() - self.window + 1;
+        let mut mp = MatrixProfile {
+            distances: vec![f64::INFINITY; count],
+            neighbors: vec![None; count],
+        };
+        for (i, j, d) in pairs {
+            for (a, b) in [(i, j), (j, i)] {
+                if d < mp.distances[a] {
+                    mp.distances[a] = d;
+                    mp.neighbors[a] = Some(b);
+                }
+            }
+        }
+        Ok(mp)
+    }
+
+    /// Up to `k` motifs, closest first.
+    pub fn motifs(&self, series: &[f64], k: usize) -> Result<Vec<Motif>> {
+        let mut pairs = self.pairs(series)?;
+        pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+        let mut taken = Vec::new();
+        let mut out = Vec::new();
+        for (first, second, distance) in pairs {
+            if out.len() == k {
+                break;
+            }
+            if self.clear(&taken, first) && self.clear(&taken, second) {
+                taken.extend([first, second]);
+                out.push(Motif {
+                    first,
+                    second,
+                    distance,
+                });
+            }
+        }
+        Ok(out)
+    }
+
+    /// Up to `k` discords, most isolated first. Windows with no comparable window are
+    /// skipped.
+    pub fn discords(&self, series: &[f64], k: usize) -> Result<Vec<Discord>> {
+        let mp = self.matrix_profile(series)?;
+        let mut order: Vec<usize> = (0..mp.distances.len())
+            .filter(|&i| mp.distances[i].is_finite())
+            .collect();
+        order.sort_by(|&a, &b| mp.distances[b].total_cmp(&mp.distances[a]));
+        let mut taken = Vec::new();
+        let mut out = Vec::new();
+        for index in order {
+            if out.len() == k {
+                break;
+            }
+            if self.clear(&taken, index) {
+                taken.push(index);
+                out.push(Discord {
+                    index,
+                    distance: mp.distances[index],
+                });
+            }
+        }
+        Ok(out)
+    }
+
+    /// Whether window `i` is outside the exclusion zone of every taken window.
+    fn clear(&self, taken: &[usize], i: usize) -> bool {
+        taken.iter().all(|&t| t.abs_diff(i) > self.zone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planted() -> Vec<f64> {
+        let mut series: Vec<f64> = (0..60).map(|t| (t as f64 * 1.7).sin() * 0.5).collect();
+        let pattern = [0.0, 3.0, 6.0, 3.0, 0.0, -3.0];
+        series[5..11].copy_from_slice(&pattern);
+        series[37..43].copy_from_slice(&pattern);
+        series[52] = 20.0;
+        series
+    }
+
+    #[test]
+    fn finds_planted_motif_and_discord() {
+        let series = planted();
+        for measure in [Measure::Dtw, Measure::SoftDtw { gamma: 0.1 }] {
+            let search = MotifSearch::new(6, measure);
+            let motifs = search.motifs(&series, 3).unwrap();
+            assert_eq!((motifs[0].first, motifs[0].second), (5, 37));
+            for (a, b) in motifs.iter().flat_map(|m| [m.first, m.second]).zip(1..) {
+                for c in motifs.iter().flat_map(|m| [m.first, m.second]).skip(b) {
+                    assert!(a.abs_diff(c) > 3, "{motifs:?}");
+                }
+            }
+            let discords = search.discords(&series, 2).unwrap();
+            assert!((47..=52).contains(&discords[0].index), "{discords:?}");
+            assert!(discords[0].index.abs_diff(discords[1].index) > 3);
+        }
+    }
+
+    #[test]
+    fn matrix_profile_respects_the_exclusion_zone() {
+        let series = planted();
+        let search = MotifSearch::new(6, Measure::Dtw).exclusion(10);
+        let mp = search.matrix_profile(&series).unwrap();
+        assert_eq!(mp.distances.len(), 55);
+        for (i, n) in mp.neighbors.iter().enumerate() {
+            assert!(n.unwrap().abs_diff(i) > 10);
+        }
+        assert_eq!(mp.neighbors[5], Some(37));
+        assert_eq!(
+            MotifSearch::new(61, Measure::Dtw).motifs(&series, 1),
+            Err(Error::SoftDtw(soft_dtw::Error::InvalidWindow {
+                window: 61,
+                len: 60
+            }))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! `nalgebra` adapters for the Soft-DTW operators (feature `nalgebra`).
+//!
+//! `nalgebra` matrices are column-major; these adapters read elements through the
+//! matrix's own storage (owned `DMatrix`/`DVector`, or any view such as a column range
+//! or a transposed copy's view), so callers never flatten into row-major buffers.
+//!
+//! Results and errors are those of [`crate::soft_dtw`].
+
+use ::nalgebra::{DMatrix, Dim, Matrix, RawStorage, U1};
+
+use crate::soft_dtw::{alignment_from_table, forward_table, table_len, Error, Result};
+
+fn validate_gamma(gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    Ok(())
+}
+
+fn validate_matrix<R, C, S>(cost: &Matrix<f64, R, C, S>, gamma: f64) -> Result<(usize, usize)>
+where
+    R: Dim,
+    C: Dim,
+    S: RawStorage<f64, R, C>,
+{
+    validate_gamma(gamma)?;
+    let (n, m) = cost.shape();
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    table_len(n, m)?;
+    // Row-major order, so the reported cell matches the slice API.
+    for i in 0..n {
+        for j in 0..m {
+            if cost[(i, j)].is_nan() {
+                return Err(Error::NanCost { i, j });
+            }
+        }
+    }
+    Ok((n, m))
+}
+
+/// Soft-DTW value for two column vectors (e.g. `DVector<f64>` or a column view).
+pub fn soft_dtw<R1, S1, R2, S2>(
+    x: &Matrix<f64, R1, U1, S1>,
+    y: &Matrix<f64, R2, U1, S2>,
+    gamma: f64,
+) -> Result<f64>
+where
+    R1: Dim,
+    R2: Dim,
+    S1: RawStorage<f64, R1, U1>,
+    S2: RawStorage<f64, R2, U1>,
+{
+    validate_gamma(gamma)?;
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let (n, m) = (x.nrows(), y.nrows());
+    table_len(n, m)?;
+    let r = forward_table(n, m, gamma, |i, j| (x[i] - y[j]).powi(2));
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW divergence for two column vectors.
+pub fn soft_dtw_divergence<R1, S1, R2, S2>(
+    x: &Matrix<f64, R1, U1, S1>,
+    y: &Matrix<f64, R2, U1, S2>,
+    gamma: f64,
+) -> Result<f64>
+where
+    R1: Dim,
+    R2: Dim,
+    S1: RawStorage<f64, R1, U1>,
+    S2: RawStorage<f64, R2, U1>,
+{
+    let xy = soft_dtw(x, y, gamma)?;
+    let xx = soft_dtw(x, x, gamma)?;
+    let yy = soft_dtw(y, y, gamma)?;
+    Ok(xy - 0.5 * xx - 0.5 * yy)
+}
+
+/// Soft-DTW value for an `n × m` cost matrix (e.g. `DMatrix<f64>` or a view).
+pub fn soft_dtw_cost<R, C, S>(cost: &Matrix<f64, R, C, S>, gamma: f64) -> Result<f64>
+where
+    R: Dim,
+    C: Dim,
+    S: RawStorage<f64, R, C>,
+{
+    let (n, m) = validate_matrix(cost, gamma)?;
+    let r = forward_table(n, m, gamma, |i, j| cost[(i, j)]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW value and expected alignment matrix for an `n × m` cost matrix.
+///
+/// Same semantics as [`crate::soft_dtw::soft_dtw_alignment`], with the alignment
+/// returned as a `DMatrix` of shape `(n, m)`.
+pub fn soft_dtw_alignment<R, C, S>(
+    cost: &Matrix<f64, R, C, S>,
+    gamma: f64,
+) -> Result<(f64, DMatrix<f64>)>
+where
+    R: Dim,
+    C: Dim,
+    S: RawStorage<f64, R, C>,
+{
+    let (n, m) = validate_matrix(cost, gamma)?;
+    let c = |i: usize, j: usize| cost[(i, j)];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    Ok((r[n * (m + 1) + m], DMatrix::from_row_slice(n, m, &e)))
+}
+
+/// Row-major copy of a matrix, matching the `cost[i*m + j]` layout of the slice APIs.
+///
+/// Useful when a `nalgebra` matrix must be handed to a slice-based operator that has
+/// no adapter here.
+pub fn to_row_major<R, C, S>(mat: &Matrix<f64, R, C, S>) -> Vec<f64>
+where
+    R: Dim,
+    C: Dim,
+    S: RawStorage<f64, R, C>,
+{
+    let (n, m) = mat.shape();
+    let mut out = Vec::with_capacity(n * m);
+    for i in 0..n {
+        for j in 0..m {
+            out.push(mat[(i, j)]);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::nalgebra::{DVector, Matrix2x3};
+
+    #[test]
+    fn column_major_matrix_matches_row_major_slice() {
+        let c = DMatrix::from_row_slice(2, 3, &[0.1, 0.9, 0.4, 0.7, 0.2, 0.8]);
+        let flat = to_row_major(&c);
+        assert_eq!(flat, vec![0.1, 0.9, 0.4, 0.7, 0.2, 0.8]);
+
+        let (v, e) = soft_dtw_alignment(&c, 0.5).unwrap();
+        let (v2, e2) = crate::soft_dtw::soft_dtw_alignment(&flat, 2, 3, 0.5).unwrap();
+        assert!((v - v2).abs() < 1e-15);
+        assert_eq!(e.shape(), (2, 3));
+        assert_eq!(to_row_major(&e), e2);
+        assert!((soft_dtw_cost(&c, 0.5).unwrap() - v).abs() < 1e-15);
+
+        // Statically-sized matrices and views go through the same generic path.
+        let fixed = Matrix2x3::new(0.1, 0.9, 0.4, 0.7, 0.2, 0.8);
+        assert!((soft_dtw_cost(&fixed, 0.5).unwrap() - v).abs() < 1e-15);
+        let view = c.columns(1, 2);
+        let sub = crate::soft_dtw::soft_dtw_cost(&[0.9, 0.4, 0.2, 0.8], 2, 2, 0.5).unwrap();
+        assert!((soft_dtw_cost(&view, 0.5).unwrap() - sub).abs() < 1e-15);
+    }
+
+    #[test]
+    fn dvector_sequences_match_slices() {
+        let x = DVector::from_vec(vec![0.0, 1.0, 2.0, 1.5]);
+        let y = DVector::from_vec(vec![0.5, 1.0, 2.5]);
+        let a = soft_dtw_divergence(&x, &y, 0.3).unwrap();
+        let b = crate::soft_dtw::soft_dtw_divergence(x.as_slice(), y.as_slice(), 0.3).unwrap();
+        assert!((a - b).abs() < 1e-15);
+        let empty = DVector::<f64>::zeros(0);
+        assert_eq!(soft_dtw(&empty, &y, 0.3), Err(Error::EmptyInput));
+    }
+}
@@ -0,0 +1,143 @@
+//! `ndarray` adapters for the Soft-DTW operators (feature `ndarray`).
+//!
+//! These accept `ArrayView1<f64>` sequences and `ArrayView2<f64>` cost matrices directly,
+//! including non-contiguous views (slices with steps, transposes, column selections):
+//! elements are read through the view's strides, so nothing is copied into a flat `Vec`.
+//!
+//! Results and errors are those of [`crate::soft_dtw`].
+
+use ::ndarray::{Array2, ArrayView1, ArrayView2};
+
+use crate::soft_dtw::{alignment_from_table, forward_table, table_len, Error, Result};
+
+fn validate_gamma(gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    Ok(())
+}
+
+fn validate_view2(cost: &ArrayView2<'_, f64>, gamma: f64) -> Result<(usize, usize)> {
+    validate_gamma(gamma)?;
+    let (n, m) = cost.dim();
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    // Broadcast views can have huge shapes without backing memory.
+    table_len(n, m)?;
+    if let Some(((i, j), _)) = cost.indexed_iter().find(|(_, c)| c.is_nan()) {
+        return Err(Error::NanCost { i, j });
+    }
+    Ok((n, m))
+}
+
+/// Soft-DTW value for two 1D sequences given as views.
+pub fn soft_dtw(x: ArrayView1<'_, f64>, y: ArrayView1<'_, f64>, gamma: f64) -> Result<f64> {
+    validate_gamma(gamma)?;
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let (n, m) = (x.len(), y.len());
+    table_len(n, m)?;
+    let r = forward_table(n, m, gamma, |i, j| (x[i] - y[j]).powi(2));
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW divergence for two 1D sequences given as views.
+pub fn soft_dtw_divergence(
+    x: ArrayView1<'_, f64>,
+    y: ArrayView1<'_, f64>,
+    gamma: f64,
+) -> Result<f64> {
+    let xy = soft_dtw(x, y, gamma)?;
+    let xx = soft_dtw(x, x, gamma)?;
+    let yy = soft_dtw(y, y, gamma)?;
+    Ok(xy - 0.5 * xx - 0.5 * yy)
+}
+
+/// Soft-DTW value for an `n × m` cost matrix view.
+pub fn soft_dtw_cost(cost: ArrayView2<'_, f64>, gamma: f64) -> Result<f64> {
+    let (n, m) = validate_view2(&cost, gamma)?;
+    let r = forward_table(n, m, gamma, |i, j| cost[[i, j]]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW value and expected alignment matrix for an `n × m` cost matrix view.
+///
+/// Same semantics as [`crate::soft_dtw::soft_dtw_alignment`], with the alignment
+/// returned as an `Array2` of shape `(n, m)`.
+pub fn soft_dtw_alignment(cost: ArrayView2<'_, f64>, gamma: f64) -> Result<(f64, Array2<f64>)> {
+    let (n, m) = validate_view2(&cost, gamma)?;
+    let c = |i: usize, j: usize| cost[[i, j]];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    let e = Array2::from_shape_vec((n, m), e).expect("alignment has n*m entries");
+    Ok((r[n * (m + 1) + m], e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::ndarray::{array, s};
+
+    #[test]
+    fn strided_views_match_flat_slices() {
+        let xs = array![0.0, 9.0, 1.0, 9.0, 2.0, 9.0, 1.5];
+        let y = array![0.5, 1.0, 2.5];
+        let x = xs.slice(s![..;2]);
+        assert!(x.as_slice().is_none());
+
+        let flat: Vec<f64> = x.iter().cloned().collect();
+        let a = soft_dtw(x, y.view(), 0.3).unwrap();
+        let b = crate::soft_dtw::soft_dtw(&flat, y.as_slice().unwrap(), 0.3).unwrap();
+        assert!((a - b).abs() < 1e-15, "view={} slice={}", a, b);
+    }
+
+    #[test]
+    fn transposed_cost_view_matches_transposed_flat_cost() {
+        let c = array![[0.1, 0.9, 0.4], [0.7, 0.2, 0.8]];
+        let ct = c.t();
+        let flat: Vec<f64> = ct.iter().cloned().collect();
+        let (v, e) = soft_dtw_alignment(ct, 0.5).unwrap();
+        let (v2, e2) = crate::soft_dtw::soft_dtw_alignment(&flat, 3, 2, 0.5).unwrap();
+        assert!((v - v2).abs() < 1e-15);
+        assert_eq!(e.dim(), (3, 2));
+        for (a, b) in e.iter().zip(&e2) {
+            assert!((a - b).abs() < 1e-15);
+        }
+        assert!((soft_dtw_cost(ct, 0.5).unwrap() - v).abs() < 1e-15);
+    }
+
+    #[test]
+    fn empty_views_are_rejected() {
+        let c = Array2::<f64>::zeros((0, 3));
+        assert_eq!(soft_dtw_cost(c.view(), 1.0), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn nan_entries_are_reported_in_logical_order() {
+        let c = array![[0.0, f64::NAN, 1.0], [2.0, 3.0, 4.0]];
+        assert_eq!(
+            soft_dtw_cost(c.view(), 1.0),
+            Err(Error::NanCost { i: 0, j: 1 })
+        );
+        assert_eq!(
+            soft_dtw_alignment(c.t(), 1.0).map(|(v, _)| v),
+            Err(Error::NanCost { i: 1, j: 0 })
+        );
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn oversized_broadcast_views_are_rejected() {
+        let one = Array2::<f64>::zeros((1, 1));
+        let huge = one.broadcast((1 << 31, 1 << 31)).unwrap();
+        assert_eq!(
+            soft_dtw_cost(huge, 1.0),
+            Err(Error::DimensionOverflow {
+                n: 1 << 31,
+                m: 1 << 31
+            })
+        );
+    }
+}
@@ -0,0 +1,754 @@
+//! Exact DTW nearest-neighbor search with cascading lower bounds.
+//!
+//! Most candidates in a DTW nearest-neighbor search are far from the query, and cheap lower
+//! bounds can rule them out without running the DP. This is the UCR-suite recipe
+//! (Rakthanmanon et al. 2012) for equal-length sequences under a Sakoe-Chiba band of radius
+//! `band` (`|i - j| <= band`) with squared differences, [`StepPattern::Symmetric1`]:
+//!
+//! 1. [`lb_kim`]: the first and last points must be matched to each other, `O(1)`;
+//! 2. [`lb_keogh`] of the candidate against the query's [`envelope`], `O(n)`;
+//! 3. reversed LB_Keogh: the query against the candidate's envelope, `O(n)`;
+//! 4. [`dtw_bounded`]: banded DTW that abandons as soon as every cell of a row, plus a
+//!    bound on the rows left (the per-point LB_Keogh terms), reaches the best distance so far.
+//!
+//! A candidate is pruned by the first stage whose bound is at least the best distance so
+//! far, so the result is exactly that of a brute-force search. [`NearestNeighbor`] runs the
+//! cascade and counts what each stage pruned ([`PruneStats`]); [`EnvelopeIndex`] keeps the
+//! envelopes of a corpus for repeated queries.
+//!
+//! ```
+//! use structop::nearest_neighbor::NearestNeighbor;
+//!
+//! let wave = |t: usize| (t as f64 * 0.37).sin() + (t as f64 * 0.05).cos();
+//! let series: Vec<f64> = (0..200).map(wave).collect();
+//! let query = &series[120..140];
+//! let mut nn = NearestNeighbor::new(query, 2).unwrap();
+//! let best = nn.search(series[..100].windows(20)).unwrap().unwrap();
+//! assert!(best.index < 81);
+//! assert_eq!(nn.stats().candidates, 81);
+//! ```
+//!
+//! [`StepPattern::Symmetric1`]: crate::soft_dtw::StepPattern::Symmetric1
+
+use std::collections::VecDeque;
+
+/// Errors for nearest-neighbor search.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Inputs must be non-empty.
+    #[error("inputs must be non-empty")]
+    EmptyInput,
+    /// Sequences must have the query's length.
+    #[error("sequence has length {len}, expected {expected}")]
+    LengthMismatch {
+        /// The provided length.
+        len: usize,
+        /// The query's length.
+        expected: usize,
+    },
+    /// A query band is wider than the band an index's envelopes were built with.
+    #[error("band {band} exceeds the index band {max}")]
+    InvalidBand {
+        /// Requested band.
+        band: usize,
+        /// Band of the index.
+        max: usize,
+    },
+    /// PAA segment count must be between 1 and the sequence length.
+    #[error("{segments} PAA segments do not fit sequences of length {len}")]
+    InvalidSegments {
+        /// Requested segment count.
+        segments: usize,
+        /// Sequence length.
+        len: usize,
+    },
+    /// A sample is NaN or infinite, which would make every bound comparison fail.
+    #[error("sample {index} of sequence {sequence} is not finite: {value}")]
+    NonFinite {
+        /// Offending sequence (0 = the query or `x`, 1 = the candidate or `y`).
+        sequence: usize,
+        /// Index of the sample.
+        index: usize,
+        /// The sample.
+        value: f64,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn check_pair(x: &[f64], y: &[f64]) -> Result<()> {
+    check_lengths(x, y)?;
+    check_finite(x, 0)?;
+    check_finite(y, 1)
+}
+
+fn check_lengths(x: &[f64], y: &[f64]) -> Result<()> {
+    if x.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    if y.len() != x.len() {
+        return Err(Error::LengthMismatch {
+            len: y.len(),
+            expected: x.len(),
+        });
+    }
+    Ok(())
+}
+
+fn check_finite(x: &[f64], sequence: usize) -> Result<()> {
+    match x.iter().position(|v| !v.is_finite()) {
+        Some(index) => Err(Error::NonFinite {
+            sequence,
+            index,
+            value: x[index],
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Keogh envelope `(upper, lower)` of `x`: the running max and min over
+/// `x[i - band..=i + band]` (clipped to the sequence), in `O(n)` (Lemire 2009).
+pub fn envelope(x: &[f64], band: usize) -> (Vec<f64>, Vec<f64>) {
+    let n = x.len();
+    let (mut upper, mut lower) = (Vec::with_capacity(n), Vec::with_capacity(n));
+    let (mut max_q, mut min_q) = (VecDeque::new(), VecDeque::<usize>::new());
+    let mut next = 0;
+    for i in 0..n {
+        let hi = i.saturating_add(band).min(n - 1);
+        while next <= hi {
+            while max_q.back().is_some_and(|&b| x[b] <= x[next]) {
+                max_q.pop_back();
+            }
+            while min_q.back().is_some_and(|&b| x[b] >= x[next]) {
+                min_q.pop_back();
+            }
+            max_q.push_back(next);
+            min_q.push_back(next);
+            next += 1;
+        }
+        let lo = i.saturating_sub(band);
+        while max_q[0] < lo {
+            max_q.pop_front();
+        }
+        while min_q[0] < lo {
+            min_q.pop_front();
+        }
+        upper.push(x[max_q[0]]);
+        lower.push(x[min_q[0]]);
+    }
+    (upper, lower)
+}
+
+/// LB_Kim (first and last points): a lower bound on the DTW of equal-length `x` and `y`
+/// for any band, since every path starts at `(0, 0)` and ends at `(n-1, n-1)`.
+pub fn lb_kim(x: &[f64], y: &[f64]) -> Result<f64> {
+    check_pair(x, y)?;
+    Ok(kim(x, y))
+}
+
+/// [`lb_kim`] on checked inputs.
+fn kim(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len();
+    let first = (x[0] - y[0]).powi(2);
+    if n == 1 {
+        first
+    } else {
+        first + (x[n - 1] - y[n - 1]).powi(2)
+    }
+}
+
+/// Squared distance from `v` to the interval `[lo, hi]`.
+fn outside(v: f64, hi: f64, lo: f64) -> f64 {
+    if v > hi {
+        (v - hi).powi(2)
+    } else if v < lo {
+        (v - lo).powi(2)
+    } else {
+        0.0
+    }
+}
+
+/// LB_Keogh: the squared distance from each point of `x` to the envelope `(upper, lower)`
+/// of `y` (see [`envelope`]), summed. A lower bound on the DTW of `x` and `y` under the band
+/// the envelope was built with; swapping the roles gives the reversed bound.
+pub fn lb_keogh(x: &[f64], upper: &[f64], lower: &[f64]) -> Result<f64> {
+    check_pair(x, upper)?;
+    check_pair(x, lower)?;
+    Ok(x.iter()
+        .zip(upper.iter().zip(lower))
+        .map(|(&v, (&hi, &lo))| outside(v, hi, lo))
+        .sum())
+}
+
+/// Banded DTW of equal-length `x` and `y` that gives up once the distance is known to
+/// reach `bound`: `None` if it does, otherwise the exact DTW value (which is then below
+/// `bound`). `bound = +inf` never abandons.
+pub fn dtw_bounded(x: &[f64], y: &[f64], band: usize, bound: f64) -> Result<Option<f64>> {
+    check_pair(x, y)?;
+    Ok(dtw_cascade(x, y, band, bound, None, &mut Vec::new()))
+}
+
+/// Banded DTW with early abandoning. `tail[k]`, when given, lower-bounds the cost of rows
+/// `k - band..` (per-point LB_Keogh terms summed from position `k`, length `n + 1`);
+/// `rows` is scratch space.
+fn dtw_cascade(
+    x: &[f64],
+    y: &[f64],
+    band: usize,
+    bound: f64,
+    tail: Option<&[f64]>,
+    rows: &mut Vec<f64>,
+) -> Option<f64> {
+    let n = x.len();
+    rows.clear();
+    rows.resize(2 * n, f64::INFINITY);
+    let (mut prev, mut cur) = rows.split_at_mut(n);
+    for i in 0..n {
+        let lo = i.saturating_sub(band);
+        let hi = i.saturating_add(band).min(n - 1);
+        // Row i + 1 reads this row on `lo - 1..=hi + 1`; clear what is left of row i - 1.
+        cur[lo.saturating_sub(1)..=(hi + 1).min(n - 1)].fill(f64::INFINITY);
+        let mut row_min = f64::INFINITY;
+        for j in lo..=hi {
+            let best = if i == 0 && j == 0 {
+                0.0
+            } else {
+                let up = prev[j];
+                let diag = if j > 0 { prev[j - 1] } else { f64::INFINITY };
+                let left = if j > 0 { cur[j - 1] } else { f64::INFINITY };
+                up.min(diag).min(left)
+            };
+            cur[j] = best + (x[i] - y[j]).powi(2);
+            row_min = row_min.min(cur[j]);
+        }
+        let rest = tail.map_or(0.0, |t| t[i.saturating_add(band).saturating_add(1).min(n)]);
+        if row_min + rest >= bound {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    let value = prev[n - 1];
+    (value < bound).then_some(value)
+}
+
+/// A candidate's position in the searched sequence and its DTW distance to the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    /// Position of the candidate in the order it was considered.
+    pub index: usize,
+    /// DTW distance to the query.
+    pub distance: f64,
+}
+
+/// What each stage of the cascade did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PruneStats {
+    /// Candidates considered.
+    pub candidates: usize,
+    /// Pruned by [`lb_kim`].
+    pub kim: usize,
+    /// Pruned by LB_PAA (queries on an [`EnvelopeIndex`] with PAA summaries).
+    pub paa: usize,
+    /// Pruned by LB_Keogh against the query's envelope.
+    pub keogh: usize,
+    /// Pruned by LB_Keogh of the query against the candidate's envelope.
+    pub keogh_reversed: usize,
+    /// DTW computations abandoned early.
+    pub abandoned: usize,
+    /// DTW computations run to the end.
+    pub dtw: usize,
+}
+
+impl PruneStats {
+    /// Fraction of candidates that never needed a full DTW.
+    pub fn pruning_rate(&self) -> f64 {
+        if self.candidates == 0 {
+            return 0.0;
+        }
+        1.0 - self.dtw as f64 / self.candidates as f64
+    }
+}
+
+/// Exact 1-nearest-neighbor query under banded DTW, keeping the best candidate so far
+/// across calls.
+#[derive(Debug, Clone)]
+pub struct NearestNeighbor<'q> {
+    query: &'q [f64],
+    band: usize,
+    upper: Vec<f64>,
+    lower: Vec<f64>,
+    best: Option<Neighbor>,
+    stats: PruneStats,
+    terms: Vec<f64>,
+    tail: Vec<f64>,
+    rows: Vec<f64>,
+}
+
+impl<'q> NearestNeighbor<'q> {
+    /// Query for `query` under a Sakoe-Chiba band of radius `band` (`band >= n - 1` is
+    /// unconstrained DTW).
+    pub fn new(query: &'q [f64], band: usize) -> Result<Self> {
+        if query.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        check_finite(query, 0)?;
+        let (upper, lower) = envelope(query, band);
+        Ok(Self {
+            query,
+            band,
+            upper,
+            lower,
+            best: None,
+            stats: PruneStats::default(),
+            terms: Vec::new(),
+            tail: Vec::new(),
+            rows: Vec::new(),
+        })
+    }
+
+    /// Best candidate so far.
+    pub fn best(&self) -> Option<Neighbor> {
+        self.best
+    }
+
+    /// Pruning statistics so far.
+    pub fn stats(&self) -> PruneStats {
+        self.stats
+    }
+
+    /// Distance to beat: the best distance so far, `+inf` before any candidate.
+    pub fn threshold(&self) -> f64 {
+        self.best.map_or(f64::INFINITY, |b| b.distance)
+    }
+
+    /// Runs the cascade on one candidate, identified by `index`. Returns its DTW distance
+    /// if it is the new best (strictly closer than every earlier candidate).
+    pub fn consider(&mut self, index: usize, candidate: &[f64]) -> Result<Option<f64>> {
+        self.consider_with(index, candidate, None)
+    }
+
+    /// [`consider`](Self::consider) with the candidate's envelope for a band at least
+    /// this query's, if precomputed.
+    fn consider_with(
+        &mut self,
+        index: usize,
+        candidate: &[f64],
+        env: Option<(&[f64], &[f64])>,
+    ) -> Result<Option<f64>> {
+        check_lengths(self.query, candidate)?;
+        // Indexed candidates (with an envelope) were checked on insertion.
+        if env.is_none() {
+            check_finite(candidate, 1)?;
+        }
+        self.stats.candidates += 1;
+        let bound = self.threshold();
+        if kim(self.query, candidate) >= bound {
+            self.stats.kim += 1;
+            return Ok(None);
+        }
+        let n = self.query.len();
+        self.terms.clear();
+        self.terms.extend(
+            candidate
+                .iter()
+                .zip(self.upper.iter().zip(&self.lower))
+                .map(|(&v, (&hi, &lo))| outside(v, hi, lo)),
+        );
+        let keogh: f64 = self.terms.iter().sum();
+        if keogh >= bound {
+            self.stats.keogh += 1;
+            return Ok(None);
+        }
+        let owned;
+        let (upper, lower) = match env {
+            Some(env) => env,
+            None => {
+                owned = envelope(candidate, self.band);
+                (owned.0.as_slice(), owned.1.as_slice())
+            }
+        };
+        let reversed: Vec<f64> = self
+            .query
+            .iter()
+            .zip(upper.iter().zip(lower))
+            .map(|(&v, (&hi, &lo))| outside(v, hi, lo))
+            .collect();
+        let reversed_sum: f64 = reversed.iter().sum();
+        if reversed_sum >= bound {
+            self.stats.keogh_reversed += 1;
+            return Ok(None);
+        }
+        // The tighter bound's per-point terms bound the rows DTW has left.
+        if reversed_sum > keogh {
+            self.terms = reversed;
+        }
+        self.tail.clear();
+        self.tail.resize(n + 1, 0.0);
+        for k in (0..n).rev() {
+            self.tail[k] = self.tail[k + 1] + self.terms[k];
+        }
+        let tail = Some(self.tail.as_slice());
+        match dtw_cascade(
+            self.query,
+            candidate,
+            self.band,
+            bound,
+            tail,
+            &mut self.rows,
+        ) {
+            None => {
+                self.stats.abandoned += 1;
+                Ok(None)
+            }
+            Some(distance) => {
+                self.stats.dtw += 1;
+                self.best = Some(Neighbor { index, distance });
+                Ok(Some(distance))
+            }
+        }
+    }
+
+    /// Considers every candidate in order (indices counting from 0) and returns the best
+    /// one so far.
+    pub fn search<'c, I>(&mut self, candidates: I) -> Result<Option<Neighbor>>
+    where
+        I: IntoIterator<Item = &'c [f64]>,
+    {
+        for (index, c) in candidates.into_iter().enumerate() {
+            self.consider(index, c)?;
+        }
+        Ok(self.best)
+    }
+}
+
+/// `segments` consecutive near-equal parts of `x` (`1 <= segments <= x.len()`).
+pub(crate) fn segments_of(x: &[f64], segments: usize) -> impl Iterator<Item = &[f64]> {
+    let len = x.len();
+    (0..segments).map(move |k| &x[k * len / segments..(k + 1) * len / segments])
+}
+
+/// Corpus of equal-length sequences with precomputed Keogh envelopes, and optionally PAA
+/// means, for repeated nearest-neighbor queries.
+///
+/// The envelopes are built once for the index band; a query may use any band up to it
+/// (a wider envelope still bounds a narrower band, just less tightly). A query ranks the
+/// corpus by a cheap bound — LB_PAA with PAA summaries, [`lb_kim`] otherwise — and runs
+/// the [`NearestNeighbor`] cascade in that order, stopping at the first sequence whose
+/// bound reaches the best distance: every later one is pruned unseen, so most of the
+/// corpus is never touched once a close match is found.
+///
+/// LB_PAA (Keogh 2002) compares the mean of each of `segments` parts of a sequence with the
+/// extremes of the query's envelope over that part, weighted by the part's length; it
+/// lower-bounds LB_Keogh, hence DTW.
+///
+/// ```
+/// use structop::nearest_neighbor::EnvelopeIndex;
+///
+/// let wave = |t: usize| (t as f64 * 0.37).sin() + (t as f64 * 0.05).cos();
+/// let series: Vec<f64> = (0..300).map(wave).collect();
+/// let mut index = EnvelopeIndex::new(16, 4, Some(4)).unwrap();
+/// for w in series[..200].windows(16) {
+///     index.insert(w).unwrap();
+/// }
+/// let results = index.query_batch([&series[250..266], &series[280..296]], 2).unwrap();
+/// let (best, stats) = results[0];
+/// assert!(best.unwrap().index < 185 && stats.dtw < stats.candidates);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvelopeIndex {
+    len: usize,
+    band: usize,
+    segments: Option<usize>,
+    data: Vec<f64>,
+    upper: Vec<f64>,
+    lower: Vec<f64>,
+    paa: Vec<f64>,
+}
+
+impl EnvelopeIndex {
+    /// Empty index for sequences of length `len`, with envelopes for bands up to `band` and
+    /// PAA means over `segments` parts if given.
+    pub fn new(len: usize, band: usize, segments: Option<usize>) -> Result<Self> {
+        if len == 0 {
+            return Err(Error::EmptyInput);
+        }
+        if let Some(segments) = segments {
+            if segments == 0 || segments > len {
+                return Err(Error::InvalidSegments { segments, len });
+            }
+        }
+        Ok(Self {
+            len,
+            band,
+            segments,
+            data: Vec::new(),
+            upper: Vec::new(),
+            lower: Vec::new(),
+            paa: Vec::new(),
+        })
+    }
+
+    /// Number of sequences.
+    pub fn len(&self) -> usize {
+        self.data.len() / self.len
+    }
+
+    /// Whether the index holds no sequence.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Largest query band.
+    pub fn band(&self) -> usize {
+        self.band
+    }
+
+    /// Sequence `id` (as returned by [`insert`](Self::insert)).
+    pub fn get(&self, id: usize) -> &[f64] {
+        &self.data[id * self.len..(id + 1) * self.len]
+    }
+
+    /// Adds a sequence and returns its id (ids count from 0 in insertion order).
+    pub fn insert(&mut self, series: &[f64]) -> Result<usize> {
+        if series.len() != self.len {
+            return Err(Error::LengthMismatch {
+                len: series.len(),
+                expected: self.len,
+            });
+        }
+        check_finite(series, 1)?;
+        let (upper, lower) = envelope(series, self.band);
+        self.data.extend_from_slice(series);
+        self.upper.extend(upper);
+        self.lower.extend(lower);
+        if let Some(segments) = self.segments {
+            self.paa.extend(
+                segments_of(series, segments).map(|s| s.iter().sum::<f64>() / s.len() as f64),
+            );
+        }
+        Ok(self.len() - 1)
+    }
+
+    /// Nearest sequence to `query` under DTW with band `band` (at most the index band), and
+    /// the pruning statistics of the search.
+    pub fn query(&self, query: &[f64], band: usize) -> Result<(Option<Neighbor>, PruneStats)> {
+        if band > self.band {
+            return Err(Error::InvalidBand {
+                band,
+                max: self.band,
+            });
+        }
+        if query.len() != self.len {
+            return Err(Error::LengthMismatch {
+                len: query.len(),
+                expected: self.len,
+            });
+        }
+        let mut nn = NearestNeighbor::new(query, band)?;
+        let keys: Vec<f64> = match self.segments {
+            Some(segments) => {
+                let hull: Vec<(f64, f64, f64)> = segments_of(&nn.upper, segments)
+                    .zip(segments_of(&nn.lower, segments))
+                    .map(|(u, l)| {
+                        let hi = u.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                        let lo = l.iter().copied().fold(f64::INFINITY, f64::min);
+                        (hi, lo, u.len() as f64)
+                    })
+                    .collect();
+                self.paa
+                    .chunks(segments)
+                    .map(|means| {
+                        means
+                            .iter()
+                            .zip(&hull)
+                            .map(|(&v, &(hi, lo, w))| w * outside(v, hi, lo))
+                            .sum()
+                    })
+                    .collect()
+            }
+            None => (0..self.len()).map(|id| kim(query, self.get(id))).collect(),
+        };
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|&a, &b| keys[a].total_cmp(&keys[b]));
+        for (k, &id) in order.iter().enumerate() {
+            if keys[id] >= nn.threshold() {
+                let skipped = order.len() - k;
+                nn.stats.candidates += skipped;
+                match self.segments {
+                    Some(_) => nn.stats.paa += skipped,
+                    None => nn.stats.kim += skipped,
+                }
+                break;
+            }
+            let (lo, hi) = (id * self.len, (id + 1) * self.len);
+            let env = (&self.upper[lo..hi], &self.lower[lo..hi]);
+            nn.consider_with(id, self.get(id), Some(env))?;
+        }
+        Ok((nn.best(), nn.stats()))
+    }
+
+    /// [`query`](Self::query) for each of `queries`, with the same band.
+    pub fn query_batch<'q, I>(
+        &self,
+        queries: I,
+        band: usize,
+    ) -> Result<Vec<(Option<Neighbor>, PruneStats)>>
+    where
+        I: IntoIterator<Item = &'q [f64]>,
+    {
+        queries.into_iter().map(|q| self.query(q, band)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(len: usize) -> Vec<f64> {
+        (0..len)
+            .map(|t| {
+                let t = t as f64;
+                (t * 0.31).sin() + 0.5 * (t * 0.077).cos() + 0.3 * (t * 1.93).sin()
+            })
+            .collect()
+    }
+
+    /// Banded DTW with the full `n × n` table.
+    fn naive_dtw(x: &[f64], y: &[f64], band: usize) -> f64 {
+        let n = x.len();
+        let mut d = vec![f64::INFINITY; (n + 1) * (n + 1)];
+        d[0] = 0.0;
+        for i in 1..=n {
+            for j in 1..=n {
+                if i.abs_diff(j) <= band {
+                    let prev = d[(i - 1) * (n + 1) + j - 1]
+                        .min(d[(i - 1) * (n + 1) + j])
+                        .min(d[i * (n + 1) + j - 1]);
+                    d[i * (n + 1) + j] = prev + (x[i - 1] - y[j - 1]).powi(2);
+                }
+            }
+        }
+        d[(n + 1) * (n + 1) - 1]
+    }
+
+    #[test]
+    fn bounds_are_below_dtw() {
+        let s = series(120);
+        let x = &s[3..19];
+        let (upper, lower) = envelope(x, 3);
+        assert_eq!(upper[0], x[..4].iter().copied().fold(f64::MIN, f64::max));
+        assert_eq!(lower[8], x[5..12].iter().copied().fold(f64::MAX, f64::min));
+        for y in s.windows(16).step_by(7) {
+            let dtw = naive_dtw(x, y, 3);
+            assert!(lb_kim(x, y).unwrap() <= dtw);
+            assert!(lb_keogh(y, &upper, &lower).unwrap() <= dtw + 1e-12);
+            assert_eq!(dtw_bounded(x, y, 3, f64::INFINITY), Ok(Some(dtw)));
+            assert_eq!(dtw_bounded(x, y, 3, dtw), Ok(None));
+        }
+        assert_eq!(
+            lb_kim(x, &s[..3]),
+            Err(Error::LengthMismatch {
+                len: 3,
+                expected: 16
+            })
+        );
+    }
+
+    #[test]
+    fn non_finite_samples_are_rejected() {
+        let s = series(40);
+        let mut bad = s[..8].to_vec();
+        bad[5] = f64::NAN;
+        assert!(matches!(
+            NearestNeighbor::new(&bad, 2),
+            Err(Error::NonFinite {
+                sequence: 0,
+                index: 5,
+                ..
+            })
+        ));
+        let mut nn = NearestNeighbor::new(&s[..8], 2).unwrap();
+        assert!(matches!(
+            nn.consider(0, &bad),
+            Err(Error::NonFinite {
+                sequence: 1,
+                index: 5,
+                ..
+            })
+        ));
+        assert_eq!(nn.stats().candidates, 0);
+        bad[5] = f64::INFINITY;
+        assert_eq!(
+            dtw_bounded(&s[..8], &bad, 2, f64::INFINITY),
+            Err(Error::NonFinite {
+                sequence: 1,
+                index: 5,
+                value: f64::INFINITY
+            })
+        );
+        let mut index = EnvelopeIndex::new(8, 2, None).unwrap();
+        assert!(index.insert(&bad).is_err() && index.is_empty());
+    }
+
+    #[test]
+    fn index_queries_match_the_linear_scan() {
+        let s = series(500);
+        for segments in [None, Some(1), Some(8)] {
+            let mut index = EnvelopeIndex::new(24, 5, segments).unwrap();
+            for w in s[..300].windows(24) {
+                index.insert(w).unwrap();
+            }
+            assert_eq!(index.len(), 277);
+            let queries: Vec<Vec<f64>> = [340, 420, 470]
+                .iter()
+                .map(|&t| s[t..t + 24].iter().map(|v| v * 1.1).collect())
+                .collect();
+            let results = index
+                .query_batch(queries.iter().map(|q| &q[..]), 3)
+                .unwrap();
+            for (q, (best, stats)) in queries.iter().zip(results) {
+                let mut nn = NearestNeighbor::new(q, 3).unwrap();
+                assert_eq!(best, nn.search(s[..300].windows(24)).unwrap());
+                assert_eq!(stats.candidates, 277);
+                assert!(stats.dtw < nn.stats().candidates / 4, "{stats:?}");
+            }
+        }
+        let index = EnvelopeIndex::new(24, 5, None).unwrap();
+        assert_eq!(
+            index.query(&s[..24], 6),
+            Err(Error::InvalidBand { band: 6, max: 5 })
+        );
+        assert_eq!(
+            EnvelopeIndex::new(24, 5, Some(25)),
+            Err(Error::InvalidSegments {
+                segments: 25,
+                len: 24
+            })
+        );
+    }
+
+    #[test]
+    fn cascade_matches_brute_force() {
+        let s = series(400);
+        for band in [0, 2, 31] {
+            let query: Vec<f64> = s[250..282].iter().map(|v| v + 0.05).collect();
+            let mut nn = NearestNeighbor::new(&query, band).unwrap();
+            let best = nn.search(s[..240].windows(32)).unwrap().unwrap();
+            let brute = s[..240]
+                .windows(32)
+                .map(|c| naive_dtw(&query, c, band))
+                .enumerate()
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            assert_eq!(best.index, brute.0);
+            assert!((best.distance - brute.1).abs() < 1e-9);
+
+            let st = nn.stats();
+            assert_eq!(st.candidates, 209);
+            let total = st.kim + st.paa + st.keogh + st.keogh_reversed + st.abandoned + st.dtw;
+            assert_eq!(total, st.candidates);
+            assert!(st.pruning_rate() > 0.5, "{st:?}");
+        }
+    }
+}
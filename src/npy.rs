@@ -0,0 +1,308 @@
+//! NumPy `.npy` / `.npz` export of DP artifacts (feature `npy`).
+//!
+//! Writes arrays in the NumPy format (version 1.0, little-endian, C order) so tables,
+//! alignments and marginals can be loaded with `np.load` and keep their shapes. `.npz`
+//! archives are written uncompressed, one `<name>.npy` member per array.
+//!
+//! ```python
+//! import numpy as np
+//! z = np.load("soft_dtw.npz")
+//! z["alignment"].shape  # (n, m)
+//! ```
+
+use std::io::{self, Seek, Write};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+/// Errors for NumPy export.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// Data length does not match the product of the shape.
+    #[error("data has length {len}, but shape {shape:?} needs {expected}")]
+    ShapeMismatch {
+        /// Provided data length.
+        len: usize,
+        /// Requested shape.
+        shape: Vec<usize>,
+        /// Product of `shape`.
+        expected: usize,
+    },
+    /// Underlying writer (or archive) failure.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Error from the Soft-DTW operator being exported.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+    /// Error from the soft shortest-path operator being exported.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Array payload: `float64` values or `uint64` indices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Data<'a> {
+    /// Written with dtype `<f8`.
+    F64(&'a [f64]),
+    /// Written with dtype `<u8`.
+    U64(&'a [u64]),
+}
+
+impl Data<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Data::F64(d) => d.len(),
+            Data::U64(d) => d.len(),
+        }
+    }
+
+    fn descr(&self) -> &'static str {
+        match self {
+            Data::F64(_) => "<f8",
+            Data::U64(_) => "<u8",
+        }
+    }
+}
+
+impl<'a> From<&'a [f64]> for Data<'a> {
+    fn from(d: &'a [f64]) -> Self {
+        Data::F64(d)
+    }
+}
+
+impl<'a> From<&'a [u64]> for Data<'a> {
+    fn from(d: &'a [u64]) -> Self {
+        Data::U64(d)
+    }
+}
+
+/// A named array for [`write_npz`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NamedArray<'a> {
+    /// Member name (without the `.npy` suffix).
+    pub name: &'a str,
+    /// Values in row-major order.
+    pub data: Data<'a>,
+    /// Array shape; `&[]` for a scalar.
+    pub shape: &'a [usize],
+}
+
+/// Writes one array in `.npy` format.
+pub fn write_npy<'a, W: Write>(mut w: W, data: impl Into<Data<'a>>, shape: &[usize]) -> Result<()> {
+    let data = data.into();
+    let expected: usize = shape.iter().product();
+    if data.len() != expected {
+        return Err(Error::ShapeMismatch {
+            len: data.len(),
+            shape: shape.to_vec(),
+            expected,
+        });
+    }
+
+    let dims = match shape {
+        [d] => format!("{},", d),
+        _ => shape
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}), }}",
+        data.descr(),
+        dims
+    );
+    // Magic (6) + version (2) + header length (2) + header, padded to a multiple of 64
+    // and terminated by a newline.
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    w.write_all(b"\x93NUMPY\x01\x00")?;
+    w.write_all(&(header.len() as u16).to_le_bytes())?;
+    w.write_all(header.as_bytes())?;
+    match data {
+        Data::F64(d) => d.iter().try_for_each(|x| w.write_all(&x.to_le_bytes()))?,
+        Data::U64(d) => d.iter().try_for_each(|x| w.write_all(&x.to_le_bytes()))?,
+    }
+    Ok(())
+}
+
+/// Writes arrays as an uncompressed `.npz` archive and returns the inner writer.
+pub fn write_npz<W: Write + Seek>(w: W, arrays: &[NamedArray<'_>]) -> Result<W> {
+    let mut zip = ZipWriter::new(w);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for a in arrays {
+        zip.start_file(format!("{}.npy", a.name), options)
+            .map_err(io::Error::from)?;
+        write_npy(&mut zip, a.data, a.shape)?;
+    }
+    Ok(zip.finish().map_err(io::Error::from)?)
+}
+
+/// Computes Soft-DTW artifacts for a cost matrix and writes them as `.npz`.
+///
+/// Members: `cost` `(n, m)`, `table` `(n+1, m+1)` (the forward DP table), `alignment`
+/// `(n, m)` (expected alignment) and `value` (scalar).
+pub fn write_soft_dtw_npz<W: Write + Seek>(
+    w: W,
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<W> {
+    let table = soft_dtw::soft_dtw_table(cost, n, m, gamma)?;
+    let value = table[n * (m + 1) + m];
+    let alignment = soft_dtw::alignment_from_table(&table, n, m, gamma, |i, j| cost[i * m + j]);
+    write_npz(
+        w,
+        &[
+            NamedArray {
+                name: "cost",
+                data: cost.into(),
+                shape: &[n, m],
+            },
+            NamedArray {
+                name: "table",
+                data: table.as_slice().into(),
+                shape: &[n + 1, m + 1],
+            },
+            NamedArray {
+                name: "alignment",
+                data: alignment.as_slice().into(),
+                shape: &[n, m],
+            },
+            NamedArray {
+                name: "value",
+                data: Data::F64(&[value]),
+                shape: &[],
+            },
+        ],
+    )
+}
+
+/// Computes soft shortest-path artifacts and writes them as `.npz`.
+///
+/// Members: `edges` `(E, 2)` (`from`, `to` as `uint64`), `cost` `(E,)`, `marginals` `(E,)`
+/// and `value` (scalar).
+pub fn write_soft_shortest_path_npz<W: Write + Seek>(
+    w: W,
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+) -> Result<W> {
+    let (value, marginals) =
+        soft_shortest_path::soft_shortest_path_edge_marginals(n, edges, gamma)?;
+    let endpoints: Vec<u64> = edges
+        .iter()
+        .flat_map(|e| [e.from as u64, e.to as u64])
+        .collect();
+    let costs: Vec<f64> = edges.iter().map(|e| e.cost).collect();
+    let k = edges.len();
+    write_npz(
+        w,
+        &[
+            NamedArray {
+                name: "edges",
+                data: endpoints.as_slice().into(),
+                shape: &[k, 2],
+            },
+            NamedArray {
+                name: "cost",
+                data: costs.as_slice().into(),
+                shape: &[k],
+            },
+            NamedArray {
+                name: "marginals",
+                data: marginals.as_slice().into(),
+                shape: &[k],
+            },
+            NamedArray {
+                name: "value",
+                data: Data::F64(&[value]),
+                shape: &[],
+            },
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    /// Parses a `.npy` buffer back into `(header, payload)`.
+    fn split_npy(buf: &[u8]) -> (String, &[u8]) {
+        assert_eq!(&buf[..8], b"\x93NUMPY\x01\x00");
+        let hlen = u16::from_le_bytes([buf[8], buf[9]]) as usize;
+        assert_eq!((10 + hlen) % 64, 0, "header must be 64-byte aligned");
+        let header = String::from_utf8(buf[10..10 + hlen].to_vec()).unwrap();
+        (header, &buf[10 + hlen..])
+    }
+
+    #[test]
+    fn npy_header_and_payload_round_trip() {
+        let data = [1.0, -2.5, 3.25, 0.0, f64::INFINITY, 6.0];
+        let mut buf = Vec::new();
+        write_npy(&mut buf, &data[..], &[2, 3]).unwrap();
+        let (header, payload) = split_npy(&buf);
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2, 3), }"));
+        assert!(header.ends_with('\n'));
+        let back: Vec<f64> = payload
+            .chunks_exact(8)
+            .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        assert_eq!(back, data);
+
+        let mut buf = Vec::new();
+        write_npy(&mut buf, &[7u64][..], &[1]).unwrap();
+        assert!(split_npy(&buf)
+            .0
+            .contains("'descr': '<u8', 'fortran_order': False, 'shape': (1,)"));
+
+        assert!(matches!(
+            write_npy(Vec::new(), &data[..], &[4]),
+            Err(Error::ShapeMismatch {
+                len: 6,
+                expected: 4,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn soft_dtw_npz_contains_shaped_members() {
+        let cost = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let out = write_soft_dtw_npz(Cursor::new(Vec::new()), &cost, 2, 3, 0.5).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(out.into_inner())).unwrap();
+        assert_eq!(archive.len(), 4);
+        for name in ["alignment.npy", "cost.npy", "table.npy", "value.npy"] {
+            assert!(archive.by_name(name).is_ok(), "missing {}", name);
+        }
+
+        let mut buf = Vec::new();
+        archive
+            .by_name("table.npy")
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        let (header, payload) = split_npy(&buf);
+        assert!(header.contains("'shape': (3, 4)"));
+        let last = f64::from_le_bytes(payload[payload.len() - 8..].try_into().unwrap());
+        assert_eq!(last, soft_dtw::soft_dtw_cost(&cost, 2, 3, 0.5).unwrap());
+
+        let mut buf = Vec::new();
+        archive
+            .by_name("value.npy")
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        assert!(split_npy(&buf).0.contains("'shape': ()"));
+    }
+}
@@ -0,0 +1,1358 @@
+//! Entropic optimal transport between histograms (Cuturi 2013; Benamou et al. 2015).
+//!
+//! Histograms live on finite supports and transport costs are given as dense row-major
+//! cost matrices. For a cost \(C\in\mathbb{R}^{n\times m}\) and marginals \(a,b\), the
+//! entropic problem is
+//! \[
+//! \operatorname{OT}_\varepsilon(a,b) = \min_{P\in U(a,b)} \langle P, C\rangle - \varepsilon H(P),
+//! \]
+//! solved by Sinkhorn scaling \(P=\operatorname{diag}(u)\,K\,\operatorname{diag}(v)\)
+//! with Gibbs kernel \(K=e^{-C/\varepsilon}\).
+//!
+//! The **entropic Wasserstein barycenter** of histograms \(a_1,\dots,a_S\) over a shared
+//! support (shared cost \(C\in\mathbb{R}^{n\times n}\)) with weights \(\lambda_s\),
+//! \[
+//! \min_b \sum_s \lambda_s \operatorname{OT}_\varepsilon(a_s, b),
+//! \]
+//! is computed by iterative Bregman projections: alternating Sinkhorn updates for every
+//! input with a geometric-mean update for \(b\).
+//!
+//! **Partial OT** moves only a given amount of mass \(s \le \min(\|a\|_1, \|b\|_1)\):
+//! the plan's marginals are bounded by \(a\) and \(b\) instead of equal to them, so
+//! outliers on either side can stay unmatched. The entropic problem is solved by Dykstra's
+//! iterated KL projections onto the two capacity constraints and the mass constraint
+//! (Benamou et al. 2015).
+//!
+//! The **barycentric projection** of a plan maps each source point to the plan-weighted
+//! average of the target points, \(T(x_i) = \sum_j P_{ij} y_j / \sum_j P_{ij}\), the
+//! soft assignment registration and domain adaptation consume.
+//!
+//! The **sliced Wasserstein** distance between point clouds averages exact 1D
+//! \(W_2^2\) distances between their projections on caller-provided directions, at
+//! \(O(L\,n \log n)\) for \(L\) directions instead of a full transport problem.
+//!
+//! **Order-preserving OT** (Su & Hua 2017) couples two *sequences*: the Gibbs kernel is
+//! tilted toward the diagonal of the alignment grid, so the plan behaves like a soft
+//! monotone alignment (as in DTW) while still tolerating small local reorderings.
+//!
+//! Notes:
+//! - Iterations are deterministic and stop on a caller-controlled tolerance or
+//!   iteration budget; non-convergence is reported, not treated as an error.
+//! - [`sinkhorn`] is the kernel-space form: for very small \(\varepsilon\) relative to the
+//!   costs, \(K\) underflows. [`sinkhorn_log`] iterates on the dual potentials in the log
+//!   domain instead, with \(\varepsilon\)-scaling warm starts, and reports the final
+//!   marginal violation.
+
+/// Errors for optimal-transport operators.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Entropic regularization \(\varepsilon\) must be positive and finite.
+    #[error("epsilon must be positive and finite, got {0}")]
+    InvalidEpsilon(f64),
+    /// Inputs must be non-empty.
+    #[error("inputs must be non-empty")]
+    EmptyInput,
+    /// Cost matrix shape mismatch.
+    #[error("cost matrix has length {len}, expected {n}*{m}={expected}")]
+    InvalidCostShape {
+        /// The provided `cost` slice length.
+        len: usize,
+        /// Expected row count.
+        n: usize,
+        /// Expected column count.
+        m: usize,
+        /// `n*m`, included explicitly for readability.
+        expected: usize,
+    },
+    /// Cost matrix entries must be finite.
+    #[error("cost matrix entry {idx} is not finite: {value}")]
+    NonFiniteCost {
+        /// Flat index of the offending entry.
+        idx: usize,
+        /// The offending value.
+        value: f64,
+    },
+    /// Histogram length does not match the support size.
+    #[error("histogram {index} has length {len}, expected {expected}")]
+    HistogramLength {
+        /// Index of the offending histogram (0 = `a`, 1 = `b` for two-sided calls).
+        index: usize,
+        /// Its length.
+        len: usize,
+        /// Expected support size.
+        expected: usize,
+    },
+    /// Histogram entries must be finite and nonnegative with positive total mass.
+    #[error("histogram {index} must be finite, nonnegative, with positive mass")]
+    InvalidHistogram {
+        /// Index of the offending histogram.
+        index: usize,
+    },
+    /// Histograms must carry the same total mass.
+    #[error("histogram {index} has mass {mass}, expected {expected}")]
+    MassMismatch {
+        /// Index of the offending histogram.
+        index: usize,
+        /// Its total mass.
+        mass: f64,
+        /// Mass of the first histogram.
+        expected: f64,
+    },
+    /// A regularization parameter is out of range.
+    #[error("parameter {name} is out of range: {value}")]
+    InvalidParameter {
+        /// Parameter name.
+        name: &'static str,
+        /// The offending value.
+        value: f64,
+    },
+    /// Barycentric weights must be finite, nonnegative, one per histogram, and sum to 1.
+    #[error("barycentric weights must be nonnegative, one per histogram, and sum to 1")]
+    InvalidWeights,
+    /// A point cloud is empty, has non-finite coordinates, or its length is not a multiple
+    /// of the dimension.
+    #[error("point cloud {index} must be non-empty and finite, with length a multiple of {dim}")]
+    InvalidPointCloud {
+        /// Index of the offending cloud (0 = `x` or source side, 1 = `y` or targets).
+        index: usize,
+        /// Point dimension.
+        dim: usize,
+    },
+    /// A transport plan has the wrong length or a negative or non-finite entry.
+    #[error("plan must have {n}*{m} finite nonnegative entries")]
+    InvalidPlan {
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+    /// A plan row carries no mass, so its source point has no projection.
+    #[error("plan row {row} has no mass")]
+    ZeroRowMass {
+        /// The row.
+        row: usize,
+    },
+    /// A projection direction is zero or non-finite.
+    #[error("projection direction {index} must be finite and nonzero")]
+    InvalidDirection {
+        /// Index of the offending direction.
+        index: usize,
+    },
+    /// The directions buffer is not a whole number of `dim`-vectors.
+    #[error("directions have length {len}, not a multiple of {dim}")]
+    DirectionLength {
+        /// Length of the buffer.
+        len: usize,
+        /// Point dimension.
+        dim: usize,
+    },
+    /// `n * m` does not fit in `usize`.
+    #[error("dimensions {n}x{m} overflow usize")]
+    DimensionOverflow {
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Stopping controls for Sinkhorn-type iterations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SinkhornOptions {
+    /// Maximum number of iterations.
+    pub max_iter: usize,
+    /// Stop once the L1 change of the monitored quantity drops below this value.
+    pub tol: f64,
+}
+
+impl Default for SinkhornOptions {
+    fn default() -> Self {
+        Self {
+            max_iter: 1000,
+            tol: 1e-9,
+        }
+    }
+}
+
+/// Result of a two-sided Sinkhorn solve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transport {
+    /// Transport plan, row-major `n × m`.
+    pub plan: Vec<f64>,
+    /// Transport cost \(\langle P, C\rangle\) (without the entropy term).
+    pub cost: f64,
+    /// Iterations performed.
+    pub iterations: usize,
+    /// Whether the tolerance was reached within `max_iter`.
+    pub converged: bool,
+}
+
+/// \(\varepsilon\)-scaling schedule for [`sinkhorn_log`].
+///
+/// Solving at a small \(\varepsilon\) from scratch takes many iterations; instead the solve
+/// starts at \(\varepsilon_0 = \max C - \min C\) and multiplies \(\varepsilon\) by `factor`
+/// until it reaches the target, each stage warm-started from the previous potentials.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EpsilonScaling {
+    /// Multiplier between stages, in `(0, 1)`.
+    pub factor: f64,
+    /// Iteration budget of each intermediate stage (0 disables scaling).
+    pub stage_iter: usize,
+}
+
+impl Default for EpsilonScaling {
+    fn default() -> Self {
+        Self {
+            factor: 0.5,
+            stage_iter: 50,
+        }
+    }
+}
+
+/// Result of [`sinkhorn_log`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogTransport {
+    /// Transport plan, row-major `n × m`.
+    pub plan: Vec<f64>,
+    /// Transport cost \(\langle P, C\rangle\) (without the entropy term).
+    pub cost: f64,
+    /// Dual potential \(f\) on the rows (`-inf` where `a` is zero).
+    pub f: Vec<f64>,
+    /// Dual potential \(g\) on the columns (`-inf` where `b` is zero).
+    pub g: Vec<f64>,
+    /// Iterations performed, over all stages.
+    pub iterations: usize,
+    /// L1 violation of the row marginals at exit (the column marginals are exact).
+    pub marginal_violation: f64,
+    /// Whether the final stage reached the tolerance within `max_iter`.
+    pub converged: bool,
+}
+
+/// Result of [`partial_sinkhorn`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialTransport {
+    /// Transport plan, row-major `n × m`, of total mass `mass`.
+    pub plan: Vec<f64>,
+    /// Transport cost \(\langle P, C\rangle\) (without the entropy term).
+    pub cost: f64,
+    /// Mass of `a` left untransported, per source point (`a - P 1`).
+    pub unmatched_a: Vec<f64>,
+    /// Mass of `b` left unreceived, per target point (`b - P^T 1`).
+    pub unmatched_b: Vec<f64>,
+    /// Iterations performed.
+    pub iterations: usize,
+    /// Whether the tolerance was reached within `max_iter`.
+    pub converged: bool,
+}
+
+/// Result of [`barycentric_projection`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarycentricProjection {
+    /// Projected points \(T(x_i)\), row-major `n × dim`.
+    pub points: Vec<f64>,
+    /// Row-normalized plan \(P_{ij} / \sum_j P_{ij}\), row-major `n × m`: the Jacobian
+    /// \(\partial T(x_i) / \partial y_j\) (times the identity).
+    pub weights: Vec<f64>,
+    /// Row masses \(\sum_j P_{ij}\).
+    pub row_mass: Vec<f64>,
+    dim: usize,
+}
+
+impl BarycentricProjection {
+    /// Backpropagates `grad` (w.r.t. [`BarycentricProjection::points`]) to the plan and
+    /// the targets the projection was computed from; returns `(grad_plan, grad_targets)`.
+    ///
+    /// \(\partial L / \partial P_{ij} = \langle g_i, y_j - T(x_i)\rangle / \sum_j P_{ij}\)
+    /// and \(\partial L / \partial y_j = \sum_i g_i P_{ij} / \sum_j P_{ij}\).
+    pub fn vjp(&self, targets: &[f64], grad: &[f64]) -> Result<(Vec<f64>, Vec<f64>)> {
+        let (n, dim) = (self.row_mass.len(), self.dim);
+        let m = self.weights.len() / n;
+        if targets.len() != m * dim {
+            return Err(Error::InvalidPointCloud { index: 1, dim });
+        }
+        if grad.len() != n * dim {
+            return Err(Error::InvalidPointCloud { index: 0, dim });
+        }
+        let mut grad_plan = vec![0.0; n * m];
+        let mut grad_targets = vec![0.0; m * dim];
+        for i in 0..n {
+            let g = &grad[i * dim..(i + 1) * dim];
+            let t = &self.points[i * dim..(i + 1) * dim];
+            for j in 0..m {
+                let y = &targets[j * dim..(j + 1) * dim];
+                let w = self.weights[i * m + j];
+                let dot: f64 = g
+                    .iter()
+                    .zip(y.iter().zip(t))
+                    .map(|(g, (y, t))| g * (y - t))
+                    .sum();
+                grad_plan[i * m + j] = dot / self.row_mass[i];
+                for (o, &gk) in grad_targets[j * dim..(j + 1) * dim].iter_mut().zip(g) {
+                    *o += w * gk;
+                }
+            }
+        }
+        Ok((grad_plan, grad_targets))
+    }
+}
+
+/// Result of [`sliced_wasserstein`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlicedWasserstein {
+    /// Sliced \(W_2^2\): the mean over directions of the 1D squared Wasserstein distance.
+    pub value: f64,
+    /// Gradient w.r.t. the coordinates of `x`, same layout.
+    pub grad_x: Vec<f64>,
+    /// Gradient w.r.t. the coordinates of `y`, same layout.
+    pub grad_y: Vec<f64>,
+}
+
+/// Result of a barycenter solve.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Barycenter {
+    /// Barycenter histogram on the shared support.
+    pub weights: Vec<f64>,
+    /// Iterations performed.
+    pub iterations: usize,
+    /// Whether the tolerance was reached within `max_iter`.
+    pub converged: bool,
+}
+
+fn validate_epsilon(epsilon: f64) -> Result<()> {
+    if epsilon <= 0.0 || !epsilon.is_finite() {
+        return Err(Error::InvalidEpsilon(epsilon));
+    }
+    Ok(())
+}
+
+fn validate_cost(cost: &[f64], n: usize, m: usize) -> Result<()> {
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    let expected = n.checked_mul(m).ok_or(Error::DimensionOverflow { n, m })?;
+    if cost.len() != expected {
+        return Err(Error::InvalidCostShape {
+            len: cost.len(),
+            n,
+            m,
+            expected,
+        });
+    }
+    if let Some((idx, &value)) = cost.iter().enumerate().find(|(_, v)| !v.is_finite()) {
+        return Err(Error::NonFiniteCost { idx, value });
+    }
+    Ok(())
+}
+
+/// Validates a histogram and returns its total mass.
+fn validate_histogram(h: &[f64], index: usize, expected: usize) -> Result<f64> {
+    if h.len() != expected {
+        return Err(Error::HistogramLength {
+            index,
+            len: h.len(),
+            expected,
+        });
+    }
+    if h.iter().any(|&x| !x.is_finite() || x < 0.0) {
+        return Err(Error::InvalidHistogram { index });
+    }
+    let mass: f64 = h.iter().sum();
+    if mass <= 0.0 {
+        return Err(Error::InvalidHistogram { index });
+    }
+    Ok(mass)
+}
+
+fn check_mass(mass: f64, expected: f64, index: usize) -> Result<()> {
+    if (mass - expected).abs() > 1e-9 * expected.max(1.0) {
+        return Err(Error::MassMismatch {
+            index,
+            mass,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+fn gibbs_kernel(cost: &[f64], epsilon: f64) -> Vec<f64> {
+    cost.iter().map(|&c| (-c / epsilon).exp()).collect()
+}
+
+/// `out = K v` for `K` of shape `n × m`.
+fn kv(k: &[f64], v: &[f64], n: usize, m: usize, out: &mut [f64]) {
+    for i in 0..n {
+        out[i] = k[i * m..(i + 1) * m]
+            .iter()
+            .zip(v)
+            .map(|(a, b)| a * b)
+            .sum();
+    }
+}
+
+/// `out = K^T u` for `K` of shape `n × m`.
+fn ktu(k: &[f64], u: &[f64], n: usize, m: usize, out: &mut [f64]) {
+    out.iter_mut().for_each(|x| *x = 0.0);
+    for i in 0..n {
+        let ui = u[i];
+        for (o, &kij) in out.iter_mut().zip(&k[i * m..(i + 1) * m]) {
+            *o += ui * kij;
+        }
+    }
+}
+
+fn safe_div(a: f64, b: f64) -> f64 {
+    if b > 0.0 {
+        a / b
+    } else {
+        0.0
+    }
+}
+
+/// Entropic OT between histograms `a` (length `n`) and `b` (length `m`) via Sinkhorn.
+///
+/// `cost` is row-major `n × m`. Both histograms must carry the same total mass.
+pub fn sinkhorn(
+    a: &[f64],
+    b: &[f64],
+    cost: &[f64],
+    epsilon: f64,
+    opts: SinkhornOptions,
+) -> Result<Transport> {
+    validate_epsilon(epsilon)?;
+    let n = a.len();
+    let m = b.len();
+    validate_cost(cost, n, m)?;
+    let mass_a = validate_histogram(a, 0, n)?;
+    let mass_b = validate_histogram(b, 1, m)?;
+    check_mass(mass_b, mass_a, 1)?;
+
+    let k = gibbs_kernel(cost, epsilon);
+    Ok(scale_kernel(a, b, &k, cost, opts))
+}
+
+/// Sinkhorn scaling of a precomputed (nonnegative) kernel `k` to marginals `a`, `b`.
+fn scale_kernel(a: &[f64], b: &[f64], k: &[f64], cost: &[f64], opts: SinkhornOptions) -> Transport {
+    let n = a.len();
+    let m = b.len();
+    let mut u = vec![1.0; n];
+    let mut v = vec![1.0; m];
+    let mut kv_buf = vec![0.0; n];
+    let mut ktu_buf = vec![0.0; m];
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < opts.max_iter {
+        iterations += 1;
+        kv(k, &v, n, m, &mut kv_buf);
+        for i in 0..n {
+            u[i] = safe_div(a[i], kv_buf[i]);
+        }
+        ktu(k, &u, n, m, &mut ktu_buf);
+        for j in 0..m {
+            v[j] = safe_div(b[j], ktu_buf[j]);
+        }
+        // After the v-update column marginals are exact; monitor the row marginals.
+        kv(k, &v, n, m, &mut kv_buf);
+        let err: f64 = (0..n).map(|i| (u[i] * kv_buf[i] - a[i]).abs()).sum();
+        if err < opts.tol {
+            converged = true;
+            break;
+        }
+    }
+
+    let mut plan = vec![0.0; n * m];
+    let mut total = 0.0;
+    for i in 0..n {
+        for j in 0..m {
+            let p = u[i] * k[i * m + j] * v[j];
+            plan[i * m + j] = p;
+            total += p * cost[i * m + j];
+        }
+    }
+    Transport {
+        plan,
+        cost: total,
+        iterations,
+        converged,
+    }
+}
+
+/// `-eps * log(sum_j exp((h_j - c_j) / eps))`, `+inf` when every term is zero.
+fn soft_c_transform(h: &[f64], c: &[f64], eps: f64) -> f64 {
+    let max = h
+        .iter()
+        .zip(c)
+        .map(|(h, c)| h - c)
+        .fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return f64::INFINITY;
+    }
+    let sum: f64 = h
+        .iter()
+        .zip(c)
+        .map(|(h, c)| ((h - c - max) / eps).exp())
+        .sum();
+    -(max + eps * sum.ln())
+}
+
+/// Entropic OT between histograms `a` and `b` like [`sinkhorn`], with log-domain updates of
+/// the dual potentials (stable for any `epsilon`) and an \(\varepsilon\)-scaling schedule.
+///
+/// `opts` controls the final stage at the target `epsilon`; the tolerance applies to the
+/// L1 violation of the row marginals.
+pub fn sinkhorn_log(
+    a: &[f64],
+    b: &[f64],
+    cost: &[f64],
+    epsilon: f64,
+    scaling: EpsilonScaling,
+    opts: SinkhornOptions,
+) -> Result<LogTransport> {
+    validate_epsilon(epsilon)?;
+    let n = a.len();
+    let m = b.len();
+    validate_cost(cost, n, m)?;
+    let mass_a = validate_histogram(a, 0, n)?;
+    let mass_b = validate_histogram(b, 1, m)?;
+    check_mass(mass_b, mass_a, 1)?;
+    if !(scaling.factor > 0.0 && scaling.factor < 1.0) {
+        return Err(Error::InvalidParameter {
+            name: "factor",
+            value: scaling.factor,
+        });
+    }
+
+    let log_a: Vec<f64> = a.iter().map(|x| x.ln()).collect();
+    let log_b: Vec<f64> = b.iter().map(|x| x.ln()).collect();
+    let mut cost_t = vec![0.0; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            cost_t[j * n + i] = cost[i * m + j];
+        }
+    }
+    let (lo, hi) = cost
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &c| {
+            (lo.min(c), hi.max(c))
+        });
+    let mut eps = if scaling.stage_iter == 0 {
+        epsilon
+    } else {
+        (hi - lo).max(epsilon)
+    };
+
+    let mut f = vec![0.0; n];
+    let mut g = vec![0.0; m];
+    let mut iterations = 0;
+    let mut violation = f64::INFINITY;
+    let mut converged = false;
+    loop {
+        let last = eps <= epsilon;
+        let budget = if last {
+            opts.max_iter
+        } else {
+            scaling.stage_iter
+        };
+        for _ in 0..budget {
+            iterations += 1;
+            for i in 0..n {
+                f[i] = soft_c_transform(&g, &cost[i * m..(i + 1) * m], eps) + eps * log_a[i];
+            }
+            for j in 0..m {
+                g[j] = soft_c_transform(&f, &cost_t[j * n..(j + 1) * n], eps) + eps * log_b[j];
+            }
+            // After the g-update the column marginals are exact; monitor the row marginals.
+            violation = (0..n)
+                .map(|i| {
+                    let row: f64 = (0..m)
+                        .map(|j| ((f[i] + g[j] - cost[i * m + j]) / eps).exp())
+                        .sum();
+                    (row - a[i]).abs()
+                })
+                .sum();
+            if violation < opts.tol {
+                converged = last;
+                break;
+            }
+        }
+        if last {
+            break;
+        }
+        eps = (eps * scaling.factor).max(epsilon);
+    }
+
+    let mut plan = vec![0.0; n * m];
+    let mut total = 0.0;
+    for i in 0..n {
+        for j in 0..m {
+            let p = ((f[i] + g[j] - cost[i * m + j]) / epsilon).exp();
+            plan[i * m + j] = p;
+            total += p * cost[i * m + j];
+        }
+    }
+    Ok(LogTransport {
+        plan,
+        cost: total,
+        f,
+        g,
+        iterations,
+        marginal_violation: violation,
+        converged,
+    })
+}
+
+/// Entropic partial OT moving `mass` between histograms `a` (length `n`) and `b` (length
+/// `m`), which may carry different total masses; `cost` is row-major `n × m`.
+///
+/// Iterates until the L1 change of the plan over a sweep of the three projections falls
+/// below `opts.tol` or `opts.max_iter` is exhausted.
+pub fn partial_sinkhorn(
+    a: &[f64],
+    b: &[f64],
+    cost: &[f64],
+    mass: f64,
+    epsilon: f64,
+    opts: SinkhornOptions,
+) -> Result<PartialTransport> {
+    validate_epsilon(epsilon)?;
+    let n = a.len();
+    let m = b.len();
+    validate_cost(cost, n, m)?;
+    let mass_a = validate_histogram(a, 0, n)?;
+    let mass_b = validate_histogram(b, 1, m)?;
+    if !(mass > 0.0 && mass <= mass_a.min(mass_b) * (1.0 + 1e-12)) {
+        return Err(Error::InvalidParameter {
+            name: "mass",
+            value: mass,
+        });
+    }
+
+    let mut plan = gibbs_kernel(cost, epsilon);
+    // Dykstra corrections of the two inequality projections; each is a row (column) scaling,
+    // so its correction is one factor per row (column). The mass constraint is affine and
+    // needs none.
+    let mut row_fix = vec![1.0; n];
+    let mut col_fix = vec![1.0; m];
+    let mut prev = plan.clone();
+    let mut col = vec![0.0; m];
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < opts.max_iter {
+        iterations += 1;
+        for i in 0..n {
+            let row = &mut plan[i * m..(i + 1) * m];
+            row.iter_mut().for_each(|p| *p *= row_fix[i]);
+            let d = safe_div(a[i], row.iter().sum()).min(1.0);
+            let d = if d > 0.0 || a[i] == 0.0 { d } else { 1.0 };
+            row.iter_mut().for_each(|p| *p *= d);
+            row_fix[i] = if d > 0.0 { 1.0 / d } else { 1.0 };
+        }
+        col.iter_mut().for_each(|c| *c = 0.0);
+        for i in 0..n {
+            for j in 0..m {
+                plan[i * m + j] *= col_fix[j];
+                col[j] += plan[i * m + j];
+            }
+        }
+        for j in 0..m {
+            let d = safe_div(b[j], col[j]).min(1.0);
+            let d = if d > 0.0 || b[j] == 0.0 { d } else { 1.0 };
+            col[j] = d;
+            col_fix[j] = if d > 0.0 { 1.0 / d } else { 1.0 };
+        }
+        for i in 0..n {
+            for j in 0..m {
+                plan[i * m + j] *= col[j];
+            }
+        }
+        let scale = safe_div(mass, plan.iter().sum());
+        plan.iter_mut().for_each(|p| *p *= scale);
+
+        let delta: f64 = plan.iter().zip(&prev).map(|(p, q)| (p - q).abs()).sum();
+        prev.copy_from_slice(&plan);
+        if delta < opts.tol {
+            converged = true;
+            break;
+        }
+    }
+
+    let mut unmatched_a = a.to_vec();
+    let mut unmatched_b = b.to_vec();
+    let mut total = 0.0;
+    for i in 0..n {
+        for j in 0..m {
+            let p = plan[i * m + j];
+            unmatched_a[i] -= p;
+            unmatched_b[j] -= p;
+            total += p * cost[i * m + j];
+        }
+    }
+    Ok(PartialTransport {
+        plan,
+        cost: total,
+        unmatched_a,
+        unmatched_b,
+        iterations,
+        converged,
+    })
+}
+
+/// Barycentric projection of the `n × m` transport `plan` (row-major) onto `targets`
+/// (row-major `m × dim`).
+pub fn barycentric_projection(
+    plan: &[f64],
+    n: usize,
+    m: usize,
+    targets: &[f64],
+    dim: usize,
+) -> Result<BarycentricProjection> {
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    let expected = n.checked_mul(m).ok_or(Error::DimensionOverflow { n, m })?;
+    if plan.len() != expected || plan.iter().any(|&p| !p.is_finite() || p < 0.0) {
+        return Err(Error::InvalidPlan { n, m });
+    }
+    if validate_points(targets, dim, 1)? != m {
+        return Err(Error::InvalidPointCloud { index: 1, dim });
+    }
+    let mut points = vec![0.0; n * dim];
+    let mut weights = vec![0.0; n * m];
+    let mut row_mass = vec![0.0; n];
+    for i in 0..n {
+        let mass: f64 = plan[i * m..(i + 1) * m].iter().sum();
+        if mass <= 0.0 {
+            return Err(Error::ZeroRowMass { row: i });
+        }
+        row_mass[i] = mass;
+        for j in 0..m {
+            let w = plan[i * m + j] / mass;
+            weights[i * m + j] = w;
+            for (o, &y) in points[i * dim..(i + 1) * dim]
+                .iter_mut()
+                .zip(&targets[j * dim..(j + 1) * dim])
+            {
+                *o += w * y;
+            }
+        }
+    }
+    Ok(BarycentricProjection {
+        points,
+        weights,
+        row_mass,
+        dim,
+    })
+}
+
+fn validate_points(points: &[f64], dim: usize, index: usize) -> Result<usize> {
+    if dim == 0 || points.is_empty() || points.len() % dim != 0 {
+        return Err(Error::InvalidPointCloud { index, dim });
+    }
+    if points.iter().any(|v| !v.is_finite()) {
+        return Err(Error::InvalidPointCloud { index, dim });
+    }
+    Ok(points.len() / dim)
+}
+
+/// Sliced \(W_2^2\) between the uniform point clouds `x` and `y` (row-major, `dim`
+/// coordinates per point; sizes may differ), averaged over `directions` (row-major, `dim`
+/// per direction, normalized internally), with gradients w.r.t. both clouds.
+///
+/// Each 1D problem is solved exactly by sorting the projections and matching quantiles;
+/// the gradient holds the sorted matching fixed (it is piecewise constant).
+pub fn sliced_wasserstein(
+    x: &[f64],
+    y: &[f64],
+    dim: usize,
+    directions: &[f64],
+) -> Result<SlicedWasserstein> {
+    let n = validate_points(x, dim, 0)?;
+    let m = validate_points(y, dim, 1)?;
+    if directions.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    if directions.len() % dim != 0 {
+        return Err(Error::DirectionLength {
+            len: directions.len(),
+            dim,
+        });
+    }
+    let count = directions.len() / dim;
+    let mut value = 0.0;
+    let mut grad_x = vec![0.0; x.len()];
+    let mut grad_y = vec![0.0; y.len()];
+    for (l, theta) in directions.chunks(dim).enumerate() {
+        let norm = theta.iter().map(|t| t * t).sum::<f64>().sqrt();
+        if !(norm > 0.0 && norm.is_finite()) {
+            return Err(Error::InvalidDirection { index: l });
+        }
+        let theta: Vec<f64> = theta.iter().map(|t| t / norm).collect();
+        let project = |p: &[f64]| -> Vec<f64> {
+            p.chunks(dim)
+                .map(|q| q.iter().zip(&theta).map(|(a, b)| a * b).sum())
+                .collect()
+        };
+        let (px, py) = (project(x), project(y));
+        let mut ix: Vec<usize> = (0..n).collect();
+        let mut iy: Vec<usize> = (0..m).collect();
+        ix.sort_by(|&a, &b| px[a].total_cmp(&px[b]));
+        iy.sort_by(|&a, &b| py[a].total_cmp(&py[b]));
+        // North-west corner on the sorted points, in integer units of 1 / (n * m): each
+        // source point carries m units and each target point n.
+        let (mut i, mut j) = (0, 0);
+        let (mut left_x, mut left_y) = (m, n);
+        while i < n && j < m {
+            let units = left_x.min(left_y);
+            let w = units as f64 / (n * m) as f64 / count as f64;
+            let (a, b) = (ix[i], iy[j]);
+            let d = px[a] - py[b];
+            value += w * d * d;
+            for (k, t) in theta.iter().enumerate() {
+                grad_x[a * dim + k] += 2.0 * w * d * t;
+                grad_y[b * dim + k] -= 2.0 * w * d * t;
+            }
+            left_x -= units;
+            left_y -= units;
+            if left_x == 0 {
+                i += 1;
+                left_x = m;
+            }
+            if left_y == 0 {
+                j += 1;
+                left_y = n;
+            }
+        }
+    }
+    Ok(SlicedWasserstein {
+        value,
+        grad_x,
+        grad_y,
+    })
+}
+
+/// Regularization parameters for order-preserving OT (Su & Hua 2017).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderPreserving {
+    /// Weight \(\lambda_1\) of the inverse-difference-moment term rewarding couplings
+    /// near the diagonal (larger = stronger preference for local, in-order matches).
+    pub lambda1: f64,
+    /// Entropic weight \(\lambda_2\) toward the diagonal prior (plays the role of \(\varepsilon\)).
+    pub lambda2: f64,
+    /// Width \(\sigma\) of the Gaussian diagonal prior (larger = tolerates more reordering).
+    pub sigma: f64,
+}
+
+impl Default for OrderPreserving {
+    fn default() -> Self {
+        Self {
+            lambda1: 50.0,
+            lambda2: 0.1,
+            sigma: 1.0,
+        }
+    }
+}
+
+/// Order-preserving Wasserstein coupling between two sequences (Su & Hua 2017).
+///
+/// `cost` is the row-major `n × m` ground cost between elements of the two sequences;
+/// both sequences carry uniform mass. The transport kernel is tilted toward monotone
+/// couplings:
+/// \[
+/// K_{ij} = P_{ij}\,\exp\!\Big(\tfrac{1}{\lambda_2}\big(\tfrac{\lambda_1}{(i/n-j/m)^2+1} - C_{ij}\big)\Big),
+/// \]
+/// where \(P\) is a Gaussian prior on the normalized distance of \((i,j)\) to the
+/// diagonal. The returned `cost` is \(\langle T, C\rangle\), the order-preserving
+/// Wasserstein distance. Small reorderings are tolerated at a price controlled by
+/// `sigma` and `lambda1`.
+pub fn order_preserving_ot(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    params: OrderPreserving,
+    opts: SinkhornOptions,
+) -> Result<Transport> {
+    validate_cost(cost, n, m)?;
+    for (name, value) in [
+        ("lambda1", params.lambda1),
+        ("lambda2", params.lambda2),
+        ("sigma", params.sigma),
+    ] {
+        let ok = value.is_finite()
+            && if name == "lambda1" {
+                value >= 0.0
+            } else {
+                value > 0.0
+            };
+        if !ok {
+            return Err(Error::InvalidParameter { name, value });
+        }
+    }
+
+    let nf = n as f64;
+    let mf = m as f64;
+    let diag_scale = (1.0 / (nf * nf) + 1.0 / (mf * mf)).sqrt();
+    let mut k = vec![0.0; n * m];
+    for i in 0..n {
+        for j in 0..m {
+            // Positions normalized to (0, 1].
+            let d = (i + 1) as f64 / nf - (j + 1) as f64 / mf;
+            let l = d.abs() / diag_scale;
+            let log_prior = -l * l / (2.0 * params.sigma * params.sigma);
+            let s = params.lambda1 / (d * d + 1.0);
+            k[i * m + j] = log_prior + (s - cost[i * m + j]) / params.lambda2;
+        }
+    }
+    // Shift by the largest log-entry before exponentiating; this keeps exp in range
+    // without changing the scaled plan.
+    let kmax = k.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    k.iter_mut().for_each(|x| *x = (*x - kmax).exp());
+
+    let a = vec![1.0 / nf; n];
+    let b = vec![1.0 / mf; m];
+    Ok(scale_kernel(&a, &b, &k, cost, opts))
+}
+
+/// Entropic Wasserstein barycenter of `histograms` over a shared support of size `n`.
+///
+/// - `histograms`: each of length `n`, all with the same total mass
+/// - `weights`: barycentric weights \(\lambda_s\) (nonnegative, sum to 1)
+/// - `cost`: shared ground cost, row-major `n × n`
+///
+/// Iterates until the L1 change of the barycenter falls below `opts.tol` or
+/// `opts.max_iter` is exhausted.
+pub fn wasserstein_barycenter(
+    histograms: &[&[f64]],
+    weights: &[f64],
+    cost: &[f64],
+    n: usize,
+    epsilon: f64,
+    opts: SinkhornOptions,
+) -> Result<Barycenter> {
+    validate_epsilon(epsilon)?;
+    if histograms.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    validate_cost(cost, n, n)?;
+    let mut mass = 0.0;
+    for (s, h) in histograms.iter().enumerate() {
+        let ms = validate_histogram(h, s, n)?;
+        if s == 0 {
+            mass = ms;
+        } else {
+            check_mass(ms, mass, s)?;
+        }
+    }
+    if weights.len() != histograms.len()
+        || weights.iter().any(|&w| !w.is_finite() || w < 0.0)
+        || (weights.iter().sum::<f64>() - 1.0).abs() > 1e-9
+    {
+        return Err(Error::InvalidWeights);
+    }
+
+    let k = gibbs_kernel(cost, epsilon);
+    let ns = histograms.len();
+    let mut u = vec![vec![1.0; n]; ns];
+    let mut v = vec![vec![1.0; n]; ns];
+    let mut ktu_all = vec![vec![0.0; n]; ns];
+    let mut buf = vec![0.0; n];
+    let mut bary = vec![mass / n as f64; n];
+
+    let mut iterations = 0;
+    let mut converged = false;
+    while iterations < opts.max_iter {
+        iterations += 1;
+        for s in 0..ns {
+            kv(&k, &v[s], n, n, &mut buf);
+            for i in 0..n {
+                u[s][i] = safe_div(histograms[s][i], buf[i]);
+            }
+            ktu(&k, &u[s], n, n, &mut ktu_all[s]);
+        }
+        // Geometric mean in log space: b = Π_s (Kᵀu_s)^{λ_s}.
+        let mut delta = 0.0;
+        for j in 0..n {
+            let mut log_b = 0.0;
+            let mut zero = false;
+            for s in 0..ns {
+                if weights[s] == 0.0 {
+                    continue;
+                }
+                let x = ktu_all[s][j];
+                if x <= 0.0 {
+                    zero = true;
+                    break;
+                }
+                log_b += weights[s] * x.ln();
+            }
+            let bj = if zero { 0.0 } else { log_b.exp() };
+            delta += (bj - bary[j]).abs();
+            bary[j] = bj;
+        }
+        for s in 0..ns {
+            for j in 0..n {
+                v[s][j] = safe_div(bary[j], ktu_all[s][j]);
+            }
+        }
+        if delta < opts.tol {
+            converged = true;
+            break;
+        }
+    }
+
+    Ok(Barycenter {
+        weights: bary,
+        iterations,
+        converged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn grid_cost(n: usize) -> Vec<f64> {
+        let scale = ((n - 1) * (n - 1)) as f64;
+        let mut c = vec![0.0; n * n];
+        for i in 0..n {
+            for j in 0..n {
+                c[i * n + j] = ((i as f64) - (j as f64)).powi(2) / scale;
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn sinkhorn_plan_has_requested_marginals() {
+        let a = [0.2, 0.5, 0.3];
+        let b = [0.6, 0.4];
+        let cost = [0.0, 1.0, 0.5, 0.2, 1.0, 0.0];
+        let t = sinkhorn(&a, &b, &cost, 0.1, SinkhornOptions::default()).unwrap();
+        assert!(t.converged);
+        for (i, &ai) in a.iter().enumerate() {
+            let r: f64 = t.plan[i * 2..i * 2 + 2].iter().sum();
+            assert!((r - ai).abs() < 1e-8, "row {} sum {}", i, r);
+        }
+        for (j, &bj) in b.iter().enumerate() {
+            let c: f64 = (0..3).map(|i| t.plan[i * 2 + j]).sum();
+            assert!((c - bj).abs() < 1e-8, "col {} sum {}", j, c);
+        }
+    }
+
+    #[test]
+    fn barycenter_of_two_diracs_sits_in_the_middle() {
+        let n = 11;
+        let cost = grid_cost(n);
+        let mut a = vec![0.0; n];
+        let mut b = vec![0.0; n];
+        a[0] = 1.0;
+        b[n - 1] = 1.0;
+        let bar = wasserstein_barycenter(
+            &[&a, &b],
+            &[0.5, 0.5],
+            &cost,
+            n,
+            0.01,
+            SinkhornOptions::default(),
+        )
+        .unwrap();
+        assert!(bar.converged);
+        let mass: f64 = bar.weights.iter().sum();
+        assert!((mass - 1.0).abs() < 1e-6, "mass={}", mass);
+        let mean: f64 = bar
+            .weights
+            .iter()
+            .enumerate()
+            .map(|(i, w)| i as f64 * w)
+            .sum();
+        assert!((mean - 5.0).abs() < 1e-3, "mean={}", mean);
+        let argmax = (0..n)
+            .max_by(|&i, &j| bar.weights[i].total_cmp(&bar.weights[j]))
+            .unwrap();
+        assert_eq!(argmax, 5);
+    }
+
+    #[test]
+    fn barycenter_respects_iteration_budget() {
+        let n = 5;
+        let cost = grid_cost(n);
+        let a = [0.5, 0.5, 0.0, 0.0, 0.0];
+        let b = [0.0, 0.0, 0.0, 0.5, 0.5];
+        let opts = SinkhornOptions {
+            max_iter: 3,
+            tol: 0.0,
+        };
+        let bar = wasserstein_barycenter(&[&a, &b], &[0.3, 0.7], &cost, n, 0.05, opts).unwrap();
+        assert_eq!(bar.iterations, 3);
+        assert!(!bar.converged);
+    }
+
+    #[test]
+    fn rejects_bad_inputs() {
+        let cost = grid_cost(3);
+        let a = [0.5, 0.5, 0.0];
+        let opts = SinkhornOptions::default();
+        assert_eq!(
+            wasserstein_barycenter(&[&a], &[1.0], &cost, 3, 0.0, opts),
+            Err(Error::InvalidEpsilon(0.0))
+        );
+        assert_eq!(
+            wasserstein_barycenter(&[&a, &a], &[0.5, 0.6], &cost, 3, 0.1, opts),
+            Err(Error::InvalidWeights)
+        );
+        let heavy = [1.0, 1.0, 0.0];
+        assert!(matches!(
+            wasserstein_barycenter(&[&a, &heavy], &[0.5, 0.5], &cost, 3, 0.1, opts),
+            Err(Error::MassMismatch { index: 1, .. })
+        ));
+        assert!(matches!(
+            sinkhorn(&a, &a, &cost[..8], 0.1, opts),
+            Err(Error::InvalidCostShape { .. })
+        ));
+    }
+
+    #[test]
+    fn log_sinkhorn_matches_kernel_form_and_survives_small_epsilon() {
+        let a = [0.2, 0.5, 0.3];
+        let b = [0.6, 0.0, 0.4];
+        let cost = [0.0, 1.0, 0.5, 0.2, 1.0, 0.0, 0.7, 0.3, 0.9];
+        let opts = SinkhornOptions::default();
+        let plain = sinkhorn(&a, &b, &cost, 0.1, opts).unwrap();
+        let log = sinkhorn_log(&a, &b, &cost, 0.1, EpsilonScaling::default(), opts).unwrap();
+        assert!(log.converged && log.marginal_violation < 1e-9);
+        assert!(log.g[1] == f64::NEG_INFINITY);
+        for (p, q) in plain.plan.iter().zip(&log.plan) {
+            assert!((p - q).abs() < 1e-8);
+        }
+
+        // At epsilon = 1e-3 the Gibbs kernel underflows to zero off the diagonal of cheap
+        // entries; the log-domain solve still converges to the unregularized plan.
+        let cost = grid_cost(5);
+        let x = [0.1, 0.3, 0.2, 0.3, 0.1];
+        let y = [0.3, 0.1, 0.2, 0.1, 0.3];
+        let t = sinkhorn_log(&x, &y, &cost, 1e-3, EpsilonScaling::default(), opts).unwrap();
+        assert!(t.converged, "{t:?}");
+        for (j, &yj) in y.iter().enumerate() {
+            let col: f64 = (0..5).map(|i| t.plan[i * 5 + j]).sum();
+            assert!((col - yj).abs() < 1e-9);
+        }
+        // Exact OT moves 0.2 mass one step in each half: cost 2 * 0.2 / 16 = 0.025.
+        assert!((t.cost - 0.025).abs() < 1e-3, "cost={}", t.cost);
+        let bad = EpsilonScaling {
+            factor: 1.0,
+            ..EpsilonScaling::default()
+        };
+        assert!(matches!(
+            sinkhorn_log(&x, &y, &cost, 1e-3, bad, opts),
+            Err(Error::InvalidParameter { name: "factor", .. })
+        ));
+    }
+
+    #[test]
+    fn partial_transport_leaves_the_outlier_unmatched() {
+        // Points 0 and 1 of `a` have close partners in `b`; point 2 and the last target are
+        // far from everything.
+        let a = [0.3, 0.3, 0.4];
+        let b = [0.3, 0.3, 0.2];
+        let cost = [0.0, 1.0, 5.0, 1.0, 0.0, 5.0, 5.0, 5.0, 9.0];
+        let t = partial_sinkhorn(&a, &b, &cost, 0.6, 0.05, SinkhornOptions::default()).unwrap();
+        assert!(t.converged);
+        assert!((t.plan.iter().sum::<f64>() - 0.6).abs() < 1e-12);
+        assert!(t
+            .unmatched_a
+            .iter()
+            .chain(&t.unmatched_b)
+            .all(|&u| u > -1e-9));
+        assert!((t.unmatched_a[2] - 0.4).abs() < 1e-3, "{:?}", t.unmatched_a);
+        assert!((t.unmatched_b[2] - 0.2).abs() < 1e-3, "{:?}", t.unmatched_b);
+        assert!(t.cost < 0.01, "cost={}", t.cost);
+
+        // Moving all the mass of balanced histograms is plain Sinkhorn.
+        let full = partial_sinkhorn(&b, &b, &cost, 0.8, 0.5, SinkhornOptions::default()).unwrap();
+        let plain = sinkhorn(&b, &b, &cost, 0.5, SinkhornOptions::default()).unwrap();
+        for (p, q) in full.plan.iter().zip(&plain.plan) {
+            assert!((p - q).abs() < 1e-6);
+        }
+        assert_eq!(
+            partial_sinkhorn(&a, &b, &cost, 0.9, 0.05, SinkhornOptions::default()),
+            Err(Error::InvalidParameter {
+                name: "mass",
+                value: 0.9
+            })
+        );
+    }
+
+    #[test]
+    fn barycentric_projection_averages_targets_and_backpropagates() {
+        use crate::gradcheck::{check_gradient, Tolerance};
+
+        let plan = [0.3, 0.1, 0.05, 0.1, 0.2, 0.3];
+        let targets = [0.0, 0.0, 1.0, 2.0, -1.0, 4.0];
+        let proj = barycentric_projection(&plan, 2, 3, &targets, 2).unwrap();
+        assert!((proj.points[0] - 1.0 / 9.0).abs() < 1e-12);
+        assert!((proj.points[3] - 8.0 / 3.0).abs() < 1e-12);
+
+        // Scalar loss: <g, T>, checked through the plan and the targets.
+        let g = [1.0, -2.0, 0.5, 3.0];
+        let loss = |p: &[f64], y: &[f64]| {
+            let t = barycentric_projection(p, 2, 3, y, 2).unwrap().points;
+            Ok(t.iter().zip(&g).map(|(t, g)| t * g).sum())
+        };
+        let (grad_plan, grad_targets) = proj.vjp(&targets, &g).unwrap();
+        let report = check_gradient(
+            |p| loss(p, &targets),
+            &plan,
+            &grad_plan,
+            Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{report:?}");
+        let report = check_gradient(
+            |y| loss(&plan, y),
+            &targets,
+            &grad_targets,
+            Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(
+            barycentric_projection(&[0.0, 0.0, 1.0, 1.0], 2, 2, &targets[..4], 2),
+            Err(Error::ZeroRowMass { row: 0 })
+        );
+    }
+
+    #[test]
+    fn sliced_wasserstein_matches_1d_transport_and_finite_differences() {
+        use crate::gradcheck::{check_gradient, Tolerance};
+
+        // On the first axis alone this is 1D OT between {0, 1} and {0.5, 2, 3}: quantile
+        // matching pairs 0 with 0.5 (1/3), 0 with 2 (1/6), 1 with 2 (1/6), 1 with 3 (1/3).
+        let x = [0.0, 5.0, 1.0, -1.0];
+        let y = [2.0, 0.0, 0.5, 7.0, 3.0, 1.0];
+        let sw = sliced_wasserstein(&x, &y, 2, &[2.0, 0.0]).unwrap();
+        let want = 0.25 / 3.0 + 4.0 / 6.0 + 1.0 / 6.0 + 4.0 / 3.0;
+        assert!((sw.value - want).abs() < 1e-12);
+        assert_eq!((sw.grad_x[1], sw.grad_y[1]), (0.0, 0.0));
+
+        let dirs = [1.0, 0.0, 0.6, 0.8, -0.3, 1.0];
+        let sw = sliced_wasserstein(&x, &y, 2, &dirs).unwrap();
+        let f = |p: &[f64]| Ok(sliced_wasserstein(p, &y, 2, &dirs).unwrap().value);
+        let report = check_gradient(f, &x, &sw.grad_x, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+        let f = |p: &[f64]| Ok(sliced_wasserstein(&x, p, 2, &dirs).unwrap().value);
+        let report = check_gradient(f, &y, &sw.grad_y, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(
+            sliced_wasserstein(&x, &y, 2, &[0.0, 0.0]),
+            Err(Error::InvalidDirection { index: 0 })
+        );
+        assert_eq!(
+            sliced_wasserstein(&x, &y, 2, &dirs[..5]),
+            Err(Error::DirectionLength { len: 5, dim: 2 })
+        );
+        assert_eq!(sliced_wasserstein(&x, &y, 2, &[]), Err(Error::EmptyInput));
+        assert_eq!(
+            sliced_wasserstein(&x[..3], &y, 2, &dirs),
+            Err(Error::InvalidPointCloud { index: 0, dim: 2 })
+        );
+    }
+
+    fn sq_cost(x: &[f64], y: &[f64]) -> Vec<f64> {
+        x.iter()
+            .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+            .collect()
+    }
+
+    #[test]
+    fn order_preserving_plan_has_uniform_marginals_and_follows_diagonal() {
+        let x = [0.0, 0.5, 1.0, 0.5, 0.0, -0.5];
+        let cost = sq_cost(&x, &x);
+        let t = order_preserving_ot(
+            &cost,
+            6,
+            6,
+            OrderPreserving::default(),
+            SinkhornOptions::default(),
+        )
+        .unwrap();
+        assert!(t.converged);
+        for i in 0..6 {
+            let r: f64 = t.plan[i * 6..(i + 1) * 6].iter().sum();
+            assert!((r - 1.0 / 6.0).abs() < 1e-8, "row {} sum {}", i, r);
+            // x has repeated values (0.5 at 1 and 3); the temporal prior must still prefer
+            // the in-order match.
+            let argmax = (0..6)
+                .max_by(|&a, &b| t.plan[i * 6 + a].total_cmp(&t.plan[i * 6 + b]))
+                .unwrap();
+            assert_eq!(argmax, i);
+        }
+    }
+
+    #[test]
+    fn order_preserving_penalizes_reversal_more_than_plain_ot() {
+        let x = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let y: Vec<f64> = x.iter().rev().cloned().collect();
+        let cost = sq_cost(&x, &y);
+        let u = [0.2; 5];
+        let opts = SinkhornOptions::default();
+        let plain = sinkhorn(&u, &u, &cost, 0.1, opts).unwrap();
+        let ordered = order_preserving_ot(&cost, 5, 5, OrderPreserving::default(), opts).unwrap();
+        // Plain OT can undo the reversal for free; the temporal prior cannot.
+        assert!(plain.cost < 1e-3, "plain={}", plain.cost);
+        assert!(ordered.cost > 1.0, "ordered={}", ordered.cost);
+
+        // Weakening the temporal regularization moves back toward plain OT.
+        let loose = OrderPreserving {
+            lambda1: 0.0,
+            sigma: 100.0,
+            ..OrderPreserving::default()
+        };
+        let relaxed = order_preserving_ot(&cost, 5, 5, loose, opts).unwrap();
+        assert!(
+            relaxed.cost < ordered.cost,
+            "relaxed={} ordered={}",
+            relaxed.cost,
+            ordered.cost
+        );
+    }
+
+    #[test]
+    fn order_preserving_rejects_bad_parameters() {
+        let cost = [0.0; 4];
+        let bad = OrderPreserving {
+            sigma: 0.0,
+            ..OrderPreserving::default()
+        };
+        assert_eq!(
+            order_preserving_ot(&cost, 2, 2, bad, SinkhornOptions::default()),
+            Err(Error::InvalidParameter {
+                name: "sigma",
+                value: 0.0
+            })
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn barycenter_is_a_probability_vector(
+            a in prop::collection::vec(0.01f64..1.0, 6),
+            b in prop::collection::vec(0.01f64..1.0, 6),
+            w in 0.0f64..1.0,
+        ) {
+            let sa: f64 = a.iter().sum();
+            let sb: f64 = b.iter().sum();
+            let a: Vec<f64> = a.iter().map(|x| x / sa).collect();
+            let b: Vec<f64> = b.iter().map(|x| x / sb).collect();
+            let cost = grid_cost(6);
+            let bar = wasserstein_barycenter(
+                &[&a, &b], &[w, 1.0 - w], &cost, 6, 0.1, SinkhornOptions::default(),
+            ).unwrap();
+            let mass: f64 = bar.weights.iter().sum();
+            prop_assert!(bar.weights.iter().all(|&x| x >= 0.0));
+            prop_assert!((mass - 1.0).abs() < 1e-5, "mass={}", mass);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Parallel batch APIs on a caller-provided rayon pool (feature `rayon`).
+//!
+//! Each function takes the `rayon::ThreadPool` to run on instead of using the global pool,
+//! so a library embedding structop decides how many threads it uses and does not contend
+//! with other users of the global pool. Results are identical to the sequential versions
+//! in [`crate::soft_dtw`], including which error is reported (the first invalid pair).
+//!
+//! ```
+//! let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+//! let xs: [&[f64]; 2] = [&[0.0, 1.0], &[1.0]];
+//! let ys: [&[f64]; 2] = [&[0.0], &[1.0, 2.0]];
+//! let v = structop::parallel::soft_dtw_batch(&pool, &xs, &ys, 1.0).unwrap();
+//! assert_eq!(v, structop::soft_dtw_batch(&xs, &ys, 1.0).unwrap());
+//! ```
+
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::soft_dtw::{self, Error, Result};
+
+fn par_pairs<F>(pool: &ThreadPool, xs: &[&[f64]], ys: &[&[f64]], f: F) -> Result<Vec<f64>>
+where
+    F: Fn(&[f64], &[f64]) -> Result<f64> + Sync,
+{
+    if xs.len() != ys.len() {
+        return Err(Error::BatchLengthMismatch {
+            xs: xs.len(),
+            ys: ys.len(),
+        });
+    }
+    // Collect every outcome, then take the first error in input order (not whichever
+    // thread failed first) so error reporting is deterministic.
+    let out: Vec<Result<f64>> =
+        pool.install(|| xs.par_iter().zip(ys).map(|(x, y)| f(x, y)).collect());
+    out.into_iter().collect()
+}
+
+/// Soft-DTW values for a batch of sequence pairs, computed on `pool`.
+pub fn soft_dtw_batch(
+    pool: &ThreadPool,
+    xs: &[&[f64]],
+    ys: &[&[f64]],
+    gamma: f64,
+) -> Result<Vec<f64>> {
+    par_pairs(pool, xs, ys, |x, y| soft_dtw::soft_dtw(x, y, gamma))
+}
+
+/// Soft-DTW divergences for a batch of sequence pairs, computed on `pool`.
+pub fn soft_dtw_divergence_batch(
+    pool: &ThreadPool,
+    xs: &[&[f64]],
+    ys: &[&[f64]],
+    gamma: f64,
+) -> Result<Vec<f64>> {
+    par_pairs(pool, xs, ys, |x, y| {
+        soft_dtw::soft_dtw_divergence(x, y, gamma)
+    })
+}
+
+/// Values of a configured [`soft_dtw::SoftDtw`] operator for a batch of sequence pairs,
+/// computed on `pool`.
+pub fn soft_dtw_batch_with(
+    pool: &ThreadPool,
+    op: &soft_dtw::SoftDtw,
+    xs: &[&[f64]],
+    ys: &[&[f64]],
+) -> Result<Vec<f64>> {
+    par_pairs(pool, xs, ys, |x, y| op.compute(x, y))
+}
+
+/// [`soft_dtw::soft_dtw_pdist`] computed on `pool`, one tile of pairs per task.
+pub fn soft_dtw_pdist(pool: &ThreadPool, xs: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    soft_dtw::validate_pdist(xs, gamma)?;
+    let n = xs.len();
+    // Errors are taken in tile order, as in the sequential version.
+    let tiles: Vec<Result<Vec<(usize, f64)>>> = pool.install(|| {
+        soft_dtw::pdist_tiles(n)
+            .into_par_iter()
+            .map_init(soft_dtw::Workspace::new, |ws, tile| {
+                soft_dtw::pdist_tile(ws, xs, tile, gamma)
+            })
+            .collect()
+    });
+    let mut out = vec![0.0; n * n.saturating_sub(1) / 2];
+    for tile in tiles {
+        for (k, v) in tile? {
+            out[k] = v;
+        }
+    }
+    Ok(out)
+}
+
+/// [`soft_dtw::soft_dtw_divergence_pdist`] computed on `pool`; each self-term is computed
+/// once.
+pub fn soft_dtw_divergence_pdist(pool: &ThreadPool, xs: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    let mut d = soft_dtw_pdist(pool, xs, gamma)?;
+    let selfs: Vec<f64> = par_pairs(pool, xs, xs, |x, y| soft_dtw::soft_dtw(x, y, gamma))?;
+    soft_dtw::apply_self_terms(&mut d, &selfs);
+    Ok(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::ThreadPoolBuilder;
+
+    #[test]
+    fn runs_on_the_given_pool_and_matches_sequential() {
+        let pool = ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+        let xs: Vec<Vec<f64>> = (0..20)
+            .map(|k| (0..5 + k % 4).map(|i| ((i * k) % 7) as f64 / 3.0).collect())
+            .collect();
+        let ys: Vec<Vec<f64>> = xs.iter().rev().cloned().collect();
+        let xs: Vec<&[f64]> = xs.iter().map(Vec::as_slice).collect();
+        let ys: Vec<&[f64]> = ys.iter().map(Vec::as_slice).collect();
+
+        assert_eq!(
+            soft_dtw_batch(&pool, &xs, &ys, 0.5).unwrap(),
+            soft_dtw::soft_dtw_batch(&xs, &ys, 0.5).unwrap()
+        );
+        assert_eq!(
+            soft_dtw_divergence_batch(&pool, &xs, &ys, 0.5).unwrap(),
+            soft_dtw::soft_dtw_divergence_batch(&xs, &ys, 0.5).unwrap()
+        );
+        let op = soft_dtw::SoftDtw::new(0.5).normalized(true);
+        let expected: Vec<f64> = xs
+            .iter()
+            .zip(&ys)
+            .map(|(x, y)| op.compute(x, y).unwrap())
+            .collect();
+        assert_eq!(soft_dtw_batch_with(&pool, &op, &xs, &ys).unwrap(), expected);
+
+        let threads = pool.install(|| {
+            xs.par_iter()
+                .map(|_| rayon::current_thread_index())
+                .collect::<Vec<_>>()
+        });
+        assert!(threads.iter().all(|t| t.is_some_and(|t| t < 3)));
+    }
+
+    #[test]
+    fn pdist_matches_sequential() {
+        let pool = ThreadPoolBuilder::new().num_threads(3).build().unwrap();
+        let seqs: Vec<Vec<f64>> = (0..70)
+            .map(|k| (0..3 + k % 4).map(|i| ((i * k) % 5) as f64 / 2.0).collect())
+            .collect();
+        let xs: Vec<&[f64]> = seqs.iter().map(Vec::as_slice).collect();
+        assert_eq!(
+            soft_dtw_pdist(&pool, &xs, 0.5).unwrap(),
+            soft_dtw::soft_dtw_pdist(&xs, 0.5).unwrap()
+        );
+        assert_eq!(
+            soft_dtw_divergence_pdist(&pool, &xs, 0.5).unwrap(),
+            soft_dtw::soft_dtw_divergence_pdist(&xs, 0.5).unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_the_first_invalid_pair() {
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let xs: [&[f64]; 3] = [&[1.0], &[], &[1.0]];
+        let ys: [&[f64]; 3] = [&[1.0], &[1.0], &[1.0]];
+        assert_eq!(soft_dtw_batch(&pool, &xs, &ys, 1.0), Err(Error::EmptyInput));
+        assert_eq!(
+            soft_dtw_batch(&pool, &xs, &ys[..2], 1.0),
+            Err(Error::BatchLengthMismatch { xs: 3, ys: 2 })
+        );
+        assert_eq!(
+            soft_dtw_batch(&pool, &xs[..1], &ys[..1], -1.0),
+            Err(Error::InvalidGamma(-1.0))
+        );
+    }
+}
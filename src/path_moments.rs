@@ -0,0 +1,270 @@
+//! Moments of the soft shortest-path value under uncertain edge costs.
+//!
+//! When edge costs are random, independent, with means \(\mu_e\) and variances
+//! \(\sigma_e^2\), the delta method propagates the first two moments through the DP without
+//! sampling. With \(p_e = \partial V_\gamma/\partial c_e\) the edge marginals and
+//! \(H = \nabla^2 V_\gamma\) at the means,
+//!
+//! \[ \mathbb{E}[V_\gamma] \approx V_\gamma(\mu) + \tfrac12 \sum_e H_{ee}\,\sigma_e^2, \qquad
+//!    \operatorname{Var}[V_\gamma] \approx \sum_e p_e^2\,\sigma_e^2, \qquad
+//!    \operatorname{Var}[p_e] \approx \sum_f H_{ef}^2\,\sigma_f^2. \]
+//!
+//! The Hessian is the path covariance of edge indicators scaled by \(-1/\gamma\):
+//! \(H_{ef} = -(p_{ef} - p_e p_f)/\gamma\), with \(p_{ef}\) the probability that both edges
+//! are on the path. Soft minima are concave, so \(H_{ee} \le 0\) and noise lowers the expected
+//! value (Jensen). Pairwise marginals need soft distances between every edge head and the
+//! later edges, so this costs `O(E² + n E)`: meant for graphs of moderate size.
+//!
+//! ```
+//! use structop::path_moments::{soft_shortest_path_moments, StochasticEdge};
+//!
+//! let edges = [
+//!     StochasticEdge { from: 0, to: 1, mean: 1.0, var: 0.2 },
+//!     StochasticEdge { from: 1, to: 2, mean: 1.0, var: 0.1 },
+//!     StochasticEdge { from: 0, to: 2, mean: 2.2, var: 0.0 },
+//! ];
+//! let m = soft_shortest_path_moments(3, &edges, 0.5).unwrap();
+//! assert!(m.mean < m.value && m.variance > 0.0);
+//! assert_eq!(m.marginal_variances.len(), 3);
+//! ```
+
+use crate::soft_shortest_path::{self, Edge, Graph};
+
+/// Errors for moment propagation.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A variance is negative, NaN or infinite.
+    #[error("edge {edge} has invalid variance {var}")]
+    InvalidVariance {
+        /// Index of the edge.
+        edge: usize,
+        /// The variance.
+        var: f64,
+    },
+    /// Error from the soft shortest path (graph validation, `gamma`, reachability).
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Directed edge whose cost is random with the given mean and variance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StochasticEdge {
+    /// Source node index.
+    pub from: usize,
+    /// Destination node index.
+    pub to: usize,
+    /// Mean cost.
+    pub mean: f64,
+    /// Cost variance.
+    pub var: f64,
+}
+
+/// Delta-method moments of the soft shortest path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathMoments {
+    /// \(V_\gamma\) at the mean costs.
+    pub value: f64,
+    /// Second-order approximation of \(\mathbb{E}[V_\gamma]\).
+    pub mean: f64,
+    /// First-order approximation of \(\operatorname{Var}[V_\gamma]\).
+    pub variance: f64,
+    /// Edge marginals at the mean costs.
+    pub marginals: Vec<f64>,
+    /// First-order approximation of each marginal's variance.
+    pub marginal_variances: Vec<f64>,
+}
+
+/// \(-\gamma \log(e^{-a/\gamma} + e^{-b/\gamma})\), `+inf` when both are.
+fn softmin2(gamma: f64, a: f64, b: f64) -> f64 {
+    let lo = a.min(b);
+    if lo == f64::INFINITY {
+        return lo;
+    }
+    lo - gamma * (-(a.max(b) - lo) / gamma).exp().ln_1p()
+}
+
+/// Soft distances from `start` to every node (`+inf` where unreachable); `out[u]` lists the
+/// out-edges of `u`.
+fn soft_distances_from(out: &[Vec<&Edge>], start: usize, gamma: f64) -> Vec<f64> {
+    let mut d = vec![f64::INFINITY; out.len()];
+    d[start] = 0.0;
+    for u in start..out.len() {
+        if d[u] == f64::INFINITY {
+            continue;
+        }
+        for e in &out[u] {
+            d[e.to] = softmin2(gamma, d[e.to], d[u] + e.cost);
+        }
+    }
+    d
+}
+
+/// Delta-method mean and variance of the soft shortest-path value from node 0 to node
+/// `n - 1` (edges in topological order, `from < to`), and the variances of the marginals.
+pub fn soft_shortest_path_moments(
+    n: usize,
+    edges: &[StochasticEdge],
+    gamma: f64,
+) -> Result<PathMoments> {
+    if let Some((edge, e)) = edges
+        .iter()
+        .enumerate()
+        .find(|(_, e)| !(e.var >= 0.0 && e.var.is_finite()))
+    {
+        return Err(Error::InvalidVariance { edge, var: e.var });
+    }
+    let base: Vec<Edge> = edges
+        .iter()
+        .map(|e| Edge {
+            from: e.from,
+            to: e.to,
+            cost: e.mean,
+        })
+        .collect();
+    let graph = Graph::new(n, base, 0, n.saturating_sub(1))?;
+    let (value, p) = graph.edge_marginals(gamma)?;
+    let base = graph.edges();
+
+    let mut out: Vec<Vec<&Edge>> = vec![Vec::new(); n];
+    for e in base {
+        out[e.from].push(e);
+    }
+    let f = soft_distances_from(&out, 0, gamma);
+    // Soft distance from each edge's head, computed once per distinct head.
+    let mut from_head: Vec<Option<Vec<f64>>> = vec![None; n];
+    for e in base {
+        if from_head[e.to].is_none() {
+            from_head[e.to] = Some(soft_distances_from(&out, e.to, gamma));
+        }
+    }
+    let mut b = vec![f64::INFINITY; n];
+    b[n - 1] = 0.0;
+    for u in (0..n).rev() {
+        for e in &out[u] {
+            b[u] = softmin2(gamma, b[u], e.cost + b[e.to]);
+        }
+    }
+
+    // Joint marginal of `e` followed (not necessarily directly) by `g` on the path.
+    let joint = |e: &Edge, g: &Edge| {
+        let d = from_head[e.to].as_ref().expect("computed for every head")[g.from];
+        (-(f[e.from] + e.cost + d + g.cost + b[g.to] - value) / gamma).exp()
+    };
+    let m = base.len();
+    let mut hess = vec![0.0; m * m];
+    for i in 0..m {
+        for j in 0..m {
+            let (e, g) = (&base[i], &base[j]);
+            let pij = if i == j {
+                p[i]
+            } else if e.to <= g.from {
+                joint(e, g)
+            } else if g.to <= e.from {
+                joint(g, e)
+            } else {
+                0.0
+            };
+            hess[i * m + j] = -(pij - p[i] * p[j]) / gamma;
+        }
+    }
+
+    let var: Vec<f64> = edges.iter().map(|e| e.var).collect();
+    let mean = value + 0.5 * (0..m).map(|i| hess[i * m + i] * var[i]).sum::<f64>();
+    let variance = p.iter().zip(&var).map(|(p, v)| p * p * v).sum();
+    let marginal_variances = hess
+        .chunks(m.max(1))
+        .take(m)
+        .map(|row| row.iter().zip(&var).map(|(h, v)| h * h * v).sum())
+        .collect();
+    Ok(PathMoments {
+        value,
+        mean,
+        variance,
+        marginals: p,
+        marginal_variances,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::soft_shortest_path_edge_marginals;
+
+    fn edge(from: usize, to: usize, mean: f64, var: f64) -> StochasticEdge {
+        StochasticEdge {
+            from,
+            to,
+            mean,
+            var,
+        }
+    }
+
+    #[test]
+    fn moments_match_finite_difference_derivatives() {
+        let edges = [
+            edge(0, 1, 1.0, 0.3),
+            edge(0, 2, 1.5, 0.1),
+            edge(1, 2, 0.2, 0.05),
+            edge(1, 3, 1.4, 0.2),
+            edge(2, 3, 0.8, 0.4),
+            edge(0, 3, 2.6, 0.0),
+        ];
+        let gamma = 0.6;
+        let m = soft_shortest_path_moments(4, &edges, gamma).unwrap();
+        let costs = |shift: &[f64]| -> Vec<Edge> {
+            edges
+                .iter()
+                .zip(shift)
+                .map(|(e, s)| Edge {
+                    from: e.from,
+                    to: e.to,
+                    cost: e.mean + s,
+                })
+                .collect()
+        };
+        let k = edges.len();
+        let h = 1e-5;
+        // Hessian column j by central differences of the marginals.
+        let mut hess = vec![0.0; k * k];
+        for j in 0..k {
+            let mut s = vec![0.0; k];
+            s[j] = h;
+            let (_, up) = soft_shortest_path_edge_marginals(4, &costs(&s), gamma).unwrap();
+            s[j] = -h;
+            let (_, down) = soft_shortest_path_edge_marginals(4, &costs(&s), gamma).unwrap();
+            for i in 0..k {
+                hess[i * k + j] = (up[i] - down[i]) / (2.0 * h);
+            }
+        }
+        let var: Vec<f64> = edges.iter().map(|e| e.var).collect();
+        let mean = m.value + 0.5 * (0..k).map(|i| hess[i * k + i] * var[i]).sum::<f64>();
+        assert!((m.mean - mean).abs() < 1e-6, "{} vs {}", m.mean, mean);
+        let want: f64 = m.marginals.iter().zip(&var).map(|(p, v)| p * p * v).sum();
+        assert_eq!(m.variance, want);
+        for i in 0..k {
+            let want: f64 = (0..k).map(|j| hess[i * k + j].powi(2) * var[j]).sum();
+            assert!((m.marginal_variances[i] - want).abs() < 1e-6);
+        }
+        assert!(m.mean < m.value);
+    }
+
+    #[test]
+    fn a_single_path_adds_variances_exactly() {
+        let edges = [edge(0, 1, 1.0, 0.5), edge(1, 2, 2.0, 0.25)];
+        let m = soft_shortest_path_moments(3, &edges, 0.3).unwrap();
+        assert!((m.value - 3.0).abs() < 1e-12 && (m.mean - 3.0).abs() < 1e-12);
+        assert!((m.variance - 0.75).abs() < 1e-12);
+        assert!(m.marginal_variances.iter().all(|v| v.abs() < 1e-12));
+        assert_eq!(
+            soft_shortest_path_moments(3, &[edge(0, 2, 1.0, -1.0)], 0.3),
+            Err(Error::InvalidVariance { edge: 0, var: -1.0 })
+        );
+        assert_eq!(
+            soft_shortest_path_moments(3, &[edge(0, 1, 1.0, 0.0)], 0.3),
+            Err(Error::SoftShortestPath(soft_shortest_path::Error::NoPath))
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Polars adapters for the Soft-DTW operators (feature `polars`).
+//!
+//! A `Float64Chunked` column is one sequence. For batches, each row of a `List(Float64)` or
+//! `Array(Float64, width)` column is one sequence; rows are borrowed from the column's
+//! Arrow buffers (chunk by chunk) and fed to the slice-based batch APIs in
+//! [`crate::soft_dtw`], and the results come back as a `Float64` `Series`.
+//!
+//! Nulls are rejected, at the row or the element level.
+
+use std::borrow::Cow;
+
+use polars_arrow::array::{Array, PrimitiveArray};
+use polars_core::prelude::{DataType, Float64Chunked, NamedFrom, Series};
+
+use crate::soft_dtw as sdtw;
+
+/// Errors for the Polars adapters.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The column (or its inner values) contains nulls.
+    #[error("input contains {0} null values")]
+    ContainsNulls(usize),
+    /// The column is not `Float64`, `List(Float64)` or `Array(Float64, _)`.
+    #[error("unsupported column dtype {0}")]
+    UnsupportedDtype(DataType),
+    /// Error from the underlying Soft-DTW operator.
+    #[error(transparent)]
+    SoftDtw(#[from] sdtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Values of a null-free `Float64Chunked`: borrowed for a single chunk, else concatenated.
+pub fn values(ca: &Float64Chunked) -> Result<Cow<'_, [f64]>> {
+    if ca.null_count() > 0 {
+        return Err(Error::ContainsNulls(ca.null_count()));
+    }
+    let mut chunks = ca.downcast_iter().map(|arr| arr.values().as_slice());
+    match (chunks.next(), ca.chunks().len()) {
+        (None, _) => Ok(Cow::Borrowed(&[])),
+        (Some(only), 1) => Ok(Cow::Borrowed(only)),
+        (Some(first), _) => Ok(Cow::Owned(
+            std::iter::once(first)
+                .chain(chunks)
+                .collect::<Vec<_>>()
+                .concat(),
+        )),
+    }
+}
+
+fn float_values(values: &dyn Array) -> Result<&[f64]> {
+    if values.null_count() > 0 {
+        return Err(Error::ContainsNulls(values.null_count()));
+    }
+    let prim = values
+        .as_any()
+        .downcast_ref::<PrimitiveArray<f64>>()
+        .expect("Float64 inner dtype is backed by PrimitiveArray<f64>");
+    Ok(prim.values().as_slice())
+}
+
+/// Borrows each row of a `List(Float64)` or `Array(Float64, _)` series as a slice.
+pub fn rows(series: &Series) -> Result<Vec<&[f64]>> {
+    if series.null_count() > 0 {
+        return Err(Error::ContainsNulls(series.null_count()));
+    }
+    let mut out = Vec::with_capacity(series.len());
+    match series.dtype() {
+        DataType::List(inner) if **inner == DataType::Float64 => {
+            let list = series.list().expect("dtype checked");
+            for arr in list.downcast_iter() {
+                let flat = float_values(arr.values().as_ref())?;
+                let offsets = arr.offsets();
+                out.extend((0..arr.len()).map(|i| {
+                    let (start, end) = offsets.start_end(i);
+                    &flat[start..end]
+                }));
+            }
+        }
+        DataType::Array(inner, width) if **inner == DataType::Float64 => {
+            let array = series.array().expect("dtype checked");
+            for arr in array.downcast_iter() {
+                let flat = float_values(arr.values().as_ref())?;
+                out.extend((0..arr.len()).map(|i| &flat[i * width..(i + 1) * width]));
+            }
+        }
+        other => return Err(Error::UnsupportedDtype(other.clone())),
+    }
+    Ok(out)
+}
+
+/// Soft-DTW value for two sequences given as `Float64` columns.
+pub fn soft_dtw(x: &Float64Chunked, y: &Float64Chunked, gamma: f64) -> Result<f64> {
+    Ok(sdtw::soft_dtw(&values(x)?, &values(y)?, gamma)?)
+}
+
+/// Soft-DTW values for row pairs of two sequence columns, as a `Float64` series named
+/// `"soft_dtw"`.
+pub fn soft_dtw_batch(xs: &Series, ys: &Series, gamma: f64) -> Result<Series> {
+    let out = sdtw::soft_dtw_batch(&rows(xs)?, &rows(ys)?, gamma)?;
+    Ok(Series::new("soft_dtw".into(), out))
+}
+
+/// Soft-DTW divergences for row pairs of two sequence columns, as a `Float64` series named
+/// `"soft_dtw_divergence"`.
+pub fn soft_dtw_divergence_batch(xs: &Series, ys: &Series, gamma: f64) -> Result<Series> {
+    let out = sdtw::soft_dtw_divergence_batch(&rows(xs)?, &rows(ys)?, gamma)?;
+    Ok(Series::new("soft_dtw_divergence".into(), out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use polars_core::prelude::{ChunkedArray, IntoSeries, ListChunked};
+
+    fn list_column(rows: &[&[f64]]) -> Series {
+        let inner: Vec<Series> = rows
+            .iter()
+            .map(|r| Series::new("".into(), r.to_vec()))
+            .collect();
+        ListChunked::from_iter(inner.into_iter().map(Some)).into_series()
+    }
+
+    #[test]
+    fn list_batch_matches_slice_api_across_chunks() {
+        let x_rows: [&[f64]; 3] = [&[0.0, 1.0, 2.0], &[1.0, -1.0], &[0.5]];
+        let y_rows: [&[f64]; 3] = [&[0.5, 1.5], &[1.0, 0.0, -1.0], &[0.0, 0.5]];
+        let mut xs = list_column(&x_rows[..2]);
+        xs.append(&list_column(&x_rows[2..])).unwrap();
+        assert_eq!(xs.chunks().len(), 2);
+        let ys = list_column(&y_rows).slice(0, 3);
+
+        let got = soft_dtw_batch(&xs, &ys, 0.7).unwrap();
+        let expected = sdtw::soft_dtw_batch(&x_rows, &y_rows, 0.7).unwrap();
+        assert_eq!(got.name().as_str(), "soft_dtw");
+        assert_eq!(
+            got.f64().unwrap().to_vec_null_aware().left().unwrap(),
+            expected
+        );
+
+        let sliced = list_column(&x_rows).slice(1, 2);
+        assert_eq!(rows(&sliced).unwrap(), &x_rows[1..]);
+    }
+
+    #[test]
+    fn single_sequences_and_errors() {
+        let x: Float64Chunked = ChunkedArray::from_vec("x".into(), vec![0.0, 1.0, 2.0]);
+        let y: Float64Chunked = ChunkedArray::from_vec("y".into(), vec![0.5, 1.5]);
+        assert_eq!(
+            soft_dtw(&x, &y, 0.5).unwrap(),
+            sdtw::soft_dtw(&[0.0, 1.0, 2.0], &[0.5, 1.5], 0.5).unwrap()
+        );
+
+        let with_null = Float64Chunked::new("x".into(), &[Some(1.0), None]);
+        assert_eq!(soft_dtw(&with_null, &y, 0.5), Err(Error::ContainsNulls(1)));
+        assert_eq!(
+            rows(&x.into_series()),
+            Err(Error::UnsupportedDtype(DataType::Float64))
+        );
+    }
+}
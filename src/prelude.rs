@@ -0,0 +1,32 @@
+//! Common operators and types in one import.
+//!
+//! ```
+//! use structop::prelude::*;
+//!
+//! fn score(x: &[f64], y: &[f64]) -> structop::Result<f64> {
+//!     let d = soft_dtw_divergence(x, y, 1.0)?;
+//!     let edges = [Edge { from: 0, to: 1, cost: d }];
+//!     Ok(soft_shortest_path_value(2, &edges, 1.0)?)
+//! }
+//! # score(&[0.0, 1.0], &[1.0]).unwrap();
+//! ```
+//!
+//! `Result` is deliberately not re-exported here, so a glob import does not shadow
+//! `std::result::Result`; use `structop::Result`.
+
+pub use crate::error::Error;
+pub use crate::ot::{
+    barycentric_projection, order_preserving_ot, partial_sinkhorn, sinkhorn, sinkhorn_log,
+    sliced_wasserstein, wasserstein_barycenter, BarycentricProjection, EpsilonScaling,
+    LogTransport, PartialTransport, SinkhornOptions, SlicedWasserstein, Transport,
+};
+pub use crate::soft_dtw::{
+    soft_dtw, soft_dtw_alignment, soft_dtw_batch, soft_dtw_cost, soft_dtw_divergence,
+    soft_dtw_divergence_batch, soft_dtw_grad, soft_dtw_symbols, DiagonalCurve, DiagonalPenalty,
+    MissingValues, Precision, SoftDtw, SoftDtwAlignment, SoftDtwStream, StepPattern,
+    StreamCheckpoint, SubstitutionMatrix, TinyGamma, TransitionPenalty,
+};
+pub use crate::soft_shortest_path::{
+    soft_shortest_path_edge_marginals, soft_shortest_path_value, Edge, Graph, SoftShortestPath,
+};
+pub use crate::string_kernels::{gap_weighted_kernel, mismatch_kernel};
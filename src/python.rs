@@ -0,0 +1,215 @@
+//! Python bindings via PyO3 and `numpy` (feature `python`).
+//!
+//! The `structop` extension module exposes the Soft-DTW and soft shortest-path operators on
+//! NumPy `float64` arrays. Inputs are copied once into host buffers (any strides or memory
+//! order are accepted); the computation then runs with the GIL released, so Python threads
+//! can call these functions concurrently.
+//!
+//! Build the extension with `maturin build --release --features python` (see
+//! `pyproject.toml`). Crate errors are raised as `ValueError`.
+//!
+//! ```python
+//! import numpy as np, structop
+//! value, grad = structop.soft_dtw_grad(np.array([0.0, 1.0]), np.array([0.5]), 1.0)
+//! ```
+
+use numpy::ndarray::{Array2, ArrayView2};
+use numpy::{PyArray1, PyArray2, PyReadonlyArray1, PyReadonlyArray2};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+fn value_error(e: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(e.to_string())
+}
+
+fn owned1(a: &PyReadonlyArray1<'_, f64>) -> Vec<f64> {
+    a.as_array().iter().copied().collect()
+}
+
+/// Row-major copy of a 2D array (any strides) with its shape.
+fn owned2(view: ArrayView2<'_, f64>) -> (Vec<f64>, usize, usize) {
+    let (n, m) = view.dim();
+    (view.iter().copied().collect(), n, m)
+}
+
+/// Soft-DTW value and the expected alignment as an `(n, m)` array.
+fn alignment2(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> soft_dtw::Result<(f64, Array2<f64>)> {
+    let (v, e) = soft_dtw::soft_dtw_alignment(cost, n, m, gamma)?;
+    let e = Array2::from_shape_vec((n, m), e).expect("alignment has n * m entries");
+    Ok((v, e))
+}
+
+/// Pairs `(from, to)` edges with their costs, one cost per edge.
+fn edges_with_costs(
+    edges: &[(usize, usize)],
+    costs: &[f64],
+) -> soft_shortest_path::Result<Vec<Edge>> {
+    if costs.len() != edges.len() {
+        return Err(soft_shortest_path::Error::EdgeCostLength {
+            len: costs.len(),
+            expected: edges.len(),
+        });
+    }
+    Ok(edges
+        .iter()
+        .zip(costs)
+        .map(|(&(from, to), &cost)| Edge { from, to, cost })
+        .collect())
+}
+
+/// `soft_dtw(x, y, gamma) -> float`: Soft-DTW value of two 1D sequences.
+#[pyfunction]
+#[pyo3(name = "soft_dtw")]
+fn py_soft_dtw(
+    py: Python<'_>,
+    x: PyReadonlyArray1<'_, f64>,
+    y: PyReadonlyArray1<'_, f64>,
+    gamma: f64,
+) -> PyResult<f64> {
+    let (x, y) = (owned1(&x), owned1(&y));
+    py.detach(|| soft_dtw::soft_dtw(&x, &y, gamma))
+        .map_err(value_error)
+}
+
+/// `soft_dtw_divergence(x, y, gamma) -> float`: debiased Soft-DTW divergence.
+#[pyfunction]
+#[pyo3(name = "soft_dtw_divergence")]
+fn py_soft_dtw_divergence(
+    py: Python<'_>,
+    x: PyReadonlyArray1<'_, f64>,
+    y: PyReadonlyArray1<'_, f64>,
+    gamma: f64,
+) -> PyResult<f64> {
+    let (x, y) = (owned1(&x), owned1(&y));
+    py.detach(|| soft_dtw::soft_dtw_divergence(&x, &y, gamma))
+        .map_err(value_error)
+}
+
+/// `soft_dtw_grad(x, y, gamma) -> (float, ndarray)`: value and gradient w.r.t. `x`.
+#[pyfunction]
+#[pyo3(name = "soft_dtw_grad")]
+fn py_soft_dtw_grad<'py>(
+    py: Python<'py>,
+    x: PyReadonlyArray1<'py, f64>,
+    y: PyReadonlyArray1<'py, f64>,
+    gamma: f64,
+) -> PyResult<(f64, Bound<'py, PyArray1<f64>>)> {
+    let (x, y) = (owned1(&x), owned1(&y));
+    let (v, g) = py
+        .detach(|| soft_dtw::soft_dtw_grad(&x, &y, gamma))
+        .map_err(value_error)?;
+    Ok((v, PyArray1::from_vec(py, g)))
+}
+
+/// `soft_dtw_cost(cost, gamma) -> float`: Soft-DTW value of an `(n, m)` cost matrix.
+#[pyfunction]
+#[pyo3(name = "soft_dtw_cost")]
+fn py_soft_dtw_cost(py: Python<'_>, cost: PyReadonlyArray2<'_, f64>, gamma: f64) -> PyResult<f64> {
+    let (cost, n, m) = owned2(cost.as_array());
+    py.detach(|| soft_dtw::soft_dtw_cost(&cost, n, m, gamma))
+        .map_err(value_error)
+}
+
+/// `soft_dtw_alignment(cost, gamma) -> (float, ndarray)`: value and the `(n, m)` expected
+/// alignment, i.e. the gradient w.r.t. the cost matrix.
+#[pyfunction]
+#[pyo3(name = "soft_dtw_alignment")]
+fn py_soft_dtw_alignment<'py>(
+    py: Python<'py>,
+    cost: PyReadonlyArray2<'py, f64>,
+    gamma: f64,
+) -> PyResult<(f64, Bound<'py, PyArray2<f64>>)> {
+    let (cost, n, m) = owned2(cost.as_array());
+    let (v, e) = py
+        .detach(|| alignment2(&cost, n, m, gamma))
+        .map_err(value_error)?;
+    Ok((v, PyArray2::from_owned_array(py, e)))
+}
+
+/// `soft_shortest_path_marginals(n, edges, costs, gamma) -> (float, ndarray)`: value from
+/// node 0 to `n - 1` and the edge marginals (the gradient w.r.t. `costs`).
+///
+/// `edges` is a sequence of `(from, to)` pairs in topological order; `costs` holds one
+/// cost per edge.
+#[pyfunction]
+#[pyo3(name = "soft_shortest_path_marginals")]
+fn py_soft_shortest_path_marginals<'py>(
+    py: Python<'py>,
+    n: usize,
+    edges: Vec<(usize, usize)>,
+    costs: PyReadonlyArray1<'py, f64>,
+    gamma: f64,
+) -> PyResult<(f64, Bound<'py, PyArray1<f64>>)> {
+    let es = edges_with_costs(&edges, &owned1(&costs)).map_err(value_error)?;
+    let (v, p) = py
+        .detach(|| soft_shortest_path::soft_shortest_path_edge_marginals(n, &es, gamma))
+        .map_err(value_error)?;
+    Ok((v, PyArray1::from_vec(py, p)))
+}
+
+/// The `structop` Python extension module.
+#[pymodule]
+fn structop(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(py_soft_dtw, m)?)?;
+    m.add_function(wrap_pyfunction!(py_soft_dtw_divergence, m)?)?;
+    m.add_function(wrap_pyfunction!(py_soft_dtw_grad, m)?)?;
+    m.add_function(wrap_pyfunction!(py_soft_dtw_cost, m)?)?;
+    m.add_function(wrap_pyfunction!(py_soft_dtw_alignment, m)?)?;
+    m.add_function(wrap_pyfunction!(py_soft_shortest_path_marginals, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use numpy::ndarray::array;
+
+    #[test]
+    fn wrappers_copy_any_layout_and_shape_results() {
+        let c = array![[0.0, 1.0, 2.0], [1.0, 0.0, 1.0]];
+        assert_eq!(owned2(c.view()), (vec![0.0, 1.0, 2.0, 1.0, 0.0, 1.0], 2, 3));
+        assert_eq!(owned2(c.t()), (vec![0.0, 1.0, 1.0, 0.0, 2.0, 1.0], 3, 2));
+
+        let (cost, n, m) = owned2(c.view());
+        let (v, e) = alignment2(&cost, n, m, 0.5).unwrap();
+        let (value, flat) = soft_dtw::soft_dtw_alignment(&cost, n, m, 0.5).unwrap();
+        assert_eq!(v, value);
+        assert_eq!(e.dim(), (2, 3));
+        assert_eq!(e.iter().copied().collect::<Vec<_>>(), flat);
+        assert_eq!(
+            alignment2(&cost[..5], n, m, 0.5).unwrap_err(),
+            soft_dtw::Error::InvalidCostShape {
+                len: 5,
+                n: 2,
+                m: 3,
+                expected: 6
+            }
+        );
+
+        let edges = [(0, 1), (1, 2), (0, 2)];
+        let es = edges_with_costs(&edges, &[1.0, 2.0, 4.0]).unwrap();
+        assert_eq!(
+            es[2],
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 4.0
+            }
+        );
+        assert_eq!(
+            edges_with_costs(&edges, &[1.0, 2.0]),
+            Err(soft_shortest_path::Error::EdgeCostLength {
+                len: 2,
+                expected: 3
+            })
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Curve registration from the expected Soft-DTW warp.
+//!
+//! The expected alignment \(E\) of two series `x` (length `n`) and `y` (length `m`) gives,
+//! for every row, the expected aligned column \(\hat{\jmath}(i) = \sum_j j E_{ij} / \sum_j
+//! E_{ij}\) (see [`crate::soft_dtw::SoftDtwAlignment::expected_path`]). That sequence is
+//! nearly but not always monotone, so [`Registration`] projects it onto non-decreasing
+//! sequences (weighted isotonic regression, each row weighted by its alignment mass) and
+//! interpolates linearly between rows. The result is a continuous, monotone time warp
+//! \(h : [0, n-1] \to [0, m-1]\) with a generalized inverse, and resampling `y` at
+//! \(h(0), \dots, h(n-1)\) puts it on the time axis of `x`.
+//!
+//! ```
+//! use structop::registration::register;
+//! use structop::soft_dtw::SoftDtw;
+//!
+//! let x = [0.0, 0.0, 1.0, 2.0, 1.0, 0.0];
+//! let y = [0.0, 1.0, 2.0, 1.0, 0.0, 0.0];
+//! let (reg, y_on_x) = register(&x, &y, &SoftDtw::new(0.01)).unwrap();
+//! assert!((reg.warp(3.0) - 2.0).abs() < 1e-3);
+//! assert!((y_on_x[3] - 2.0).abs() < 1e-3);
+//! ```
+
+use crate::soft_dtw::{self, SoftDtw, SoftDtwAlignment};
+
+/// Errors for registration.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The alignment does not have `n * m` entries.
+    #[error("alignment has length {len}, expected {expected}")]
+    AlignmentShape {
+        /// Length given.
+        len: usize,
+        /// `n * m`.
+        expected: usize,
+    },
+    /// A row of the alignment has no (or non-finite) mass, so its expected column is undefined.
+    #[error("row {row} of the alignment has mass {mass}")]
+    EmptyRow {
+        /// Row index.
+        row: usize,
+        /// Sum of the row.
+        mass: f64,
+    },
+    /// The series to resample does not have the registration's column count.
+    #[error("series has length {len}, registration expects {expected}")]
+    SeriesLength {
+        /// Length given.
+        len: usize,
+        /// `m`.
+        expected: usize,
+    },
+    /// Error from the Soft-DTW operator.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Monotone, piecewise-linear time warp from `[0, n-1]` (the first series) to `[0, m-1]`
+/// (the second).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registration {
+    knots: Vec<f64>,
+    m: usize,
+}
+
+impl Registration {
+    /// Registration from a Soft-DTW analysis of two series or a cost matrix.
+    pub fn new(res: &SoftDtwAlignment<'_>) -> Result<Self> {
+        let (n, m) = res.shape();
+        Self::from_alignment(res.alignment(), n, m)
+    }
+
+    /// Registration from an expected alignment, row-major `n × m`.
+    pub fn from_alignment(alignment: &[f64], n: usize, m: usize) -> Result<Self> {
+        if n == 0 || m == 0 {
+            return Err(soft_dtw::Error::EmptyInput.into());
+        }
+        if alignment.len() != n * m {
+            return Err(Error::AlignmentShape {
+                len: alignment.len(),
+                expected: n * m,
+            });
+        }
+        // Pool adjacent violators over (mean column, mass, rows) blocks.
+        let mut blocks: Vec<(f64, f64, usize)> = Vec::with_capacity(n);
+        for (row, cells) in alignment.chunks(m).enumerate() {
+            let mass: f64 = cells.iter().sum();
+            let first: f64 = cells.iter().enumerate().map(|(j, &p)| j as f64 * p).sum();
+            if !(mass > 0.0 && mass.is_finite() && first.is_finite()) {
+                return Err(Error::EmptyRow { row, mass });
+            }
+            let mut block = (first / mass, mass, 1);
+            while let Some(&(v, w, k)) = blocks.last() {
+                if v <= block.0 {
+                    break;
+                }
+                blocks.pop();
+                let total = w + block.1;
+                block = ((v * w + block.0 * block.1) / total, total, k + block.2);
+            }
+            blocks.push(block);
+        }
+        let hi = (m - 1) as f64;
+        let knots = blocks
+            .iter()
+            .flat_map(|&(v, _, k)| std::iter::repeat(v.clamp(0.0, hi)).take(k))
+            .collect();
+        Ok(Self { knots, m })
+    }
+
+    /// Monotone expected column of each row, \(h(0), \dots, h(n-1)\).
+    pub fn knots(&self) -> &[f64] {
+        &self.knots
+    }
+
+    /// Shape `(n, m)` of the underlying alignment.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.knots.len(), self.m)
+    }
+
+    /// \(h(t)\): the position in the second series aligned with time `t` of the first,
+    /// interpolated linearly between rows; `t` is clamped to `[0, n-1]`.
+    pub fn warp(&self, t: f64) -> f64 {
+        lerp(&self.knots, t)
+    }
+
+    /// Generalized inverse \(h^{-1}(s) = \min\{t : h(t) \ge s\}\): the earliest time of the
+    /// first series aligned with position `s` of the second. Values below \(h(0)\) map to
+    /// `0`, values above \(h(n-1)\) to `n - 1`.
+    pub fn inverse(&self, s: f64) -> f64 {
+        let k = self.knots.partition_point(|&v| v < s);
+        if k == 0 {
+            return 0.0;
+        }
+        if k == self.knots.len() {
+            return (k - 1) as f64;
+        }
+        let (a, b) = (self.knots[k - 1], self.knots[k]);
+        (k - 1) as f64 + (s - a) / (b - a)
+    }
+
+    /// `y` (of length `m`) resampled on the time axis of the first series: element `i` is
+    /// `y` interpolated linearly at \(h(i)\).
+    pub fn resample(&self, y: &[f64]) -> Result<Vec<f64>> {
+        if y.len() != self.m {
+            return Err(Error::SeriesLength {
+                len: y.len(),
+                expected: self.m,
+            });
+        }
+        Ok(self.knots.iter().map(|&s| lerp(y, s)).collect())
+    }
+}
+
+/// `v` interpolated linearly at fractional index `t`, clamped to the ends.
+fn lerp(v: &[f64], t: f64) -> f64 {
+    let t = t.clamp(0.0, (v.len() - 1) as f64);
+    let k = (t as usize).min(v.len().saturating_sub(2));
+    match v.get(k + 1) {
+        Some(&b) => v[k] + (t - k as f64) * (b - v[k]),
+        None => v[k],
+    }
+}
+
+/// Registers `y` onto `x` under `op`: the registration from their expected alignment and
+/// `y` resampled on the time axis of `x`.
+pub fn register(x: &[f64], y: &[f64], op: &SoftDtw) -> Result<(Registration, Vec<f64>)> {
+    let reg = Registration::new(&op.analyze(x, y)?)?;
+    let resampled = reg.resample(y)?;
+    Ok((reg, resampled))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warp_is_monotone_invertible_and_registers_a_shifted_curve() {
+        let x: Vec<f64> = (0..40).map(|i| (i as f64 * 0.2).sin()).collect();
+        let y: Vec<f64> = (0..40).map(|i| ((i as f64 + 3.0) * 0.2).sin()).collect();
+        let (reg, y_on_x) = register(&x, &y, &SoftDtw::new(0.01)).unwrap();
+        assert_eq!(reg.shape(), (40, 40));
+        assert!(reg.knots().windows(2).all(|w| w[0] <= w[1]));
+        // Away from the ends, x[i] lines up with y[i - 3] (loosely near the flat extrema).
+        for i in 8..32 {
+            assert!((reg.warp(i as f64) - (i as f64 - 3.0)).abs() < 0.25, "{i}");
+            assert!((y_on_x[i] - x[i]).abs() < 0.02, "{i}");
+            let t = i as f64 + 0.5;
+            assert!((reg.inverse(reg.warp(t)) - t).abs() < 1e-9);
+        }
+
+        // Row 1 goes backwards and is pooled with row 0, weighted by mass (1 and 3).
+        let e = [0.0, 0.0, 1.0, 0.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        let reg = Registration::from_alignment(&e, 3, 4).unwrap();
+        assert_eq!(reg.knots(), &[0.5, 0.5, 3.0]);
+        assert_eq!(reg.inverse(0.5), 0.0);
+        assert_eq!(reg.inverse(1.75), 1.5);
+        assert_eq!(reg.inverse(9.0), 2.0);
+        assert_eq!(reg.warp(-1.0), 0.5);
+        assert_eq!(
+            reg.resample(&[0.0, 2.0, 4.0, 6.0]).unwrap(),
+            vec![1.0, 1.0, 6.0]
+        );
+
+        assert_eq!(
+            Registration::from_alignment(&e[..11], 3, 4),
+            Err(Error::AlignmentShape {
+                len: 11,
+                expected: 12
+            })
+        );
+        let mut empty = e;
+        empty[4..8].fill(0.0);
+        assert_eq!(
+            Registration::from_alignment(&empty, 3, 4),
+            Err(Error::EmptyRow { row: 1, mass: 0.0 })
+        );
+        assert_eq!(
+            reg.resample(&[1.0, 2.0]),
+            Err(Error::SeriesLength {
+                len: 2,
+                expected: 4
+            })
+        );
+    }
+}
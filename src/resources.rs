@@ -0,0 +1,197 @@
+//! Up-front memory and operation estimates for planned computations.
+//!
+//! A soft-DTW table over two series of a few hundred thousand points each is hundreds of
+//! gigabytes; a service that only discovers this in the allocator aborts. [`estimate`] and
+//! [`estimate_graph`] predict, from the shapes and operator settings alone, the peak working
+//! memory and the operation counts of a call, so oversized requests can be rejected or
+//! routed before anything is allocated.
+//!
+//! The figures follow the kernels' actual allocation pattern (a banded soft-DTW still
+//! allocates the full table, for instance) and are upper bounds up to small per-call
+//! constants. Inputs owned by the caller (the series, the cost matrix, the edge list) are
+//! not counted. Counts saturate at `u64::MAX` rather than overflow.
+//!
+//! ```
+//! use structop::resources::{estimate, Output};
+//! use structop::soft_dtw::SoftDtw;
+//!
+//! let op = SoftDtw::new(1.0);
+//! let est = estimate(100_000, 100_000, &op, Output::Gradient);
+//! assert!(est.memory_bytes > 100_000_000_000);
+//! assert!(estimate(1_000, 1_000, &op, Output::Value).memory_bytes < 10_000_000);
+//! ```
+
+use crate::soft_dtw::{Precision, SoftDtw};
+use crate::soft_shortest_path::SoftShortestPath;
+
+/// Flops per soft-DTW forward cell: three weighted candidates and a soft-min over them.
+const FORWARD_FLOPS: u64 = 16;
+/// Transcendentals per forward cell: three `exp` and one `ln`.
+const FORWARD_TRANSCENDENTALS: u64 = 4;
+/// Flops per backward cell: a selection probability and product for each successor.
+const BACKWARD_FLOPS: u64 = 15;
+/// Transcendentals per backward cell: one `exp` per successor.
+const BACKWARD_TRANSCENDENTALS: u64 = 3;
+/// Flops per edge per pass of a soft shortest path.
+const EDGE_FLOPS: u64 = 4;
+/// Double-double arithmetic, counted in `f64` operations.
+const DOUBLE_DOUBLE_FLOPS: u64 = 10;
+
+const F64: u64 = std::mem::size_of::<f64>() as u64;
+const USIZE: u64 = std::mem::size_of::<usize>() as u64;
+
+/// What a planned call returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Output {
+    /// The soft value only (a forward pass).
+    Value,
+    /// The value and its gradient: the expected alignment for soft-DTW, the edge marginals
+    /// for a soft shortest path (a forward and a backward pass).
+    Gradient,
+}
+
+/// Predicted cost of a planned computation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    /// Peak working memory in bytes, including the returned gradient.
+    pub memory_bytes: u64,
+    /// DP cells (soft-DTW) or edges (shortest path) visited per pass.
+    pub cells: u64,
+    /// Arithmetic operations, excluding transcendentals.
+    pub flops: u64,
+    /// Calls to `exp` and `ln`.
+    pub transcendentals: u64,
+}
+
+fn entry_bytes(precision: Precision) -> u64 {
+    match precision {
+        Precision::Double => F64,
+        Precision::DoubleDouble => 2 * F64,
+    }
+}
+
+fn flop_factor(precision: Precision) -> u64 {
+    match precision {
+        Precision::Double => 1,
+        Precision::DoubleDouble => DOUBLE_DOUBLE_FLOPS,
+    }
+}
+
+/// Estimate for `op` applied to an `n × m` cost matrix (or to series of lengths `n` and `m`).
+pub fn estimate(n: usize, m: usize, op: &SoftDtw, output: Output) -> ResourceEstimate {
+    let (n, m) = (n as u64, m as u64);
+    let table = n.saturating_add(1).saturating_mul(m.saturating_add(1));
+    let cells = match op.band_width() {
+        Some(b) => n.saturating_mul(m.min((b as u64).saturating_mul(2).saturating_add(1))),
+        None => n.saturating_mul(m),
+    };
+    let precision = op.precision_mode();
+    let factor = flop_factor(precision);
+    let mut est = ResourceEstimate {
+        memory_bytes: table.saturating_mul(entry_bytes(precision)),
+        cells,
+        flops: cells.saturating_mul(FORWARD_FLOPS * factor),
+        transcendentals: cells.saturating_mul(FORWARD_TRANSCENDENTALS),
+    };
+    if output == Output::Gradient {
+        // The plain double kernel walks the alignment in place; the others also keep a
+        // table of adjoints.
+        let adjoints = if op.plain() && precision == Precision::Double {
+            0
+        } else {
+            table
+        };
+        let extra = n
+            .saturating_mul(m)
+            .saturating_add(adjoints)
+            .saturating_mul(F64);
+        est.memory_bytes = est.memory_bytes.saturating_add(extra);
+        est.flops = est
+            .flops
+            .saturating_add(cells.saturating_mul(BACKWARD_FLOPS * factor));
+        est.transcendentals = est
+            .transcendentals
+            .saturating_add(cells.saturating_mul(BACKWARD_TRANSCENDENTALS));
+    }
+    est
+}
+
+/// Estimate for `op` applied to a DAG with `n` nodes and `n_edges` edges.
+pub fn estimate_graph(
+    n: usize,
+    n_edges: usize,
+    op: &SoftShortestPath,
+    output: Output,
+) -> ResourceEstimate {
+    let (n, e) = (n as u64, n_edges as u64);
+    let precision = op.precision_mode();
+    let factor = flop_factor(precision);
+    // One adjacency (offsets and edge indices), one potential per node, and soft-min
+    // scratch bounded by the largest in-degree.
+    let adjacency = n.saturating_add(1).saturating_add(e).saturating_mul(USIZE);
+    let pass = ResourceEstimate {
+        memory_bytes: adjacency
+            .saturating_add(n.saturating_mul(entry_bytes(precision)))
+            .saturating_add(e.saturating_mul(2 * F64)),
+        cells: e,
+        flops: e.saturating_mul(EDGE_FLOPS * factor),
+        transcendentals: e.saturating_add(n),
+    };
+    match output {
+        Output::Value => pass,
+        // A backward pass over the reversed adjacency, then one `exp` per edge marginal.
+        Output::Gradient => ResourceEstimate {
+            memory_bytes: pass
+                .memory_bytes
+                .saturating_mul(2)
+                .saturating_add(e.saturating_mul(F64)),
+            cells: e,
+            flops: pass
+                .flops
+                .saturating_mul(2)
+                .saturating_add(e.saturating_mul(EDGE_FLOPS)),
+            transcendentals: pass.transcendentals.saturating_mul(2).saturating_add(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_track_shape_band_precision_and_output() {
+        let op = SoftDtw::new(1.0);
+        let value = estimate(10, 20, &op, Output::Value);
+        assert_eq!(value.memory_bytes, 11 * 21 * 8);
+        assert_eq!(value.cells, 200);
+        assert_eq!(value.transcendentals, 800);
+        let grad = estimate(10, 20, &op, Output::Gradient);
+        assert_eq!(grad.memory_bytes, (11 * 21 + 200) * 8);
+        assert_eq!(grad.transcendentals, 200 * 7);
+
+        // A band narrows the work but not the table; double-double doubles the table.
+        let banded = estimate(10, 20, &SoftDtw::new(1.0).band(2), Output::Gradient);
+        assert_eq!(banded.cells, 50);
+        assert_eq!(banded.memory_bytes, (2 * 11 * 21 + 200) * 8);
+        let dd = estimate(
+            10,
+            20,
+            &op.precision(Precision::DoubleDouble),
+            Output::Value,
+        );
+        assert_eq!(dd.memory_bytes, 2 * value.memory_bytes);
+        assert_eq!(dd.flops, 10 * value.flops);
+
+        let huge = estimate(usize::MAX, usize::MAX, &op, Output::Gradient);
+        assert_eq!(huge.memory_bytes, u64::MAX);
+
+        let path = SoftShortestPath::new(1.0);
+        let v = estimate_graph(5, 8, &path, Output::Value);
+        assert_eq!(v.cells, 8);
+        assert_eq!(v.transcendentals, 13);
+        let g = estimate_graph(5, 8, &path, Output::Gradient);
+        assert!(g.memory_bytes > 2 * v.memory_bytes);
+        assert_eq!(g.transcendentals, 2 * 13 + 8);
+    }
+}
@@ -0,0 +1,140 @@
+//! Risk-sensitive soft shortest paths (exponential utility).
+//!
+//! The soft shortest path weighs paths by the Gibbs distribution
+//! \(p_\gamma(\pi) \propto e^{-C(\pi)/\gamma}\); \(\gamma\) controls smoothing. A separate risk
+//! parameter \(\beta\) scores that distribution by the exponential-utility certainty
+//! equivalent of its cost (the entropic risk),
+//!
+//! \[ R_\beta = \tfrac1\beta \log \mathbb{E}_{p_\gamma}\big[e^{\beta C(\pi)}\big]
+//!            = \tfrac1\beta \big(\log Z_{1/\gamma - \beta} - \log Z_{1/\gamma}\big),
+//!    \qquad Z_\lambda = \textstyle\sum_\pi e^{-\lambda C(\pi)}. \]
+//!
+//! \(\beta > 0\) is risk-averse (\(R_\beta\) above the expected cost, weighting expensive
+//! paths up), \(\beta < 0\) risk-seeking; \(R_\beta \to \mathbb{E}_{p_\gamma}[C]\) as
+//! \(\beta \to 0\). The risk-adjusted path distribution is the tilted Gibbs distribution at
+//! inverse temperature \(\lambda = 1/\gamma - \beta\), whose edge marginals are returned with
+//! the gradient of \(R_\beta\) w.r.t. the edge costs. Path sets of a DAG are finite, so any
+//! sign of \(\lambda\) is allowed: \(\lambda < 0\) favours the longest paths.
+//!
+//! ```
+//! use structop::risk_sensitive::risk_sensitive_path;
+//! use structop::soft_shortest_path::{Edge, Graph};
+//!
+//! let edges = vec![
+//!     Edge { from: 0, to: 1, cost: 1.0 },
+//!     Edge { from: 0, to: 1, cost: 2.0 },
+//! ];
+//! let graph = Graph::new(2, edges, 0, 1).unwrap();
+//! let averse = risk_sensitive_path(&graph, 1.0, 0.5).unwrap();
+//! let seeking = risk_sensitive_path(&graph, 1.0, -0.5).unwrap();
+//! assert!(averse.value > seeking.value);
+//! assert!(averse.marginals[1] > seeking.marginals[1]);
+//! ```
+
+use crate::soft_shortest_path::{self, Edge, Graph};
+
+/// Errors for risk-sensitive paths.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The risk parameter must be finite and nonzero (its limit at zero is the expected
+    /// cost).
+    #[error("risk parameter must be finite and nonzero, got {0}")]
+    InvalidRisk(f64),
+    /// Error from the soft shortest path.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Result of [`risk_sensitive_path`]; vectors are indexed like [`Graph::edges`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskSensitivePath {
+    /// Certainty equivalent \(R_\beta\).
+    pub value: f64,
+    /// Edge marginals of the tilted (risk-adjusted) path distribution.
+    pub marginals: Vec<f64>,
+    /// Gradient of \(R_\beta\) w.r.t. the edge costs.
+    pub grad: Vec<f64>,
+}
+
+/// Entropic risk \(R_\beta\) of the path cost under the Gibbs distribution at `gamma`, with
+/// the tilted marginals and the gradient.
+pub fn risk_sensitive_path(graph: &Graph, gamma: f64, beta: f64) -> Result<RiskSensitivePath> {
+    let (value, p) = graph.edge_marginals(gamma)?;
+    if !(beta != 0.0 && beta.is_finite()) {
+        return Err(Error::InvalidRisk(beta));
+    }
+    let lambda = 1.0 / gamma - beta;
+    // log Z_lambda is minus the soft value at gamma = 1 on costs scaled by lambda.
+    let scaled: Vec<Edge> = graph
+        .edges()
+        .iter()
+        .map(|e| Edge {
+            cost: lambda * e.cost,
+            ..*e
+        })
+        .collect();
+    let tilted = Graph::new(graph.n(), scaled, graph.source(), graph.sink())?;
+    let (tilted_value, q) = tilted.edge_marginals(1.0)?;
+    let grad = p
+        .iter()
+        .zip(&q)
+        .map(|(p, q)| (p / gamma - lambda * q) / beta)
+        .collect();
+    Ok(RiskSensitivePath {
+        value: (value / gamma - tilted_value) / beta,
+        marginals: q,
+        grad,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+
+    fn diamond(costs: &[f64]) -> Graph {
+        let ends = [(0, 1), (0, 2), (1, 2), (1, 3), (2, 3)];
+        let edges = ends
+            .iter()
+            .zip(costs)
+            .map(|(&(from, to), &cost)| Edge { from, to, cost })
+            .collect();
+        Graph::new(4, edges, 0, 3).unwrap()
+    }
+
+    #[test]
+    fn matches_path_enumeration_and_finite_differences() {
+        let costs = [1.0, 2.5, 0.3, 2.0, 0.7_f64];
+        // Paths 0-1-3, 0-2-3 and 0-1-2-3.
+        let paths = [
+            costs[0] + costs[3],
+            costs[1] + costs[4],
+            costs[0] + costs[2] + costs[4],
+        ];
+        let (gamma, beta) = (0.8, 0.6);
+        let w: Vec<f64> = paths.iter().map(|c| (-c / gamma).exp()).collect();
+        let z: f64 = w.iter().sum();
+        let mean =
+            |f: &dyn Fn(f64) -> f64| w.iter().zip(&paths).map(|(w, &c)| w * f(c)).sum::<f64>() / z;
+        let want = mean(&|c| (beta * c).exp()).ln() / beta;
+        let r = risk_sensitive_path(&diamond(&costs), gamma, beta).unwrap();
+        assert!((r.value - want).abs() < 1e-12, "{} vs {want}", r.value);
+        let expected = mean(&|c| c);
+        let seeking = risk_sensitive_path(&diamond(&costs), gamma, -beta).unwrap();
+        assert!(seeking.value < expected && expected < r.value);
+        // Past beta = 1/gamma the tilt favours the longest path.
+        let longest = risk_sensitive_path(&diamond(&costs), gamma, 10.0).unwrap();
+        assert!((longest.value - mean(&|c| (10.0 * c).exp()).ln() / 10.0).abs() < 1e-9);
+
+        let f = |c: &[f64]| Ok(risk_sensitive_path(&diamond(c), gamma, beta).unwrap().value);
+        let report = check_gradient(f, &costs, &r.grad, Tolerance::default()).unwrap();
+        assert!(report.passed(), "{report:?}");
+        assert_eq!(
+            risk_sensitive_path(&diamond(&costs), gamma, 0.0),
+            Err(Error::InvalidRisk(0.0))
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Piecewise Aggregate Approximation (PAA) and SAX symbolization.
+//!
+//! [`paa`] summarizes a series by the means of `segments` consecutive near-equal parts (the
+//! same parts, and so the same means, as the PAA summaries of
+//! [`crate::nearest_neighbor::EnvelopeIndex`]). [`Sax`] (Lin et al. 2003) maps each mean to
+//! one of `alphabet` symbols by cutting the real line at breakpoints: by default the
+//! quantiles of the standard normal, which make the symbols equiprobable for z-normalized
+//! series (see [`crate::znorm`]). The resulting words plug into the rest of the crate:
+//!
+//! - [`Sax::substitution_matrix`] holds the squared gaps between symbol regions, for
+//!   [`crate::soft_dtw::soft_dtw_symbols`] and its gradients on words;
+//! - [`Sax::mindist`] lower-bounds the Euclidean distance between the series behind two words;
+//! - [`Sax::lb_dtw`] lower-bounds the banded DTW of [`crate::nearest_neighbor`] between a
+//!   query and the series behind a word, so a corpus stored as words can be pruned before
+//!   the [`NearestNeighbor`](crate::nearest_neighbor::NearestNeighbor) cascade.
+//!
+//! ```
+//! use structop::sax::Sax;
+//!
+//! let sax = Sax::new(4, 4).unwrap();
+//! let x = [-1.5, -1.2, -0.3, -0.1, 0.2, 0.4, 1.1, 1.6];
+//! assert_eq!(sax.word(&x).unwrap(), vec![0, 1, 2, 3]);
+//! ```
+
+use crate::nearest_neighbor::{envelope, segments_of};
+use crate::soft_dtw::SubstitutionMatrix;
+
+/// Errors for PAA and SAX.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The segment count must be between 1 and the series length.
+    #[error("{segments} segments do not fit a series of length {len}")]
+    InvalidSegments {
+        /// Requested segment count.
+        segments: usize,
+        /// Series length.
+        len: usize,
+    },
+    /// The alphabet needs at least two symbols.
+    #[error("alphabet size must be at least 2, got {0}")]
+    InvalidAlphabet(usize),
+    /// Breakpoints must be finite and strictly increasing (and at least one).
+    #[error("breakpoints must be finite and strictly increasing")]
+    InvalidBreakpoints,
+    /// A word does not have one symbol per segment.
+    #[error("word has length {len}, expected {expected}")]
+    WordLength {
+        /// Length given.
+        len: usize,
+        /// Segment count.
+        expected: usize,
+    },
+    /// A symbol is outside the alphabet.
+    #[error("symbol {symbol} at position {position} is outside an alphabet of {alphabet}")]
+    SymbolOutOfRange {
+        /// Index in the word.
+        position: usize,
+        /// The symbol.
+        symbol: usize,
+        /// Alphabet size.
+        alphabet: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn check_segments(len: usize, segments: usize) -> Result<()> {
+    if segments == 0 || segments > len {
+        return Err(Error::InvalidSegments { segments, len });
+    }
+    Ok(())
+}
+
+/// Means of `segments` consecutive near-equal parts of `x`.
+pub fn paa(x: &[f64], segments: usize) -> Result<Vec<f64>> {
+    check_segments(x.len(), segments)?;
+    Ok(segments_of(x, segments)
+        .map(|s| s.iter().sum::<f64>() / s.len() as f64)
+        .collect())
+}
+
+/// Standard normal quantile (Acklam's rational approximation, relative error below
+/// `1.2e-9`) for `p` in `(0, 0.5]`.
+fn normal_quantile_lower(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let horner = |coef: &[f64], x: f64| coef.iter().fold(0.0, |acc, &c| acc * x + c);
+    if p < 0.02425 {
+        let q = (-2.0 * p.ln()).sqrt();
+        horner(&C, q) / (horner(&D, q) * q + 1.0)
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        horner(&A, r) * q / (horner(&B, r) * r + 1.0)
+    }
+}
+
+/// The `alphabet - 1` breakpoints cutting the standard normal into `alphabet` equiprobable
+/// regions (the classic SAX table), exactly antisymmetric about zero.
+pub fn gaussian_breakpoints(alphabet: usize) -> Result<Vec<f64>> {
+    if alphabet < 2 {
+        return Err(Error::InvalidAlphabet(alphabet));
+    }
+    Ok((1..alphabet)
+        .map(|k| match (2 * k).cmp(&alphabet) {
+            std::cmp::Ordering::Less => normal_quantile_lower(k as f64 / alphabet as f64),
+            std::cmp::Ordering::Equal => 0.0,
+            std::cmp::Ordering::Greater => {
+                -normal_quantile_lower((alphabet - k) as f64 / alphabet as f64)
+            }
+        })
+        .collect())
+}
+
+/// SAX symbolization: PAA over a fixed number of segments, then one symbol per mean.
+///
+/// Symbol `a` covers `[breakpoints[a - 1], breakpoints[a])`, with the outer regions open.
+/// Series are taken as given: z-normalize them first for the equiprobable default table,
+/// and symbolize a query and its candidates on the same scale for the lower bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sax {
+    segments: usize,
+    breakpoints: Vec<f64>,
+}
+
+impl Sax {
+    /// `segments` symbols per word over an alphabet of `alphabet` with
+    /// [`gaussian_breakpoints`].
+    pub fn new(segments: usize, alphabet: usize) -> Result<Self> {
+        Self::with_breakpoints(segments, gaussian_breakpoints(alphabet)?)
+    }
+
+    /// `segments` symbols per word with custom breakpoints (an alphabet of
+    /// `breakpoints.len() + 1`).
+    pub fn with_breakpoints(segments: usize, breakpoints: Vec<f64>) -> Result<Self> {
+        if segments == 0 {
+            return Err(Error::InvalidSegments { segments, len: 0 });
+        }
+        let increasing = breakpoints.windows(2).all(|w| w[0] < w[1]);
+        if breakpoints.is_empty() || !increasing || breakpoints.iter().any(|b| !b.is_finite()) {
+            return Err(Error::InvalidBreakpoints);
+        }
+        Ok(Self {
+            segments,
+            breakpoints,
+        })
+    }
+
+    /// Symbols per word.
+    pub fn segments(&self) -> usize {
+        self.segments
+    }
+
+    /// Alphabet size.
+    pub fn alphabet(&self) -> usize {
+        self.breakpoints.len() + 1
+    }
+
+    /// Breakpoints between consecutive symbols.
+    pub fn breakpoints(&self) -> &[f64] {
+        &self.breakpoints
+    }
+
+    /// Symbol of a single value.
+    pub fn symbol(&self, v: f64) -> usize {
+        self.breakpoints.partition_point(|&b| b <= v)
+    }
+
+    /// SAX word of `x` (at least [`segments`](Self::segments) long).
+    pub fn word(&self, x: &[f64]) -> Result<Vec<usize>> {
+        Ok(paa(x, self.segments)?
+            .into_iter()
+            .map(|v| self.symbol(v))
+            .collect())
+    }
+
+    /// Interval `[lo, hi]` of values mapped to symbol `a` (infinite at the ends).
+    fn region(&self, a: usize) -> (f64, f64) {
+        let lo = a
+            .checked_sub(1)
+            .map_or(f64::NEG_INFINITY, |k| self.breakpoints[k]);
+        let hi = self.breakpoints.get(a).copied().unwrap_or(f64::INFINITY);
+        (lo, hi)
+    }
+
+    /// Gap between the regions of symbols `a` and `b`: zero for equal or adjacent symbols.
+    fn gap(&self, a: usize, b: usize) -> f64 {
+        let (lo, hi) = (a.min(b), a.max(b));
+        if hi - lo <= 1 {
+            0.0
+        } else {
+            self.breakpoints[hi - 1] - self.breakpoints[lo]
+        }
+    }
+
+    /// Squared gaps between symbol regions: a lower bound on the squared difference of any
+    /// two values with those symbols, as local costs for symbol-sequence Soft-DTW.
+    pub fn substitution_matrix(&self) -> SubstitutionMatrix {
+        SubstitutionMatrix::from_fn(self.alphabet(), |a, b| self.gap(a, b).powi(2))
+            .expect("squared gaps between finite breakpoints are never NaN")
+    }
+
+    fn check_word(&self, word: &[usize]) -> Result<()> {
+        if word.len() != self.segments {
+            return Err(Error::WordLength {
+                len: word.len(),
+                expected: self.segments,
+            });
+        }
+        match word.iter().position(|&a| a >= self.alphabet()) {
+            Some(position) => Err(Error::SymbolOutOfRange {
+                position,
+                symbol: word[position],
+                alphabet: self.alphabet(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Segment lengths of a series of length `len`.
+    fn segment_lengths(&self, len: usize) -> impl Iterator<Item = f64> + '_ {
+        (0..self.segments)
+            .map(move |k| ((k + 1) * len / self.segments - k * len / self.segments) as f64)
+    }
+
+    /// MINDIST between the words of two series of length `len`: a lower bound on their
+    /// Euclidean distance, with each segment's gap weighted by its length.
+    pub fn mindist(&self, a: &[usize], b: &[usize], len: usize) -> Result<f64> {
+        check_segments(len, self.segments)?;
+        self.check_word(a)?;
+        self.check_word(b)?;
+        Ok(a.iter()
+            .zip(b)
+            .zip(self.segment_lengths(len))
+            .map(|((&a, &b), w)| w * self.gap(a, b).powi(2))
+            .sum::<f64>()
+            .sqrt())
+    }
+
+    /// Lower bound on the banded DTW (squared differences, `|i - j| <= band`, as in
+    /// [`crate::nearest_neighbor`]) between `query` and any series of the same length whose
+    /// word is `word`: LB_PAA with each segment mean only known up to its symbol's region.
+    pub fn lb_dtw(&self, query: &[f64], word: &[usize], band: usize) -> Result<f64> {
+        check_segments(query.len(), self.segments)?;
+        self.check_word(word)?;
+        let (upper, lower) = envelope(query, band);
+        Ok(segments_of(&upper, self.segments)
+            .zip(segments_of(&lower, self.segments))
+            .zip(word)
+            .map(|((u, l), &a)| {
+                let hi = u.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                let lo = l.iter().copied().fold(f64::INFINITY, f64::min);
+                let (r_lo, r_hi) = self.region(a);
+                let gap = (r_lo - hi).max(lo - r_hi).max(0.0);
+                u.len() as f64 * gap * gap
+            })
+            .sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nearest_neighbor::dtw_bounded;
+    use crate::soft_dtw::soft_dtw_symbols;
+
+    #[test]
+    fn breakpoints_words_and_bounds() {
+        let b = gaussian_breakpoints(4).unwrap();
+        assert!((b[0] + 0.6744897501960817).abs() < 1e-8 && b[1] == 0.0 && b[2] == -b[0]);
+        let b = gaussian_breakpoints(3).unwrap();
+        assert!((b[1] - 0.4307272992954576).abs() < 1e-8 && b[0] == -b[1]);
+        let b = gaussian_breakpoints(100).unwrap();
+        assert!((b[0] + 2.3263478740408408).abs() < 1e-8);
+        assert_eq!(gaussian_breakpoints(1), Err(Error::InvalidAlphabet(1)));
+
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        assert_eq!(paa(&x, 3).unwrap(), vec![1.5, 3.5, 6.0]);
+        assert_eq!(
+            paa(&x, 8),
+            Err(Error::InvalidSegments {
+                segments: 8,
+                len: 7
+            })
+        );
+
+        let sax = Sax::new(6, 5).unwrap();
+        let wave = |t: usize, phase: f64| (t as f64 * 0.3 + phase).sin() * 1.3;
+        let series: Vec<Vec<f64>> = (0..8)
+            .map(|p| (0..30).map(|t| wave(t, p as f64 * 0.7)).collect())
+            .collect();
+        let words: Vec<Vec<usize>> = series.iter().map(|s| sax.word(s).unwrap()).collect();
+        let sub = sax.substitution_matrix();
+        for (s, a) in series.iter().zip(&words) {
+            for (t, b) in series.iter().zip(&words) {
+                let euclid = s.iter().zip(t).map(|(u, v)| (u - v).powi(2)).sum::<f64>();
+                assert!(sax.mindist(a, b, 30).unwrap().powi(2) <= euclid + 1e-12);
+                let dtw = dtw_bounded(s, t, 3, f64::INFINITY).unwrap().unwrap();
+                assert!(sax.lb_dtw(s, b, 3).unwrap() <= dtw + 1e-12);
+                assert!(soft_dtw_symbols(a, b, &sub, 0.1).is_ok());
+            }
+        }
+        assert!(sax.lb_dtw(&series[0], &words[4], 1).unwrap() > 0.0);
+
+        assert_eq!(
+            sax.mindist(&words[0][1..], &words[1], 30),
+            Err(Error::WordLength {
+                len: 5,
+                expected: 6
+            })
+        );
+        assert_eq!(
+            sax.lb_dtw(&series[0], &[0, 1, 2, 3, 4, 5], 1),
+            Err(Error::SymbolOutOfRange {
+                position: 5,
+                symbol: 5,
+                alphabet: 5
+            })
+        );
+        assert_eq!(
+            Sax::with_breakpoints(2, vec![0.0, 0.0]),
+            Err(Error::InvalidBreakpoints)
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Soft segmental DP: sequence chunking with bounded segment length.
+//!
+//! A segmentation of positions `0..n` is a sequence of contiguous spans `[start, end)`, each
+//! of length at most `max_len`, covering every position exactly once. A caller-provided
+//! function scores each span, and the soft value is
+//! \(V_\gamma = -\gamma \log \sum_{\text{segmentations}} \exp(-\sum_{\text{spans}} c(s, e)
+//! / \gamma)\). A forward/backward pass over span boundaries gives \(V_\gamma\) and the
+//! marginal probability of every span (its gradient), in `O(n * max_len)` evaluations of the
+//! cost, each made once.
+//!
+//! This is the shared core of semi-Markov CRFs (fold the label choice into the span cost, or
+//! run one pass per label set), word segmentation and phrase extraction.
+//!
+//! ```
+//! use structop::segmental::soft_segmentation;
+//!
+//! // Splitting "aab" into "aa" + "b" is cheap, every other span costs 1 per position.
+//! let cheap = [(0, 2), (2, 3)];
+//! let cost = |s: usize, e: usize| if cheap.contains(&(s, e)) { 0.0 } else { (e - s) as f64 };
+//! let seg = soft_segmentation(3, 2, 0.1, cost).unwrap();
+//! let best = seg.spans.iter().find(|p| (p.start, p.end) == (0, 2)).unwrap();
+//! assert!(best.prob > 0.99);
+//! ```
+
+use crate::math::{exp, ln};
+
+/// Errors for segmental DPs.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Smoothing parameter \(\gamma\) must be positive and finite.
+    #[error("gamma must be positive and finite, got {0}")]
+    InvalidGamma(f64),
+    /// The sequence must be non-empty.
+    #[error("empty sequence")]
+    EmptyInput,
+    /// The maximum segment length must be positive.
+    #[error("maximum segment length must be positive")]
+    ZeroMaxLen,
+    /// A span cost is NaN or `-inf` (`+inf` forbids the span).
+    #[error("span [{start}, {end}) has invalid cost {cost}")]
+    InvalidCost {
+        /// Span start.
+        start: usize,
+        /// Span end (exclusive).
+        end: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// No segmentation has finite cost.
+    #[error("no segmentation has finite cost")]
+    NoPath,
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// One span `[start, end)` with its marginal probability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpanMarginal {
+    /// First position of the span.
+    pub start: usize,
+    /// One past the last position.
+    pub end: usize,
+    /// Probability that a segmentation uses the span (the gradient w.r.t. its cost).
+    pub prob: f64,
+}
+
+/// Output of [`soft_segmentation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segmentation {
+    /// Soft value \(V_\gamma\).
+    pub value: f64,
+    /// Every span of length `1..=max_len`, ordered by start then end.
+    pub spans: Vec<SpanMarginal>,
+}
+
+/// `-gamma * log(sum(exp(-a / gamma)))` over the finite candidates, `+inf` if none.
+fn softmin(gamma: f64, candidates: impl Iterator<Item = f64> + Clone) -> f64 {
+    let best = candidates.clone().fold(f64::INFINITY, f64::min);
+    if !best.is_finite() {
+        return f64::INFINITY;
+    }
+    let sum: f64 = candidates.map(|a| exp(-(a - best) / gamma)).sum();
+    best - gamma * ln(sum)
+}
+
+/// Soft value and span marginals of segmenting `n` positions into spans of length at most
+/// `max_len`, with `cost(start, end)` the cost of span `[start, end)`.
+pub fn soft_segmentation<F>(n: usize, max_len: usize, gamma: f64, cost: F) -> Result<Segmentation>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if n == 0 {
+        return Err(Error::EmptyInput);
+    }
+    if max_len == 0 {
+        return Err(Error::ZeroMaxLen);
+    }
+    let max_len = max_len.min(n);
+    // costs[s * max_len + (len - 1)]: cost of [s, s + len), +inf past the end.
+    let mut costs = vec![f64::INFINITY; n * max_len];
+    for start in 0..n {
+        for end in start + 1..=(start + max_len).min(n) {
+            let c = cost(start, end);
+            if c.is_nan() || c == f64::NEG_INFINITY {
+                return Err(Error::InvalidCost {
+                    start,
+                    end,
+                    cost: c,
+                });
+            }
+            costs[start * max_len + end - start - 1] = c;
+        }
+    }
+    let c = |s: usize, e: usize| costs[s * max_len + e - s - 1];
+
+    // alpha[e]: soft cost of segmenting 0..e; beta[s]: of segmenting s..n.
+    let mut alpha = vec![f64::INFINITY; n + 1];
+    alpha[0] = 0.0;
+    for e in 1..=n {
+        let starts = e.saturating_sub(max_len)..e;
+        alpha[e] = softmin(gamma, starts.map(|s| alpha[s] + c(s, e)));
+    }
+    let value = alpha[n];
+    if !value.is_finite() {
+        return Err(Error::NoPath);
+    }
+    let mut beta = vec![f64::INFINITY; n + 1];
+    beta[n] = 0.0;
+    for s in (0..n).rev() {
+        let ends = s + 1..=(s + max_len).min(n);
+        beta[s] = softmin(gamma, ends.map(|e| c(s, e) + beta[e]));
+    }
+
+    let mut spans = Vec::with_capacity(n * max_len);
+    for (start, &a) in alpha[..n].iter().enumerate() {
+        let ends = start + 1..=(start + max_len).min(n);
+        for (end, &b) in ends.clone().zip(&beta[ends]) {
+            let z = a + c(start, end) + b - value;
+            spans.push(SpanMarginal {
+                start,
+                end,
+                prob: if z.is_finite() { exp(-z / gamma) } else { 0.0 },
+            });
+        }
+    }
+    Ok(Segmentation { value, spans })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(s: usize, e: usize) -> f64 {
+        ((s * 7 + e * 3) % 5) as f64 * 0.4 + 0.1 * (e - s) as f64
+    }
+
+    /// All segmentations of `s..n` into spans of length at most `max_len`.
+    fn segmentations(s: usize, n: usize, max_len: usize) -> Vec<Vec<(usize, usize)>> {
+        if s == n {
+            return vec![vec![]];
+        }
+        let mut out = Vec::new();
+        for e in s + 1..=(s + max_len).min(n) {
+            for mut rest in segmentations(e, n, max_len) {
+                rest.insert(0, (s, e));
+                out.push(rest);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn matches_enumeration_and_covers_each_position_once() {
+        let (n, max_len, gamma) = (6, 3, 0.7);
+        let seg = soft_segmentation(n, max_len, gamma, cost).unwrap();
+        let all = segmentations(0, n, max_len);
+        let weight =
+            |s: &[(usize, usize)]| (-s.iter().map(|&(a, b)| cost(a, b)).sum::<f64>() / gamma).exp();
+        let z: f64 = all.iter().map(|s| weight(s)).sum();
+        assert!((seg.value + gamma * z.ln()).abs() < 1e-12);
+        for span in &seg.spans {
+            let want: f64 = all
+                .iter()
+                .filter(|s| s.contains(&(span.start, span.end)))
+                .map(|s| weight(s) / z)
+                .sum();
+            assert!((span.prob - want).abs() < 1e-12);
+        }
+        for i in 0..n {
+            let covered: f64 = seg
+                .spans
+                .iter()
+                .filter(|p| p.start <= i && i < p.end)
+                .map(|p| p.prob)
+                .sum();
+            assert!((covered - 1.0).abs() < 1e-12);
+        }
+        assert_eq!(seg.spans.len(), 3 + 3 + 3 + 3 + 2 + 1);
+    }
+
+    #[test]
+    fn forbidden_spans_and_invalid_inputs() {
+        // Only length-2 spans are allowed, so odd lengths have no segmentation.
+        let pairs = |s: usize, e: usize| if e - s == 2 { 0.0 } else { f64::INFINITY };
+        let seg = soft_segmentation(4, 3, 1.0, pairs).unwrap();
+        let used: Vec<_> = seg
+            .spans
+            .iter()
+            .filter(|p| p.prob > 0.0)
+            .map(|p| (p.start, p.end))
+            .collect();
+        assert_eq!(used, [(0, 2), (2, 4)]);
+        assert_eq!(soft_segmentation(3, 3, 1.0, pairs), Err(Error::NoPath));
+        assert_eq!(soft_segmentation(0, 3, 1.0, pairs), Err(Error::EmptyInput));
+        assert_eq!(soft_segmentation(3, 0, 1.0, pairs), Err(Error::ZeroMaxLen));
+        assert!(matches!(
+            soft_segmentation(3, 2, 1.0, |_, _| f64::NAN),
+            Err(Error::InvalidCost {
+                start: 0,
+                end: 1,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! shapeDTW: (soft) DTW over local shape descriptors.
+//!
+//! Plain DTW compares points by value alone, so it readily aligns points that have similar
+//! values but sit in different local shapes (a peak against a slope). shapeDTW (Zhao & Itti
+//! 2018) describes each point by the subsequence around it and runs DTW on distances
+//! between descriptors instead:
+//!
+//! 1. the window of half-width `radius` around point `i` (ends padded by repeating the
+//!    first and last points), so each sequence keeps one descriptor per point;
+//! 2. a [`Descriptor`] of the window: the raw values, per-segment least-squares slopes, or
+//!    per-segment means (PAA);
+//! 3. the squared Euclidean distance between descriptors as the cost matrix of a
+//!    [`SoftDtw`], whose band, step pattern and other options all apply.
+//!
+//! Alignments are over the original points. Slope descriptors ignore offsets, so the
+//! distance is invariant to adding a constant to either sequence.
+//!
+//! ```
+//! use structop::shape_dtw::{Descriptor, ShapeDtw};
+//! use structop::soft_dtw::SoftDtw;
+//!
+//! let op = ShapeDtw::new(SoftDtw::new(0.1), 2).descriptor(Descriptor::Slope { segments: 2 });
+//! let x = [0.0, 1.0, 3.0, 1.0, 0.0, 0.0];
+//! let y = [5.0, 5.0, 6.0, 8.0, 6.0, 5.0];
+//! let (v, e) = op.alignment(&x, &y).unwrap();
+//! assert_eq!(e.len(), 36);
+//! assert!(v.is_finite());
+//! ```
+
+use crate::soft_dtw::{self, SoftDtw};
+
+/// Errors for shapeDTW.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Segment count must be between 1 and the window width `2 * radius + 1`.
+    #[error("{segments} segments do not fit a window of width {width}")]
+    InvalidSegments {
+        /// Requested segment count.
+        segments: usize,
+        /// Window width.
+        width: usize,
+    },
+    /// Error from the underlying Soft-DTW.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Local shape descriptor of a window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Descriptor {
+    /// The window's values.
+    #[default]
+    Raw,
+    /// Least-squares slope of each of `segments` near-equal parts of the window.
+    Slope {
+        /// Number of parts.
+        segments: usize,
+    },
+    /// Mean of each of `segments` near-equal parts of the window (piecewise aggregate
+    /// approximation).
+    Paa {
+        /// Number of parts.
+        segments: usize,
+    },
+}
+
+/// shapeDTW operator: a [`SoftDtw`] applied to local shape descriptors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapeDtw {
+    op: SoftDtw,
+    radius: usize,
+    descriptor: Descriptor,
+}
+
+impl ShapeDtw {
+    /// shapeDTW with windows of half-width `radius` and raw-window descriptors, aligned by
+    /// `op`.
+    pub fn new(op: SoftDtw, radius: usize) -> Self {
+        Self {
+            op,
+            radius,
+            descriptor: Descriptor::Raw,
+        }
+    }
+
+    /// Descriptor of each window.
+    pub fn descriptor(mut self, descriptor: Descriptor) -> Self {
+        self.descriptor = descriptor;
+        self
+    }
+
+    /// Window width `2 * radius + 1`.
+    pub fn width(&self) -> usize {
+        2 * self.radius + 1
+    }
+
+    /// Length of one descriptor.
+    pub fn dim(&self) -> Result<usize> {
+        let width = self.width();
+        match self.descriptor {
+            Descriptor::Raw => Ok(width),
+            Descriptor::Slope { segments } | Descriptor::Paa { segments } => {
+                if segments == 0 || segments > width {
+                    return Err(Error::InvalidSegments { segments, width });
+                }
+                Ok(segments)
+            }
+        }
+    }
+
+    /// Descriptors of every point of `x`, row-major `x.len() × dim()`.
+    pub fn descriptors(&self, x: &[f64]) -> Result<Vec<f64>> {
+        let dim = self.dim()?;
+        let width = self.width();
+        let mut out = Vec::with_capacity(x.len() * dim);
+        let mut window = Vec::with_capacity(width);
+        for i in 0..x.len() {
+            window.clear();
+            window.extend((0..width).map(|k| {
+                let t = (i + k).saturating_sub(self.radius).min(x.len() - 1);
+                x[t]
+            }));
+            match self.descriptor {
+                Descriptor::Raw => out.extend_from_slice(&window),
+                Descriptor::Slope { segments } => {
+                    out.extend(segments_of(&window, segments).map(slope));
+                }
+                Descriptor::Paa { segments } => {
+                    out.extend(
+                        segments_of(&window, segments)
+                            .map(|s| s.iter().sum::<f64>() / s.len() as f64),
+                    );
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Cost matrix (row-major `x.len() × y.len()`): squared Euclidean distances between
+    /// the descriptors of `x` and of `y`.
+    pub fn cost_matrix(&self, x: &[f64], y: &[f64]) -> Result<Vec<f64>> {
+        let dim = self.dim()?;
+        let (dx, dy) = (self.descriptors(x)?, self.descriptors(y)?);
+        Ok(dx
+            .chunks(dim)
+            .flat_map(|a| {
+                dy.chunks(dim)
+                    .map(move |b| a.iter().zip(b).map(|(p, q)| (p - q) * (p - q)).sum::<f64>())
+            })
+            .collect())
+    }
+
+    /// shapeDTW value between `x` and `y`.
+    pub fn compute(&self, x: &[f64], y: &[f64]) -> Result<f64> {
+        let cost = self.cost_matrix(x, y)?;
+        Ok(self.op.compute_cost(&cost, x.len(), y.len())?)
+    }
+
+    /// shapeDTW value and the expected alignment of the points of `x` and `y` (row-major
+    /// `x.len() × y.len()`).
+    pub fn alignment(&self, x: &[f64], y: &[f64]) -> Result<(f64, Vec<f64>)> {
+        let cost = self.cost_matrix(x, y)?;
+        Ok(self.op.alignment(&cost, x.len(), y.len())?)
+    }
+}
+
+/// `segments` consecutive near-equal parts of `window` (`1 <= segments <= window.len()`).
+fn segments_of(window: &[f64], segments: usize) -> impl Iterator<Item = &[f64]> {
+    let len = window.len();
+    (0..segments).map(move |k| &window[k * len / segments..(k + 1) * len / segments])
+}
+
+/// Least-squares slope of `v` against `0..v.len()`; zero for a single point.
+fn slope(v: &[f64]) -> f64 {
+    let len = v.len() as f64;
+    let t_mean = (len - 1.0) / 2.0;
+    let v_mean = v.iter().sum::<f64>() / len;
+    let (num, den) = v
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(num, den), (t, &y)| {
+            let dt = t as f64 - t_mean;
+            (num + dt * (y - v_mean), den + dt * dt)
+        });
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptors_describe_padded_windows() {
+        let x = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let raw = ShapeDtw::new(SoftDtw::new(0.1), 1);
+        assert_eq!(
+            &raw.descriptors(&x).unwrap()[..6],
+            &[0.0, 0.0, 1.0, 0.0, 1.0, 2.0]
+        );
+        let slopes = raw.descriptor(Descriptor::Slope { segments: 1 });
+        assert_eq!(slopes.descriptors(&x), Ok(vec![0.5, 1.0, 1.0, 1.0, 0.5]));
+        let paa = ShapeDtw::new(SoftDtw::new(0.1), 2).descriptor(Descriptor::Paa { segments: 2 });
+        // Window [0, 0, 0, 1, 2] splits as [0, 0] and [0, 1, 2].
+        assert_eq!(&paa.descriptors(&x).unwrap()[..2], &[0.0, 1.0]);
+        assert_eq!(
+            paa.descriptor(Descriptor::Paa { segments: 6 }).dim(),
+            Err(Error::InvalidSegments {
+                segments: 6,
+                width: 5
+            })
+        );
+    }
+
+    #[test]
+    fn radius_zero_is_soft_dtw_and_slopes_ignore_offsets() {
+        let (x, y) = ([0.0, 1.0, 3.0, 1.0, 0.0], [0.5, 2.5, 3.0, 0.0]);
+        let op = ShapeDtw::new(SoftDtw::new(0.3), 0);
+        assert_eq!(
+            op.compute(&x, &y),
+            soft_dtw::soft_dtw(&x, &y, 0.3).map_err(Error::from)
+        );
+
+        let op = ShapeDtw::new(SoftDtw::new(0.3), 2).descriptor(Descriptor::Slope { segments: 2 });
+        let shifted: Vec<f64> = y.iter().map(|v| v + 10.0).collect();
+        let (v, e) = op.alignment(&x, &y).unwrap();
+        let (w, f) = op.alignment(&x, &shifted).unwrap();
+        assert!((v - w).abs() < 1e-9);
+        assert!(e.iter().zip(&f).all(|(a, b)| (a - b).abs() < 1e-9));
+        assert_eq!(
+            op.compute(&[], &y),
+            Err(Error::SoftDtw(soft_dtw::Error::EmptyInput))
+        );
+    }
+}
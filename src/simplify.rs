@@ -0,0 +1,192 @@
+//! Value-preserving simplification of soft shortest-path graphs.
+//!
+//! Large lattices are mostly dead structure: nodes no source-to-sink path visits, and chains
+//! of nodes with one way in and one way out. [`simplify`] removes both before the DP runs:
+//!
+//! - nodes outside `source..=sink`, unreachable from the source or unable to reach the sink
+//!   are pruned with their edges (no path uses them, so their marginals are 0);
+//! - every other node with exactly one incoming and one outgoing edge is contracted, the two
+//!   edges becoming one whose cost is their sum (paths map one-to-one, so the Gibbs
+//!   distribution is unchanged).
+//!
+//! The simplified graph therefore has the same value at every \(\gamma\), and each of its
+//! edges records the original edges it stands for: every one of them has the merged edge's
+//! marginal, which [`Simplified::expand_marginals`] maps back.
+//!
+//! ```
+//! use structop::simplify::simplify;
+//! use structop::soft_shortest_path::{Edge, Graph};
+//!
+//! let edges = vec![
+//!     Edge { from: 0, to: 1, cost: 1.0 },
+//!     Edge { from: 1, to: 2, cost: 1.0 },
+//!     Edge { from: 0, to: 2, cost: 2.5 },
+//!     Edge { from: 1, to: 3, cost: 0.0 }, // dead end
+//! ];
+//! let graph = Graph::new(4, edges, 0, 2).unwrap();
+//! let s = simplify(&graph).unwrap();
+//! assert_eq!((s.graph.n(), s.graph.edges().len()), (2, 2));
+//! let (v, p) = s.graph.edge_marginals(0.5).unwrap();
+//! let (v_ref, p_ref) = graph.edge_marginals(0.5).unwrap();
+//! assert!((v - v_ref).abs() < 1e-12);
+//! let p = s.expand_marginals(&p).unwrap();
+//! assert!(p.iter().zip(&p_ref).all(|(a, b)| (a - b).abs() < 1e-12));
+//! ```
+
+use crate::soft_shortest_path::{Edge, Error, Graph, Result};
+
+/// A simplified graph with the mapping back to the original.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simplified {
+    /// The simplified graph; its source is node 0 and its sink the last node.
+    pub graph: Graph,
+    /// For each simplified edge, the original edges it stands for (in path order).
+    pub edge_origins: Vec<Vec<usize>>,
+    /// For each original node, its index in the simplified graph (`None` if removed).
+    pub node_map: Vec<Option<usize>>,
+    original_edges: usize,
+}
+
+impl Simplified {
+    /// Per-original-edge marginals from marginals of the simplified graph (0 for removed
+    /// edges).
+    pub fn expand_marginals(&self, marginals: &[f64]) -> Result<Vec<f64>> {
+        if marginals.len() != self.edge_origins.len() {
+            return Err(Error::GradientLengthMismatch {
+                len: marginals.len(),
+                expected: self.edge_origins.len(),
+            });
+        }
+        let mut out = vec![0.0; self.original_edges];
+        for (origins, &p) in self.edge_origins.iter().zip(marginals) {
+            for &k in origins {
+                out[k] = p;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Prunes dead nodes and contracts one-in/one-out chains of `graph`.
+pub fn simplify(graph: &Graph) -> Result<Simplified> {
+    let (n, s, t) = (graph.n(), graph.source(), graph.sink());
+    let edges = graph.edges();
+
+    // Reachability forward from the source and backward from the sink, in node order.
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by_key(|&k| edges[k].from);
+    let mut reach = vec![false; n];
+    reach[s] = true;
+    for &k in &order {
+        let e = edges[k];
+        if reach[e.from] && e.to <= t {
+            reach[e.to] = true;
+        }
+    }
+    let mut live = vec![false; n];
+    live[t] = reach[t];
+    for &k in order.iter().rev() {
+        let e = edges[k];
+        if live[e.to] && reach[e.from] {
+            live[e.from] = true;
+        }
+    }
+    live[s] = true;
+    live[t] = true;
+
+    // Each slot holds a (possibly merged) edge and the original edges it covers.
+    let mut slots: Vec<Option<(Edge, Vec<usize>)>> = Vec::new();
+    let mut ins = vec![Vec::new(); n];
+    let mut outs = vec![Vec::new(); n];
+    for (k, e) in edges.iter().enumerate() {
+        if live[e.from] && live[e.to] && reach[e.from] {
+            ins[e.to].push(slots.len());
+            outs[e.from].push(slots.len());
+            slots.push(Some((*e, vec![k])));
+        }
+    }
+    for v in s + 1..t {
+        if !(live[v] && ins[v].len() == 1 && outs[v].len() == 1) {
+            continue;
+        }
+        let (a, b) = (ins[v][0], outs[v][0]);
+        let (next, tail) = slots[b].take().expect("live slot");
+        let (edge, origins) = slots[a].as_mut().expect("live slot");
+        edge.to = next.to;
+        edge.cost += next.cost;
+        origins.extend(tail);
+        for slot in ins[next.to].iter_mut().filter(|slot| **slot == b) {
+            *slot = a;
+        }
+        live[v] = false;
+    }
+
+    let mut node_map = vec![None; n];
+    let mut kept = 0;
+    for v in 0..n {
+        if live[v] {
+            node_map[v] = Some(kept);
+            kept += 1;
+        }
+    }
+    let index = |v: usize| node_map[v].expect("endpoints of kept edges are kept");
+    let (mut new_edges, mut edge_origins) = (Vec::new(), Vec::new());
+    for (edge, origins) in slots.into_iter().flatten() {
+        new_edges.push(Edge {
+            from: index(edge.from),
+            to: index(edge.to),
+            cost: edge.cost,
+        });
+        edge_origins.push(origins);
+    }
+    Ok(Simplified {
+        graph: Graph::new(kept, new_edges, 0, kept - 1)?,
+        edge_origins,
+        node_map,
+        original_edges: edges.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simplified_lattice_keeps_value_and_marginals() {
+        let e = |from, to, cost| Edge { from, to, cost };
+        // Source 1, sink 8; node 0 precedes the source, 9 follows the sink, 5 is a dead end,
+        // 7 is unreachable, and 2-3-4 and 6 form chains.
+        let edges = vec![
+            e(0, 1, 0.4),
+            e(1, 2, 0.5),
+            e(2, 3, 0.1),
+            e(3, 4, 0.2),
+            e(4, 8, 0.3),
+            e(1, 6, 0.7),
+            e(6, 8, 0.6),
+            e(1, 8, 1.6),
+            e(2, 5, 0.0),
+            e(7, 8, 0.0),
+            e(8, 9, 0.0),
+            e(1, 4, 0.9),
+        ];
+        let graph = Graph::new(10, edges, 1, 8).unwrap();
+        let s = simplify(&graph).unwrap();
+        // Left: source, 4 (two ways in) and sink.
+        assert_eq!(s.graph.n(), 3);
+        assert_eq!(
+            s.edge_origins,
+            vec![vec![1, 2, 3], vec![4], vec![5, 6], vec![7], vec![11]]
+        );
+        assert_eq!(s.node_map[4], Some(1));
+        assert_eq!(s.node_map[5], None);
+        for gamma in [0.05, 0.5, 3.0] {
+            let (v_ref, p_ref) = graph.edge_marginals(gamma).unwrap();
+            let (v, p) = s.graph.edge_marginals(gamma).unwrap();
+            assert!((v - v_ref).abs() < 1e-12);
+            let p = s.expand_marginals(&p).unwrap();
+            assert!(p.iter().zip(&p_ref).all(|(a, b)| (a - b).abs() < 1e-12));
+        }
+        assert!(s.expand_marginals(&[0.0]).is_err());
+    }
+}
@@ -13,6 +13,9 @@
 //! \operatorname{softmin}_\gamma(a,b,c) = -\gamma \log\left(e^{-a/\gamma}+e^{-b/\gamma}+e^{-c/\gamma}\right).
 //! \]
 //!
+//! The gradient w.r.t. the cost matrix is the **expected alignment** \(E=\partial R_{n,m}/\partial C\),
+//! computed by a backward recursion over the same table (`soft_dtw_alignment`).
+//!
 //! Notes:
 //! - `soft_dtw` is not a metric in general.
 //! - `soft_dtw(x,y,γ)` is a smooth relaxation of DTW, but it can be biased (and
@@ -22,6 +25,15 @@
 //!   This is typically nonnegative and is zero on identical inputs (under the
 //!   usual squared-distance setting).
 
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Range;
+
+use crate::math::{exp, ln, softmin_dd, DoubleDouble};
+use crate::soft_shortest_path::{Edge, Graph};
+
 /// Errors for Soft-DTW operators.
 #[derive(thiserror::Error, Debug, Clone, PartialEq)]
 pub enum Error {
@@ -43,6 +55,125 @@ pub enum Error {
         /// `n*m`, included explicitly for readability.
         expected: usize,
     },
+    /// A tensor argument has the wrong number of dimensions.
+    #[error("tensor has {rank} dimensions, expected {expected}")]
+    TensorRank {
+        /// Dimensions of the given tensor.
+        rank: usize,
+        /// Dimensions the operator takes.
+        expected: usize,
+    },
+    /// Batch inputs must pair up one-to-one.
+    #[error("batch length mismatch: {xs} left sequences vs {ys} right sequences")]
+    BatchLengthMismatch {
+        /// Number of left-hand sequences.
+        xs: usize,
+        /// Number of right-hand sequences.
+        ys: usize,
+    },
+    /// The band excludes every warping path from `(0, 0)` to `(n-1, m-1)`.
+    #[error("band of half-width {band} leaves no warping path for a {n}x{m} cost matrix")]
+    NoWarpingPath {
+        /// Band half-width.
+        band: usize,
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+    /// A cost (or substitution) matrix entry is NaN (the first one in row-major order).
+    /// `+inf` is allowed: it forbids the cell (or the symbol pair).
+    #[error("cost[{i}][{j}] is NaN")]
+    NanCost {
+        /// Row index.
+        i: usize,
+        /// Column index.
+        j: usize,
+    },
+    /// Substitution matrix shape mismatch.
+    #[error("substitution matrix has length {len}, expected {alphabet}*{alphabet}={expected}")]
+    InvalidSubstitutionShape {
+        /// The provided slice length.
+        len: usize,
+        /// Alphabet size.
+        alphabet: usize,
+        /// `alphabet*alphabet`, included explicitly for readability.
+        expected: usize,
+    },
+    /// A symbol is outside `0..alphabet`.
+    #[error("symbol {symbol} at position {position} is out of range for alphabet size {alphabet}")]
+    SymbolOutOfRange {
+        /// Position in the offending sequence.
+        position: usize,
+        /// The offending symbol.
+        symbol: usize,
+        /// Alphabet size.
+        alphabet: usize,
+    },
+    /// The `(n+1) × (m+1)` DP table does not fit in memory addressable by `usize`.
+    #[error("dimensions {n}x{m} overflow the DP table size")]
+    DimensionOverflow {
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+    /// A gradient buffer does not have one entry per parameter.
+    #[error("gradient buffer has length {len}, expected {expected}")]
+    GradientLengthMismatch {
+        /// The provided buffer length.
+        len: usize,
+        /// Number of parameters.
+        expected: usize,
+    },
+    /// A window width must be between 1 and the series length.
+    #[error("window width {window} is invalid for a series of length {len}")]
+    InvalidWindow {
+        /// Requested width.
+        window: usize,
+        /// Series length.
+        len: usize,
+    },
+    /// A transition penalty is NaN or `-inf`.
+    #[error("transition penalty must be finite or +inf, got {0}")]
+    InvalidPenalty(f64),
+    /// The strength of a diagonal penalty is not finite and non-negative.
+    #[error("diagonal penalty strength must be finite and non-negative, got {0}")]
+    InvalidDiagonalPenalty(f64),
+    /// A stream checkpoint does not match the reference it is resumed against.
+    #[error("checkpoint row has length {len}, reference has length {expected}")]
+    CheckpointMismatch {
+        /// Length of the checkpointed row.
+        len: usize,
+        /// Length of the reference.
+        expected: usize,
+    },
+    /// A stream checkpoint has consumed no elements but carries a non-empty row.
+    #[error("checkpoint has consumed no elements but carries a row of length {len}")]
+    CheckpointNotStarted {
+        /// Length of the checkpointed row.
+        len: usize,
+    },
+    /// A stream checkpoint row entry is NaN or `-inf`.
+    #[error("checkpoint row entry {index} must be finite or +inf, got {value}")]
+    InvalidCheckpointRow {
+        /// Index of the entry in the row.
+        index: usize,
+        /// The offending value.
+        value: f64,
+    },
+    /// A sample is NaN (missing) and the [`MissingValues`] policy rejects it, or every sample
+    /// of the sequence is missing under [`MissingValues::Expected`].
+    #[error("sample {index} of sequence {sequence} is missing")]
+    MissingSample {
+        /// Which sequence (0 = `x`, 1 = `y`).
+        sequence: usize,
+        /// Index of the first missing sample.
+        index: usize,
+    },
+    /// The substitute cost of [`MissingValues::Cost`] is not finite and non-negative.
+    #[error("missing-sample cost must be finite and non-negative, got {0}")]
+    InvalidMissingCost(f64),
 }
 
 /// Convenience result type for this module.
@@ -59,8 +190,200 @@ fn softmin3(gamma: f64, a: f64, b: f64, c: f64) -> f64 {
         // If all are +∞ in original space, return +∞.
         return f64::INFINITY;
     }
-    let s = (xa - m).exp() + (xb - m).exp() + (xc - m).exp();
-    -gamma * (m + s.ln())
+    let s = exp(xa - m) + exp(xb - m) + exp(xc - m);
+    -gamma * (m + ln(s))
+}
+
+/// Length of the `(n+1) × (m+1)` DP table, checked so that it and its size in bytes fit in
+/// `usize` / `isize` (the allocation limit). Every index into the table (and into the
+/// `n × m` alignment, which is smaller) is then below this length, so the `i * w + j`
+/// arithmetic in the kernels cannot wrap, even on 32-bit targets.
+pub(crate) fn table_len(n: usize, m: usize) -> Result<usize> {
+    n.checked_add(1)
+        .zip(m.checked_add(1))
+        .and_then(|(rows, cols)| rows.checked_mul(cols))
+        .filter(|&len| len <= isize::MAX as usize / std::mem::size_of::<f64>())
+        .ok_or(Error::DimensionOverflow { n, m })
+}
+
+/// Shape and gamma checks shared by the cost-matrix entry points (no scan of the entries).
+fn validate_shape(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    // Before the shape check, which would otherwise compare against a wrapped `n * m`.
+    table_len(n, m)?;
+    if cost.len() != n * m {
+        return Err(Error::InvalidCostShape {
+            len: cost.len(),
+            n,
+            m,
+            expected: n * m,
+        });
+    }
+    Ok(())
+}
+
+/// [`validate_shape`] plus a scan for NaN entries, which would otherwise silently turn the
+/// value and the whole alignment into NaN.
+pub(crate) fn validate_cost(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<()> {
+    validate_shape(cost, n, m, gamma)?;
+    match cost.iter().position(|c| c.is_nan()) {
+        Some(k) => Err(Error::NanCost { i: k / m, j: k % m }),
+        None => Ok(()),
+    }
+}
+
+/// Side of the square tiles the full-table kernels traverse: 64 × 64 cells of `f64` is 32 KiB,
+/// so a tile of the table (and of the cost it reads) stays in L1/L2 while it is filled.
+const TILE: usize = 64;
+
+/// 1-based table indices of tile `t` along an axis of `len` cells.
+fn tile_range(t: usize, len: usize) -> Range<usize> {
+    1 + t * TILE..(1 + (t + 1) * TILE).min(len + 1)
+}
+
+/// Forward DP table `R` of shape `(n+1, m+1)`, row-major, for a cost accessor
+/// `cost(i, j)` over 0-based cell indices.
+///
+/// The accessor form lets callers with strided or non-slice storage share the kernel. The
+/// table is filled tile by tile (tiles in row-major order, cells row-major within a tile),
+/// which respects every dependency and computes each cell exactly as a plain row-major
+/// sweep would, while keeping the working set cache-sized for large `n` and `m`; it also
+/// makes column-major cost input (see [`soft_dtw_cost_col_major`]) read locally.
+pub(crate) fn forward_table<F>(n: usize, m: usize, gamma: f64, cost: F) -> Vec<f64>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    let mut r = Vec::new();
+    forward_table_into(&mut r, n, m, gamma, cost);
+    r
+}
+
+/// [`forward_table`] writing into `r` (cleared and resized), reusing its allocation.
+fn forward_table_into<F>(r: &mut Vec<f64>, n: usize, m: usize, gamma: f64, cost: F)
+where
+    F: Fn(usize, usize) -> f64,
+{
+    // DP table R has shape (n+1, m+1). We store row-major in a flat Vec.
+    // Indexing: r[i*(m+1) + j]
+    let w = m + 1;
+    r.clear();
+    r.resize((n + 1) * (m + 1), f64::INFINITY);
+    r[0] = 0.0;
+
+    for ti in 0..n.div_ceil(TILE) {
+        for tj in 0..m.div_ceil(TILE) {
+            for i in tile_range(ti, n) {
+                for j in tile_range(tj, m) {
+                    let d = cost(i - 1, j - 1);
+                    let a = r[(i - 1) * w + j];
+                    let b = r[i * w + (j - 1)];
+                    let c = r[(i - 1) * w + (j - 1)];
+                    r[i * w + j] = d + softmin3(gamma, a, b, c);
+                }
+            }
+        }
+    }
+}
+
+/// Backward pass: expected alignment matrix `E` (row-major `n × m`) from a forward table.
+///
+/// `E[i,j]` sums, over the three successors `s` of `(i,j)`, `E[s]` times the probability
+/// that the soft-min at `s` selected `(i,j)`: \(\exp((R_s - C_s - R_{ij})/\gamma)\).
+pub(crate) fn alignment_from_table<F>(
+    r: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    cost: F,
+) -> Vec<f64>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    let mut e = Vec::new();
+    alignment_from_table_into(&mut e, r, n, m, gamma, cost);
+    e
+}
+
+/// [`alignment_from_table`] writing into `e` (cleared and resized), reusing its allocation.
+fn alignment_from_table_into<F>(
+    e: &mut Vec<f64>,
+    r: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    cost: F,
+) where
+    F: Fn(usize, usize) -> f64,
+{
+    let w = m + 1;
+    e.clear();
+    e.resize(n * m, 0.0);
+    e[(n - 1) * m + (m - 1)] = 1.0;
+    for ti in (0..n.div_ceil(TILE)).rev() {
+        for tj in (0..m.div_ceil(TILE)).rev() {
+            for i in tile_range(ti, n).rev() {
+                for j in tile_range(tj, m).rev() {
+                    if i == n && j == m {
+                        continue;
+                    }
+                    let rij = r[i * w + j];
+                    let mut acc = 0.0;
+                    for (si, sj) in [(i + 1, j), (i, j + 1), (i + 1, j + 1)] {
+                        if si > n || sj > m {
+                            continue;
+                        }
+                        let es = e[(si - 1) * m + (sj - 1)];
+                        if es == 0.0 {
+                            continue;
+                        }
+                        let z = (r[si * w + sj] - cost(si - 1, sj - 1) - rij) / gamma;
+                        acc += es * exp(z);
+                    }
+                    e[(i - 1) * m + (j - 1)] = acc;
+                }
+            }
+        }
+    }
+}
+
+/// Log of the expected alignment, `ln E[i,j]` (row-major `n × m`), from a forward table.
+///
+/// Computed as \(-(R_{ij} + B_{ij} - R_{nm})/\gamma\) with \(B\) the soft cost of finishing
+/// from `(i,j)` (a reverse DP), so probabilities far below `f64::MIN_POSITIVE` keep their
+/// logarithm instead of flushing to zero; cells on no path get `-inf`.
+fn log_alignment_from_table<F>(r: &[f64], n: usize, m: usize, gamma: f64, cost: F) -> Vec<f64>
+where
+    F: Fn(usize, usize) -> f64,
+{
+    let w = m + 1;
+    let mut b = vec![f64::INFINITY; (n + 1) * w];
+    b[n * w + m] = 0.0;
+    let value = r[n * w + m];
+    let mut out = vec![f64::NEG_INFINITY; n * m];
+    for i in (1..=n).rev() {
+        for j in (1..=m).rev() {
+            if i < n || j < m {
+                let succ = |si: usize, sj: usize| {
+                    if si > n || sj > m {
+                        f64::INFINITY
+                    } else {
+                        cost(si - 1, sj - 1) + b[si * w + sj]
+                    }
+                };
+                b[i * w + j] = softmin3(gamma, succ(i + 1, j), succ(i, j + 1), succ(i + 1, j + 1));
+            }
+            let z = r[i * w + j] + b[i * w + j];
+            if z.is_finite() {
+                out[(i - 1) * m + (j - 1)] = -(z - value) / gamma;
+            }
+        }
+    }
+    out
 }
 
 /// Soft-DTW value for two 1D sequences.
@@ -72,92 +395,2483 @@ pub fn soft_dtw(x: &[f64], y: &[f64], gamma: f64) -> Result<f64> {
         return Err(Error::EmptyInput);
     }
 
-    let n = x.len();
-    let m = y.len();
+    let n = x.len();
+    let m = y.len();
+    table_len(n, m)?;
+    let r = forward_table(n, m, gamma, |i, j| (x[i] - y[j]).powi(2));
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW value and its gradient w.r.t. `x` for two 1D sequences.
+///
+/// With \(d(a,b)=(a-b)^2\), the chain rule through the expected alignment gives
+/// \(\partial \operatorname{softDTW}_\gamma / \partial x_i = \sum_j 2 E_{ij} (x_i - y_j)\).
+/// The gradient w.r.t. `y` is `soft_dtw_grad(y, x, gamma)` (the value is symmetric).
+pub fn soft_dtw_grad(x: &[f64], y: &[f64], gamma: f64) -> Result<(f64, Vec<f64>)> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+
+    let n = x.len();
+    let m = y.len();
+    table_len(n, m)?;
+    let c = |i: usize, j: usize| (x[i] - y[j]).powi(2);
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    let grad = (0..n)
+        .map(|i| (0..m).map(|j| 2.0 * e[i * m + j] * (x[i] - y[j])).sum())
+        .collect();
+    Ok((r[n * (m + 1) + m], grad))
+}
+
+/// [`soft_dtw_grad`] adding `scale` times the gradient into `grad_out` (length `x.len()`)
+/// instead of returning it, for gradient accumulation over a minibatch. Returns the value.
+///
+/// `grad_out` is left untouched on error. [`Workspace::soft_dtw_grad_accumulate`] also
+/// reuses the DP buffers.
+pub fn soft_dtw_grad_accumulate(
+    x: &[f64],
+    y: &[f64],
+    gamma: f64,
+    grad_out: &mut [f64],
+    scale: f64,
+) -> Result<f64> {
+    Workspace::new().soft_dtw_grad_accumulate(x, y, gamma, grad_out, scale)
+}
+
+/// [`soft_dtw_alignment`] adding `scale` times the alignment (the gradient w.r.t. the cost
+/// matrix) into `grad_out` (length `n * m`). Returns the value.
+pub fn soft_dtw_cost_grad_accumulate(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    grad_out: &mut [f64],
+    scale: f64,
+) -> Result<f64> {
+    Workspace::new().soft_dtw_cost_grad_accumulate(cost, n, m, gamma, grad_out, scale)
+}
+
+fn check_grad_len(grad_out: &[f64], expected: usize) -> Result<()> {
+    if grad_out.len() != expected {
+        return Err(Error::GradientLengthMismatch {
+            len: grad_out.len(),
+            expected,
+        });
+    }
+    Ok(())
+}
+
+/// Soft-DTW value given a precomputed cost matrix `cost` (row-major).
+///
+/// This is the more general form used in practice when the elements are not scalars
+/// (e.g. sentence embeddings) and you want \(d(x_i,y_j)\) to be an arbitrary distance.
+///
+/// `cost` must have length `n * m`, storing `cost[i*m + j] = d(x_i, y_j)` for
+/// 0-based indices `i in 0..n`, `j in 0..m`. The first NaN entry is reported as
+/// [`Error::NanCost`]; [`soft_dtw_cost_unchecked`] skips that scan.
+pub fn soft_dtw_cost(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+    validate_cost(cost, n, m, gamma)?;
+
+    let r = forward_table(n, m, gamma, |i, j| cost[i * m + j]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Log-partition \(\log Z = \log \sum_{A} e^{-\langle A, C\rangle/\gamma} =
+/// -\operatorname{softDTW}_\gamma / \gamma\) over all alignments of a cost matrix.
+///
+/// For globally normalized models: the negative log-likelihood of a reference alignment
+/// `A` is `<A, C> / gamma + log_z`, and the gradient of `log_z` w.r.t. the costs is the
+/// alignment from [`soft_dtw_alignment`] scaled by `-1 / gamma`.
+pub fn soft_dtw_log_partition(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+    Ok(-soft_dtw_cost(cost, n, m, gamma)? / gamma)
+}
+
+/// Soft-DTW value and alignment marginals for a precomputed cost matrix.
+///
+/// Returns `(value, alignment)` where `alignment` is row-major `n × m` and
+/// `alignment[i*m + j]` is the expected alignment
+/// \(E_{ij} = \partial \operatorname{softDTW}_\gamma / \partial C_{ij}\), i.e. the
+/// probability that cell `(i, j)` lies on the warping path under the Gibbs
+/// distribution over paths (Cuturi & Blondel 2017, Algorithm 2).
+pub fn soft_dtw_alignment(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<(f64, Vec<f64>)> {
+    validate_cost(cost, n, m, gamma)?;
+    let c = |i: usize, j: usize| cost[i * m + j];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    Ok((r[n * (m + 1) + m], e))
+}
+
+/// [`soft_dtw_alignment`] with the alignment as log-probabilities `ln E[i,j]`.
+///
+/// As \(\gamma\) shrinks, off-path cells of the linear alignment underflow to exactly 0 long
+/// before their log-probabilities stop being informative (e.g. as log-likelihood terms).
+/// These are computed from forward and reverse potentials without exponentiating, so they
+/// stay finite for every cell on some warping path.
+pub fn soft_dtw_log_alignment(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    validate_cost(cost, n, m, gamma)?;
+    let c = |i: usize, j: usize| cost[i * m + j];
+    let r = forward_table(n, m, gamma, c);
+    let e = log_alignment_from_table(&r, n, m, gamma, c);
+    Ok((r[n * (m + 1) + m], e))
+}
+
+/// [`soft_dtw_cost`] without the NaN scan (shape and `gamma` are still checked).
+///
+/// For callers that already guarantee NaN-free costs; a NaN entry makes the value NaN.
+pub fn soft_dtw_cost_unchecked(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+    validate_shape(cost, n, m, gamma)?;
+    let r = forward_table(n, m, gamma, |i, j| cost[i * m + j]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// [`soft_dtw_alignment`] without the NaN scan (shape and `gamma` are still checked).
+///
+/// For callers that already guarantee NaN-free costs; a NaN entry poisons the value and
+/// every alignment entry it can reach.
+pub fn soft_dtw_alignment_unchecked(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    validate_shape(cost, n, m, gamma)?;
+    let c = |i: usize, j: usize| cost[i * m + j];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    Ok((r[n * (m + 1) + m], e))
+}
+
+/// Forward DP table for a precomputed cost matrix: row-major `(n+1) × (m+1)`.
+///
+/// `table[i*(m+1) + j]` is \(R_{i,j}\) (so the boundary row and column are `+inf` except
+/// \(R_{0,0}=0\)) and the Soft-DTW value is the last entry. Mostly useful for inspection.
+pub fn soft_dtw_table(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<Vec<f64>> {
+    validate_cost(cost, n, m, gamma)?;
+    Ok(forward_table(n, m, gamma, |i, j| cost[i * m + j]))
+}
+
+/// [`validate_cost`] for a column-major cost matrix (`cost[j*n + i] = d(x_i, y_j)`).
+fn validate_cost_col_major(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<()> {
+    validate_shape(cost, n, m, gamma)?;
+    match cost.iter().position(|c| c.is_nan()) {
+        Some(k) => Err(Error::NanCost { i: k % n, j: k / n }),
+        None => Ok(()),
+    }
+}
+
+/// [`soft_dtw_cost`] for a column-major cost matrix: `cost[j*n + i] = d(x_i, y_j)`.
+///
+/// For costs produced column-major (e.g. by Fortran-order or transposed GEMM output), this
+/// avoids materializing the transpose; the result is bit-identical to [`soft_dtw_cost`] on
+/// the row-major matrix.
+pub fn soft_dtw_cost_col_major(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+    validate_cost_col_major(cost, n, m, gamma)?;
+    let r = forward_table(n, m, gamma, |i, j| cost[j * n + i]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// [`soft_dtw_alignment`] for a column-major cost matrix; the alignment is returned
+/// column-major as well (`alignment[j*n + i]`).
+pub fn soft_dtw_alignment_col_major(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    validate_cost_col_major(cost, n, m, gamma)?;
+    let c = |i: usize, j: usize| cost[j * n + i];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    let mut out = vec![0.0; n * m];
+    for (i, row) in e.chunks(m).enumerate() {
+        for (j, &v) in row.iter().enumerate() {
+            out[j * n + i] = v;
+        }
+    }
+    Ok((r[n * (m + 1) + m], out))
+}
+
+/// Local costs between the symbols of a discrete alphabet `0..alphabet`.
+///
+/// Row-major `alphabet × alphabet`: `cost(a, b)` is the cost of aligning symbol `a` of the
+/// first sequence with symbol `b` of the second. The matrix need not be symmetric, and
+/// `+inf` forbids a pair.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubstitutionMatrix {
+    alphabet: usize,
+    costs: Vec<f64>,
+}
+
+impl SubstitutionMatrix {
+    /// Wraps row-major `costs`, which must have `alphabet * alphabet` entries, none NaN.
+    pub fn new(alphabet: usize, costs: Vec<f64>) -> Result<Self> {
+        let expected = alphabet.checked_mul(alphabet);
+        if expected != Some(costs.len()) {
+            return Err(Error::InvalidSubstitutionShape {
+                len: costs.len(),
+                alphabet,
+                expected: expected.unwrap_or(usize::MAX),
+            });
+        }
+        if let Some(k) = costs.iter().position(|c| c.is_nan()) {
+            return Err(Error::NanCost {
+                i: k / alphabet,
+                j: k % alphabet,
+            });
+        }
+        Ok(Self { alphabet, costs })
+    }
+
+    /// Builds the matrix from `cost(a, b)`.
+    pub fn from_fn<F>(alphabet: usize, cost: F) -> Result<Self>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let costs = (0..alphabet * alphabet)
+            .map(|k| cost(k / alphabet, k % alphabet))
+            .collect();
+        Self::new(alphabet, costs)
+    }
+
+    /// Zero on the diagonal and `mismatch` elsewhere.
+    pub fn uniform(alphabet: usize, mismatch: f64) -> Result<Self> {
+        Self::from_fn(alphabet, |a, b| if a == b { 0.0 } else { mismatch })
+    }
+
+    /// Alphabet size.
+    pub fn alphabet(&self) -> usize {
+        self.alphabet
+    }
+
+    /// Cost of aligning `a` with `b`. Panics if either is out of range.
+    pub fn cost(&self, a: usize, b: usize) -> f64 {
+        assert!(
+            a < self.alphabet && b < self.alphabet,
+            "symbol out of range"
+        );
+        self.costs[a * self.alphabet + b]
+    }
+
+    /// Row-major entries.
+    pub fn as_slice(&self) -> &[f64] {
+        &self.costs
+    }
+
+    fn check_symbols(&self, s: &[usize]) -> Result<()> {
+        match s.iter().position(|&a| a >= self.alphabet) {
+            Some(position) => Err(Error::SymbolOutOfRange {
+                position,
+                symbol: s[position],
+                alphabet: self.alphabet,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+fn validate_symbols(x: &[usize], y: &[usize], sub: &SubstitutionMatrix, gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    table_len(x.len(), y.len())?;
+    sub.check_symbols(x)?;
+    sub.check_symbols(y)
+}
+
+/// Soft-DTW value for two symbol sequences, with local cost `sub.cost(x_i, y_j)`.
+pub fn soft_dtw_symbols(
+    x: &[usize],
+    y: &[usize],
+    sub: &SubstitutionMatrix,
+    gamma: f64,
+) -> Result<f64> {
+    validate_symbols(x, y, sub, gamma)?;
+    let (n, m, k) = (x.len(), y.len(), sub.alphabet);
+    let r = forward_table(n, m, gamma, |i, j| sub.costs[x[i] * k + y[j]]);
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW value for two symbol sequences and its gradient w.r.t. the substitution matrix.
+///
+/// The gradient is row-major `alphabet × alphabet`: entry `(a, b)` sums the expected
+/// alignment over the cells where `x_i = a` and `y_j = b`.
+pub fn soft_dtw_symbols_grad(
+    x: &[usize],
+    y: &[usize],
+    sub: &SubstitutionMatrix,
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    validate_symbols(x, y, sub, gamma)?;
+    let (n, m, k) = (x.len(), y.len(), sub.alphabet);
+    let c = |i: usize, j: usize| sub.costs[x[i] * k + y[j]];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    let mut grad = vec![0.0; k * k];
+    for (idx, &eij) in e.iter().enumerate() {
+        grad[x[idx / m] * k + y[idx % m]] += eij;
+    }
+    Ok((r[n * (m + 1) + m], grad))
+}
+
+/// [`soft_dtw_symbols_grad`] adding `scale` times the gradient into `grad_out` (length
+/// `alphabet * alphabet`). Returns the value.
+pub fn soft_dtw_symbols_grad_accumulate(
+    x: &[usize],
+    y: &[usize],
+    sub: &SubstitutionMatrix,
+    gamma: f64,
+    grad_out: &mut [f64],
+    scale: f64,
+) -> Result<f64> {
+    validate_symbols(x, y, sub, gamma)?;
+    let (n, m, k) = (x.len(), y.len(), sub.alphabet);
+    check_grad_len(grad_out, k * k)?;
+    let c = |i: usize, j: usize| sub.costs[x[i] * k + y[j]];
+    let r = forward_table(n, m, gamma, c);
+    let e = alignment_from_table(&r, n, m, gamma, c);
+    for (idx, &eij) in e.iter().enumerate() {
+        grad_out[x[idx / m] * k + y[idx % m]] += scale * eij;
+    }
+    Ok(r[n * (m + 1) + m])
+}
+
+/// Soft-DTW divergence (commonly used because it is nonnegative and zero on identical inputs).
+pub fn soft_dtw_divergence(x: &[f64], y: &[f64], gamma: f64) -> Result<f64> {
+    soft_dtw_divergence_asym(x, y, gamma, gamma)
+}
+
+/// Soft-DTW divergence with separate smoothing for the cross term (`gamma_xy`) and the
+/// self-terms (`gamma_self`).
+///
+/// Debiasing with a smaller self-term \(\gamma\) tightens the divergence. Non-negativity
+/// and zero on identical inputs hold only for `gamma_self == gamma_xy`, which is
+/// [`soft_dtw_divergence`].
+pub fn soft_dtw_divergence_asym(
+    x: &[f64],
+    y: &[f64],
+    gamma_xy: f64,
+    gamma_self: f64,
+) -> Result<f64> {
+    let xy = soft_dtw(x, y, gamma_xy)?;
+    let xx = soft_dtw(x, x, gamma_self)?;
+    let yy = soft_dtw(y, y, gamma_self)?;
+    Ok(xy - 0.5 * xx - 0.5 * yy)
+}
+
+/// Soft-DTW divergence and its gradients w.r.t. `x` and `y`, as `(value, grad_x, grad_y)`.
+///
+/// The self-terms depend on one sequence each, and since the value is symmetric the
+/// derivative of \(\operatorname{softDTW}_\gamma(x, x)\) in `x` is twice its derivative in
+/// the first argument, so `grad_x` is the cross gradient minus
+/// `soft_dtw_grad(x, x, gamma).1` (likewise for `y`).
+pub fn soft_dtw_divergence_grad(
+    x: &[f64],
+    y: &[f64],
+    gamma: f64,
+) -> Result<(f64, Vec<f64>, Vec<f64>)> {
+    soft_dtw_divergence_asym_grad(x, y, gamma, gamma)
+}
+
+/// [`soft_dtw_divergence_asym`] and its gradients w.r.t. `x` and `y`, as
+/// `(value, grad_x, grad_y)` (see [`soft_dtw_divergence_grad`]).
+pub fn soft_dtw_divergence_asym_grad(
+    x: &[f64],
+    y: &[f64],
+    gamma_xy: f64,
+    gamma_self: f64,
+) -> Result<(f64, Vec<f64>, Vec<f64>)> {
+    if gamma_xy <= 0.0 || !gamma_xy.is_finite() {
+        return Err(Error::InvalidGamma(gamma_xy));
+    }
+    let (xx, gxx) = soft_dtw_grad(x, x, gamma_self)?;
+    let (yy, gyy) = soft_dtw_grad(y, y, gamma_self)?;
+    let (n, m) = (x.len(), y.len());
+    table_len(n, m)?;
+    let c = |i: usize, j: usize| (x[i] - y[j]).powi(2);
+    let r = forward_table(n, m, gamma_xy, c);
+    let e = alignment_from_table(&r, n, m, gamma_xy, c);
+    let (mut gx, mut gy) = (gxx, gyy);
+    gx.iter_mut().for_each(|g| *g = -*g);
+    gy.iter_mut().for_each(|g| *g = -*g);
+    for i in 0..n {
+        for j in 0..m {
+            let d = 2.0 * e[i * m + j] * (x[i] - y[j]);
+            gx[i] += d;
+            gy[j] -= d;
+        }
+    }
+    Ok((r[n * (m + 1) + m] - 0.5 * xx - 0.5 * yy, gx, gy))
+}
+
+/// Soft-DTW divergence given precomputed cost matrices:
+/// - `cost_xy` shape `n×m`
+/// - `cost_xx` shape `n×n`
+/// - `cost_yy` shape `m×m`
+///
+/// This mirrors the scalar `soft_dtw_divergence` but works for arbitrary distances.
+pub fn soft_dtw_divergence_cost(
+    cost_xy: &[f64],
+    cost_xx: &[f64],
+    cost_yy: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<f64> {
+    soft_dtw_divergence_asym_cost(cost_xy, cost_xx, cost_yy, n, m, gamma, gamma)
+}
+
+/// [`soft_dtw_divergence_asym`] given precomputed cost matrices (shapes as in
+/// [`soft_dtw_divergence_cost`]).
+pub fn soft_dtw_divergence_asym_cost(
+    cost_xy: &[f64],
+    cost_xx: &[f64],
+    cost_yy: &[f64],
+    n: usize,
+    m: usize,
+    gamma_xy: f64,
+    gamma_self: f64,
+) -> Result<f64> {
+    let xy = soft_dtw_cost(cost_xy, n, m, gamma_xy)?;
+    let xx = soft_dtw_cost(cost_xx, n, n, gamma_self)?;
+    let yy = soft_dtw_cost(cost_yy, m, m, gamma_self)?;
+    Ok(xy - 0.5 * xx - 0.5 * yy)
+}
+
+fn validate_batch(xs: usize, ys: usize) -> Result<()> {
+    if xs != ys {
+        return Err(Error::BatchLengthMismatch { xs, ys });
+    }
+    Ok(())
+}
+
+/// Soft-DTW values for a batch of sequence pairs `(xs[k], ys[k])`.
+///
+/// Sequences are borrowed, so callers holding rows of a larger buffer (Arrow, Polars,
+/// memory-mapped data) can pass them without copying. Fails on the first invalid pair.
+pub fn soft_dtw_batch(xs: &[&[f64]], ys: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    validate_batch(xs.len(), ys.len())?;
+    xs.iter()
+        .zip(ys)
+        .map(|(x, y)| soft_dtw(x, y, gamma))
+        .collect()
+}
+
+/// Soft-DTW divergences for a batch of sequence pairs `(xs[k], ys[k])`.
+pub fn soft_dtw_divergence_batch(xs: &[&[f64]], ys: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    validate_batch(xs.len(), ys.len())?;
+    xs.iter()
+        .zip(ys)
+        .map(|(x, y)| soft_dtw_divergence(x, y, gamma))
+        .collect()
+}
+
+/// Tile edge, in sequences, of the pairwise loops: the sequences of a tile stay in cache while
+/// all of its pairs are scored.
+const PDIST_TILE: usize = 32;
+
+/// Position of the pair `i < j` in the condensed upper triangle of `n` items, the order of
+/// [`soft_dtw_pdist`] (and of SciPy's `pdist`): `(0,1), (0,2), …, (0,n-1), (1,2), …`.
+pub fn pdist_index(n: usize, i: usize, j: usize) -> usize {
+    debug_assert!(i < j && j < n);
+    n * i - i * (i + 1) / 2 + (j - i - 1)
+}
+
+/// Tiles `(rows, cols)` covering the pairs `i < j` of `0..n`, in the order the pairwise
+/// functions score them.
+pub(crate) fn pdist_tiles(n: usize) -> Vec<(Range<usize>, Range<usize>)> {
+    let block = |b: usize| b..(b + PDIST_TILE).min(n);
+    let starts = (0..n).step_by(PDIST_TILE);
+    starts
+        .clone()
+        .flat_map(|bi| {
+            starts
+                .clone()
+                .filter(move |&bj| bj >= bi)
+                .map(move |bj| (bi, bj))
+        })
+        .map(|(bi, bj)| (block(bi), block(bj)))
+        .collect()
+}
+
+/// Condensed indices and values of the pairs `i < j` in one tile.
+pub(crate) fn pdist_tile(
+    ws: &mut Workspace,
+    xs: &[&[f64]],
+    (rows, cols): (Range<usize>, Range<usize>),
+    gamma: f64,
+) -> Result<Vec<(usize, f64)>> {
+    let mut out = Vec::with_capacity(rows.len() * cols.len());
+    for i in rows {
+        for j in cols.clone().filter(|&j| j > i) {
+            out.push((
+                pdist_index(xs.len(), i, j),
+                ws.soft_dtw(xs[i], xs[j], gamma)?,
+            ));
+        }
+    }
+    Ok(out)
+}
+
+/// Checks what [`soft_dtw_pdist`] needs up front, so small inputs report the same errors.
+pub(crate) fn validate_pdist(xs: &[&[f64]], gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if xs.iter().any(|x| x.is_empty()) {
+        return Err(Error::EmptyInput);
+    }
+    Ok(())
+}
+
+/// Soft-DTW values between all pairs of `xs`, as the condensed upper triangle (pair `i < j`
+/// at [`pdist_index`]`(xs.len(), i, j)`, `N(N-1)/2` entries).
+///
+/// The value is symmetric, so each pair is scored once. Pairs are visited in square tiles of
+/// sequences rather than row by row, which keeps the working set in cache for large `N`,
+/// and all tables share one [`Workspace`]. `parallel::soft_dtw_pdist` (feature `rayon`) is
+/// the parallel version.
+pub fn soft_dtw_pdist(xs: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    validate_pdist(xs, gamma)?;
+    let n = xs.len();
+    let mut out = vec![0.0; n * n.saturating_sub(1) / 2];
+    let mut ws = Workspace::new();
+    for tile in pdist_tiles(n) {
+        for (k, v) in pdist_tile(&mut ws, xs, tile, gamma)? {
+            out[k] = v;
+        }
+    }
+    Ok(out)
+}
+
+/// Soft-DTW divergences between all pairs of `xs`, condensed as in [`soft_dtw_pdist`].
+///
+/// Each self-term `sdtw(x_i, x_i)` is computed once and shared by the `N - 1` pairs that use
+/// it, instead of twice per pair.
+pub fn soft_dtw_divergence_pdist(xs: &[&[f64]], gamma: f64) -> Result<Vec<f64>> {
+    let mut d = soft_dtw_pdist(xs, gamma)?;
+    let mut ws = Workspace::new();
+    let selfs = xs
+        .iter()
+        .map(|x| ws.soft_dtw(x, x, gamma))
+        .collect::<Result<Vec<_>>>()?;
+    apply_self_terms(&mut d, &selfs);
+    Ok(d)
+}
+
+/// Memo of the self-terms `sdtw(x, x)` of a pool of sequences, keyed by caller-provided ids,
+/// for divergences between many pairs drawn from the same pool.
+///
+/// A divergence needs both self-terms, so an `N × M` cross matrix of divergences computed pair
+/// by pair runs `2 N M` self-alignments; with the cache it runs `N + M`, each the first time
+/// its id is seen. The cache trusts ids: a sequence must not change while its id is cached
+/// (use [`forget`](Self::forget) or [`clear`](Self::clear) when it does).
+///
+/// ```
+/// use structop::soft_dtw::{soft_dtw_divergence, SelfTermCache};
+///
+/// let pool = [vec![0.0, 1.0, 2.0], vec![1.0, 1.0], vec![2.0, 0.0, 1.0, 0.5]];
+/// let mut cache = SelfTermCache::new(0.5).unwrap();
+/// let d = cache.divergence(0, &pool[0], 2, &pool[2]).unwrap();
+/// assert!((d - soft_dtw_divergence(&pool[0], &pool[2], 0.5).unwrap()).abs() < 1e-12);
+/// assert_eq!(cache.len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SelfTermCache<K> {
+    gamma: f64,
+    terms: HashMap<K, f64>,
+    ws: Workspace,
+}
+
+impl<K: Hash + Eq> SelfTermCache<K> {
+    /// Empty cache for smoothing `gamma`.
+    pub fn new(gamma: f64) -> Result<Self> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        Ok(Self {
+            gamma,
+            terms: HashMap::new(),
+            ws: Workspace::new(),
+        })
+    }
+
+    /// Smoothing parameter.
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    /// Number of cached self-terms.
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Whether nothing is cached.
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Drops the self-term of `id`, if cached.
+    pub fn forget(&mut self, id: &K) {
+        self.terms.remove(id);
+    }
+
+    /// Drops every cached self-term.
+    pub fn clear(&mut self) {
+        self.terms.clear();
+    }
+
+    /// `sdtw(x, x)`, computed on the first call for `id`.
+    pub fn self_term(&mut self, id: K, x: &[f64]) -> Result<f64> {
+        if let Some(&v) = self.terms.get(&id) {
+            return Ok(v);
+        }
+        let v = self.ws.soft_dtw(x, x, self.gamma)?;
+        self.terms.insert(id, v);
+        Ok(v)
+    }
+
+    /// Soft-DTW divergence between `x` (with id `id_x`) and `y` (with id `id_y`).
+    pub fn divergence(&mut self, id_x: K, x: &[f64], id_y: K, y: &[f64]) -> Result<f64> {
+        let xy = self.ws.soft_dtw(x, y, self.gamma)?;
+        Ok(xy - 0.5 * self.self_term(id_x, x)? - 0.5 * self.self_term(id_y, y)?)
+    }
+
+    /// Divergences between every `xs[i]` and `ys[j]`, row-major `xs.len() × ys.len()`; items
+    /// are `(id, sequence)`.
+    pub fn cdist(&mut self, xs: &[(K, &[f64])], ys: &[(K, &[f64])]) -> Result<Vec<f64>>
+    where
+        K: Clone,
+    {
+        let mut selfs = |items: &[(K, &[f64])]| {
+            items
+                .iter()
+                .map(|(id, x)| self.self_term(id.clone(), x))
+                .collect::<Result<Vec<_>>>()
+        };
+        let (sx, sy) = (selfs(xs)?, selfs(ys)?);
+        let mut out = Vec::with_capacity(xs.len() * ys.len());
+        for ((_, x), a) in xs.iter().zip(&sx) {
+            for ((_, y), b) in ys.iter().zip(&sy) {
+                out.push(self.ws.soft_dtw(x, y, self.gamma)? - 0.5 * a - 0.5 * b);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Turns condensed values into divergences given the self-terms.
+pub(crate) fn apply_self_terms(d: &mut [f64], selfs: &[f64]) {
+    let n = selfs.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            d[pdist_index(n, i, j)] -= 0.5 * selfs[i] + 0.5 * selfs[j];
+        }
+    }
+}
+
+/// Soft-DTW between `query` and every window `series[k..k + window]`, in order of `k`: a
+/// distance profile for alignment-based similarity search.
+///
+/// The DP tables of different windows start at different columns, so they cannot be shared;
+/// what adjacent windows do share is their `window - 1` common points, so the costs
+/// `(query[i] - series[t])²` are computed once for the whole series (`n × T` instead of
+/// `n × window` per window) and every window runs in one reused table. Each entry equals
+/// [`soft_dtw`] on that window exactly.
+pub fn soft_dtw_profile(
+    query: &[f64],
+    series: &[f64],
+    window: usize,
+    gamma: f64,
+) -> Result<Vec<f64>> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    let cost = profile_costs(query, series, window)?;
+    let n = query.len();
+    let mut r = Vec::new();
+    Ok((0..=series.len() - window)
+        .map(|k| {
+            forward_table_into(&mut r, n, window, gamma, |i, j| cost[(k + j) * n + i]);
+            r[n * (window + 1) + window]
+        })
+        .collect())
+}
+
+/// [`soft_dtw_profile`] with hard DTW (squared differences, [`StepPattern::Symmetric1`]).
+pub fn dtw_profile(query: &[f64], series: &[f64], window: usize) -> Result<Vec<f64>> {
+    let cost = profile_costs(query, series, window)?;
+    let n = query.len();
+    let op = SoftDtw::new(1.0);
+    let mut r = Vec::new();
+    Ok((0..=series.len() - window)
+        .map(|k| {
+            op.hard_forward_into(&mut r, n, window, |i, j| cost[(k + j) * n + i]);
+            r[n * (window + 1) + window]
+        })
+        .collect())
+}
+
+/// Validates a profile's inputs and returns the costs `(query[i] - series[t])²`,
+/// column-major: those of series point `t` are `cost[t * n..(t + 1) * n]`.
+fn profile_costs(query: &[f64], series: &[f64], window: usize) -> Result<Vec<f64>> {
+    if query.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    if window == 0 || window > series.len() {
+        return Err(Error::InvalidWindow {
+            window,
+            len: series.len(),
+        });
+    }
+    table_len(query.len(), window)?;
+    Ok(series
+        .iter()
+        .flat_map(|s| query.iter().map(move |q| (q - s).powi(2)))
+        .collect())
+}
+
+/// Hard cyclic DTW: the smallest DTW value between `x` and a rotation
+/// `y[r..] ++ y[..r]` of `y`, with the minimizing rotation `r` (the first one on ties).
+///
+/// Alignments with the rotations are paths on `x` against `y` doubled, from column `r` to
+/// column `r + m - 1`. Optimal paths for different rotations can be chosen not to cross, so
+/// the paths of rotations `i < j` bound the search for every rotation between them; solving
+/// the middle rotation and recursing on both halves (Maes 1990, as adapted to DTW by
+/// Palazón-González & Marzal 2012) costs `O(n m log m)` instead of `O(n m²)` for all
+/// rotations. Squared differences, [`StepPattern::Symmetric1`].
+pub fn cyclic_dtw(x: &[f64], y: &[f64]) -> Result<(f64, usize)> {
+    if x.is_empty() || y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let (n, m) = (x.len(), y.len());
+    table_len(n, 2 * m)?;
+    let yy: Vec<f64> = y.iter().chain(y).copied().collect();
+    let mut values = vec![f64::INFINITY; m];
+    let (v, first, last) = cyclic_bounded(x, &yy, 0, &vec![0; n], &vec![m - 1; n]);
+    values[0] = v;
+    // Rotation m is rotation 0 shifted by m columns.
+    let right: Vec<usize> = last.iter().map(|c| c + m).collect();
+    cyclic_between(x, &yy, (0, &first), (m, &right), &mut values);
+    let (r, v) = values
+        .iter()
+        .copied()
+        .enumerate()
+        .fold(
+            (0, f64::INFINITY),
+            |best, (r, v)| if v < best.1 { (r, v) } else { best },
+        );
+    Ok((v, r))
+}
+
+/// Solves the rotations strictly between `i` and `j`, given the leftmost columns of rotation
+/// `i`'s path and the rightmost columns of rotation `j`'s path, row by row.
+fn cyclic_between(
+    x: &[f64],
+    yy: &[f64],
+    (i, left): (usize, &[usize]),
+    (j, right): (usize, &[usize]),
+    values: &mut [f64],
+) {
+    if j - i < 2 {
+        return;
+    }
+    let k = (i + j) / 2;
+    let m = values.len();
+    let lo: Vec<usize> = left.iter().map(|&c| c.max(k)).collect();
+    let hi: Vec<usize> = right.iter().map(|&c| c.min(k + m - 1)).collect();
+    let (v, first, last) = cyclic_bounded(x, yy, k, &lo, &hi);
+    values[k] = v;
+    cyclic_between(x, yy, (i, left), (k, &last), values);
+    cyclic_between(x, yy, (k, &first), (j, right), values);
+}
+
+/// Hard DTW of `x` against `yy` over the paths from `(0, k)` to `(n-1, hi[n-1])` within
+/// columns `lo[t]..=hi[t]` of each row `t`, with the leftmost and rightmost column of each
+/// row on one optimal path (the bounds themselves if the region holds no path).
+fn cyclic_bounded(
+    x: &[f64],
+    yy: &[f64],
+    k: usize,
+    lo: &[usize],
+    hi: &[usize],
+) -> (f64, Vec<usize>, Vec<usize>) {
+    let n = x.len();
+    let mut offsets = Vec::with_capacity(n + 1);
+    offsets.push(0);
+    for t in 0..n {
+        offsets.push(offsets[t] + (hi[t] + 1).saturating_sub(lo[t]));
+    }
+    let mut d = vec![f64::INFINITY; offsets[n]];
+    let get = |d: &[f64], t: usize, c: usize| {
+        if c >= lo[t] && c <= hi[t] {
+            d[offsets[t] + c - lo[t]]
+        } else {
+            f64::INFINITY
+        }
+    };
+    for t in 0..n {
+        for c in lo[t]..=hi[t] {
+            let best = if t == 0 && c == k {
+                0.0
+            } else {
+                let left = if c > 0 {
+                    get(&d, t, c - 1)
+                } else {
+                    f64::INFINITY
+                };
+                let (up, diag) = match t {
+                    0 => (f64::INFINITY, f64::INFINITY),
+                    _ if c == 0 => (get(&d, t - 1, c), f64::INFINITY),
+                    _ => (get(&d, t - 1, c), get(&d, t - 1, c - 1)),
+                };
+                up.min(left).min(diag)
+            };
+            d[offsets[t] + c - lo[t]] = best + (x[t] - yy[c]).powi(2);
+        }
+    }
+    let (mut t, mut c) = (n - 1, hi[n - 1]);
+    let value = get(&d, t, c);
+    if !value.is_finite() {
+        return (value, lo.to_vec(), hi.to_vec());
+    }
+    let (mut first, mut last) = (vec![c; n], vec![c; n]);
+    while (t, c) != (0, k) {
+        let diag = if t > 0 && c > 0 {
+            get(&d, t - 1, c - 1)
+        } else {
+            f64::INFINITY
+        };
+        let up = if t > 0 {
+            get(&d, t - 1, c)
+        } else {
+            f64::INFINITY
+        };
+        let left = if c > 0 {
+            get(&d, t, c - 1)
+        } else {
+            f64::INFINITY
+        };
+        if diag <= up && diag <= left {
+            (t, c) = (t - 1, c - 1);
+            last[t] = c;
+        } else if up <= left {
+            t -= 1;
+            last[t] = c;
+        } else {
+            c -= 1;
+        }
+        first[t] = c;
+    }
+    (value, first, last)
+}
+
+/// Soft cyclic DTW: the soft minimum, at temperature `gamma`, of the Soft-DTW values between
+/// `x` and every rotation `y[r..] ++ y[..r]` of `y`, with the posterior weight of each
+/// rotation (which sums to one).
+///
+/// Soft alignments spread over crossing paths, so the bounding of [`cyclic_dtw`] does not
+/// apply: this evaluates all `m` rotations (`O(n m²)`), reusing one [`Workspace`].
+pub fn soft_cyclic_dtw(x: &[f64], y: &[f64], gamma: f64) -> Result<(f64, Vec<f64>)> {
+    if y.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let m = y.len();
+    let yy: Vec<f64> = y.iter().chain(y).copied().collect();
+    let mut ws = Workspace::new();
+    let values = (0..m)
+        .map(|r| ws.soft_dtw(x, &yy[r..r + m], gamma))
+        .collect::<Result<Vec<_>>>()?;
+    let best = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let weights: Vec<f64> = values.iter().map(|v| exp(-(v - best) / gamma)).collect();
+    let total: f64 = weights.iter().sum();
+    Ok((
+        best - gamma * ln(total),
+        weights.iter().map(|w| w / total).collect(),
+    ))
+}
+
+/// Reusable buffers for repeated Soft-DTW calls.
+///
+/// The free functions allocate a fresh `(n+1) × (m+1)` table (and an `n × m` alignment) on
+/// every call. A `Workspace` keeps those buffers between calls and only grows them, so a
+/// serving loop scoring many pairs does no per-call allocation once it has seen its largest
+/// input. Results are identical to the free functions.
+///
+/// ```
+/// use structop::soft_dtw::{soft_dtw, Workspace};
+///
+/// let mut ws = Workspace::new();
+/// for y in [[0.0, 1.0], [1.0, 2.0]] {
+///     assert_eq!(ws.soft_dtw(&[0.0, 1.0, 2.0], &y, 0.5), soft_dtw(&[0.0, 1.0, 2.0], &y, 0.5));
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    table: Vec<f64>,
+    alignment: Vec<f64>,
+}
+
+impl Workspace {
+    /// An empty workspace; buffers are allocated on first use.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// [`soft_dtw`] using this workspace's buffers.
+    pub fn soft_dtw(&mut self, x: &[f64], y: &[f64], gamma: f64) -> Result<f64> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        if x.is_empty() || y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let (n, m) = (x.len(), y.len());
+        table_len(n, m)?;
+        forward_table_into(&mut self.table, n, m, gamma, |i, j| (x[i] - y[j]).powi(2));
+        Ok(self.table[n * (m + 1) + m])
+    }
+
+    /// [`soft_dtw_grad_accumulate`] using this workspace's buffers.
+    pub fn soft_dtw_grad_accumulate(
+        &mut self,
+        x: &[f64],
+        y: &[f64],
+        gamma: f64,
+        grad_out: &mut [f64],
+        scale: f64,
+    ) -> Result<f64> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        if x.is_empty() || y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let (n, m) = (x.len(), y.len());
+        table_len(n, m)?;
+        check_grad_len(grad_out, n)?;
+        let c = |i: usize, j: usize| (x[i] - y[j]).powi(2);
+        forward_table_into(&mut self.table, n, m, gamma, c);
+        alignment_from_table_into(&mut self.alignment, &self.table, n, m, gamma, c);
+        for (i, (g, row)) in grad_out
+            .iter_mut()
+            .zip(self.alignment.chunks(m))
+            .enumerate()
+        {
+            let d: f64 = row.iter().zip(y).map(|(e, yj)| 2.0 * e * (x[i] - yj)).sum();
+            *g += scale * d;
+        }
+        Ok(self.table[n * (m + 1) + m])
+    }
+
+    /// [`soft_dtw_cost_grad_accumulate`] using this workspace's buffers.
+    pub fn soft_dtw_cost_grad_accumulate(
+        &mut self,
+        cost: &[f64],
+        n: usize,
+        m: usize,
+        gamma: f64,
+        grad_out: &mut [f64],
+        scale: f64,
+    ) -> Result<f64> {
+        validate_cost(cost, n, m, gamma)?;
+        check_grad_len(grad_out, n * m)?;
+        let c = |i: usize, j: usize| cost[i * m + j];
+        forward_table_into(&mut self.table, n, m, gamma, c);
+        alignment_from_table_into(&mut self.alignment, &self.table, n, m, gamma, c);
+        for (g, e) in grad_out.iter_mut().zip(&self.alignment) {
+            *g += scale * e;
+        }
+        Ok(self.table[n * (m + 1) + m])
+    }
+
+    /// [`soft_dtw_cost`] using this workspace's buffers.
+    pub fn soft_dtw_cost(&mut self, cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
+        validate_cost(cost, n, m, gamma)?;
+        forward_table_into(&mut self.table, n, m, gamma, |i, j| cost[i * m + j]);
+        Ok(self.table[n * (m + 1) + m])
+    }
+
+    /// [`soft_dtw_alignment`] using this workspace's buffers; the alignment is borrowed from
+    /// the workspace until the next call.
+    pub fn soft_dtw_alignment(
+        &mut self,
+        cost: &[f64],
+        n: usize,
+        m: usize,
+        gamma: f64,
+    ) -> Result<(f64, &[f64])> {
+        validate_cost(cost, n, m, gamma)?;
+        let c = |i: usize, j: usize| cost[i * m + j];
+        forward_table_into(&mut self.table, n, m, gamma, c);
+        alignment_from_table_into(&mut self.alignment, &self.table, n, m, gamma, c);
+        Ok((self.table[n * (m + 1) + m], &self.alignment))
+    }
+}
+
+/// Local step pattern of the warping recursion.
+///
+/// Steps go to `(i, j)` from `(i-1, j)`, `(i, j-1)` and `(i-1, j-1)`; the patterns differ in
+/// how often the cell cost is paid on each step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StepPattern {
+    /// Every step pays `C[i,j]` once (the recursion in the module docs).
+    #[default]
+    Symmetric1,
+    /// The diagonal step pays `2 C[i,j]`, so every path pays for `n + m` cell visits and
+    /// diagonal moves are not favoured over a horizontal-vertical detour.
+    Symmetric2,
+}
+
+impl StepPattern {
+    /// Cost multipliers for the steps from `(i-1, j)`, `(i, j-1)` and `(i-1, j-1)`.
+    fn weights(self) -> [f64; 3] {
+        match self {
+            StepPattern::Symmetric1 => [1.0, 1.0, 1.0],
+            StepPattern::Symmetric2 => [1.0, 1.0, 2.0],
+        }
+    }
+}
+
+/// Additive penalties on the warping steps, a step-transition prior for [`SoftDtw`].
+///
+/// Each penalty is added to its step's candidate inside the softmin, so a path pays the
+/// penalty once per step of that kind on top of its costs; the first cell `(0, 0)` is entered,
+/// not stepped into, and pays none. Penalising `up` and `left` discourages the "staircase"
+/// warps that noisy costs otherwise produce. A penalty of `+inf` forbids the step. The
+/// gradient of the value w.r.t. the penalties is the expected number of steps of each kind
+/// (see [`SoftDtw::transition_grad`]). Penalties depending on the previous step as well
+/// (a full transition matrix) would need a three-state recursion and are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TransitionPenalty {
+    /// Penalty of a diagonal step, from `(i-1, j-1)`.
+    pub diag: f64,
+    /// Penalty of a vertical step, from `(i-1, j)`.
+    pub up: f64,
+    /// Penalty of a horizontal step, from `(i, j-1)`.
+    pub left: f64,
+}
+
+impl TransitionPenalty {
+    /// Penalties in the order of [`StepPattern`] weights: up, left, diagonal.
+    fn steps(self) -> [f64; 3] {
+        [self.up, self.left, self.diag]
+    }
+
+    fn from_steps([up, left, diag]: [f64; 3]) -> Self {
+        Self { diag, up, left }
+    }
+}
+
+/// Shape of a [`DiagonalPenalty`]: the weight \(w(i, j)\) of 0-based cell `(i, j)` of an
+/// `n × m` cost matrix.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum DiagonalCurve {
+    /// \(|i - j|\).
+    #[default]
+    Offset,
+    /// A user curve `w(i, j, n, m)`, which must be finite and non-negative; e.g. the distance
+    /// to the line from `(0, 0)` to `(n-1, m-1)` for sequences of unequal length.
+    Custom(fn(usize, usize, usize, usize) -> f64),
+}
+
+impl PartialEq for DiagonalCurve {
+    /// Custom curves compare by address, which may differ for the same function across
+    /// codegen units (a spurious inequality, never a spurious equality).
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Offset, Self::Offset) => true,
+            (Self::Custom(a), Self::Custom(b)) => *a as usize == *b as usize,
+            _ => false,
+        }
+    }
+}
+
+/// A soft alternative to a band for [`SoftDtw`]: \(\lambda\, w(i, j)\) is added to the
+/// local cost of every cell, so long excursions from the diagonal are discouraged rather
+/// than forbidden. Each step into a cell pays the penalty with the cell cost (scaled by the
+/// step pattern's weight). The gradient of the value w.r.t. \(\lambda\) is
+/// \(\sum_{ij} E_{ij} w(i, j)\) (see [`SoftDtw::diagonal_grad`]).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DiagonalPenalty {
+    /// Strength \(\lambda \ge 0\); zero disables the penalty.
+    pub lambda: f64,
+    /// Weight of each cell.
+    pub curve: DiagonalCurve,
+}
+
+impl DiagonalPenalty {
+    /// \(\lambda |i - j|\).
+    pub fn offset(lambda: f64) -> Self {
+        Self {
+            lambda,
+            curve: DiagonalCurve::Offset,
+        }
+    }
+
+    /// Weight \(w(i, j)\) of 0-based cell `(i, j)`.
+    pub fn weight(&self, i: usize, j: usize, n: usize, m: usize) -> f64 {
+        match self.curve {
+            DiagonalCurve::Offset => i.abs_diff(j) as f64,
+            DiagonalCurve::Custom(w) => w(i, j, n, m),
+        }
+    }
+
+    /// `cost` with the penalty added (unchanged when \(\lambda = 0\)).
+    fn apply<F>(self, n: usize, m: usize, cost: F) -> impl Fn(usize, usize) -> f64
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        move |i, j| {
+            let c = cost(i, j);
+            if self.lambda == 0.0 {
+                c
+            } else {
+                c + self.lambda * self.weight(i, j, n, m)
+            }
+        }
+    }
+}
+
+/// What the builders do when \(\gamma\) is too small for the exp/ln recursion.
+///
+/// As \(\gamma \to 0\) the soft value tends to the hard (min-plus) value and the expected
+/// alignment to a one-hot path, but in floating point `-a/γ` overflows long before that: the
+/// value comes out infinite and the alignment zero or NaN. The hard fallback returns the
+/// min-plus value and a subgradient, the indicator of one argmin path (ties broken towards
+/// the diagonal step, then the vertical one).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TinyGamma {
+    /// Always use the soft recursion.
+    #[default]
+    Off,
+    /// Run the soft recursion and fall back to the hard DP only when its output is
+    /// degenerate (non-finite value, or an alignment / marginals that no longer sum to a
+    /// unit flow). Results are continuous in \(\gamma\) for as long as they are usable.
+    ///
+    /// Value-only calls (`compute`, `compute_cost`) run no backward pass, so they can only
+    /// see a non-finite value: near the threshold they may return the finite soft value
+    /// while the gradient-returning call on the same input falls back to the hard result.
+    /// Use [`TinyGamma::Below`] when value and gradient must switch together.
+    Detect,
+    /// Use the hard DP whenever \(\gamma\) is below the threshold, regardless of whether the
+    /// soft recursion would have succeeded.
+    Below(f64),
+}
+
+impl TinyGamma {
+    /// Whether the hard DP is forced for this `gamma`.
+    pub(crate) fn forces_hard(self, gamma: f64) -> bool {
+        matches!(self, TinyGamma::Below(t) if gamma < t)
+    }
+
+    /// Whether the soft output should be replaced by the hard one.
+    pub(crate) fn detects(self) -> bool {
+        self == TinyGamma::Detect
+    }
+}
+
+/// Relative tolerance on the unit-flow check used by [`TinyGamma::Detect`].
+pub(crate) const DEGENERATE_TOL: f64 = 1e-6;
+
+/// Working precision of the DP potentials in the builders.
+///
+/// With plain `f64` potentials the rounding error of a cell grows like `|R| ε`, and the
+/// probabilities \(\exp((R_s - R_p - c)/\gamma)\) in the backward pass inherit it divided
+/// by \(\gamma\): on deep DPs with large accumulated costs and small \(\gamma\), expected
+/// alignments and edge marginals visibly stop summing to one. `DoubleDouble` keeps the
+/// potentials as double-double numbers (about 32 significant digits), so the log-sum-exp
+/// shifts and the backward exponents are formed without that cancellation; only the
+/// bounded exponentials themselves use `f64`. It is several times slower and meant for
+/// validating gradients, not for production use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precision {
+    /// `f64` potentials.
+    #[default]
+    Double,
+    /// Double-double potentials.
+    DoubleDouble,
+}
+
+/// How the sequence entry points of [`SoftDtw`] ([`SoftDtw::compute`],
+/// [`SoftDtw::analyze`], [`SoftDtw::missing_cost_grad`]) treat NaN samples, which mark
+/// missing values (gaps in sensor series, say).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MissingValues {
+    /// Missing samples are an error ([`Error::MissingSample`]).
+    #[default]
+    Reject,
+    /// Every cell involving a missing sample costs this constant, e.g. a learned one (see
+    /// [`SoftDtw::missing_cost_grad`]).
+    Cost(f64),
+    /// A missing sample is marginalized over the observed samples of its sequence: the cell
+    /// costs the expected squared distance \((\mu - b)^2 + \sigma^2\) (with both
+    /// variances when both samples are missing).
+    Expected,
+}
+
+/// Local costs of cells involving a missing sample, resolved from a [`MissingValues`].
+#[derive(Debug, Clone, Copy)]
+enum MissingFill {
+    Cost(f64),
+    Expected { mean: [f64; 2], var: [f64; 2] },
+}
+
+impl MissingFill {
+    /// Squared distance of `x[i]` and `y[j]`, or its substitute.
+    fn cost(self, x: &[f64], y: &[f64], i: usize, j: usize) -> f64 {
+        let (a, b) = (x[i], y[j]);
+        if !a.is_nan() && !b.is_nan() {
+            return (a - b).powi(2);
+        }
+        match self {
+            MissingFill::Cost(c) => c,
+            MissingFill::Expected { mean, var } => {
+                let (a, va) = if a.is_nan() {
+                    (mean[0], var[0])
+                } else {
+                    (a, 0.0)
+                };
+                let (b, vb) = if b.is_nan() {
+                    (mean[1], var[1])
+                } else {
+                    (b, 0.0)
+                };
+                (a - b).powi(2) + va + vb
+            }
+        }
+    }
+}
+
+/// Outcome of [`SoftDtw::select_band`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandSelection {
+    /// Selected Sakoe-Chiba half-width.
+    pub band: usize,
+    /// Value computed with that band.
+    pub value: f64,
+    /// Certified upper bound on `value` minus the unconstrained value (`+inf` when none is
+    /// available, i.e. for negative costs or penalties).
+    pub gap: f64,
+    /// Whether `gap` is zero: the band admits every cell, or the bound underflows, so `value`
+    /// is the unconstrained value to floating-point precision.
+    pub exact: bool,
+}
+
+/// Configurable Soft-DTW operator.
+///
+/// ```
+/// use structop::soft_dtw::{SoftDtw, StepPattern};
+///
+/// let op = SoftDtw::new(0.5).band(2).step_pattern(StepPattern::Symmetric2).normalized(true);
+/// let v = op.compute(&[0.0, 1.0, 2.0], &[0.0, 2.0]).unwrap();
+/// assert!(v.is_finite());
+/// ```
+///
+/// With the defaults (no band, [`StepPattern::Symmetric1`], not normalized) the results are
+/// exactly those of [`soft_dtw`], [`soft_dtw_cost`] and [`soft_dtw_alignment`]. `gamma` is
+/// validated when computing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftDtw {
+    gamma: f64,
+    band: Option<usize>,
+    step_pattern: StepPattern,
+    normalized: bool,
+    tiny_gamma: TinyGamma,
+    precision: Precision,
+    penalty: TransitionPenalty,
+    diagonal: DiagonalPenalty,
+    missing: MissingValues,
+}
+
+impl SoftDtw {
+    /// Soft-DTW with smoothing `gamma` and default options.
+    pub fn new(gamma: f64) -> Self {
+        Self {
+            gamma,
+            band: None,
+            step_pattern: StepPattern::default(),
+            normalized: false,
+            tiny_gamma: TinyGamma::default(),
+            precision: Precision::default(),
+            penalty: TransitionPenalty::default(),
+            diagonal: DiagonalPenalty::default(),
+            missing: MissingValues::default(),
+        }
+    }
+
+    /// Restricts paths to a Sakoe-Chiba band: cell `(i, j)` is allowed when `j` is within
+    /// `band` columns of the diagonal from `(0, 0)` to `(n-1, m-1)`, i.e.
+    /// `|j - i (m-1)/(n-1)| <= band`. Cells outside the band get zero alignment.
+    pub fn band(mut self, band: usize) -> Self {
+        self.band = Some(band);
+        self
+    }
+
+    /// Sets the local step pattern.
+    pub fn step_pattern(mut self, step_pattern: StepPattern) -> Self {
+        self.step_pattern = step_pattern;
+        self
+    }
+
+    /// Divides the value (and the alignment) by `n + m`, making scores comparable across
+    /// sequence lengths.
+    pub fn normalized(mut self, normalized: bool) -> Self {
+        self.normalized = normalized;
+        self
+    }
+
+    /// Sets the policy for \(\gamma\) too small for the soft recursion (see [`TinyGamma`]).
+    pub fn tiny_gamma(mut self, tiny_gamma: TinyGamma) -> Self {
+        self.tiny_gamma = tiny_gamma;
+        self
+    }
+
+    /// Sets the working precision of the soft recursion (see [`Precision`]).
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Sets additive step penalties (see [`TransitionPenalty`]); validated when computing.
+    pub fn transition_penalty(mut self, penalty: TransitionPenalty) -> Self {
+        self.penalty = penalty;
+        self
+    }
+
+    /// Sets a penalty on cells away from the diagonal (see [`DiagonalPenalty`]); validated
+    /// when computing.
+    pub fn diagonal_penalty(mut self, diagonal: DiagonalPenalty) -> Self {
+        self.diagonal = diagonal;
+        self
+    }
+
+    /// Sets the treatment of NaN (missing) samples in sequence inputs (see
+    /// [`MissingValues`]).
+    pub fn missing_values(mut self, missing: MissingValues) -> Self {
+        self.missing = missing;
+        self
+    }
+
+    /// Value for two 1D sequences with the squared distance.
+    pub fn compute(&self, x: &[f64], y: &[f64]) -> Result<f64> {
+        self.check_gamma()?;
+        if x.is_empty() || y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let (n, m) = (x.len(), y.len());
+        table_len(n, m)?;
+        match self.missing_fill(x, y)? {
+            None => self.value(n, m, |i, j| (x[i] - y[j]).powi(2)),
+            Some(fill) => self.value(n, m, |i, j| fill.cost(x, y, i, j)),
+        }
+    }
+
+    /// Value for two 1D sequences and its gradient w.r.t. the constant of
+    /// [`MissingValues::Cost`]: the expected number of visits (weighted by the step
+    /// pattern) to cells involving a missing sample. Zero under the other policies.
+    pub fn missing_cost_grad(&self, x: &[f64], y: &[f64]) -> Result<(f64, f64)> {
+        self.check_gamma()?;
+        if x.is_empty() || y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let (n, m) = (x.len(), y.len());
+        table_len(n, m)?;
+        let fill = self.missing_fill(x, y)?;
+        let cost = self.sequence_cost(x, y, fill);
+        let (value, e, _) = self.solve(&cost, n, m, false)?;
+        if !matches!(fill, Some(MissingFill::Cost(_))) {
+            return Ok((value, 0.0));
+        }
+        let grad = e
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| x[k / m].is_nan() || y[k % m].is_nan())
+            .map(|(_, p)| p)
+            .sum();
+        Ok((value, grad))
+    }
+
+    /// Value for a precomputed row-major `n × m` cost matrix.
+    pub fn compute_cost(&self, cost: &[f64], n: usize, m: usize) -> Result<f64> {
+        validate_cost(cost, n, m, self.gamma)?;
+        self.value(n, m, |i, j| cost[i * m + j])
+    }
+
+    /// Picks a Sakoe-Chiba half-width for a cost matrix, ignoring any band already set.
+    ///
+    /// Starting from 1, the band doubles until its certified [`BandSelection::gap`] is at
+    /// most `tol`, or until it admits every cell. With nonnegative costs and penalties, a
+    /// path that leaves the band costs at least the cheapest in-band prefix ending at an exit
+    /// plus the cheapest in-band suffix starting at an entry, read off the banded hard-DTW
+    /// forward and backward tables. If that bound `L` exceeds the banded value `v`, the paths
+    /// outside carry Gibbs mass at most \(3^{n+m} e^{-L/\gamma}\) against \(e^{-v/\gamma}\)
+    /// inside, so the value drops by at most
+    /// \(\gamma \ln(1 + 3^{n+m} e^{-(L - v)/\gamma})\) when the band is lifted. A value
+    /// that plateaus under doubling is not certified by itself; a cheap path far from the
+    /// diagonal keeps the bound large until the band reaches it. Each step costs `O(n b)`,
+    /// so the whole search costs about twice the final band. A `tol` of NaN never certifies
+    /// and expands to the full width.
+    pub fn select_band(&self, cost: &[f64], n: usize, m: usize, tol: f64) -> Result<BandSelection> {
+        validate_cost(cost, n, m, self.gamma)?;
+        self.check_penalty()?;
+        let c = self.diagonal.apply(n, m, |i, j| cost[i * m + j]);
+        let certifiable = (0..n * m).all(|k| c(k / m, k % m) >= 0.0)
+            && self.penalty.steps().iter().all(|&p| p >= 0.0);
+        let scale = if self.normalized { (n + m) as f64 } else { 1.0 };
+        let full = m - 1;
+        let mut band = full.min(1);
+        loop {
+            // The full band is evaluated unbanded, so `exact` results match `compute_cost`.
+            let op = Self {
+                band: (band < full).then_some(band),
+                normalized: false,
+                ..*self
+            };
+            let value = match op.value(n, m, |i, j| cost[i * m + j]) {
+                Ok(v) => Some(v),
+                Err(Error::NoWarpingPath { .. }) => None,
+                Err(e) => return Err(e),
+            };
+            let gap = match value {
+                _ if band >= full => 0.0,
+                Some(v) if certifiable => op.band_gap(n, m, &c, v),
+                _ => f64::INFINITY,
+            };
+            if band >= full || gap <= tol {
+                let value = value.expect("unbanded values are always Ok");
+                return Ok(BandSelection {
+                    band,
+                    value: value / scale,
+                    gap: gap / scale,
+                    exact: gap == 0.0,
+                });
+            }
+            band = (2 * band).min(full);
+        }
+    }
+
+    /// Value and expected alignment (gradient w.r.t. the cost matrix, row-major `n × m`).
+    ///
+    /// When the hard fallback of [`TinyGamma`] applies, the alignment is the (weighted)
+    /// indicator of one argmin path.
+    pub fn alignment(&self, cost: &[f64], n: usize, m: usize) -> Result<(f64, Vec<f64>)> {
+        let (value, e, _) = self.solve(cost, n, m, false)?;
+        Ok((value, e))
+    }
+
+    /// Value and its gradient w.r.t. the [`TransitionPenalty`]: the expected number of
+    /// diagonal, vertical and horizontal steps under the Gibbs distribution over paths
+    /// (divided by `n + m` when normalized), for learning the penalties.
+    ///
+    /// Under the hard fallback of [`TinyGamma`], the step counts of one argmin path.
+    pub fn transition_grad(
+        &self,
+        cost: &[f64],
+        n: usize,
+        m: usize,
+    ) -> Result<(f64, TransitionPenalty)> {
+        let (value, _, steps) = self.solve(cost, n, m, true)?;
+        Ok((value, TransitionPenalty::from_steps(steps)))
+    }
+
+    /// Value and its gradient w.r.t. the strength \(\lambda\) of the [`DiagonalPenalty`]:
+    /// \(\sum_{ij} E_{ij} w(i, j)\), the expected weighted excursion from the diagonal.
+    pub fn diagonal_grad(&self, cost: &[f64], n: usize, m: usize) -> Result<(f64, f64)> {
+        let (value, e, _) = self.solve(cost, n, m, false)?;
+        let grad = e
+            .iter()
+            .enumerate()
+            .filter(|&(_, &p)| p != 0.0)
+            .map(|(k, &p)| p * self.diagonal.weight(k / m, k % m, n, m))
+            .sum();
+        Ok((value, grad))
+    }
+
+    /// Lazy result for two 1D sequences with the squared distance: the value is computed
+    /// here, everything else on first use (see [`SoftDtwAlignment`]).
+    pub fn analyze<'a>(&self, x: &'a [f64], y: &'a [f64]) -> Result<SoftDtwAlignment<'a>> {
+        self.check_gamma()?;
+        if x.is_empty() || y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let (n, m) = (x.len(), y.len());
+        table_len(n, m)?;
+        let cost = self.sequence_cost(x, y, self.missing_fill(x, y)?);
+        // Equal infinite samples give NaN costs; reject them before the value is cached so the
+        // lazy backward pass never meets them.
+        validate_cost(&cost, n, m, self.gamma)?;
+        let mut out = self.analyze_cost_inner(Cow::Owned(cost), n, m)?;
+        out.sequences = Some((x, y));
+        Ok(out)
+    }
+
+    /// Lazy result for a precomputed row-major `n × m` cost matrix (see
+    /// [`SoftDtwAlignment`]); it has no divergence.
+    pub fn analyze_cost<'a>(
+        &self,
+        cost: &'a [f64],
+        n: usize,
+        m: usize,
+    ) -> Result<SoftDtwAlignment<'a>> {
+        validate_cost(cost, n, m, self.gamma)?;
+        self.analyze_cost_inner(Cow::Borrowed(cost), n, m)
+    }
+
+    fn analyze_cost_inner<'a>(
+        &self,
+        cost: Cow<'a, [f64]>,
+        n: usize,
+        m: usize,
+    ) -> Result<SoftDtwAlignment<'a>> {
+        let value = self.value(n, m, |i, j| cost[i * m + j])?;
+        Ok(SoftDtwAlignment {
+            op: *self,
+            cost,
+            n,
+            m,
+            value,
+            sequences: None,
+            marginals: OnceCell::new(),
+        })
+    }
+
+    /// The warping DAG for a cost matrix as an explicit shortest-path [`Graph`], so the
+    /// [`crate::soft_shortest_path`] operators apply to alignments.
+    ///
+    /// Node 0 is the corner before the first cell and cell `(i, j)` is node `1 + i m + j`
+    /// (the sink is the last cell). Each step into an in-band cell is an edge costing the
+    /// step's weighted cell cost plus its [`TransitionPenalty`]; steps into cells of cost
+    /// `+inf` (or with an infinite penalty) are left out. The graph's soft value is the
+    /// unnormalized value of this operator, and [`AlignmentGraph::alignment`] turns its edge
+    /// marginals back into the expected alignment. The graph has `n m + 1` nodes and up to
+    /// `3 n m` edges, so this is for reuse of the graph machinery, not for speed.
+    pub fn alignment_graph(&self, cost: &[f64], n: usize, m: usize) -> Result<AlignmentGraph> {
+        validate_cost(cost, n, m, self.gamma)?;
+        self.check_penalty()?;
+        let local = self.diagonal.apply(n, m, |i, j| cost[i * m + j]);
+        let weights = self.step_pattern.weights();
+        let node = |i: usize, j: usize| 1 + (i - 1) * m + (j - 1);
+        let mut edges = Vec::new();
+        let mut heads = Vec::new();
+        for i in 1..=n {
+            let band = self.band_cols(i, n, m);
+            // Only consulted for i > 1.
+            let prev_band = self.band_cols(i.max(2) - 1, n, m);
+            for j in band.clone() {
+                let d = local(i - 1, j - 1);
+                let penalties = self.penalties(i, j);
+                let preds = [
+                    (i > 1 && prev_band.contains(&j)).then(|| node(i - 1, j)),
+                    (j > 1 && band.contains(&(j - 1))).then(|| node(i, j - 1)),
+                    if i == 1 && j == 1 {
+                        Some(0)
+                    } else {
+                        (i > 1 && j > 1 && prev_band.contains(&(j - 1))).then(|| node(i - 1, j - 1))
+                    },
+                ];
+                for (k, from) in preds.into_iter().enumerate() {
+                    let c = weights[k] * d + penalties[k];
+                    if let (Some(from), true) = (from, c.is_finite()) {
+                        edges.push(Edge {
+                            from,
+                            to: node(i, j),
+                            cost: c,
+                        });
+                        heads.push(((i - 1) * m + (j - 1), weights[k]));
+                    }
+                }
+            }
+        }
+        let graph = Graph::new(n * m + 1, edges, 0, n * m).expect("the grid is a valid DAG");
+        Ok(AlignmentGraph { graph, heads, n, m })
+    }
+
+    /// Value, alignment and expected step counts (only when `steps` is set), all scaled
+    /// when normalized.
+    fn solve(
+        &self,
+        cost: &[f64],
+        n: usize,
+        m: usize,
+        steps: bool,
+    ) -> Result<(f64, Vec<f64>, [f64; 3])> {
+        validate_cost(cost, n, m, self.gamma)?;
+        self.check_penalty()?;
+        let c = self.diagonal.apply(n, m, |i, j| cost[i * m + j]);
+        let c = &c;
+        let soft = if self.tiny_gamma.forces_hard(self.gamma) {
+            None
+        } else {
+            let (v, e, k) = self.soft_alignment(n, m, c, steps);
+            let wc = self.step_pattern.weights()[2];
+            let degenerate = !v.is_finite()
+                || e.iter().any(|v| !v.is_finite())
+                || (e[0] / wc - 1.0).abs() > DEGENERATE_TOL;
+            if self.tiny_gamma.detects() && degenerate {
+                None
+            } else {
+                Some((v, e, k))
+            }
+        };
+        let (value, mut e, mut k) = match soft {
+            Some(out) => out,
+            None => {
+                let r = self.hard_forward(n, m, c);
+                let (e, k) = self.hard_backtrack(&r, n, m, c);
+                (r[n * (m + 1) + m], e, k)
+            }
+        };
+        let value = self.finish(value, n, m)?;
+        if self.normalized {
+            let scale = 1.0 / (n + m) as f64;
+            e.iter_mut().chain(&mut k).for_each(|v| *v *= scale);
+        }
+        Ok((value, e, k))
+    }
+
+    /// Substitute costs for the missing samples of `x` and `y` under the policy, or `None`
+    /// if no sample is missing.
+    fn missing_fill(&self, x: &[f64], y: &[f64]) -> Result<Option<MissingFill>> {
+        let first = |s: &[f64]| s.iter().position(|v| v.is_nan());
+        let (sequence, index) = match (first(x), first(y)) {
+            (None, None) => return Ok(None),
+            (Some(i), _) => (0, i),
+            (None, Some(j)) => (1, j),
+        };
+        match self.missing {
+            MissingValues::Reject => Err(Error::MissingSample { sequence, index }),
+            MissingValues::Cost(c) if !(c >= 0.0 && c.is_finite()) => {
+                Err(Error::InvalidMissingCost(c))
+            }
+            MissingValues::Cost(c) => Ok(Some(MissingFill::Cost(c))),
+            MissingValues::Expected => {
+                let mut mean = [0.0; 2];
+                let mut var = [0.0; 2];
+                for (k, s) in [x, y].into_iter().enumerate() {
+                    let observed: Vec<f64> = s.iter().copied().filter(|v| !v.is_nan()).collect();
+                    if observed.is_empty() {
+                        return Err(Error::MissingSample {
+                            sequence: k,
+                            index: 0,
+                        });
+                    }
+                    let len = observed.len() as f64;
+                    mean[k] = observed.iter().sum::<f64>() / len;
+                    var[k] = observed.iter().map(|v| (v - mean[k]).powi(2)).sum::<f64>() / len;
+                }
+                Ok(Some(MissingFill::Expected { mean, var }))
+            }
+        }
+    }
+
+    /// Row-major squared-distance matrix of `x` and `y`, with substitutes for missing cells.
+    fn sequence_cost(&self, x: &[f64], y: &[f64], fill: Option<MissingFill>) -> Vec<f64> {
+        let (n, m) = (x.len(), y.len());
+        match fill {
+            None => x
+                .iter()
+                .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+                .collect(),
+            Some(fill) => (0..n * m).map(|k| fill.cost(x, y, k / m, k % m)).collect(),
+        }
+    }
+
+    fn check_gamma(&self) -> Result<()> {
+        if self.gamma <= 0.0 || !self.gamma.is_finite() {
+            return Err(Error::InvalidGamma(self.gamma));
+        }
+        Ok(())
+    }
+
+    fn check_penalty(&self) -> Result<()> {
+        let lambda = self.diagonal.lambda;
+        if !(lambda >= 0.0 && lambda.is_finite()) {
+            return Err(Error::InvalidDiagonalPenalty(lambda));
+        }
+        match self
+            .penalty
+            .steps()
+            .into_iter()
+            .find(|p| p.is_nan() || *p == f64::NEG_INFINITY)
+        {
+            Some(p) => Err(Error::InvalidPenalty(p)),
+            None => Ok(()),
+        }
+    }
+
+    /// Penalties of the steps (up, left, diagonal) into 1-based cell `(i, j)`; the first
+    /// cell is entered from the corner of the table, which is not a step.
+    fn penalties(&self, i: usize, j: usize) -> [f64; 3] {
+        if i == 1 && j == 1 {
+            [0.0; 3]
+        } else {
+            self.penalty.steps()
+        }
+    }
+
+    /// Band half-width, if any (for [`crate::resources`]).
+    pub(crate) fn band_width(&self) -> Option<usize> {
+        self.band
+    }
+
+    /// Potential precision (for [`crate::resources`]).
+    pub(crate) fn precision_mode(&self) -> Precision {
+        self.precision
+    }
+
+    /// Whether the unbanded, unpenalized `Symmetric1` kernels apply.
+    pub(crate) fn plain(&self) -> bool {
+        self.band.is_none()
+            && self.step_pattern == StepPattern::Symmetric1
+            && self.penalty == TransitionPenalty::default()
+    }
+
+    /// 1-based columns of row `i` (1-based) inside the band: `j` with
+    /// `|(j-1) - (i-1)(m-1)/(n-1)| <= band`. The loops only visit these, so a band of
+    /// half-width `b` costs `O(n b)` time.
+    fn band_cols(&self, i: usize, n: usize, m: usize) -> std::ops::RangeInclusive<usize> {
+        match self.band {
+            None => 1..=m,
+            Some(b) => {
+                let center = if n > 1 {
+                    (i - 1) as f64 * (m - 1) as f64 / (n - 1) as f64
+                } else {
+                    0.0
+                };
+                let lo = (center - b as f64).ceil().max(0.0) as usize;
+                let hi = (center + b as f64).floor().min((m - 1) as f64) as usize;
+                lo + 1..=hi + 1
+            }
+        }
+    }
+
+    fn finish(&self, value: f64, n: usize, m: usize) -> Result<f64> {
+        if let Some(band) = self.band {
+            if value == f64::INFINITY {
+                return Err(Error::NoWarpingPath { band, n, m });
+            }
+        }
+        Ok(if self.normalized {
+            value / (n + m) as f64
+        } else {
+            value
+        })
+    }
+
+    fn value<F>(&self, n: usize, m: usize, cost: F) -> Result<f64>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        self.check_penalty()?;
+        let cost = self.diagonal.apply(n, m, cost);
+        let hard = || self.hard_forward(n, m, &cost)[n * (m + 1) + m];
+        let value = if self.tiny_gamma.forces_hard(self.gamma) {
+            hard()
+        } else {
+            let v = self.soft_value(n, m, &cost);
+            if self.tiny_gamma.detects() && !v.is_finite() {
+                hard()
+            } else {
+                v
+            }
+        };
+        self.finish(value, n, m)
+    }
+
+    fn soft_value<F>(&self, n: usize, m: usize, cost: F) -> f64
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        match self.precision {
+            Precision::Double => self.forward(n, m, cost)[n * (m + 1) + m],
+            Precision::DoubleDouble => self.dd_forward(n, m, cost)[n * (m + 1) + m].to_f64(),
+        }
+    }
+
+    fn soft_alignment<F>(
+        &self,
+        n: usize,
+        m: usize,
+        cost: F,
+        steps: bool,
+    ) -> (f64, Vec<f64>, [f64; 3])
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        match self.precision {
+            Precision::Double => {
+                let r = self.forward(n, m, &cost);
+                let (e, k) = self.backward(&r, n, m, cost, steps);
+                (r[n * (m + 1) + m], e, k)
+            }
+            Precision::DoubleDouble => {
+                let r = self.dd_forward(n, m, &cost);
+                let (e, k) = self.dd_backward(&r, n, m, cost);
+                (r[n * (m + 1) + m].to_f64(), e, k)
+            }
+        }
+    }
+
+    /// [`SoftDtw::forward`] with double-double potentials.
+    fn dd_forward<F>(&self, n: usize, m: usize, cost: F) -> Vec<DoubleDouble>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let [wa, wb, wc] = self.step_pattern.weights();
+        let mut r = vec![DoubleDouble::INFINITY; (n + 1) * w];
+        r[0] = DoubleDouble::from(0.0);
+        for i in 1..=n {
+            for j in self.band_cols(i, n, m) {
+                let d = DoubleDouble::from(cost(i - 1, j - 1));
+                let [pa, pb, pc] = self.penalties(i, j).map(DoubleDouble::from);
+                let cands = [
+                    r[(i - 1) * w + j] + d.mul_f64(wa) + pa,
+                    r[i * w + (j - 1)] + d.mul_f64(wb) + pb,
+                    r[(i - 1) * w + (j - 1)] + d.mul_f64(wc) + pc,
+                ];
+                r[i * w + j] = softmin_dd(self.gamma, &cands);
+            }
+        }
+        r
+    }
+
+    /// [`SoftDtw::backward`] over a double-double table.
+    fn dd_backward<F>(
+        &self,
+        r: &[DoubleDouble],
+        n: usize,
+        m: usize,
+        cost: F,
+    ) -> (Vec<f64>, [f64; 3])
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let weights = self.step_pattern.weights();
+        let mut g = vec![0.0; (n + 1) * w];
+        let mut e = vec![0.0; n * m];
+        let mut steps = [0.0; 3];
+        g[n * w + m] = 1.0;
+        for i in (1..=n).rev() {
+            for j in self.band_cols(i, n, m).rev() {
+                let (gs, rs) = (g[i * w + j], r[i * w + j]);
+                if gs == 0.0 || !rs.is_finite() {
+                    continue;
+                }
+                let d = DoubleDouble::from(cost(i - 1, j - 1));
+                let preds = [(i - 1, j), (i, j - 1), (i - 1, j - 1)];
+                let penalties = self.penalties(i, j);
+                for (k, (pi, pj)) in preds.into_iter().enumerate() {
+                    let (rp, wk, pk) = (r[pi * w + pj], weights[k], penalties[k]);
+                    if !rp.is_finite() || !pk.is_finite() {
+                        continue;
+                    }
+                    let z = (rs - rp - d.mul_f64(wk) - DoubleDouble::from(pk)).div_f64(self.gamma);
+                    let flow = gs * exp(z.to_f64());
+                    g[pi * w + pj] += flow;
+                    e[(i - 1) * m + (j - 1)] += flow * wk;
+                    if i > 1 || j > 1 {
+                        steps[k] += flow;
+                    }
+                }
+            }
+        }
+        (e, steps)
+    }
+
+    /// Min-plus version of [`SoftDtw::forward`], with the same band and step pattern.
+    fn hard_forward<F>(&self, n: usize, m: usize, cost: F) -> Vec<f64>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let mut r = Vec::new();
+        self.hard_forward_into(&mut r, n, m, cost);
+        r
+    }
+
+    /// [`SoftDtw::hard_forward`] writing into `r` (cleared and resized), reusing its
+    /// allocation.
+    fn hard_forward_into<F>(&self, r: &mut Vec<f64>, n: usize, m: usize, cost: F)
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let [wa, wb, wc] = self.step_pattern.weights();
+        r.clear();
+        r.resize((n + 1) * w, f64::INFINITY);
+        r[0] = 0.0;
+        for i in 1..=n {
+            for j in self.band_cols(i, n, m) {
+                let d = cost(i - 1, j - 1);
+                let [pa, pb, pc] = self.penalties(i, j);
+                let a = r[(i - 1) * w + j] + wa * d + pa;
+                let b = r[i * w + (j - 1)] + wb * d + pb;
+                let c = r[(i - 1) * w + (j - 1)] + wc * d + pc;
+                r[i * w + j] = a.min(b).min(c);
+            }
+        }
+    }
+
+    /// Min-plus table of the cheapest in-band continuation from each 1-based cell to
+    /// `(n, m)`, excluding the cell's own cost (`+inf` outside the band).
+    fn hard_backward<F>(&self, n: usize, m: usize, cost: F) -> Vec<f64>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let weights = self.step_pattern.weights();
+        let mut b = vec![f64::INFINITY; (n + 1) * w];
+        b[n * w + m] = 0.0;
+        for i in (1..=n).rev() {
+            for j in self.band_cols(i, n, m).rev() {
+                let succs = [(i + 1, j), (i, j + 1), (i + 1, j + 1)];
+                for (k, (si, sj)) in succs.into_iter().enumerate() {
+                    if si > n || sj > m || b[si * w + sj] == f64::INFINITY {
+                        continue;
+                    }
+                    let step = weights[k] * cost(si - 1, sj - 1) + self.penalties(si, sj)[k];
+                    b[i * w + j] = b[i * w + j].min(step + b[si * w + sj]);
+                }
+            }
+        }
+        b
+    }
+
+    /// Certified bound on how much the (unnormalized) banded value `value` exceeds the
+    /// unconstrained one, for nonnegative costs and penalties (see
+    /// [`SoftDtw::select_band`]).
+    fn band_gap<F>(&self, n: usize, m: usize, cost: F, value: f64) -> f64
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let fwd = self.hard_forward(n, m, &cost);
+        let bwd = self.hard_backward(n, m, &cost);
+        let inside = |i: usize, j: usize| {
+            (1..=n).contains(&i) && (1..=m).contains(&j) && self.band_cols(i, n, m).contains(&j)
+        };
+        let (mut exit, mut entry) = (f64::INFINITY, f64::INFINITY);
+        for i in 1..=n {
+            for j in self.band_cols(i, n, m) {
+                let succs = [(i + 1, j), (i, j + 1), (i + 1, j + 1)];
+                if succs
+                    .iter()
+                    .any(|&(si, sj)| si <= n && sj <= m && !inside(si, sj))
+                {
+                    exit = exit.min(fwd[i * w + j]);
+                }
+                let preds = [(i - 1, j), (i, j - 1), (i - 1, j - 1)];
+                if preds
+                    .iter()
+                    .any(|&(pi, pj)| pi >= 1 && pj >= 1 && !inside(pi, pj))
+                {
+                    entry = entry.min(bwd[i * w + j]);
+                }
+            }
+        }
+        let paths = (n + m) as f64 * 3f64.ln();
+        self.gamma * ((paths - (exit + entry - value) / self.gamma).exp()).ln_1p()
+    }
+
+    /// Adds each step's weight on one argmin path to `E` and counts the steps of each kind.
+    fn hard_backtrack<F>(&self, r: &[f64], n: usize, m: usize, cost: F) -> (Vec<f64>, [f64; 3])
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let weights = self.step_pattern.weights();
+        let mut e = vec![0.0; n * m];
+        let mut steps = [0.0; 3];
+        for (i, j, k) in self.hard_path(r, n, m, cost) {
+            e[i * m + j] += weights[k];
+            if i > 0 || j > 0 {
+                steps[k] += 1.0;
+            }
+        }
+        (e, steps)
+    }
+
+    /// Follows one argmin path back from `(n, m)`: the 0-based cells from the last to the
+    /// first, each with the kind of step into it (0 up, 1 left, 2 diagonal). Empty when the
+    /// value is infinite.
+    fn hard_path<F>(&self, r: &[f64], n: usize, m: usize, cost: F) -> Vec<(usize, usize, usize)>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        let w = m + 1;
+        let weights = self.step_pattern.weights();
+        let mut path = Vec::new();
+        if !r[n * w + m].is_finite() {
+            return path;
+        }
+        let (mut i, mut j) = (n, m);
+        while i > 0 && j > 0 {
+            let d = cost(i - 1, j - 1);
+            let penalties = self.penalties(i, j);
+            // Diagonal first, then up, then left.
+            let preds = [(i - 1, j - 1, 2), (i - 1, j, 0), (i, j - 1, 1)];
+            let step_cost =
+                |(pi, pj, k): (usize, usize, usize)| r[pi * w + pj] + weights[k] * d + penalties[k];
+            let (pi, pj, k) = preds
+                .into_iter()
+                .filter(|&p| step_cost(p).is_finite())
+                .min_by(|&x, &y| step_cost(x).total_cmp(&step_cost(y)))
+                .expect("a finite cell has a finite predecessor");
+            path.push((i - 1, j - 1, k));
+            (i, j) = (pi, pj);
+        }
+        path
+    }
+
+    fn forward<F>(&self, n: usize, m: usize, cost: F) -> Vec<f64>
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        if self.plain() {
+            return forward_table(n, m, self.gamma, cost);
+        }
+        let w = m + 1;
+        let [wa, wb, wc] = self.step_pattern.weights();
+        let mut r = vec![f64::INFINITY; (n + 1) * w];
+        r[0] = 0.0;
+        for i in 1..=n {
+            for j in self.band_cols(i, n, m) {
+                let d = cost(i - 1, j - 1);
+                let [pa, pb, pc] = self.penalties(i, j);
+                let a = r[(i - 1) * w + j] + pa;
+                let b = r[i * w + (j - 1)] + pb;
+                let c = r[(i - 1) * w + (j - 1)] + pc;
+                r[i * w + j] = match self.step_pattern {
+                    StepPattern::Symmetric1 => d + softmin3(self.gamma, a, b, c),
+                    _ => softmin3(self.gamma, a + wa * d, b + wb * d, c + wc * d),
+                };
+            }
+        }
+        r
+    }
+
+    /// Reverse-mode pass over the table: `g[p]` accumulates \(\partial R_{n,m}/\partial R_p\)
+    /// and each step into `s` with weight `w` adds `g[s] · P(step) · w` to `E[s]`. The
+    /// flows of the steps of each kind are summed into the expected step counts, which the
+    /// plain fast path skips unless `steps` is set.
+    fn backward<F>(
+        &self,
+        r: &[f64],
+        n: usize,
+        m: usize,
+        cost: F,
+        steps: bool,
+    ) -> (Vec<f64>, [f64; 3])
+    where
+        F: Fn(usize, usize) -> f64,
+    {
+        if self.plain() && !steps {
+            return (alignment_from_table(r, n, m, self.gamma, cost), [0.0; 3]);
+        }
+        let w = m + 1;
+        let weights = self.step_pattern.weights();
+        let mut g = vec![0.0; (n + 1) * w];
+        let mut e = vec![0.0; n * m];
+        let mut counts = [0.0; 3];
+        g[n * w + m] = 1.0;
+        for i in (1..=n).rev() {
+            for j in self.band_cols(i, n, m).rev() {
+                let (gs, rs) = (g[i * w + j], r[i * w + j]);
+                if gs == 0.0 || !rs.is_finite() {
+                    continue;
+                }
+                let d = cost(i - 1, j - 1);
+                let preds = [(i - 1, j), (i, j - 1), (i - 1, j - 1)];
+                let penalties = self.penalties(i, j);
+                for (k, (pi, pj)) in preds.into_iter().enumerate() {
+                    let (rp, wk, pk) = (r[pi * w + pj], weights[k], penalties[k]);
+                    if !rp.is_finite() || !pk.is_finite() {
+                        continue;
+                    }
+                    let flow = gs * exp((rs - rp - wk * d - pk) / self.gamma);
+                    g[pi * w + pj] += flow;
+                    e[(i - 1) * m + (j - 1)] += flow * wk;
+                    if i > 1 || j > 1 {
+                        counts[k] += flow;
+                    }
+                }
+            }
+        }
+        (e, counts)
+    }
+}
+
+/// Result of [`SoftDtw::analyze`] or [`SoftDtw::analyze_cost`].
+///
+/// Only the value is computed up front, with the forward pass alone; the alignment, the
+/// expected step counts and the quantities derived from them come from one backward pass run
+/// on first use and cached. The hard path and the divergence are computed on each call.
+/// Every quantity honours the operator's options (band, step pattern, penalties, precision,
+/// normalization and the [`TinyGamma`] fallback), so the value and alignment equal those of
+/// [`SoftDtw::compute_cost`] and [`SoftDtw::alignment`].
+///
+/// ```
+/// use structop::soft_dtw::SoftDtw;
+///
+/// let (x, y) = ([0.0, 1.0, 2.0, 2.0], [0.0, 2.0, 2.0]);
+/// let res = SoftDtw::new(0.1).analyze(&x, &y).unwrap();
+/// assert_eq!(res.hard_path(), vec![(0, 0), (1, 0), (2, 1), (3, 2)]);
+/// assert!(res.entropy() >= 0.0 && res.divergence().unwrap().unwrap() >= 0.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SoftDtwAlignment<'a> {
+    op: SoftDtw,
+    cost: Cow<'a, [f64]>,
+    n: usize,
+    m: usize,
+    value: f64,
+    sequences: Option<(&'a [f64], &'a [f64])>,
+    marginals: OnceCell<(Vec<f64>, [f64; 3])>,
+}
+
+impl SoftDtwAlignment<'_> {
+    /// Shape `(n, m)` of the cost matrix.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.n, self.m)
+    }
+
+    /// Soft-DTW value.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Soft-DTW divergence `v(x, y) - (v(x, x) + v(y, y)) / 2` with the same options, or
+    /// `None` for results built from a cost matrix (the self-costs are unknown).
+    pub fn divergence(&self) -> Option<Result<f64>> {
+        let (x, y) = self.sequences?;
+        let selfs = self
+            .op
+            .compute(x, x)
+            .and_then(|xx| Ok((xx, self.op.compute(y, y)?)));
+        Some(selfs.map(|(xx, yy)| self.value - 0.5 * xx - 0.5 * yy))
+    }
+
+    fn marginals(&self) -> &(Vec<f64>, [f64; 3]) {
+        self.marginals.get_or_init(|| {
+            let (_, e, steps) = self
+                .op
+                .solve(&self.cost, self.n, self.m, true)
+                .expect("validated when the value was computed");
+            (e, steps)
+        })
+    }
+
+    /// Expected alignment, row-major `n × m` (as [`SoftDtw::alignment`]).
+    pub fn alignment(&self) -> &[f64] {
+        &self.marginals().0
+    }
+
+    /// Expected number of steps of each kind (as [`SoftDtw::transition_grad`]).
+    pub fn expected_steps(&self) -> TransitionPenalty {
+        TransitionPenalty::from_steps(self.marginals().1)
+    }
+
+    /// Expected column of each row: `sum_j j E[i,j] / sum_j E[i,j]`, the soft path read as
+    /// a warping function from the first sequence to the second. Rows with no mass (outside
+    /// a band that excludes them, say) are NaN.
+    pub fn expected_path(&self) -> Vec<f64> {
+        self.alignment()
+            .chunks(self.m)
+            .map(|row| {
+                let mass: f64 = row.iter().sum();
+                row.iter()
+                    .enumerate()
+                    .map(|(j, &p)| j as f64 * p)
+                    .sum::<f64>()
+                    / mass
+            })
+            .collect()
+    }
+
+    /// Variance of each cell's membership indicator `1[(i, j) on the path]` under the Gibbs
+    /// distribution, `E[i,j] (1 - E[i,j])` (row-major `n × m`): zero where the path is
+    /// certain, largest where a cell is on half of the probability mass.
+    pub fn cell_variance(&self) -> Vec<f64> {
+        self.alignment().iter().map(|&p| p * (1.0 - p)).collect()
+    }
+
+    /// Per-row credible interval `(lo, hi)` of the aligned column: the central `level`
+    /// quantile range of row `i` of the alignment, normalized to a distribution over `j`.
+    /// Together with [`expected_path`](Self::expected_path) it gives an uncertainty band
+    /// around the expected warp. `level` is clamped to `[0, 1]`; rows with no mass are `None`.
+    pub fn credible_band(&self, level: f64) -> Vec<Option<(usize, usize)>> {
+        let tail = (1.0 - level.clamp(0.0, 1.0)) / 2.0;
+        self.alignment()
+            .chunks(self.m)
+            .map(|row| {
+                let mass: f64 = row.iter().sum();
+                if mass <= 0.0 {
+                    return None;
+                }
+                let (mut lo, mut hi, mut cum) = (None, None, 0.0);
+                for (j, &p) in row.iter().enumerate() {
+                    cum += p;
+                    if lo.is_none() && cum > tail * mass {
+                        lo = Some(j);
+                    }
+                    if cum >= (1.0 - tail) * mass {
+                        hi = Some(j);
+                        break;
+                    }
+                }
+                Some((lo?, hi.unwrap_or(self.m - 1)))
+            })
+            .collect()
+    }
+
+    /// Entropy (in nats) of the Gibbs distribution over warping paths,
+    /// \(H = (\mathbb{E}[C] - V)/\gamma\) with \(V\) the unnormalized value and
+    /// \(\mathbb{E}[C] = \langle E, C\rangle\) plus the expected penalties. Zero for a
+    /// single path, `ln` of the number of paths for an all-zero cost. Cancellation makes it
+    /// inaccurate once \(\gamma\) is tiny relative to the costs (and it is clamped at zero
+    /// there); under the hard fallback of [`TinyGamma`] it is meaningless.
+    pub fn entropy(&self) -> f64 {
+        let (e, steps) = self.marginals();
+        let scale = if self.op.normalized {
+            (self.n + self.m) as f64
+        } else {
+            1.0
+        };
+        let (n, m) = (self.n, self.m);
+        let local = self.op.diagonal.apply(n, m, |i, j| self.cost[i * m + j]);
+        let cost = e
+            .iter()
+            .enumerate()
+            .filter(|&(_, &p)| p != 0.0)
+            .map(|(k, &p)| p * local(k / m, k % m))
+            .sum::<f64>();
+        let penalty = steps
+            .iter()
+            .zip(self.op.penalty.steps())
+            .filter(|&(&k, _)| k != 0.0)
+            .map(|(&k, p)| k * p)
+            .sum::<f64>();
+        ((cost + penalty - self.value) * scale / self.op.gamma).max(0.0)
+    }
+
+    /// Minimum-cost warping path under the same band, step pattern and penalties (the
+    /// \(\gamma \to 0\) limit), as 0-based cells from `(0, 0)` to `(n-1, m-1)`. Empty when
+    /// no path is admissible.
+    pub fn hard_path(&self) -> Vec<(usize, usize)> {
+        let (n, m) = (self.n, self.m);
+        let c = self.op.diagonal.apply(n, m, |i, j| self.cost[i * m + j]);
+        let c = &c;
+        let r = self.op.hard_forward(n, m, c);
+        let mut path: Vec<_> = self
+            .op
+            .hard_path(&r, n, m, c)
+            .into_iter()
+            .map(|(i, j, _)| (i, j))
+            .collect();
+        path.reverse();
+        path
+    }
+}
+
+/// Output of [`SoftDtw::alignment_graph`]: the warping DAG and the cell of each edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignmentGraph {
+    graph: Graph,
+    heads: Vec<(usize, f64)>,
+    n: usize,
+    m: usize,
+}
+
+impl AlignmentGraph {
+    /// The warping DAG.
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// 0-based cell `(i, j)` that edge `edge` steps into.
+    pub fn cell(&self, edge: usize) -> (usize, usize) {
+        let k = self.heads[edge].0;
+        (k / self.m, k % self.m)
+    }
+
+    /// Expected alignment (row-major `n × m`) from edge marginals indexed like
+    /// [`Graph::edges`]: each cell collects the marginals of the steps into it, times their
+    /// step weights, which is the gradient w.r.t. the cost matrix.
+    pub fn alignment(&self, marginals: &[f64]) -> Vec<f64> {
+        let mut e = vec![0.0; self.n * self.m];
+        for (&(k, w), p) in self.heads.iter().zip(marginals) {
+            e[k] += w * p;
+        }
+        e
+    }
+}
+
+/// Incremental Soft-DTW of a streamed sequence `x` against a fixed reference `y`.
+///
+/// Only the last row of the forward table is kept, so memory is `O(m)` regardless of how
+/// many elements of `x` are pushed; elements can arrive one at a time, from an iterator,
+/// or in chunks read from a file or socket. After each push, [`SoftDtwStream::value`] is
+/// `soft_dtw(&x[..len], y, gamma)` for the prefix consumed so far (bit-for-bit, since the
+/// recurrence is evaluated in the same order as the full-table kernel).
+#[derive(Debug, Clone)]
+pub struct SoftDtwStream<'a> {
+    y: &'a [f64],
+    gamma: f64,
+    row: Vec<f64>,
+    prev: Vec<f64>,
+    len: usize,
+}
+
+impl<'a> SoftDtwStream<'a> {
+    /// Starts a stream against the reference `y`.
+    pub fn new(y: &'a [f64], gamma: f64) -> Result<Self> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        if y.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        let mut row = vec![f64::INFINITY; y.len() + 1];
+        row[0] = 0.0;
+        Ok(Self {
+            y,
+            gamma,
+            prev: row.clone(),
+            row,
+            len: 0,
+        })
+    }
+
+    /// Consumes one element of `x` (one row of the DP table).
+    pub fn push(&mut self, xi: f64) {
+        std::mem::swap(&mut self.row, &mut self.prev);
+        self.row[0] = f64::INFINITY;
+        for j in 1..=self.y.len() {
+            let d = (xi - self.y[j - 1]).powi(2);
+            let a = self.prev[j];
+            let b = self.row[j - 1];
+            let c = self.prev[j - 1];
+            self.row[j] = d + softmin3(self.gamma, a, b, c);
+        }
+        self.len += 1;
+    }
+
+    /// Number of elements of `x` consumed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether no element of `x` has been consumed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Soft-DTW value of the prefix consumed so far (`EmptyInput` before the first push).
+    pub fn value(&self) -> Result<f64> {
+        if self.is_empty() {
+            return Err(Error::EmptyInput);
+        }
+        Ok(self.row[self.y.len()])
+    }
 
-    // DP table R has shape (n+1, m+1). We store row-major in a flat Vec.
-    // Indexing: r[i*(m+1) + j]
-    let w = m + 1;
-    let mut r = vec![f64::INFINITY; (n + 1) * (m + 1)];
-    r[0] = 0.0;
+    /// The stream's state, to persist and later continue with [`SoftDtwStream::resume`].
+    pub fn checkpoint(&self) -> StreamCheckpoint {
+        StreamCheckpoint {
+            gamma: self.gamma,
+            len: self.len,
+            row: if self.is_empty() {
+                Vec::new()
+            } else {
+                self.row[1..].to_vec()
+            },
+        }
+    }
 
-    for i in 1..=n {
-        for j in 1..=m {
-            let d = (x[i - 1] - y[j - 1]).powi(2);
-            let a = r[(i - 1) * w + j];
-            let b = r[i * w + (j - 1)];
-            let c = r[(i - 1) * w + (j - 1)];
-            r[i * w + j] = d + softmin3(gamma, a, b, c);
+    /// Continues a stream from `checkpoint` against the same reference `y`; pushes then
+    /// give the same values, bit for bit, as if the stream had never stopped. A checkpoint
+    /// whose row does not fit `y`, or holds NaN or `-inf`, is rejected.
+    pub fn resume(y: &'a [f64], checkpoint: &StreamCheckpoint) -> Result<Self> {
+        let mut stream = Self::new(y, checkpoint.gamma)?;
+        if checkpoint.len == 0 && checkpoint.row.is_empty() {
+            return Ok(stream);
+        }
+        if checkpoint.len == 0 {
+            return Err(Error::CheckpointNotStarted {
+                len: checkpoint.row.len(),
+            });
         }
+        if checkpoint.row.len() != y.len() {
+            return Err(Error::CheckpointMismatch {
+                len: checkpoint.row.len(),
+                expected: y.len(),
+            });
+        }
+        let bad = |v: &f64| v.is_nan() || *v == f64::NEG_INFINITY;
+        if let Some(index) = checkpoint.row.iter().position(bad) {
+            let value = checkpoint.row[index];
+            return Err(Error::InvalidCheckpointRow { index, value });
+        }
+        stream.row[0] = f64::INFINITY;
+        stream.row[1..].copy_from_slice(&checkpoint.row);
+        stream.len = checkpoint.len;
+        Ok(stream)
     }
-    Ok(r[n * w + m])
 }
 
-/// Soft-DTW value given a precomputed cost matrix `cost` (row-major).
-///
-/// This is the more general form used in practice when the elements are not scalars
-/// (e.g. sentence embeddings) and you want \(d(x_i,y_j)\) to be an arbitrary distance.
+/// Saved state of a [`SoftDtwStream`] (serializable with the `serde` feature).
 ///
-/// `cost` must have length `n * m`, storing `cost[i*m + j] = d(x_i, y_j)` for
-/// 0-based indices `i in 0..n`, `j in 0..m`.
-pub fn soft_dtw_cost(cost: &[f64], n: usize, m: usize, gamma: f64) -> Result<f64> {
-    if gamma <= 0.0 || !gamma.is_finite() {
-        return Err(Error::InvalidGamma(gamma));
-    }
-    if n == 0 || m == 0 {
-        return Err(Error::EmptyInput);
-    }
-    if cost.len() != n * m {
-        return Err(Error::InvalidCostShape {
-            len: cost.len(),
-            n,
-            m,
-            expected: n * m,
-        });
-    }
-
-    let w = m + 1;
-    let mut r = vec![f64::INFINITY; (n + 1) * (m + 1)];
-    r[0] = 0.0;
+/// Holds `gamma`, the number of elements consumed and the last DP row, but not the
+/// reference, which is passed again to [`SoftDtwStream::resume`]. The row's `+inf`
+/// boundary entry is left out, so the state has no non-finite numbers for finite input
+/// and survives formats such as JSON.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StreamCheckpoint {
+    /// Smoothing parameter of the stream.
+    pub gamma: f64,
+    /// Number of elements of `x` consumed.
+    pub len: usize,
+    /// Last row of the forward table, columns `1..=m` (empty before the first push).
+    pub row: Vec<f64>,
+}
 
-    for i in 1..=n {
-        for j in 1..=m {
-            let d = cost[(i - 1) * m + (j - 1)];
-            let a = r[(i - 1) * w + j];
-            let b = r[i * w + (j - 1)];
-            let c = r[(i - 1) * w + (j - 1)];
-            r[i * w + j] = d + softmin3(gamma, a, b, c);
+impl Extend<f64> for SoftDtwStream<'_> {
+    fn extend<I: IntoIterator<Item = f64>>(&mut self, iter: I) {
+        for xi in iter {
+            self.push(xi);
         }
     }
-    Ok(r[n * w + m])
 }
 
-/// Soft-DTW divergence (commonly used because it is nonnegative and zero on identical inputs).
-pub fn soft_dtw_divergence(x: &[f64], y: &[f64], gamma: f64) -> Result<f64> {
-    let xy = soft_dtw(x, y, gamma)?;
-    let xx = soft_dtw(x, x, gamma)?;
-    let yy = soft_dtw(y, y, gamma)?;
-    Ok(xy - 0.5 * xx - 0.5 * yy)
+impl<'b> Extend<&'b f64> for SoftDtwStream<'_> {
+    fn extend<I: IntoIterator<Item = &'b f64>>(&mut self, iter: I) {
+        self.extend(iter.into_iter().copied());
+    }
 }
 
-/// Soft-DTW divergence given precomputed cost matrices:
-/// - `cost_xy` shape `n×m`
-/// - `cost_xx` shape `n×n`
-/// - `cost_yy` shape `m×m`
+/// Soft-DTW value for a sequence `x` given as an iterator, in `O(m)` memory.
 ///
-/// This mirrors the scalar `soft_dtw_divergence` but works for arbitrary distances.
-pub fn soft_dtw_divergence_cost(
-    cost_xy: &[f64],
-    cost_xx: &[f64],
-    cost_yy: &[f64],
-    n: usize,
-    m: usize,
-    gamma: f64,
-) -> Result<f64> {
-    let xy = soft_dtw_cost(cost_xy, n, m, gamma)?;
-    let xx = soft_dtw_cost(cost_xx, n, n, gamma)?;
-    let yy = soft_dtw_cost(cost_yy, m, m, gamma)?;
-    Ok(xy - 0.5 * xx - 0.5 * yy)
+/// Equivalent to `soft_dtw(&x.collect::<Vec<_>>(), y, gamma)` without materializing `x`;
+/// see [`SoftDtwStream`] for chunked or incremental input.
+pub fn soft_dtw_iter<I>(x: I, y: &[f64], gamma: f64) -> Result<f64>
+where
+    I: IntoIterator<Item = f64>,
+{
+    let mut stream = SoftDtwStream::new(y, gamma)?;
+    stream.extend(x);
+    stream.value()
 }
 
 #[cfg(test)]
@@ -166,6 +2880,45 @@ mod tests {
     use proptest::prelude::*;
     use std::f64;
 
+    #[test]
+    fn tiled_kernels_match_plain_sweep_and_col_major_input() {
+        // Several tiles in each direction, with partial tiles at the edges.
+        let (n, m, gamma) = (150, 97, 0.3);
+        let cost: Vec<f64> = (0..n * m)
+            .map(|k| ((k * 37 % 101) as f64 / 50.0).sin())
+            .collect();
+        let w = m + 1;
+        let mut naive = vec![f64::INFINITY; (n + 1) * w];
+        naive[0] = 0.0;
+        for i in 1..=n {
+            for j in 1..=m {
+                let (a, b) = (naive[(i - 1) * w + j], naive[i * w + j - 1]);
+                let c = naive[(i - 1) * w + j - 1];
+                naive[i * w + j] = cost[(i - 1) * m + j - 1] + softmin3(gamma, a, b, c);
+            }
+        }
+        assert_eq!(soft_dtw_table(&cost, n, m, gamma).unwrap(), naive);
+
+        let mut col = vec![0.0; n * m];
+        for i in 0..n {
+            for j in 0..m {
+                col[j * n + i] = cost[i * m + j];
+            }
+        }
+        let (v, e) = soft_dtw_alignment(&cost, n, m, gamma).unwrap();
+        let (vc, ec) = soft_dtw_alignment_col_major(&col, n, m, gamma).unwrap();
+        assert_eq!(v, vc);
+        assert_eq!(soft_dtw_cost_col_major(&col, n, m, gamma).unwrap(), v);
+        assert!((0..n * m).all(|k| e[k] == ec[(k % m) * n + k / m]));
+        let row_sum: f64 = e[..m].iter().sum();
+        assert!(row_sum >= 1.0 - 1e-9, "row 0 mass {row_sum}");
+        col[3 * n + 5] = f64::NAN;
+        assert_eq!(
+            soft_dtw_cost_col_major(&col, n, m, gamma),
+            Err(Error::NanCost { i: 5, j: 3 })
+        );
+    }
+
     #[test]
     fn identical_sequences_have_zero_divergence() {
         let x = [1.0, 2.0, 3.0];
@@ -182,6 +2935,40 @@ mod tests {
         assert!((a - b).abs() < 1e-12, "a={} b={}", a, b);
     }
 
+    #[test]
+    fn asymmetric_divergence_splits_gammas_with_gradients() {
+        let x = [1.0, 2.0, 3.0, 2.5];
+        let y = [1.0, 2.5, 2.0];
+        let d = soft_dtw_divergence_asym(&x, &y, 0.5, 0.1).unwrap();
+        let want = soft_dtw(&x, &y, 0.5).unwrap()
+            - 0.5 * soft_dtw(&x, &x, 0.1).unwrap()
+            - 0.5 * soft_dtw(&y, &y, 0.1).unwrap();
+        assert_eq!(d, want);
+        assert_eq!(
+            soft_dtw_divergence_asym(&x, &y, 0.5, 0.5),
+            soft_dtw_divergence(&x, &y, 0.5)
+        );
+
+        let (v, gx, gy) = soft_dtw_divergence_asym_grad(&x, &y, 0.5, 0.1).unwrap();
+        assert!((v - d).abs() < 1e-12);
+        let tol = crate::gradcheck::Tolerance::default();
+        let f = |p: &[f64]| Ok(soft_dtw_divergence_asym(p, &y, 0.5, 0.1)?);
+        let report = crate::gradcheck::check_gradient(f, &x, &gx, tol).unwrap();
+        assert!(report.passed(), "{:?}", report);
+        let f = |p: &[f64]| Ok(soft_dtw_divergence_asym(&x, p, 0.5, 0.1)?);
+        let report = crate::gradcheck::check_gradient(f, &y, &gy, tol).unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        assert_eq!(
+            soft_dtw_divergence_asym_grad(&x, &y, 0.0, 0.1),
+            Err(Error::InvalidGamma(0.0))
+        );
+        assert_eq!(
+            soft_dtw_divergence_asym(&x, &y, 0.5, -1.0),
+            Err(Error::InvalidGamma(-1.0))
+        );
+    }
+
     proptest! {
         #[test]
         fn divergence_is_nonnegative_for_small_random_inputs(
@@ -213,6 +3000,949 @@ mod tests {
         let v_cost = soft_dtw_cost(&cost_xy, n, m, gamma).unwrap();
 
         assert!((v_scalar - v_cost).abs() < 1e-12, "scalar={} cost={}", v_scalar, v_cost);
+        let log_z = soft_dtw_log_partition(&cost_xy, n, m, gamma).unwrap();
+        assert_eq!(log_z, -v_cost / gamma);
+    }
+
+    #[test]
+    fn alignment_matches_finite_differences_of_value() {
+        let n = 3usize;
+        let m = 4usize;
+        let gamma = 0.6;
+        let mut cost = vec![
+            0.3, 1.2, 0.4, 0.9, //
+            0.8, 0.1, 0.7, 0.5, //
+            1.1, 0.6, 0.2, 0.3, //
+        ];
+        let (v, e) = soft_dtw_alignment(&cost, n, m, gamma).unwrap();
+        assert!((v - soft_dtw_cost(&cost, n, m, gamma).unwrap()).abs() < 1e-15);
+        let h = 1e-6;
+        for k in 0..n * m {
+            let orig = cost[k];
+            cost[k] = orig + h;
+            let up = soft_dtw_cost(&cost, n, m, gamma).unwrap();
+            cost[k] = orig - h;
+            let dn = soft_dtw_cost(&cost, n, m, gamma).unwrap();
+            cost[k] = orig;
+            let fd = (up - dn) / (2.0 * h);
+            assert!(
+                (e[k] - fd).abs() < 1e-7,
+                "k={} analytic={} fd={}",
+                k,
+                e[k],
+                fd
+            );
+        }
+        // Every path starts at (0,0) and ends at (n-1,m-1).
+        assert!((e[0] - 1.0).abs() < 1e-12);
+        assert!((e[n * m - 1] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sequence_gradient_matches_finite_differences() {
+        let mut x = vec![0.2, -0.4, 1.1];
+        let y = [0.0, 0.5, -0.3, 0.9];
+        let gamma = 0.4;
+        let (v, g) = soft_dtw_grad(&x, &y, gamma).unwrap();
+        assert!((v - soft_dtw(&x, &y, gamma).unwrap()).abs() < 1e-15);
+        let h = 1e-6;
+        for i in 0..x.len() {
+            let orig = x[i];
+            x[i] = orig + h;
+            let up = soft_dtw(&x, &y, gamma).unwrap();
+            x[i] = orig - h;
+            let dn = soft_dtw(&x, &y, gamma).unwrap();
+            x[i] = orig;
+            let fd = (up - dn) / (2.0 * h);
+            assert!(
+                (g[i] - fd).abs() < 1e-7,
+                "i={} analytic={} fd={}",
+                i,
+                g[i],
+                fd
+            );
+        }
+    }
+
+    #[test]
+    fn batch_matches_pairwise_calls() {
+        let xs: [&[f64]; 2] = [&[0.0, 1.0, 2.0], &[1.0, -1.0]];
+        let ys: [&[f64]; 2] = [&[0.5, 1.5], &[1.0, 0.0, -1.0]];
+        let got = soft_dtw_batch(&xs, &ys, 0.7).unwrap();
+        let div = soft_dtw_divergence_batch(&xs, &ys, 0.7).unwrap();
+        for k in 0..2 {
+            assert_eq!(got[k], soft_dtw(xs[k], ys[k], 0.7).unwrap());
+            assert_eq!(div[k], soft_dtw_divergence(xs[k], ys[k], 0.7).unwrap());
+        }
+        assert_eq!(
+            soft_dtw_batch(&xs, &ys[..1], 0.7),
+            Err(Error::BatchLengthMismatch { xs: 2, ys: 1 })
+        );
+    }
+
+    #[test]
+    fn streaming_matches_full_table_for_prefixes_and_chunks() {
+        let x = [0.3, -1.2, 0.8, 2.0, 0.1, -0.5, 1.4];
+        let y = [0.0, 1.0, -1.0, 0.5];
+        let gamma = 0.6;
+        let mut stream = SoftDtwStream::new(&y, gamma).unwrap();
+        assert_eq!(stream.value(), Err(Error::EmptyInput));
+        for chunk in x.chunks(3) {
+            stream.extend(chunk);
+            let prefix = &x[..stream.len()];
+            assert_eq!(
+                stream.value().unwrap(),
+                soft_dtw(prefix, &y, gamma).unwrap()
+            );
+        }
+        assert_eq!(
+            soft_dtw_iter(x.iter().copied(), &y, gamma).unwrap(),
+            soft_dtw(&x, &y, gamma).unwrap()
+        );
+        assert_eq!(
+            soft_dtw_iter(std::iter::empty(), &y, gamma),
+            Err(Error::EmptyInput)
+        );
+    }
+
+    #[test]
+    fn stream_resumes_from_checkpoint_bit_for_bit() {
+        let x = [0.3, -1.2, 0.8, 2.0, 0.1, -0.5, 1.4];
+        let y = [0.0, 1.0, -1.0, 0.5];
+        let mut stream = SoftDtwStream::new(&y, 0.6).unwrap();
+        let fresh = SoftDtwStream::resume(&y, &stream.checkpoint()).unwrap();
+        assert!(fresh.is_empty());
+        stream.extend(&x[..4]);
+        let saved = stream.checkpoint();
+        #[cfg(feature = "json")]
+        let saved: StreamCheckpoint =
+            serde_json::from_str(&serde_json::to_string(&saved).unwrap()).unwrap();
+        let mut resumed = SoftDtwStream::resume(&y, &saved).unwrap();
+        resumed.extend(&x[4..]);
+        assert_eq!(resumed.len(), x.len());
+        assert_eq!(resumed.value().unwrap(), soft_dtw(&x, &y, 0.6).unwrap());
+        assert_eq!(
+            SoftDtwStream::resume(&y[1..], &saved).err(),
+            Some(Error::CheckpointMismatch {
+                len: 4,
+                expected: 3
+            })
+        );
+        let mut bad = saved.clone();
+        bad.row[2] = f64::NAN;
+        assert!(matches!(
+            SoftDtwStream::resume(&y, &bad),
+            Err(Error::InvalidCheckpointRow { index: 2, .. })
+        ));
+        bad.row[2] = f64::NEG_INFINITY;
+        assert!(matches!(
+            SoftDtwStream::resume(&y, &bad),
+            Err(Error::InvalidCheckpointRow { index: 2, .. })
+        ));
+        bad.row[2] = f64::INFINITY;
+        assert!(SoftDtwStream::resume(&y, &bad).is_ok());
+        let unstarted = StreamCheckpoint { len: 0, ..saved };
+        assert_eq!(
+            SoftDtwStream::resume(&y, &unstarted).err(),
+            Some(Error::CheckpointNotStarted { len: 4 })
+        );
+    }
+
+    #[test]
+    fn builder_defaults_match_functions_and_options_change_results() {
+        let cost = [0.3, 1.2, 0.4, 0.9, 0.8, 0.1, 0.7, 0.5, 1.1, 0.6, 0.2, 0.3];
+        let (n, m, gamma) = (3, 4, 0.6);
+        let op = SoftDtw::new(gamma);
+        assert_eq!(
+            op.compute_cost(&cost, n, m),
+            soft_dtw_cost(&cost, n, m, gamma)
+        );
+        assert_eq!(
+            op.alignment(&cost, n, m),
+            soft_dtw_alignment(&cost, n, m, gamma)
+        );
+        assert_eq!(
+            op.compute(&[1.0, 2.0], &[0.0]),
+            soft_dtw(&[1.0, 2.0], &[0.0], gamma)
+        );
+
+        // A band wide enough to cover every cell, through the general kernel.
+        let wide = op.band(m);
+        let v_wide = wide.compute_cost(&cost, n, m).unwrap();
+        assert!((v_wide - soft_dtw_cost(&cost, n, m, gamma).unwrap()).abs() < 1e-12);
+
+        let normalized = op.normalized(true).compute_cost(&cost, n, m).unwrap();
+        assert_eq!(normalized, soft_dtw_cost(&cost, n, m, gamma).unwrap() / 7.0);
+        assert_eq!(
+            SoftDtw::new(gamma).band(0).compute_cost(&[0.0; 10], 2, 5),
+            Err(Error::NoWarpingPath {
+                band: 0,
+                n: 2,
+                m: 5
+            })
+        );
+        assert_eq!(
+            SoftDtw::new(0.0).compute(&[], &[1.0]),
+            Err(Error::InvalidGamma(0.0))
+        );
+    }
+
+    #[test]
+    fn banded_symmetric2_alignment_matches_finite_differences() {
+        let n = 4usize;
+        let m = 5usize;
+        let op = SoftDtw::new(0.5)
+            .band(1)
+            .step_pattern(StepPattern::Symmetric2)
+            .normalized(true);
+        let mut cost: Vec<f64> = (0..n * m).map(|k| ((k * 7 % 11) as f64) / 10.0).collect();
+        let (v, e) = op.alignment(&cost, n, m).unwrap();
+        assert_eq!(v, op.compute_cost(&cost, n, m).unwrap());
+        let h = 1e-6;
+        for k in 0..n * m {
+            let orig = cost[k];
+            cost[k] = orig + h;
+            let up = op.compute_cost(&cost, n, m).unwrap();
+            cost[k] = orig - h;
+            let dn = op.compute_cost(&cost, n, m).unwrap();
+            cost[k] = orig;
+            let fd = (up - dn) / (2.0 * h);
+            assert!(
+                (e[k] - fd).abs() < 1e-7,
+                "k={} analytic={} fd={}",
+                k,
+                e[k],
+                fd
+            );
+        }
+        // Off-band cells never lie on a path.
+        assert_eq!(e[m - 1], 0.0);
+    }
+
+    #[test]
+    fn workspace_reuse_matches_free_functions() {
+        let mut ws = Workspace::new();
+        // Large first, then smaller: stale buffer contents must not leak into results.
+        let big: Vec<f64> = (0..30).map(|k| ((k * 5 % 13) as f64) / 7.0).collect();
+        for (n, m) in [(5, 6), (2, 3), (3, 2)] {
+            let cost = &big[..n * m];
+            assert_eq!(
+                ws.soft_dtw_cost(cost, n, m, 0.4),
+                soft_dtw_cost(cost, n, m, 0.4)
+            );
+            let (v, e) = ws.soft_dtw_alignment(cost, n, m, 0.4).unwrap();
+            assert_eq!(
+                (v, e.to_vec()),
+                soft_dtw_alignment(cost, n, m, 0.4).unwrap()
+            );
+            let (x, y) = (&big[..n], &big[n..n + m]);
+            assert_eq!(ws.soft_dtw(x, y, 0.4), soft_dtw(x, y, 0.4));
+        }
+        assert_eq!(
+            ws.soft_dtw_cost(&big, 2, 2, 0.4),
+            soft_dtw_cost(&big, 2, 2, 0.4)
+        );
+    }
+
+    #[test]
+    fn oversized_dimensions_fail_before_allocating() {
+        let n = usize::MAX / 2;
+        assert_eq!(
+            soft_dtw_cost(&[], n, 3, 1.0),
+            Err(Error::DimensionOverflow { n, m: 3 })
+        );
+        // n * m wraps to 2 here; the shape check must not be fooled.
+        let n = usize::MAX / 2 + 2;
+        assert_eq!(
+            soft_dtw_alignment(&[0.0, 0.0], n, 2, 1.0),
+            Err(Error::DimensionOverflow { n, m: 2 })
+        );
+        assert_eq!(table_len(2, 3), Ok(12));
+        assert!(table_len(usize::MAX, 1).is_err());
+    }
+
+    #[test]
+    fn nan_costs_are_reported_unless_unchecked() {
+        let cost = [0.0, 1.0, f64::INFINITY, 2.0, f64::NAN, f64::NAN];
+        assert_eq!(
+            soft_dtw_cost(&cost, 2, 3, 1.0),
+            Err(Error::NanCost { i: 1, j: 1 })
+        );
+        assert_eq!(
+            soft_dtw_alignment(&cost, 3, 2, 1.0),
+            Err(Error::NanCost { i: 2, j: 0 })
+        );
+        assert_eq!(
+            SoftDtw::new(1.0).band(1).compute_cost(&cost, 2, 3),
+            Err(Error::NanCost { i: 1, j: 1 })
+        );
+        assert!(soft_dtw_cost_unchecked(&cost, 2, 3, 1.0).unwrap().is_nan());
+        let (v, e) = soft_dtw_alignment_unchecked(&cost, 2, 3, 1.0).unwrap();
+        assert!(v.is_nan() && e[0].is_nan());
+        // Without NaNs the unchecked variants agree exactly.
+        let clean = [0.0, 1.0, f64::INFINITY, 2.0, 0.5, 0.25];
+        assert_eq!(
+            soft_dtw_cost_unchecked(&clean, 2, 3, 1.0),
+            soft_dtw_cost(&clean, 2, 3, 1.0)
+        );
+        assert_eq!(
+            soft_dtw_alignment_unchecked(&clean, 2, 3, 1.0),
+            soft_dtw_alignment(&clean, 2, 3, 1.0)
+        );
+        assert_eq!(
+            soft_dtw_cost_unchecked(&clean, 2, 2, 1.0),
+            Err(Error::InvalidCostShape {
+                len: 6,
+                n: 2,
+                m: 2,
+                expected: 4
+            })
+        );
+    }
+
+    #[test]
+    fn double_double_keeps_deep_alignments_normalized() {
+        // Large accumulated costs and a small gamma: f64 potentials lose ~1e-6 of the flow.
+        let n = 60;
+        let cost: Vec<f64> = (0..n * n)
+            .map(|k| 1e5 * (1.0 + (k * 7919 % 1009) as f64 / 1009.0))
+            .collect();
+        let (v, e) = SoftDtw::new(1e-3).alignment(&cost, n, n).unwrap();
+        assert!((e[0] - 1.0).abs() > 1e-7, "e[0]={}", e[0]);
+        let dd = SoftDtw::new(1e-3).precision(Precision::DoubleDouble);
+        let (v_dd, e_dd) = dd.alignment(&cost, n, n).unwrap();
+        assert!((e_dd[0] - 1.0).abs() < 1e-12, "e[0]={}", e_dd[0]);
+        assert!((v - v_dd).abs() < 1e-8 * v.abs());
+        assert_eq!(dd.compute_cost(&cost, n, n), Ok(v_dd));
+
+        // On benign inputs both precisions agree, including with a band and Symmetric2.
+        let small = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7, 0.5, 0.9, 0.2];
+        let op = SoftDtw::new(0.5)
+            .band(1)
+            .step_pattern(StepPattern::Symmetric2);
+        let (v, e) = op.alignment(&small, 3, 3).unwrap();
+        let (v_dd, e_dd) = op
+            .precision(Precision::DoubleDouble)
+            .alignment(&small, 3, 3)
+            .unwrap();
+        assert!((v - v_dd).abs() < 1e-14);
+        for (a, b) in e.iter().zip(&e_dd) {
+            assert!((a - b).abs() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn symbol_sequences_use_the_substitution_matrix() {
+        let sub =
+            SubstitutionMatrix::new(3, vec![0.0, 1.0, 2.5, 1.0, 0.0, 0.7, 2.0, 0.3, 0.0]).unwrap();
+        let (x, y) = ([0, 2, 1, 1], [2, 0, 1]);
+        let cost: Vec<f64> = x
+            .iter()
+            .flat_map(|&a| y.iter().map(|&b| sub.cost(a, b)).collect::<Vec<_>>())
+            .collect();
+        let v = soft_dtw_symbols(&x, &y, &sub, 0.4).unwrap();
+        assert_eq!(v, soft_dtw_cost(&cost, 4, 3, 0.4).unwrap());
+
+        let (v2, grad) = soft_dtw_symbols_grad(&x, &y, &sub, 0.4).unwrap();
+        assert_eq!(v, v2);
+        let report = crate::gradcheck::check_gradient(
+            |c| {
+                let sub = SubstitutionMatrix::new(3, c.to_vec()).unwrap();
+                Ok(soft_dtw_symbols(&x, &y, &sub, 0.4)?)
+            },
+            sub.as_slice(),
+            &grad,
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        assert_eq!(
+            soft_dtw_symbols(&[0, 3], &y, &sub, 0.4),
+            Err(Error::SymbolOutOfRange {
+                position: 1,
+                symbol: 3,
+                alphabet: 3
+            })
+        );
+        assert_eq!(
+            SubstitutionMatrix::new(2, vec![0.0; 3]),
+            Err(Error::InvalidSubstitutionShape {
+                len: 3,
+                alphabet: 2,
+                expected: 4
+            })
+        );
+        assert_eq!(
+            SubstitutionMatrix::uniform(2, f64::NAN),
+            Err(Error::NanCost { i: 0, j: 1 })
+        );
+    }
+
+    #[test]
+    fn band_selection_stops_once_the_gap_is_certified() {
+        // Near-identical sequences: the soft path hugs the diagonal.
+        let x: Vec<f64> = (0..64).map(|k| (k as f64 * 0.3).sin()).collect();
+        let y: Vec<f64> = (0..60).map(|k| (k as f64 * 0.32).sin()).collect();
+        let cost: Vec<f64> = x
+            .iter()
+            .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+            .collect();
+        let op = SoftDtw::new(0.05);
+        let full = op.compute_cost(&cost, 64, 60).unwrap();
+        let sel = op.select_band(&cost, 64, 60, 1e-9).unwrap();
+        assert!(sel.band < 59 && sel.gap <= 1e-9, "{:?}", sel);
+        assert!(
+            sel.value >= full && sel.value - full <= sel.gap,
+            "{:?} vs {}",
+            sel,
+            full
+        );
+        assert_eq!(op.band(sel.band).compute_cost(&cost, 64, 60), Ok(sel.value));
+
+        // A zero-cost corridor six cells off the diagonal: bands 1 and 2 see the same value,
+        // but only a band containing the corridor is certified.
+        let n = 16;
+        let corridor: Vec<f64> = (0..n * n)
+            .map(|k| if k % n == k / n + 6 { 0.0 } else { 1.0 })
+            .collect();
+        let op = SoftDtw::new(0.01);
+        let full = op.compute_cost(&corridor, n, n).unwrap();
+        let banded = |b| op.band(b).compute_cost(&corridor, n, n).unwrap();
+        assert_eq!(banded(1), banded(2));
+        let sel = op.select_band(&corridor, n, n, 1e-6).unwrap();
+        assert_eq!((sel.band, sel.value), (8, full));
+        assert!(banded(2) - full > 1.0 && sel.gap <= 1e-6);
+
+        // Narrow matrices reach the full width, which is exact.
+        let op = SoftDtw::new(0.05);
+        let sel = op.select_band(&cost[..3 * 60], 60, 3, 0.0).unwrap();
+        let value = op.compute_cost(&cost[..180], 60, 3).unwrap();
+        assert_eq!(
+            sel,
+            BandSelection {
+                band: 2,
+                value,
+                gap: 0.0,
+                exact: true
+            }
+        );
+        let sel = op.select_band(&cost[..5], 5, 1, 1e-3).unwrap();
+        assert_eq!((sel.band, sel.exact), (0, true));
+        let negative: Vec<f64> = cost.iter().map(|c| c - 1.0).collect();
+        let sel = op.select_band(&negative, 64, 60, 1.0).unwrap();
+        assert_eq!((sel.band, sel.gap, sel.exact), (59, 0.0, true));
+    }
+
+    #[test]
+    fn diagonal_penalty_folds_into_the_cost_with_its_gradient() {
+        let (n, m) = (6, 5);
+        let cost: Vec<f64> = (0..n * m).map(|k| ((k * 7) % 5) as f64 * 0.2).collect();
+        let op = SoftDtw::new(0.3).step_pattern(StepPattern::Symmetric2);
+        assert_eq!(
+            op.diagonal_penalty(DiagonalPenalty::offset(0.0))
+                .alignment(&cost, n, m),
+            op.alignment(&cost, n, m)
+        );
+
+        let lambda = 0.4;
+        let offset = |k: usize| (k / m).abs_diff(k % m) as f64;
+        let penalized: Vec<f64> = cost
+            .iter()
+            .enumerate()
+            .map(|(k, c)| c + lambda * offset(k))
+            .collect();
+        let diag = op.diagonal_penalty(DiagonalPenalty::offset(lambda));
+        let (v, e) = diag.alignment(&cost, n, m).unwrap();
+        assert_eq!((v, e), op.alignment(&penalized, n, m).unwrap());
+        let res = diag.analyze_cost(&cost, n, m).unwrap();
+        let plain = op.analyze_cost(&penalized, n, m).unwrap();
+        assert_eq!(
+            (res.entropy(), res.hard_path()),
+            (plain.entropy(), plain.hard_path())
+        );
+        let graph = diag.alignment_graph(&cost, n, m).unwrap();
+        assert!((graph.graph().value(0.3).unwrap() - v).abs() < 1e-12);
+
+        // A custom curve, and the gradient w.r.t. its strength.
+        fn rows(i: usize, _: usize, _: usize, _: usize) -> f64 {
+            i as f64 * 0.5
+        }
+        let curve = |l: f64| DiagonalPenalty {
+            lambda: l,
+            curve: DiagonalCurve::Custom(rows),
+        };
+        let (_, grad) = op
+            .diagonal_penalty(curve(lambda))
+            .diagonal_grad(&cost, n, m)
+            .unwrap();
+        let report = crate::gradcheck::check_gradient(
+            |l| Ok(op.diagonal_penalty(curve(l[0])).compute_cost(&cost, n, m)?),
+            &[lambda],
+            &[grad],
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        assert_eq!(
+            op.diagonal_penalty(DiagonalPenalty::offset(-1.0))
+                .compute_cost(&cost, n, m),
+            Err(Error::InvalidDiagonalPenalty(-1.0))
+        );
+    }
+
+    #[test]
+    fn missing_samples_follow_the_policy() {
+        let x = [0.0, f64::NAN, 1.0, 2.0];
+        let y = [0.5, 1.5, f64::NAN];
+        let op = SoftDtw::new(0.4);
+        assert_eq!(
+            op.compute(&x, &y),
+            Err(Error::MissingSample {
+                sequence: 0,
+                index: 1
+            })
+        );
+        assert_eq!(
+            op.compute(&y, &[1.0]),
+            Err(Error::MissingSample {
+                sequence: 0,
+                index: 2
+            })
+        );
+
+        let filled = |f: &dyn Fn(f64, f64) -> f64| -> Vec<f64> {
+            x.iter()
+                .flat_map(|&a| y.iter().map(move |&b| f(a, b)))
+                .collect()
+        };
+        let c = 0.7;
+        let cost = filled(&|a, b| {
+            if a.is_nan() || b.is_nan() {
+                c
+            } else {
+                (a - b) * (a - b)
+            }
+        });
+        let with_cost = |c: f64| op.missing_values(MissingValues::Cost(c));
+        assert_eq!(with_cost(c).compute(&x, &y), op.compute_cost(&cost, 4, 3));
+        let (v, grad) = with_cost(c).missing_cost_grad(&x, &y).unwrap();
+        assert_eq!(with_cost(c).analyze(&x, &y).unwrap().value(), v);
+        let report = crate::gradcheck::check_gradient(
+            |p| Ok(with_cost(p[0]).compute(&x, &y)?),
+            &[c],
+            &[grad],
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed() && grad > 0.0, "{:?}", report);
+
+        // Observed x: mean 1, variance 2/3; observed y: mean 1, variance 1/4.
+        let expected = op.missing_values(MissingValues::Expected);
+        let cost = filled(&|a, b| match (a.is_nan(), b.is_nan()) {
+            (false, false) => (a - b) * (a - b),
+            (true, false) => (1.0 - b) * (1.0 - b) + 2.0 / 3.0,
+            (false, true) => (a - 1.0) * (a - 1.0) + 0.25,
+            (true, true) => 2.0 / 3.0 + 0.25,
+        });
+        let v = expected.compute(&x, &y).unwrap();
+        assert!((v - op.compute_cost(&cost, 4, 3).unwrap()).abs() < 1e-12);
+        assert_eq!(expected.missing_cost_grad(&x, &y).unwrap().1, 0.0);
+        assert_eq!(
+            expected.compute(&x, &[f64::NAN]),
+            Err(Error::MissingSample {
+                sequence: 1,
+                index: 0
+            })
+        );
+        assert_eq!(
+            with_cost(-1.0).compute(&x, &y),
+            Err(Error::InvalidMissingCost(-1.0))
+        );
+    }
+
+    #[test]
+    fn transition_penalties_shift_mass_to_the_diagonal() {
+        let x = [0.0f64, 0.4, 0.1, 0.9, 0.3, 0.6];
+        let y = [0.2, 0.1, 0.8, 0.5, 0.4];
+        let cost: Vec<f64> = x
+            .iter()
+            .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+            .collect();
+        let op = SoftDtw::new(0.3).step_pattern(StepPattern::Symmetric2);
+        let zero = op.transition_penalty(TransitionPenalty::default());
+        assert_eq!(zero.alignment(&cost, 6, 5), op.alignment(&cost, 6, 5));
+
+        let p = TransitionPenalty {
+            diag: 0.1,
+            up: 0.7,
+            left: 0.4,
+        };
+        let (v, k) = op
+            .transition_penalty(p)
+            .transition_grad(&cost, 6, 5)
+            .unwrap();
+        assert_eq!(op.transition_penalty(p).compute_cost(&cost, 6, 5), Ok(v));
+        // Every path has `up + diag = n - 1` and `left + diag = m - 1` steps.
+        assert!((k.up + k.diag - 5.0).abs() < 1e-12 && (k.left + k.diag - 4.0).abs() < 1e-12);
+        let report = crate::gradcheck::check_gradient(
+            |q| {
+                let p = TransitionPenalty {
+                    diag: q[0],
+                    up: q[1],
+                    left: q[2],
+                };
+                Ok(op.transition_penalty(p).compute_cost(&cost, 6, 5)?)
+            },
+            &[p.diag, p.up, p.left],
+            &[k.diag, k.up, k.left],
+            crate::gradcheck::Tolerance::default(),
+        )
+        .unwrap();
+        assert!(report.passed(), "{:?}", report);
+
+        // Penalising off-diagonal steps straightens the path.
+        let (_, k0) = op.transition_grad(&cost, 6, 5).unwrap();
+        assert!(k.diag > k0.diag, "{:?} vs {:?}", k, k0);
+        // Forbidding horizontal steps leaves paths with four diagonal steps and one vertical.
+        let strict = TransitionPenalty {
+            left: f64::INFINITY,
+            ..p
+        };
+        let (_, k) = op
+            .transition_penalty(strict)
+            .transition_grad(&cost, 6, 5)
+            .unwrap();
+        assert!((k.diag - 4.0).abs() < 1e-12 && (k.up - 1.0).abs() < 1e-12 && k.left == 0.0);
+
+        let bad = TransitionPenalty {
+            left: f64::NAN,
+            ..p
+        };
+        assert!(matches!(
+            op.transition_penalty(bad).compute(&x, &y),
+            Err(Error::InvalidPenalty(v)) if v.is_nan()
+        ));
+    }
+
+    #[test]
+    fn analyzed_results_match_the_eager_apis() {
+        let (x, y) = ([0.0, 1.0, 2.0, 2.0, 0.5], [0.0, 2.0, 2.0, 1.0]);
+        let op = SoftDtw::new(0.2)
+            .step_pattern(StepPattern::Symmetric2)
+            .normalized(true);
+        let res = op.analyze(&x, &y).unwrap();
+        let cost: Vec<f64> = x
+            .iter()
+            .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+            .collect();
+        assert_eq!(res.shape(), (5, 4));
+        assert_eq!(Ok(res.value()), op.compute(&x, &y));
+        assert_eq!(
+            op.alignment(&cost, 5, 4),
+            Ok((res.value(), res.alignment().to_vec()))
+        );
+        assert_eq!(
+            op.transition_grad(&cost, 5, 4).map(|g| g.1),
+            Ok(res.expected_steps())
+        );
+        let (xx, yy) = (op.compute(&x, &x).unwrap(), op.compute(&y, &y).unwrap());
+        assert_eq!(
+            res.divergence(),
+            Some(Ok(res.value() - 0.5 * xx - 0.5 * yy))
+        );
+
+        let path = res.hard_path();
+        assert_eq!((path[0], path[path.len() - 1]), ((0, 0), (4, 3)));
+        let expected = res.expected_path();
+        assert_eq!(expected.len(), 5);
+        assert!(expected.windows(2).all(|w| w[0] <= w[1]), "{:?}", expected);
+
+        // All-zero costs make every path equally likely: 3 paths through a 2 × 2 matrix.
+        let zero = SoftDtw::new(0.7).analyze_cost(&[0.0; 4], 2, 2).unwrap();
+        assert!((zero.entropy() - 3f64.ln()).abs() < 1e-12);
+        assert!(zero.divergence().is_none());
+        // Each path has probability 1/3, so the off-diagonal cells are on one of them.
+        let var = zero.cell_variance();
+        assert!((var[1] - 2.0 / 9.0).abs() < 1e-12 && var[0].abs() < 1e-12);
+        assert_eq!(zero.credible_band(0.9), vec![Some((0, 1)), Some((0, 1))]);
+        assert_eq!(res.credible_band(0.0).len(), 5);
+        let banded = SoftDtw::new(0.01)
+            .analyze_cost(&[0.0, 9.0, 9.0, 0.0], 2, 2)
+            .unwrap();
+        assert_eq!(banded.credible_band(0.95), vec![Some((0, 0)), Some((1, 1))]);
+        let single = SoftDtw::new(0.7)
+            .analyze_cost(&[1.0, 2.0, 3.0], 1, 3)
+            .unwrap();
+        assert!(single.entropy().abs() < 1e-12);
+        assert_eq!(single.hard_path(), vec![(0, 0), (0, 1), (0, 2)]);
+
+        let inf = f64::INFINITY;
+        let nan_at = |i, j| Err(Error::NanCost { i, j });
+        assert_eq!(
+            op.analyze(&[inf, 1.0], &[inf, 2.0]).map(|r| r.value()),
+            nan_at(0, 0)
+        );
+        assert_eq!(
+            op.analyze(&[1.0, -inf], &[0.0, -inf]).map(|r| r.value()),
+            nan_at(1, 1)
+        );
+    }
+
+    #[test]
+    fn pdist_scores_each_pair_once_and_shares_self_terms() {
+        // More sequences than a tile, so pairs straddle tile boundaries.
+        let seqs: Vec<Vec<f64>> = (0..PDIST_TILE + 5)
+            .map(|k| (0..3 + k % 5).map(|i| ((i * k) % 7) as f64 / 3.0).collect())
+            .collect();
+        let xs: Vec<&[f64]> = seqs.iter().map(Vec::as_slice).collect();
+        let n = xs.len();
+        let d = soft_dtw_pdist(&xs, 0.4).unwrap();
+        let div = soft_dtw_divergence_pdist(&xs, 0.4).unwrap();
+        assert_eq!((d.len(), div.len()), (n * (n - 1) / 2, n * (n - 1) / 2));
+        for i in 0..n {
+            for j in i + 1..n {
+                let k = pdist_index(n, i, j);
+                assert_eq!(d[k], soft_dtw(xs[i], xs[j], 0.4).unwrap());
+                let want = soft_dtw_divergence(xs[i], xs[j], 0.4).unwrap();
+                assert!((div[k] - want).abs() < 1e-12, "{} vs {}", div[k], want);
+            }
+        }
+        assert_eq!(pdist_index(n, n - 2, n - 1), d.len() - 1);
+
+        assert_eq!(soft_dtw_pdist(&xs[..1], 0.4), Ok(vec![]));
+        assert_eq!(soft_dtw_pdist(&xs[..1], 0.0), Err(Error::InvalidGamma(0.0)));
+        assert_eq!(soft_dtw_pdist(&[&[1.0], &[]], 0.4), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn self_term_cache_computes_each_self_term_once() {
+        let seqs = [
+            vec![0.0, 1.0, 2.0],
+            vec![1.0, 1.0],
+            vec![2.0, 0.0, 1.0, 0.5],
+            vec![0.3],
+        ];
+        let items: Vec<(&str, &[f64])> = ["a", "b", "c", "d"]
+            .into_iter()
+            .zip(seqs.iter().map(Vec::as_slice))
+            .collect();
+        let mut cache = SelfTermCache::new(0.4).unwrap();
+        let d = cache.cdist(&items[..2], &items[1..]).unwrap();
+        assert_eq!((d.len(), cache.len()), (6, 4));
+        for (i, (_, x)) in items[..2].iter().enumerate() {
+            for (j, (_, y)) in items[1..].iter().enumerate() {
+                let want = soft_dtw_divergence(x, y, 0.4).unwrap();
+                assert!((d[i * 3 + j] - want).abs() < 1e-12);
+            }
+        }
+        // A cached id is trusted: its stored self-term is used even for another sequence.
+        let stale = cache.divergence("a", &seqs[1], "b", &seqs[1]).unwrap();
+        assert!(stale.abs() > 1e-6);
+        cache.forget(&"a");
+        assert!(
+            cache
+                .divergence("a", &seqs[1], "b", &seqs[1])
+                .unwrap()
+                .abs()
+                < 1e-12
+        );
+        assert_eq!(
+            SelfTermCache::<u32>::new(0.0).err(),
+            Some(Error::InvalidGamma(0.0))
+        );
+    }
+
+    #[test]
+    fn alignment_graph_agrees_with_the_dtw_recursion() {
+        let inf = f64::INFINITY;
+        let cost = [
+            0.3, 1.2, 0.4, 0.8, 0.1, 0.7, 0.9, 0.5, 0.2, 1.1, 0.6, inf, 0.3, 0.4, 0.2,
+        ];
+        let p = TransitionPenalty {
+            diag: 0.0,
+            up: 0.2,
+            left: 0.1,
+        };
+        for op in [
+            SoftDtw::new(0.4),
+            SoftDtw::new(0.4)
+                .band(1)
+                .step_pattern(StepPattern::Symmetric2),
+            SoftDtw::new(0.4).transition_penalty(p),
+        ] {
+            let g = op.alignment_graph(&cost, 5, 3).unwrap();
+            let (v, marg) = g.graph().edge_marginals(0.4).unwrap();
+            let (want, e) = op.alignment(&cost, 5, 3).unwrap();
+            assert!((v - want).abs() < 1e-12, "{} vs {}", v, want);
+            for (a, b) in g.alignment(&marg).iter().zip(&e) {
+                assert!((a - b).abs() < 1e-12, "{} vs {}", a, b);
+            }
+        }
+        let g = SoftDtw::new(0.4).alignment_graph(&cost, 5, 3).unwrap();
+        let last = g.graph().edges().len() - 1;
+        assert_eq!(
+            (g.graph().n(), g.cell(0), g.cell(last)),
+            (16, (0, 0), (4, 2))
+        );
+    }
+
+    #[test]
+    fn log_alignment_survives_underflow() {
+        let cost = [
+            0.3,
+            1.2,
+            0.4,
+            0.8,
+            0.1,
+            0.7,
+            0.9,
+            0.5,
+            0.2,
+            f64::INFINITY,
+            0.6,
+            0.3,
+        ];
+        let (v, e) = soft_dtw_alignment(&cost, 4, 3, 0.5).unwrap();
+        let (lv, le) = soft_dtw_log_alignment(&cost, 4, 3, 0.5).unwrap();
+        assert_eq!(v, lv);
+        for (p, lp) in e.iter().zip(&le) {
+            assert!((p - lp.exp()).abs() < 1e-12, "{} vs {}", p, lp);
+        }
+        assert_eq!(le[9], f64::NEG_INFINITY);
+
+        // At tiny gamma the off-path cells are exactly 0 in linear space.
+        let (_, e) = soft_dtw_alignment(&cost, 4, 3, 1e-3).unwrap();
+        let (_, le) = soft_dtw_log_alignment(&cost, 4, 3, 1e-3).unwrap();
+        assert!(e.contains(&0.0));
+        for (k, (&p, &lp)) in e.iter().zip(&le).enumerate() {
+            assert!(k == 9 || lp.is_finite(), "cell {} has {}", k, lp);
+            assert!(p == 0.0 || (p.ln() - lp).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn gradients_accumulate_into_caller_buffers() {
+        let (x, y) = ([0.0, 1.0, 2.0, 1.5], [0.5, 1.0, 2.5]);
+        let (v, g) = soft_dtw_grad(&x, &y, 0.3).unwrap();
+        let mut acc = vec![1.0; 4];
+        assert_eq!(soft_dtw_grad_accumulate(&x, &y, 0.3, &mut acc, 0.5), Ok(v));
+        for (a, g) in acc.iter().zip(&g) {
+            assert!((a - (1.0 + 0.5 * g)).abs() < 1e-15);
+        }
+
+        let cost = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let (v, e) = soft_dtw_alignment(&cost, 2, 3, 0.3).unwrap();
+        let mut acc = vec![0.0; 6];
+        let mut ws = Workspace::new();
+        for _ in 0..2 {
+            assert_eq!(
+                ws.soft_dtw_cost_grad_accumulate(&cost, 2, 3, 0.3, &mut acc, 1.0),
+                Ok(v)
+            );
+        }
+        assert_eq!(acc, e.iter().map(|e| 2.0 * e).collect::<Vec<_>>());
+
+        let sub = SubstitutionMatrix::uniform(3, 1.0).unwrap();
+        let (v, g) = soft_dtw_symbols_grad(&[0, 2], &[1, 2], &sub, 0.3).unwrap();
+        let mut acc = vec![0.0; 9];
+        let got = soft_dtw_symbols_grad_accumulate(&[0, 2], &[1, 2], &sub, 0.3, &mut acc, -1.0);
+        assert_eq!(got, Ok(v));
+        assert_eq!(acc, g.iter().map(|g| -g).collect::<Vec<_>>());
+
+        let mut short = vec![0.0; 3];
+        assert_eq!(
+            soft_dtw_grad_accumulate(&x, &y, 0.3, &mut short, 1.0),
+            Err(Error::GradientLengthMismatch {
+                len: 3,
+                expected: 4
+            })
+        );
+        assert_eq!(short, vec![0.0; 3]);
+    }
+
+    #[test]
+    fn profile_scores_every_window() {
+        let series: Vec<f64> = (0..20).map(|t| ((t * 7) % 11) as f64 / 3.0).collect();
+        let query = [1.0, 2.5, 0.5, 3.0];
+        let profile = soft_dtw_profile(&query, &series, 6, 0.4).unwrap();
+        assert_eq!(profile.len(), 15);
+        let hard = dtw_profile(&query, &series, 6).unwrap();
+        for (k, &v) in profile.iter().enumerate() {
+            assert_eq!(v, soft_dtw(&query, &series[k..k + 6], 0.4).unwrap());
+            let tiny = SoftDtw::new(1e-9).tiny_gamma(TinyGamma::Below(1e-6));
+            assert_eq!(Ok(hard[k]), tiny.compute(&query, &series[k..k + 6]));
+        }
+        assert_eq!(
+            soft_dtw_profile(&query, &series, 21, 0.4),
+            Err(Error::InvalidWindow {
+                window: 21,
+                len: 20
+            })
+        );
+    }
+
+    #[test]
+    fn cyclic_dtw_matches_every_rotation() {
+        fn dtw(x: &[f64], y: &[f64]) -> f64 {
+            let cost: Vec<f64> = x
+                .iter()
+                .flat_map(|a| y.iter().map(move |b| (a - b).powi(2)))
+                .collect();
+            let c = |i: usize, j: usize| cost[i * y.len() + j];
+            let op = SoftDtw::new(1.0);
+            op.hard_forward(x.len(), y.len(), c)[(x.len() + 1) * (y.len() + 1) - 1]
+        }
+        for (n, m) in [(1, 1), (4, 7), (9, 5), (8, 8), (3, 12)] {
+            let x: Vec<f64> = (0..n).map(|i| ((i * 5 + 1) % 7) as f64 * 0.7).collect();
+            let y: Vec<f64> = (0..m).map(|j| ((j * 3 + 2) % 5) as f64).collect();
+            let rotations: Vec<f64> = (0..m)
+                .map(|r| {
+                    let rot: Vec<f64> = y[r..].iter().chain(&y[..r]).copied().collect();
+                    dtw(&x, &rot)
+                })
+                .collect();
+            let (v, r) = cyclic_dtw(&x, &y).unwrap();
+            assert_eq!(v, rotations.iter().copied().fold(f64::INFINITY, f64::min));
+            assert_eq!(rotations[r], v);
+
+            let (sv, w) = soft_cyclic_dtw(&x, &y, 0.5).unwrap();
+            assert!((w.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+            let rot: Vec<f64> = y[1..].iter().chain(&y[..1]).copied().collect();
+            assert!(sv <= soft_dtw(&x, &rot, 0.5).unwrap());
+        }
+        assert_eq!(cyclic_dtw(&[1.0], &[]), Err(Error::EmptyInput));
+    }
+
+    #[test]
+    fn tiny_gamma_falls_back_to_hard_dtw() {
+        let cost = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let hard = crate::invariants::hard_dtw(&cost, 2, 3).unwrap();
+        let tiny = SoftDtw::new(1e-310);
+        assert_eq!(tiny.compute_cost(&cost, 2, 3), Ok(f64::INFINITY));
+        let op = tiny.tiny_gamma(TinyGamma::Detect);
+        assert_eq!(op.compute_cost(&cost, 2, 3), Ok(hard));
+        let (v, e) = op.alignment(&cost, 2, 3).unwrap();
+        assert_eq!(v, hard);
+        // Path (0,0) -> (1,1) -> (1,2).
+        assert_eq!(e, vec![1.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+
+        // Detect keeps the soft result while it is usable; Below switches regardless.
+        let soft = SoftDtw::new(1e-4).alignment(&cost, 2, 3).unwrap();
+        let detect = SoftDtw::new(1e-4).tiny_gamma(TinyGamma::Detect);
+        assert_eq!(detect.alignment(&cost, 2, 3), Ok(soft));
+        let below = SoftDtw::new(1e-4).tiny_gamma(TinyGamma::Below(1e-3));
+        assert_eq!(
+            below.alignment(&cost, 2, 3),
+            Ok((hard, vec![1.0, 0.0, 0.0, 0.0, 1.0, 1.0]))
+        );
+        assert_eq!(
+            SoftDtw::new(0.0)
+                .tiny_gamma(TinyGamma::Below(1e-3))
+                .compute_cost(&cost, 2, 3),
+            Err(Error::InvalidGamma(0.0))
+        );
     }
 
     fn dtw_squared(x: &[f64], y: &[f64]) -> f64 {
@@ -308,4 +4038,3 @@ mod tests {
         );
     }
 }
-
@@ -0,0 +1,248 @@
+//! Batched Soft-DTW over `f32` cost matrices, several pairs per SIMD register.
+//!
+//! Retrieval and reranking pipelines score thousands of candidate alignments per query, all
+//! of the same shape and with `f32` embeddings. These kernels run [`LANES`] pairs in lock
+//! step: the DP tables are interleaved so that every cell update is the same branch-free
+//! arithmetic on a short array of lanes (the backward pass masks lanes with a select
+//! rather than skipping them), which leaves the compiler free to use vector registers. On
+//! `x86_64` the kernels are compiled a second time with AVX2 enabled and selected at
+//! runtime; on `aarch64` NEON is part of the baseline.
+//!
+//! Costs are widened to `f64` on load and the log-sum-exp runs in `f64`, so each pair gets
+//! exactly the value and alignment of [`crate::soft_dtw::soft_dtw_alignment`] on the widened
+//! matrix, rounded to `f32` on output, whichever kernel ran. `exp`/`ln` are the crate's
+//! kernel functions: with `fast-math` they are branch-light polynomials that can vectorize
+//! with the rest of the cell update; otherwise they are per-lane libm calls, which keep the
+//! log-sum-exp scalar and leave only the surrounding arithmetic to vectorize. Any gain
+//! over looping [`crate::soft_dtw::soft_dtw_alignment`] therefore depends on the target and
+//! on `fast-math`; measure before relying on it.
+//!
+//! ```
+//! use structop::soft_dtw_f32::soft_dtw_f32_batch;
+//!
+//! let a = [0.0f32, 1.0, 1.0, 0.0];
+//! let b = [2.0f32, 0.5, 0.5, 2.0];
+//! let v = soft_dtw_f32_batch(&[&a, &b], 2, 2, 0.1).unwrap();
+//! assert!(v[0] < v[1]);
+//! ```
+
+use crate::math::{exp, ln};
+use crate::soft_dtw::{table_len, Error, Result};
+
+/// Pairs processed together: four `f64` lanes fill an AVX2 register (two NEON registers).
+pub const LANES: usize = 4;
+
+type Lanes = [f64; LANES];
+
+fn validate(costs: &[&[f32]], n: usize, m: usize, gamma: f64) -> Result<()> {
+    if gamma <= 0.0 || !gamma.is_finite() {
+        return Err(Error::InvalidGamma(gamma));
+    }
+    if n == 0 || m == 0 {
+        return Err(Error::EmptyInput);
+    }
+    table_len(n, m)?;
+    for cost in costs {
+        if cost.len() != n * m {
+            return Err(Error::InvalidCostShape {
+                len: cost.len(),
+                n,
+                m,
+                expected: n * m,
+            });
+        }
+        if let Some(k) = cost.iter().position(|c| c.is_nan()) {
+            return Err(Error::NanCost { i: k / m, j: k % m });
+        }
+    }
+    Ok(())
+}
+
+/// Branch-free soft-min of three lanes of candidates (the lane form of the scalar kernel,
+/// with the same operations in the same order).
+#[inline(always)]
+fn softmin3(gamma: f64, a: &Lanes, b: &Lanes, c: &Lanes) -> Lanes {
+    std::array::from_fn(|l| {
+        let (xa, xb, xc) = (-a[l] / gamma, -b[l] / gamma, -c[l] / gamma);
+        let top = xa.max(xb).max(xc);
+        let s = exp(xa - top) + exp(xb - top) + exp(xc - top);
+        if top.is_finite() {
+            -gamma * (top + ln(s))
+        } else {
+            f64::INFINITY
+        }
+    })
+}
+
+/// Forward table `(n+1) × (m+1)` of lanes, and (if `e` is given) the expected alignments.
+#[inline(always)]
+fn kernel(
+    cost: &[Lanes],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    r: &mut [Lanes],
+    e: Option<&mut [Lanes]>,
+) {
+    let w = m + 1;
+    r.fill([f64::INFINITY; LANES]);
+    r[0] = [0.0; LANES];
+    for i in 1..=n {
+        for j in 1..=m {
+            let s = softmin3(
+                gamma,
+                &r[(i - 1) * w + j],
+                &r[i * w + j - 1],
+                &r[(i - 1) * w + j - 1],
+            );
+            let d = &cost[(i - 1) * m + j - 1];
+            r[i * w + j] = std::array::from_fn(|l| d[l] + s[l]);
+        }
+    }
+    let Some(e) = e else { return };
+    e.fill([0.0; LANES]);
+    e[(n - 1) * m + m - 1] = [1.0; LANES];
+    for i in (1..=n).rev() {
+        for j in (1..=m).rev() {
+            if i == n && j == m {
+                continue;
+            }
+            let rij = r[i * w + j];
+            let mut acc = [0.0; LANES];
+            for (si, sj) in [(i + 1, j), (i, j + 1), (i + 1, j + 1)] {
+                if si > n || sj > m {
+                    continue;
+                }
+                let (es, rs, cs) = (
+                    e[(si - 1) * m + sj - 1],
+                    r[si * w + sj],
+                    cost[(si - 1) * m + sj - 1],
+                );
+                // A lane whose successor carries no mass adds exactly zero, even where
+                // `exp` overflows or its argument is NaN (`inf - inf` next to `+inf` costs).
+                for l in 0..LANES {
+                    let flow = es[l] * exp((rs[l] - cs[l] - rij[l]) / gamma);
+                    acc[l] += if es[l] != 0.0 { flow } else { 0.0 };
+                }
+            }
+            e[(i - 1) * m + j - 1] = acc;
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn kernel_avx2(
+    cost: &[Lanes],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    r: &mut [Lanes],
+    e: Option<&mut [Lanes]>,
+) {
+    kernel(cost, n, m, gamma, r, e)
+}
+
+fn dispatch(
+    cost: &[Lanes],
+    n: usize,
+    m: usize,
+    gamma: f64,
+    r: &mut [Lanes],
+    e: Option<&mut [Lanes]>,
+) {
+    #[cfg(target_arch = "x86_64")]
+    if std::arch::is_x86_feature_detected!("avx2") {
+        // SAFETY: the CPU supports AVX2, the only feature `kernel_avx2` enables.
+        return unsafe { kernel_avx2(cost, n, m, gamma, r, e) };
+    }
+    kernel(cost, n, m, gamma, r, e)
+}
+
+/// Runs every group of [`LANES`] pairs (the last one padded with zero costs) and hands each
+/// real pair's value and, if requested, alignment lane to `out`.
+fn run<F>(costs: &[&[f32]], n: usize, m: usize, gamma: f64, alignments: bool, mut out: F)
+where
+    F: FnMut(f64, Option<Vec<f32>>),
+{
+    let mut cost = vec![[0.0; LANES]; n * m];
+    let mut r = vec![[0.0; LANES]; (n + 1) * (m + 1)];
+    let mut e = vec![[0.0; LANES]; if alignments { n * m } else { 0 }];
+    for group in costs.chunks(LANES) {
+        for (k, lanes) in cost.iter_mut().enumerate() {
+            *lanes = std::array::from_fn(|l| group.get(l).map_or(0.0, |c| f64::from(c[k])));
+        }
+        dispatch(&cost, n, m, gamma, &mut r, alignments.then_some(&mut e[..]));
+        for l in 0..group.len() {
+            let align = alignments.then(|| e.iter().map(|v| v[l] as f32).collect());
+            out(r[n * (m + 1) + m][l], align);
+        }
+    }
+}
+
+/// Soft-DTW values for a batch of `n × m` row-major `f32` cost matrices.
+pub fn soft_dtw_f32_batch(costs: &[&[f32]], n: usize, m: usize, gamma: f64) -> Result<Vec<f32>> {
+    validate(costs, n, m, gamma)?;
+    let mut values = Vec::with_capacity(costs.len());
+    run(costs, n, m, gamma, false, |v, _| values.push(v as f32));
+    Ok(values)
+}
+
+/// Soft-DTW values and expected alignments (row-major `n × m` each, the gradients w.r.t. the
+/// costs) for a batch of `n × m` row-major `f32` cost matrices.
+pub fn soft_dtw_f32_batch_alignment(
+    costs: &[&[f32]],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<(Vec<f32>, Vec<Vec<f32>>)> {
+    validate(costs, n, m, gamma)?;
+    let mut values = Vec::with_capacity(costs.len());
+    let mut aligns = Vec::with_capacity(costs.len());
+    run(costs, n, m, gamma, true, |v, e| {
+        values.push(v as f32);
+        aligns.extend(e);
+    });
+    Ok((values, aligns))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_dtw::soft_dtw_alignment;
+
+    #[test]
+    fn lanes_match_the_f64_kernel_on_widened_costs() {
+        let (n, m, gamma) = (7, 5, 0.3);
+        // Six pairs: one full group of four and a padded group of two.
+        let costs: Vec<Vec<f32>> = (0..6)
+            .map(|p| {
+                (0..n * m)
+                    .map(|k| ((k * 13 + p * 7) % 11) as f32 * 0.25)
+                    .collect()
+            })
+            .collect();
+        let refs: Vec<&[f32]> = costs.iter().map(|c| &c[..]).collect();
+        let (values, aligns) = soft_dtw_f32_batch_alignment(&refs, n, m, gamma).unwrap();
+        assert_eq!(values, soft_dtw_f32_batch(&refs, n, m, gamma).unwrap());
+        for (p, cost) in costs.iter().enumerate() {
+            let wide: Vec<f64> = cost.iter().map(|&c| f64::from(c)).collect();
+            let (v, e) = soft_dtw_alignment(&wide, n, m, gamma).unwrap();
+            assert_eq!(values[p], v as f32);
+            let e: Vec<f32> = e.iter().map(|&x| x as f32).collect();
+            assert_eq!(aligns[p], e);
+        }
+
+        assert_eq!(soft_dtw_f32_batch(&[], n, m, gamma), Ok(vec![]));
+        let mut bad = costs[1].clone();
+        bad[m + 2] = f32::NAN;
+        assert_eq!(
+            soft_dtw_f32_batch(&[&costs[0], &bad], n, m, gamma),
+            Err(Error::NanCost { i: 1, j: 2 })
+        );
+        assert!(matches!(
+            soft_dtw_f32_batch(&[&costs[0][1..]], n, m, gamma),
+            Err(Error::InvalidCostShape { .. })
+        ));
+    }
+}
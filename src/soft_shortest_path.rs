@@ -19,6 +19,10 @@
 //!
 //! We expose those marginals explicitly via a forward-backward pass.
 
+use crate::math::{compensated_sum, exp, ln, softmin_dd, DoubleDouble};
+use crate::soft_dtw::DEGENERATE_TOL;
+pub use crate::soft_dtw::{Precision, TinyGamma};
+
 /// Errors for soft shortest-path operators.
 #[derive(thiserror::Error, Debug, Clone, PartialEq)]
 pub enum Error {
@@ -50,9 +54,86 @@ pub enum Error {
         /// Destination endpoint of that edge.
         to: usize,
     },
+    /// An edge cost is NaN or infinite.
+    #[error("edge {edge_idx} has non-finite cost {cost}")]
+    NonFiniteCost {
+        /// Index of the offending edge in the provided slice.
+        edge_idx: usize,
+        /// The cost.
+        cost: f64,
+    },
     /// No path exists from source to sink.
     #[error("no path exists from source to sink")]
     NoPath,
+    /// Source/sink must be in bounds with `source < sink`.
+    #[error("invalid endpoints: source={source_node}, sink={sink_node} for n={n}")]
+    InvalidEndpoints {
+        /// Requested source node.
+        source_node: usize,
+        /// Requested sink node.
+        sink_node: usize,
+        /// Number of nodes in the graph.
+        n: usize,
+    },
+    /// A gradient buffer does not have one entry per edge.
+    #[error("gradient buffer has length {len}, expected {expected}")]
+    GradientLengthMismatch {
+        /// The provided buffer length.
+        len: usize,
+        /// Number of edges.
+        expected: usize,
+    },
+    /// An edge-cost vector does not have one entry per edge.
+    #[error("edge costs have length {len}, expected {expected}")]
+    EdgeCostLength {
+        /// The provided length.
+        len: usize,
+        /// Number of edges.
+        expected: usize,
+    },
+    /// A tensor argument has the wrong number of dimensions.
+    #[error("tensor has {rank} dimensions, expected {expected}")]
+    TensorRank {
+        /// Dimensions of the given tensor.
+        rank: usize,
+        /// Dimensions the operator takes.
+        expected: usize,
+    },
+    /// A node-cost vector does not have one entry per node.
+    #[error("node costs have length {len}, expected {expected}")]
+    NodeCostLength {
+        /// The provided length.
+        len: usize,
+        /// Number of nodes.
+        expected: usize,
+    },
+    /// A node cost is NaN or infinite.
+    #[error("node {node} has non-finite cost {cost}")]
+    NonFiniteNodeCost {
+        /// The node.
+        node: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// A requested node is out of bounds.
+    #[error("node {node} out of bounds for n={n}")]
+    NodeOutOfBounds {
+        /// The requested node.
+        node: usize,
+        /// Number of nodes in the graph.
+        n: usize,
+    },
+    /// A warm start was captured for a different node count, edge count or gamma.
+    #[error("warm start does not match a graph with n={n} and {edges} edges at this gamma")]
+    WarmStartMismatch {
+        /// Number of nodes in the graph.
+        n: usize,
+        /// Number of edges in the graph.
+        edges: usize,
+    },
+    /// A cost-change tolerance must be non-negative and finite.
+    #[error("tolerance must be non-negative and finite, got {0}")]
+    InvalidTolerance(f64),
 }
 
 /// Convenience result type for this module.
@@ -69,6 +150,17 @@ pub struct Edge {
     pub cost: f64,
 }
 
+/// Marginal of stepping directly from `from` to `to`, summed over parallel edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairMarginal {
+    /// Source node index.
+    pub from: usize,
+    /// Destination node index.
+    pub to: usize,
+    /// \(\mathbb{P}_\gamma(\pi \text{ steps } u \to v)\).
+    pub marginal: f64,
+}
+
 fn log_sum_exp(xs: &[f64]) -> f64 {
     let mut m = f64::NEG_INFINITY;
     for &x in xs {
@@ -79,11 +171,9 @@ fn log_sum_exp(xs: &[f64]) -> f64 {
     if !m.is_finite() {
         return f64::NEG_INFINITY;
     }
-    let mut s = 0.0;
-    for &x in xs {
-        s += (x - m).exp();
-    }
-    m + s.ln()
+    // Compensated, so nodes with many incoming edges don't accumulate summation error.
+    let s = compensated_sum(xs.iter().map(|&x| exp(x - m)));
+    m + ln(s)
 }
 
 fn softmin_gamma(gamma: f64, candidates: &[f64], scratch: &mut Vec<f64>) -> f64 {
@@ -122,12 +212,9 @@ fn validate(n: usize, edges: &[Edge]) -> Result<()> {
             });
         }
         if !e.cost.is_finite() {
-            // Keep it simple: non-finite costs are not supported in this operator.
-            return Err(Error::EdgeOutOfBounds {
+            return Err(Error::NonFiniteCost {
                 edge_idx: k,
-                from: e.from,
-                to: e.to,
-                n,
+                cost: e.cost,
             });
         }
     }
@@ -136,126 +223,882 @@ fn validate(n: usize, edges: &[Edge]) -> Result<()> {
 
 /// Compute the soft shortest-path value \(V_\gamma\) from node 0 to node n-1.
 pub fn soft_shortest_path_value(n: usize, edges: &[Edge], gamma: f64) -> Result<f64> {
-    if gamma <= 0.0 || !gamma.is_finite() {
-        return Err(Error::InvalidGamma(gamma));
-    }
-    validate(n, edges)?;
+    Workspace::new().value(n, edges, gamma)
+}
 
-    let mut incoming: Vec<Vec<usize>> = vec![Vec::new(); n];
-    for (k, e) in edges.iter().enumerate() {
-        incoming[e.to].push(k);
-    }
+/// Log-partition \(\log Z = \log \sum_\pi e^{-C(\pi)/\gamma} = -V_\gamma/\gamma\) over
+/// paths from 0 to n-1.
+///
+/// For globally normalized models: the negative log-likelihood of a gold path \(\pi^*\) is
+/// \(C(\pi^*)/\gamma + \log Z\), and \(\partial \log Z / \partial c_e = -p_e/\gamma\).
+pub fn soft_shortest_path_log_partition(n: usize, edges: &[Edge], gamma: f64) -> Result<f64> {
+    Ok(-soft_shortest_path_value(n, edges, gamma)? / gamma)
+}
 
-    let mut dp = vec![f64::INFINITY; n];
-    dp[0] = 0.0;
-    let mut scratch = Vec::new();
-    let mut cands = Vec::new();
+/// Compute edge marginals \(p_e = \mathbb{P}_\gamma(e \in \pi)\) for paths from 0 to n-1.
+///
+/// Returns `(value, edge_marginals)` where `edge_marginals.len() == edges.len()`.
+pub fn soft_shortest_path_edge_marginals(
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    let mut ws = Workspace::new();
+    let value = ws.run_marginals(n, edges, gamma, false)?;
+    Ok((value, ws.marginals))
+}
 
-    for v in 1..n {
-        cands.clear();
-        for &ek in &incoming[v] {
-            let e = edges[ek];
-            let a = dp[e.from];
-            if a.is_finite() {
-                cands.push(a + e.cost);
-            }
-        }
-        if cands.is_empty() {
-            dp[v] = f64::INFINITY;
-        } else {
-            dp[v] = softmin_gamma(gamma, &cands, &mut scratch);
+/// Value and aggregated marginals of each node pair `(u, v)` joined by at least one edge,
+/// sorted by `(u, v)`.
+///
+/// Parallel edges (several labels between the same states, as in lattices) are allowed
+/// everywhere; the per-edge marginals from [`soft_shortest_path_edge_marginals`] give the
+/// probability of each labelled edge. A path visits `u` at most once, so it takes at most one
+/// edge out of it, and the pair marginal \(\mathbb{P}_\gamma(\pi \text{ steps } u \to v)\) is
+/// the sum over the parallel edges: the marginal of a single edge whose cost is the soft
+/// minimum of theirs. Within a pair, the edges share it in proportion to
+/// \(e^{-c_e/\gamma}\).
+pub fn soft_shortest_path_pair_marginals(
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+) -> Result<(f64, Vec<PairMarginal>)> {
+    let (value, p) = soft_shortest_path_edge_marginals(n, edges, gamma)?;
+    Ok((value, aggregate_pairs(edges, &p)))
+}
+
+/// Sums edge marginals over parallel edges, sorted by `(from, to)`.
+fn aggregate_pairs(edges: &[Edge], p: &[f64]) -> Vec<PairMarginal> {
+    let mut pairs: Vec<PairMarginal> = edges
+        .iter()
+        .zip(p)
+        .map(|(e, &marginal)| PairMarginal {
+            from: e.from,
+            to: e.to,
+            marginal,
+        })
+        .collect();
+    pairs.sort_by_key(|q| (q.from, q.to));
+    pairs.dedup_by(|next, kept| {
+        let same = (next.from, next.to) == (kept.from, kept.to);
+        if same {
+            kept.marginal += next.marginal;
         }
+        same
+    });
+    pairs
+}
+
+/// Value, edge marginals and node marginals when each node `v` also costs `node_costs[v]`
+/// (paths pay for every node they visit, source and sink included).
+///
+/// The node marginal \(\mathbb{P}_\gamma(v \in \pi)\) is \(\partial V_\gamma / \partial
+/// (\text{node cost})\), so models scoring nodes (token lattices) get their gradient directly
+/// instead of copying node scores onto every incident edge. Edge costs may be all zero.
+pub fn soft_shortest_path_node_marginals(
+    n: usize,
+    edges: &[Edge],
+    node_costs: &[f64],
+    gamma: f64,
+) -> Result<(f64, Vec<f64>, Vec<f64>)> {
+    let folded = fold_node_costs(n, edges, node_costs)?;
+    let (value, p) = soft_shortest_path_edge_marginals(n, &folded, gamma)?;
+    let q = node_marginals(n, edges, &p, 0);
+    Ok((value + node_costs[0], p, q))
+}
+
+/// Edges with each destination's node cost added, after checking the node costs.
+fn fold_node_costs(n: usize, edges: &[Edge], node_costs: &[f64]) -> Result<Vec<Edge>> {
+    if node_costs.len() != n {
+        return Err(Error::NodeCostLength {
+            len: node_costs.len(),
+            expected: n,
+        });
     }
+    if let Some((node, &cost)) = node_costs.iter().enumerate().find(|(_, c)| !c.is_finite()) {
+        return Err(Error::NonFiniteNodeCost { node, cost });
+    }
+    // Out-of-bounds endpoints are left for `validate` to report.
+    let cost = |v: usize| node_costs.get(v).copied().unwrap_or(0.0);
+    Ok(edges
+        .iter()
+        .map(|e| Edge {
+            cost: e.cost + cost(e.to),
+            ..*e
+        })
+        .collect())
+}
 
-    let v = dp[n - 1];
-    if !v.is_finite() {
-        return Err(Error::NoPath);
+/// Node marginals from edge marginals: 1 at the source, else the inflow.
+fn node_marginals(n: usize, edges: &[Edge], p: &[f64], source: usize) -> Vec<f64> {
+    let mut q = vec![0.0; n];
+    q[source] = 1.0;
+    for (e, p) in edges.iter().zip(p) {
+        q[e.to] += p;
     }
-    Ok(v)
+    q
 }
 
-/// Compute edge marginals \(p_e = \mathbb{P}_\gamma(e \in \pi)\) for paths from 0 to n-1.
+/// Adds `scale` times the edge marginals (the gradient of the value w.r.t. the edge costs)
+/// into `grad_out` (length `edges.len()`) and returns the value, for gradient accumulation
+/// over a minibatch. `grad_out` is left untouched on error.
+pub fn soft_shortest_path_grad_accumulate(
+    n: usize,
+    edges: &[Edge],
+    gamma: f64,
+    grad_out: &mut [f64],
+    scale: f64,
+) -> Result<f64> {
+    Workspace::new().grad_accumulate(n, edges, gamma, grad_out, scale)
+}
+
+/// [`soft_shortest_path_edge_marginals`] with the marginals as log-probabilities `ln p_e`.
 ///
-/// Returns `(value, edge_marginals)` where `edge_marginals.len() == edges.len()`.
-pub fn soft_shortest_path_edge_marginals(
+/// For small \(\gamma\), marginals of edges off the best path underflow to exactly 0;
+/// `ln p_e = -(f_u + c_e + b_v - V_\gamma)/\gamma` is formed from the potentials without
+/// exponentiating, so it stays finite for every edge on some source-to-sink path (edges on
+/// none get `-inf`).
+pub fn soft_shortest_path_edge_log_marginals(
     n: usize,
     edges: &[Edge],
     gamma: f64,
 ) -> Result<(f64, Vec<f64>)> {
-    if gamma <= 0.0 || !gamma.is_finite() {
-        return Err(Error::InvalidGamma(gamma));
+    let mut ws = Workspace::new();
+    let value = ws.run_marginals(n, edges, gamma, true)?;
+    Ok((value, ws.marginals))
+}
+
+/// Fills a CSR adjacency: the edges with `key == v` are `list[start[v]..start[v + 1]]`, in
+/// edge order.
+fn fill_csr<I>(start: &mut Vec<usize>, list: &mut Vec<usize>, n: usize, keys: I)
+where
+    I: Iterator<Item = usize> + Clone,
+{
+    start.clear();
+    start.resize(n + 1, 0);
+    for key in keys.clone() {
+        start[key + 1] += 1;
     }
-    validate(n, edges)?;
+    for v in 1..=n {
+        start[v] += start[v - 1];
+    }
+    list.clear();
+    list.resize(start[n], 0);
+    // `start[v]` serves as the write cursor for `v`, ending at the start of `v + 1`...
+    for (k, key) in keys.enumerate() {
+        list[start[key]] = k;
+        start[key] += 1;
+    }
+    // ...so shift back by one slot.
+    for v in (1..=n).rev() {
+        start[v] = start[v - 1];
+    }
+    start[0] = 0;
+}
 
-    let mut incoming: Vec<Vec<usize>> = vec![Vec::new(); n];
-    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); n];
-    for (k, e) in edges.iter().enumerate() {
-        incoming[e.to].push(k);
-        outgoing[e.from].push(k);
+/// Reusable buffers for repeated soft shortest-path calls.
+///
+/// The free functions build the adjacency lists and potential vectors from scratch on every
+/// call. A `Workspace` keeps them (with the adjacency in flat CSR form rather than one `Vec`
+/// per node) and only grows them, so a serving loop does no per-call allocation once it has
+/// seen its largest graph. Results are identical to the free functions.
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    in_start: Vec<usize>,
+    in_edges: Vec<usize>,
+    out_start: Vec<usize>,
+    out_edges: Vec<usize>,
+    fwd: Vec<f64>,
+    bwd: Vec<f64>,
+    cands: Vec<f64>,
+    scratch: Vec<f64>,
+    marginals: Vec<f64>,
+    dirty: Vec<bool>,
+    /// Gamma of the last call that left both potentials and the marginals in place.
+    solved: Option<f64>,
+}
+
+/// Potentials and edge costs of an earlier solve, to warm-start
+/// [`Workspace::warm_edge_marginals`]; captured by [`Workspace::warm_start`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarmStart {
+    gamma: f64,
+    costs: Vec<f64>,
+    fwd: Vec<f64>,
+    bwd: Vec<f64>,
+}
+
+impl Workspace {
+    /// An empty workspace; buffers are allocated on first use.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    // Forward potentials (soft shortest from source to each node)
-    let mut fwd = vec![f64::INFINITY; n];
-    fwd[0] = 0.0;
-    let mut scratch = Vec::new();
-    let mut cands = Vec::new();
-    for v in 1..n {
-        cands.clear();
-        for &ek in &incoming[v] {
+    /// [`soft_shortest_path_value`] using this workspace's buffers.
+    pub fn value(&mut self, n: usize, edges: &[Edge], gamma: f64) -> Result<f64> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        validate(n, edges)?;
+        self.forward(n, edges, gamma);
+        let v = self.fwd[n - 1];
+        if !v.is_finite() {
+            return Err(Error::NoPath);
+        }
+        Ok(v)
+    }
+
+    /// [`soft_shortest_path_edge_marginals`] using this workspace's buffers; the marginals
+    /// are borrowed from the workspace until the next call.
+    pub fn edge_marginals(
+        &mut self,
+        n: usize,
+        edges: &[Edge],
+        gamma: f64,
+    ) -> Result<(f64, &[f64])> {
+        let value = self.run_marginals(n, edges, gamma, false)?;
+        Ok((value, &self.marginals))
+    }
+
+    /// [`soft_shortest_path_grad_accumulate`] using this workspace's buffers.
+    pub fn grad_accumulate(
+        &mut self,
+        n: usize,
+        edges: &[Edge],
+        gamma: f64,
+        grad_out: &mut [f64],
+        scale: f64,
+    ) -> Result<f64> {
+        if grad_out.len() != edges.len() {
+            return Err(Error::GradientLengthMismatch {
+                len: grad_out.len(),
+                expected: edges.len(),
+            });
+        }
+        let value = self.run_marginals(n, edges, gamma, false)?;
+        for (g, p) in grad_out.iter_mut().zip(&self.marginals) {
+            *g += scale * p;
+        }
+        Ok(value)
+    }
+
+    /// [`soft_shortest_path_edge_log_marginals`] using this workspace's buffers.
+    pub fn edge_log_marginals(
+        &mut self,
+        n: usize,
+        edges: &[Edge],
+        gamma: f64,
+    ) -> Result<(f64, &[f64])> {
+        let value = self.run_marginals(n, edges, gamma, true)?;
+        Ok((value, &self.marginals))
+    }
+
+    /// Potentials and costs of the last marginals call (`edge_marginals`,
+    /// `edge_log_marginals`, `grad_accumulate` or `warm_edge_marginals`) on `edges`, or
+    /// `None` if the last call computed no backward potentials or had another edge count.
+    pub fn warm_start(&self, edges: &[Edge]) -> Option<WarmStart> {
+        let gamma = self
+            .solved
+            .filter(|_| edges.len() == self.marginals.len())?;
+        Some(WarmStart {
+            gamma,
+            costs: edges.iter().map(|e| e.cost).collect(),
+            fwd: self.fwd.clone(),
+            bwd: self.bwd.clone(),
+        })
+    }
+
+    /// [`Workspace::edge_marginals`] starting from the potentials of an earlier solve of the
+    /// same graph with other costs.
+    ///
+    /// An edge counts as changed if its cost moved by more than `tolerance` since `warm` was
+    /// captured. A forward potential is recomputed only if an incoming edge changed or its
+    /// tail was recomputed, and symmetrically for backward potentials, so a step that touches
+    /// a few edges re-sweeps only the nodes downstream (forward) and upstream (backward) of
+    /// them. With `tolerance == 0` the result is identical to a cold solve; otherwise each
+    /// reused potential is off by at most `tolerance` times the number of edges on the
+    /// longest path through which it was computed.
+    pub fn warm_edge_marginals(
+        &mut self,
+        n: usize,
+        edges: &[Edge],
+        gamma: f64,
+        warm: &WarmStart,
+        tolerance: f64,
+    ) -> Result<(f64, &[f64])> {
+        if gamma <= 0.0 || !gamma.is_finite() {
+            return Err(Error::InvalidGamma(gamma));
+        }
+        if !(tolerance >= 0.0 && tolerance.is_finite()) {
+            return Err(Error::InvalidTolerance(tolerance));
+        }
+        validate(n, edges)?;
+        if warm.gamma != gamma || warm.fwd.len() != n || warm.costs.len() != edges.len() {
+            return Err(Error::WarmStartMismatch {
+                n,
+                edges: edges.len(),
+            });
+        }
+        self.solved = None;
+        let changed = |k: usize| (edges[k].cost - warm.costs[k]).abs() > tolerance;
+
+        fill_csr(
+            &mut self.in_start,
+            &mut self.in_edges,
+            n,
+            edges.iter().map(|e| e.to),
+        );
+        self.fwd.clone_from(&warm.fwd);
+        self.dirty.clear();
+        self.dirty.resize(n, false);
+        for v in 1..n {
+            let ins = &self.in_edges[self.in_start[v]..self.in_start[v + 1]];
+            if ins.iter().any(|&k| changed(k) || self.dirty[edges[k].from]) {
+                self.dirty[v] = true;
+                self.forward_node(v, edges, gamma);
+            }
+        }
+        let value = self.fwd[n - 1];
+        if !value.is_finite() {
+            return Err(Error::NoPath);
+        }
+
+        fill_csr(
+            &mut self.out_start,
+            &mut self.out_edges,
+            n,
+            edges.iter().map(|e| e.from),
+        );
+        self.bwd.clone_from(&warm.bwd);
+        self.dirty.clear();
+        self.dirty.resize(n, false);
+        for u in (0..n - 1).rev() {
+            let outs = &self.out_edges[self.out_start[u]..self.out_start[u + 1]];
+            if outs.iter().any(|&k| changed(k) || self.dirty[edges[k].to]) {
+                self.dirty[u] = true;
+                self.backward_node(u, edges, gamma);
+            }
+        }
+        self.fill_marginals(edges, gamma, value, false);
+        self.solved = Some(gamma);
+        Ok((value, &self.marginals))
+    }
+
+    /// Forward potentials (soft shortest from source to each node) into `self.fwd`.
+    fn forward(&mut self, n: usize, edges: &[Edge], gamma: f64) {
+        self.forward_from(n, edges, gamma, 0);
+    }
+
+    /// Soft shortest distances from `start` to each node into `self.fwd` (`+inf` before
+    /// `start` and where unreachable).
+    fn forward_from(&mut self, n: usize, edges: &[Edge], gamma: f64, start: usize) {
+        self.solved = None;
+        fill_csr(
+            &mut self.in_start,
+            &mut self.in_edges,
+            n,
+            edges.iter().map(|e| e.to),
+        );
+        self.fwd.clear();
+        self.fwd.resize(n, f64::INFINITY);
+        self.fwd[start] = 0.0;
+        for v in start + 1..n {
+            self.forward_node(v, edges, gamma);
+        }
+    }
+
+    /// Forward potential of `v` from those of its predecessors (in-edge CSR built).
+    fn forward_node(&mut self, v: usize, edges: &[Edge], gamma: f64) {
+        self.cands.clear();
+        for &ek in &self.in_edges[self.in_start[v]..self.in_start[v + 1]] {
             let e = edges[ek];
-            let a = fwd[e.from];
+            let a = self.fwd[e.from];
             if a.is_finite() {
-                cands.push(a + e.cost);
+                self.cands.push(a + e.cost);
             }
         }
-        fwd[v] = if cands.is_empty() {
+        self.fwd[v] = if self.cands.is_empty() {
             f64::INFINITY
         } else {
-            softmin_gamma(gamma, &cands, &mut scratch)
+            softmin_gamma(gamma, &self.cands, &mut self.scratch)
         };
     }
 
-    let value = fwd[n - 1];
-    if !value.is_finite() {
-        return Err(Error::NoPath);
-    }
-
-    // Backward potentials (soft shortest from each node to sink)
-    let mut bwd = vec![f64::INFINITY; n];
-    bwd[n - 1] = 0.0;
-    for u_rev in 1..n {
-        let u = n - 1 - u_rev;
-        cands.clear();
-        for &ek in &outgoing[u] {
+    /// Backward potential of `u` from those of its successors (out-edge CSR built).
+    fn backward_node(&mut self, u: usize, edges: &[Edge], gamma: f64) {
+        self.cands.clear();
+        for &ek in &self.out_edges[self.out_start[u]..self.out_start[u + 1]] {
             let e = edges[ek];
-            let a = bwd[e.to];
+            let a = self.bwd[e.to];
             if a.is_finite() {
-                cands.push(e.cost + a);
+                self.cands.push(e.cost + a);
             }
         }
-        bwd[u] = if cands.is_empty() {
+        self.bwd[u] = if self.cands.is_empty() {
             f64::INFINITY
         } else {
-            softmin_gamma(gamma, &cands, &mut scratch)
+            softmin_gamma(gamma, &self.cands, &mut self.scratch)
+        };
+    }
+
+    /// Edge marginals into `self.marginals`, as log-probabilities when `log` is set.
+    fn run_marginals(&mut self, n: usize, edges: &[Edge], gamma: f64, log: bool) -> Result<f64> {
+        let value = self.value(n, edges, gamma)?;
+
+        // Backward potentials (soft shortest from each node to sink)
+        fill_csr(
+            &mut self.out_start,
+            &mut self.out_edges,
+            n,
+            edges.iter().map(|e| e.from),
+        );
+        self.bwd.clear();
+        self.bwd.resize(n, f64::INFINITY);
+        self.bwd[n - 1] = 0.0;
+        for u in (0..n - 1).rev() {
+            self.backward_node(u, edges, gamma);
+        }
+        self.fill_marginals(edges, gamma, value, log);
+        self.solved = Some(gamma);
+        Ok(value)
+    }
+
+    /// Edge marginals (log-marginals if `log`) into `self.marginals` from both potentials.
+    fn fill_marginals(&mut self, edges: &[Edge], gamma: f64, value: f64, log: bool) {
+        // Edge marginals:
+        // p_e = exp(-(fwd[u] + c_e + bwd[v] - value)/gamma)
+        self.marginals.clear();
+        self.marginals
+            .resize(edges.len(), if log { f64::NEG_INFINITY } else { 0.0 });
+        for (k, e) in edges.iter().enumerate() {
+            let a = self.fwd[e.from];
+            let b = self.bwd[e.to];
+            if a.is_finite() && b.is_finite() {
+                let z = -((a + e.cost + b - value) / gamma);
+                self.marginals[k] = if log {
+                    z
+                } else if z < -745.0 {
+                    // prevent overflow in exp for extremely negative (shouldn’t happen much)
+                    0.0
+                } else {
+                    exp(z)
+                };
+            }
+        }
+    }
+}
+
+/// A validated DAG with explicit source and sink nodes.
+///
+/// Construction checks bounds, topological order (`from < to`), finite costs, and
+/// `source < sink`. Because edges respect the node order, every source-to-sink path stays
+/// within nodes `source..=sink`; the operators run on that node range and give edges
+/// outside it a marginal of zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph {
+    n: usize,
+    edges: Vec<Edge>,
+    source: usize,
+    sink: usize,
+}
+
+impl Graph {
+    /// Validates and builds a graph on nodes `0..n`.
+    pub fn new(n: usize, edges: Vec<Edge>, source: usize, sink: usize) -> Result<Self> {
+        validate(n, &edges)?;
+        if source >= sink || sink >= n {
+            return Err(Error::InvalidEndpoints {
+                source_node: source,
+                sink_node: sink,
+                n,
+            });
+        }
+        Ok(Self {
+            n,
+            edges,
+            source,
+            sink,
+        })
+    }
+
+    /// Number of nodes.
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Edges, in the order given at construction.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Source node.
+    pub fn source(&self) -> usize {
+        self.source
+    }
+
+    /// Sink node.
+    pub fn sink(&self) -> usize {
+        self.sink
+    }
+
+    /// Soft shortest-path value \(V_\gamma\) from source to sink.
+    pub fn value(&self, gamma: f64) -> Result<f64> {
+        let (n, edges, _) = window(&self.edges, self.source, self.sink);
+        soft_shortest_path_value(n, &edges, gamma)
+    }
+
+    /// Log-partition \(-V_\gamma/\gamma\) over source-to-sink paths (see
+    /// [`soft_shortest_path_log_partition`]).
+    pub fn log_partition(&self, gamma: f64) -> Result<f64> {
+        Ok(-self.value(gamma)? / gamma)
+    }
+
+    /// Value and edge marginals (indexed like [`Graph::edges`]).
+    pub fn edge_marginals(&self, gamma: f64) -> Result<(f64, Vec<f64>)> {
+        windowed_marginals(&self.edges, self.source, self.sink, gamma)
+    }
+
+    /// Value and pair marginals as in [`soft_shortest_path_pair_marginals`] (pairs outside
+    /// `source..=sink` get zero).
+    pub fn pair_marginals(&self, gamma: f64) -> Result<(f64, Vec<PairMarginal>)> {
+        let (value, p) = self.edge_marginals(gamma)?;
+        Ok((value, aggregate_pairs(&self.edges, &p)))
+    }
+
+    /// [`soft_shortest_path_node_marginals`] between this graph's source and sink: value,
+    /// edge marginals and node marginals (zero outside `source..=sink`).
+    pub fn node_marginals(
+        &self,
+        gamma: f64,
+        node_costs: &[f64],
+    ) -> Result<(f64, Vec<f64>, Vec<f64>)> {
+        let folded = fold_node_costs(self.n, &self.edges, node_costs)?;
+        let (value, p) = windowed_marginals(&folded, self.source, self.sink, gamma)?;
+        let q = node_marginals(self.n, &self.edges, &p, self.source);
+        Ok((value + node_costs[self.source], p, q))
+    }
+
+    /// Value and transit probabilities \(\mathbb{P}_\gamma(\pi \text{ visits } u \text{ and
+    /// later } v)\) for each `(u, v)` in `pairs`, from the forward/backward potentials:
+    /// \(\exp(-(f_u + d_\gamma(u, v) + b_v - V_\gamma)/\gamma)\) with \(d_\gamma\) the soft
+    /// distance from `u` to `v`. A pair `(u, u)` gives the probability of visiting `u`;
+    /// pairs with `u > v` or a node outside `source..=sink` give 0.
+    ///
+    /// Costs one forward pass per distinct `u`.
+    pub fn transit_probabilities(
+        &self,
+        gamma: f64,
+        pairs: &[(usize, usize)],
+    ) -> Result<(f64, Vec<f64>)> {
+        if let Some(node) = pairs.iter().map(|&(u, v)| u.max(v)).find(|&x| x >= self.n) {
+            return Err(Error::NodeOutOfBounds { node, n: self.n });
+        }
+        let (n, edges, _) = window(&self.edges, self.source, self.sink);
+        let mut ws = Workspace::new();
+        let value = ws.run_marginals(n, &edges, gamma, false)?;
+        let (fwd, bwd) = (std::mem::take(&mut ws.fwd), std::mem::take(&mut ws.bwd));
+        let inner = |x: usize| {
+            (self.source..=self.sink)
+                .contains(&x)
+                .then(|| x - self.source)
         };
+        let mut order: Vec<(usize, usize, usize)> = pairs
+            .iter()
+            .enumerate()
+            .filter_map(|(k, &(u, v))| Some((inner(u)?, inner(v)?, k)))
+            .filter(|&(u, v, _)| u <= v)
+            .collect();
+        order.sort_unstable();
+        let mut out = vec![0.0; pairs.len()];
+        let mut current = None;
+        for (u, v, k) in order {
+            if current != Some(u) {
+                ws.forward_from(n, &edges, gamma, u);
+                current = Some(u);
+            }
+            let z = -(fwd[u] + ws.fwd[v] + bwd[v] - value) / gamma;
+            out[k] = if z.is_finite() { exp(z) } else { 0.0 };
+        }
+        Ok((value, out))
     }
+}
 
-    // Edge marginals:
-    // p_e = exp(-(fwd[u] + c_e + bwd[v] - value)/gamma)
+/// Edges inside `s..=t`, re-indexed from 0, with their original indices.
+fn window(edges: &[Edge], s: usize, t: usize) -> (usize, Vec<Edge>, Vec<usize>) {
+    let mut inner = Vec::new();
+    let mut index = Vec::new();
+    for (k, e) in edges.iter().enumerate() {
+        if e.from >= s && e.to <= t {
+            inner.push(Edge {
+                from: e.from - s,
+                to: e.to - s,
+                cost: e.cost,
+            });
+            index.push(k);
+        }
+    }
+    (t - s + 1, inner, index)
+}
+
+/// Hard shortest path from node 0 to node `n-1` of a validated DAG: its cost and the
+/// indicator of its edges (ties go to the earliest edge in `edges`).
+fn hard_path(n: usize, edges: &[Edge]) -> Result<(f64, Vec<f64>)> {
+    let mut order: Vec<usize> = (0..edges.len()).collect();
+    order.sort_by_key(|&k| edges[k].from);
+    let mut dist = vec![f64::INFINITY; n];
+    let mut pred = vec![usize::MAX; n];
+    dist[0] = 0.0;
+    for k in order {
+        let e = edges[k];
+        let d = dist[e.from] + e.cost;
+        if d < dist[e.to] {
+            dist[e.to] = d;
+            pred[e.to] = k;
+        }
+    }
+    if !dist[n - 1].is_finite() {
+        return Err(Error::NoPath);
+    }
     let mut p = vec![0.0; edges.len()];
+    let mut v = n - 1;
+    while v != 0 {
+        p[pred[v]] = 1.0;
+        v = edges[pred[v]].from;
+    }
+    Ok((dist[n - 1], p))
+}
+
+/// Value and edge marginals from node 0 to node `n-1` of a validated DAG, with
+/// double-double potentials (see [`Precision`]).
+fn dd_marginals(n: usize, edges: &[Edge], gamma: f64) -> Result<(f64, Vec<f64>)> {
+    let mut incoming = vec![Vec::new(); n];
+    let mut outgoing = vec![Vec::new(); n];
     for (k, e) in edges.iter().enumerate() {
-        let a = fwd[e.from];
-        let b = bwd[e.to];
-        if a.is_finite() && b.is_finite() {
-            let z = -((a + e.cost + b - value) / gamma);
-            // prevent overflow in exp for extremely negative (shouldn’t happen much)
-            p[k] = if z < -745.0 { 0.0 } else { z.exp() };
-        } else {
-            p[k] = 0.0;
+        incoming[e.to].push(k);
+        outgoing[e.from].push(k);
+    }
+    let mut cands = Vec::new();
+    let mut fwd = vec![DoubleDouble::INFINITY; n];
+    fwd[0] = DoubleDouble::from(0.0);
+    for v in 1..n {
+        cands.clear();
+        cands.extend(
+            incoming[v]
+                .iter()
+                .map(|&k| fwd[edges[k].from] + edges[k].cost.into()),
+        );
+        fwd[v] = softmin_dd(gamma, &cands);
+    }
+    let value = fwd[n - 1];
+    if !value.is_finite() {
+        return Err(Error::NoPath);
+    }
+    let mut bwd = vec![DoubleDouble::INFINITY; n];
+    bwd[n - 1] = DoubleDouble::from(0.0);
+    for u in (0..n - 1).rev() {
+        cands.clear();
+        cands.extend(
+            outgoing[u]
+                .iter()
+                .map(|&k| bwd[edges[k].to] + edges[k].cost.into()),
+        );
+        bwd[u] = softmin_dd(gamma, &cands);
+    }
+    let marginals = edges
+        .iter()
+        .map(|e| {
+            let (a, b) = (fwd[e.from], bwd[e.to]);
+            if a.is_finite() && b.is_finite() {
+                let z = (value - a - e.cost.into() - b).div_f64(gamma);
+                exp(z.to_f64())
+            } else {
+                0.0
+            }
+        })
+        .collect();
+    Ok((value.to_f64(), marginals))
+}
+
+fn windowed_marginals(edges: &[Edge], s: usize, t: usize, gamma: f64) -> Result<(f64, Vec<f64>)> {
+    let (n, inner, index) = window(edges, s, t);
+    let (v, pw) = soft_shortest_path_edge_marginals(n, &inner, gamma)?;
+    let mut p = vec![0.0; edges.len()];
+    for (k, pk) in index.into_iter().zip(pw) {
+        p[k] = pk;
+    }
+    Ok((v, p))
+}
+
+/// Configurable soft shortest-path operator.
+///
+/// ```
+/// use structop::soft_shortest_path::{Edge, SoftShortestPath};
+///
+/// let edges = [
+///     Edge { from: 0, to: 1, cost: 1.0 },
+///     Edge { from: 1, to: 2, cost: 1.0 },
+///     Edge { from: 2, to: 3, cost: 9.0 },
+/// ];
+/// let v = SoftShortestPath::new(0.5).sink(2).compute(4, &edges).unwrap();
+/// assert!(v <= 2.0);
+/// ```
+///
+/// Source and sink default to node 0 and node `n-1`, where the results are exactly those of
+/// [`soft_shortest_path_value`] and [`soft_shortest_path_edge_marginals`]; otherwise they
+/// match [`Graph`] with the same endpoints. `gamma` is validated when computing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftShortestPath {
+    gamma: f64,
+    source: Option<usize>,
+    sink: Option<usize>,
+    tiny_gamma: TinyGamma,
+    precision: Precision,
+}
+
+impl SoftShortestPath {
+    /// Soft shortest path with smoothing `gamma` from node 0 to node `n-1`.
+    pub fn new(gamma: f64) -> Self {
+        Self {
+            gamma,
+            source: None,
+            sink: None,
+            tiny_gamma: TinyGamma::default(),
+            precision: Precision::default(),
+        }
+    }
+
+    /// Sets the source node.
+    pub fn source(mut self, source: usize) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Sets the sink node.
+    pub fn sink(mut self, sink: usize) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Potential precision (for [`crate::resources`]).
+    pub(crate) fn precision_mode(&self) -> Precision {
+        self.precision
+    }
+
+    fn endpoints(&self, n: usize, edges: &[Edge]) -> Result<(usize, usize)> {
+        validate(n, edges)?;
+        let (s, t) = (self.source.unwrap_or(0), self.sink.unwrap_or(n - 1));
+        if s >= t || t >= n {
+            return Err(Error::InvalidEndpoints {
+                source_node: s,
+                sink_node: t,
+                n,
+            });
         }
+        Ok((s, t))
+    }
+
+    /// Sets the policy for \(\gamma\) too small for the soft recursion (see [`TinyGamma`]).
+    ///
+    /// The hard fallback returns the shortest-path cost and the indicator of one shortest
+    /// path as marginals.
+    pub fn tiny_gamma(mut self, tiny_gamma: TinyGamma) -> Self {
+        self.tiny_gamma = tiny_gamma;
+        self
+    }
+
+    /// Sets the working precision of the forward and backward potentials (see
+    /// [`Precision`]).
+    pub fn precision(mut self, precision: Precision) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Soft shortest-path value from source to sink.
+    pub fn compute(&self, n: usize, edges: &[Edge]) -> Result<f64> {
+        if self.tiny_gamma.forces_hard(self.gamma) {
+            return self.hard(n, edges).map(|(v, _)| v);
+        }
+        match self.soft_value(n, edges) {
+            // A non-finite soft value surfaces as `NoPath`.
+            Err(Error::NoPath) if self.tiny_gamma.detects() => self.hard(n, edges).map(|(v, _)| v),
+            out => out,
+        }
+    }
+
+    /// Value and edge marginals (indexed like `edges`; zero outside `source..=sink`).
+    pub fn edge_marginals(&self, n: usize, edges: &[Edge]) -> Result<(f64, Vec<f64>)> {
+        if self.tiny_gamma.forces_hard(self.gamma) {
+            return self.hard(n, edges);
+        }
+        match self.soft_marginals(n, edges) {
+            Err(Error::NoPath) if self.tiny_gamma.detects() => self.hard(n, edges),
+            Ok((v, p)) if self.tiny_gamma.detects() && self.degenerate(n, edges, v, &p) => {
+                self.hard(n, edges)
+            }
+            out => out,
+        }
+    }
+
+    fn soft_value(&self, n: usize, edges: &[Edge]) -> Result<f64> {
+        if self.precision == Precision::DoubleDouble {
+            return self.soft_marginals(n, edges).map(|(v, _)| v);
+        }
+        if self.source.is_none() && self.sink.is_none() {
+            return soft_shortest_path_value(n, edges, self.gamma);
+        }
+        let (s, t) = self.endpoints(n, edges)?;
+        let (n, inner, _) = window(edges, s, t);
+        soft_shortest_path_value(n, &inner, self.gamma)
+    }
+
+    fn soft_marginals(&self, n: usize, edges: &[Edge]) -> Result<(f64, Vec<f64>)> {
+        if self.precision == Precision::DoubleDouble {
+            let gamma = self.gamma;
+            return self.windowed(n, edges, |n, edges| dd_marginals(n, edges, gamma));
+        }
+        if self.source.is_none() && self.sink.is_none() {
+            return soft_shortest_path_edge_marginals(n, edges, self.gamma);
+        }
+        let (s, t) = self.endpoints(n, edges)?;
+        windowed_marginals(edges, s, t, self.gamma)
+    }
+
+    /// Whether soft marginals have lost the unit flow out of the source.
+    fn degenerate(&self, n: usize, edges: &[Edge], value: f64, p: &[f64]) -> bool {
+        let (s, t) = (self.source.unwrap_or(0), self.sink.unwrap_or(n - 1));
+        let outflow: f64 = edges
+            .iter()
+            .zip(p)
+            .filter(|(e, _)| e.from == s && e.to <= t)
+            .map(|(_, &pk)| pk)
+            .sum();
+        !value.is_finite()
+            || p.iter().any(|pk| !pk.is_finite())
+            || (outflow - 1.0).abs() > DEGENERATE_TOL
     }
 
-    Ok((value, p))
+    /// Hard fallback for [`TinyGamma`]; gamma and the graph are still validated.
+    fn hard(&self, n: usize, edges: &[Edge]) -> Result<(f64, Vec<f64>)> {
+        self.windowed(n, edges, hard_path)
+    }
+
+    /// Runs `op` on the validated `source..=sink` window and maps marginals back.
+    fn windowed<G>(&self, n: usize, edges: &[Edge], op: G) -> Result<(f64, Vec<f64>)>
+    where
+        G: FnOnce(usize, &[Edge]) -> Result<(f64, Vec<f64>)>,
+    {
+        if self.gamma <= 0.0 || !self.gamma.is_finite() {
+            return Err(Error::InvalidGamma(self.gamma));
+        }
+        let (s, t) = self.endpoints(n, edges)?;
+        let (n, inner, index) = window(edges, s, t);
+        let (v, pw) = op(n, &inner)?;
+        let mut p = vec![0.0; edges.len()];
+        for (k, pk) in index.into_iter().zip(pw) {
+            p[k] = pk;
+        }
+        Ok((v, p))
+    }
 }
 
 #[cfg(test)]
@@ -296,6 +1139,702 @@ mod tests {
         assert!((v - v_expected).abs() < 1e-9, "v={} v_expected={}", v, v_expected);
     }
 
+    #[test]
+    fn marginals_accumulate_into_caller_buffers() {
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 2.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 2.5,
+            },
+        ];
+        let (v, p) = soft_shortest_path_edge_marginals(3, &edges, 0.5).unwrap();
+        let mut acc = vec![1.0; 3];
+        let mut ws = Workspace::new();
+        assert_eq!(ws.grad_accumulate(3, &edges, 0.5, &mut acc, 2.0), Ok(v));
+        assert_eq!(
+            soft_shortest_path_grad_accumulate(3, &edges, 0.5, &mut acc, 2.0),
+            Ok(v)
+        );
+        for (a, p) in acc.iter().zip(&p) {
+            assert!((a - (1.0 + 4.0 * p)).abs() < 1e-15);
+        }
+        assert_eq!(
+            soft_shortest_path_grad_accumulate(3, &edges, 0.5, &mut acc[..2], 1.0),
+            Err(Error::GradientLengthMismatch {
+                len: 2,
+                expected: 3
+            })
+        );
+    }
+
+    #[test]
+    fn log_marginals_keep_underflowed_edges() {
+        // Paths 0-1-4 (cost 3) and 0-2-4 (cost 7); node 3 is a dead end.
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 4,
+                cost: 2.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 3.0,
+            },
+            Edge {
+                from: 2,
+                to: 4,
+                cost: 4.0,
+            },
+            Edge {
+                from: 0,
+                to: 3,
+                cost: 0.0,
+            },
+        ];
+        let (v, p) = soft_shortest_path_edge_marginals(5, &edges, 0.5).unwrap();
+        let (lv, lp) = soft_shortest_path_edge_log_marginals(5, &edges, 0.5).unwrap();
+        assert_eq!(v, lv);
+        for (a, b) in p[..4].iter().zip(&lp) {
+            assert!((a.ln() - b).abs() < 1e-12);
+        }
+        assert_eq!(lp[4], f64::NEG_INFINITY);
+
+        // At gamma = 1e-3 the worse path has probability e^-4000.
+        let (_, p) = soft_shortest_path_edge_marginals(5, &edges, 1e-3).unwrap();
+        let mut ws = Workspace::new();
+        let (_, lp) = ws.edge_log_marginals(5, &edges, 1e-3).unwrap();
+        assert_eq!(p[2], 0.0);
+        assert!((lp[2] + 4000.0).abs() < 1e-6, "{}", lp[2]);
+    }
+
+    #[test]
+    fn graph_with_inner_endpoints_ignores_outside_edges() {
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 5.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 1.0,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 7.0,
+            },
+        ];
+        let g = Graph::new(5, edges, 1, 3).unwrap();
+        let (v, p) = g.edge_marginals(0.5).unwrap();
+        let inner = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 2.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 1.0,
+            },
+        ];
+        let (v_ref, p_ref) = soft_shortest_path_edge_marginals(3, &inner, 0.5).unwrap();
+        assert_eq!(v, v_ref);
+        assert_eq!(p, vec![0.0, p_ref[0], p_ref[1], p_ref[2], 0.0]);
+        assert_eq!(g.value(0.5).unwrap(), v_ref);
+
+        assert_eq!(
+            Graph::new(5, vec![], 3, 3),
+            Err(Error::InvalidEndpoints {
+                source_node: 3,
+                sink_node: 3,
+                n: 5
+            })
+        );
+    }
+
+    #[test]
+    fn parallel_edges_aggregate_like_a_collapsed_edge() {
+        let gamma = 0.4;
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 0.3,
+            },
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.5,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 2.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 0.9,
+            },
+        ];
+        let (v, p) = soft_shortest_path_edge_marginals(3, &edges, gamma).unwrap();
+        let (v_pairs, pairs) = soft_shortest_path_pair_marginals(3, &edges, gamma).unwrap();
+        assert_eq!(v, v_pairs);
+        let keys: Vec<_> = pairs.iter().map(|q| (q.from, q.to)).collect();
+        assert_eq!(keys, [(0, 1), (0, 2), (1, 2)]);
+        assert!((pairs[0].marginal - (p[0] + p[2])).abs() < 1e-15);
+        assert!((pairs[2].marginal - (p[1] + p[4])).abs() < 1e-15);
+
+        let softmin = |a: f64, b: f64| -gamma * ((-a / gamma).exp() + (-b / gamma).exp()).ln();
+        let collapsed = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: softmin(1.0, 1.5),
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 2.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: softmin(0.3, 0.9),
+            },
+        ];
+        let (v_ref, p_ref) = soft_shortest_path_edge_marginals(3, &collapsed, gamma).unwrap();
+        assert!((v - v_ref).abs() < 1e-12);
+        for (pair, want) in pairs.iter().zip(&p_ref) {
+            assert!((pair.marginal - want).abs() < 1e-12);
+        }
+        let g = Graph::new(3, edges, 0, 2).unwrap();
+        assert_eq!(g.pair_marginals(gamma).unwrap(), (v, pairs));
+    }
+
+    #[test]
+    fn node_costs_match_path_enumeration() {
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 0.5,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 0.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 0.2,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 1.0,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 0.0,
+            },
+        ];
+        let nodes = [0.3, 1.0, 0.4, 0.1];
+        let gamma = 0.5;
+        // Paths 0-1-3, 0-2-3, 0-1-2-3 with their edge plus node costs.
+        let paths: [(f64, &[usize]); 3] = [
+            (1.5 + 1.4, &[0, 1, 3]),
+            (0.8, &[0, 2, 3]),
+            (0.7 + 1.8, &[0, 1, 2, 3]),
+        ];
+        let z: f64 = paths.iter().map(|(c, _)| (-c / gamma).exp()).sum();
+        let (v, p, q) = soft_shortest_path_node_marginals(4, &edges, &nodes, gamma).unwrap();
+        assert!((v + gamma * z.ln()).abs() < 1e-12);
+        for (node, q) in q.iter().enumerate() {
+            let want: f64 = paths
+                .iter()
+                .filter(|(_, visits)| visits.contains(&node))
+                .map(|(c, _)| (-c / gamma).exp() / z)
+                .sum();
+            assert!((q - want).abs() < 1e-12, "node {node}: {q} vs {want}");
+        }
+        assert!((p[1] - (-0.8 / gamma).exp() / z).abs() < 1e-12);
+
+        let g = Graph::new(4, edges.clone(), 0, 3).unwrap();
+        assert_eq!(g.node_marginals(gamma, &nodes).unwrap(), (v, p, q));
+        // Without node costs the same paths cost 1.5, 0 and 0.7.
+        let z: f64 = [1.5, 0.0, 0.7]
+            .iter()
+            .map(|c: &f64| (-c / gamma).exp())
+            .sum();
+        let log_z = soft_shortest_path_log_partition(4, &edges, gamma).unwrap();
+        assert!((log_z - z.ln()).abs() < 1e-12);
+        assert_eq!(g.log_partition(gamma).unwrap(), log_z);
+        assert_eq!(
+            soft_shortest_path_node_marginals(4, &edges, &nodes[..3], gamma),
+            Err(Error::NodeCostLength {
+                len: 3,
+                expected: 4
+            })
+        );
+        assert_eq!(
+            g.node_marginals(gamma, &[0.0, f64::NAN, 0.0, 0.0])
+                .map_err(|e| e.to_string()),
+            Err("node 1 has non-finite cost NaN".to_string())
+        );
+    }
+
+    #[test]
+    fn transit_probabilities_sum_path_probabilities() {
+        // Paths 1-2-4 (cost 2), 1-3-4 (cost 3) and 1-2-3-4 (cost 2.5) between source 1 and sink 4.
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 9.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 2,
+                to: 4,
+                cost: 1.0,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 1.0,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 0.5,
+            },
+        ];
+        let g = Graph::new(5, edges, 1, 4).unwrap();
+        let gamma = 0.7;
+        let w: Vec<f64> = [2.0, 3.0, 2.5]
+            .iter()
+            .map(|c: &f64| (-c / gamma).exp())
+            .collect();
+        let z: f64 = w.iter().sum();
+        let pairs = [(2, 3), (1, 4), (3, 3), (2, 4), (3, 2), (0, 4), (2, 2)];
+        let (v, p) = g.transit_probabilities(gamma, &pairs).unwrap();
+        assert!((v + gamma * z.ln()).abs() < 1e-12);
+        let (via2, via3) = ((w[0] + w[2]) / z, (w[1] + w[2]) / z);
+        let want = [w[2] / z, 1.0, via3, via2, 0.0, 0.0, via2];
+        for (p, want) in p.iter().zip(want) {
+            assert!((p - want).abs() < 1e-12, "{p} vs {want}");
+        }
+        assert_eq!(
+            g.transit_probabilities(gamma, &[(1, 5)]),
+            Err(Error::NodeOutOfBounds { node: 5, n: 5 })
+        );
+    }
+
+    #[test]
+    fn builder_matches_functions_and_graph() {
+        let edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 5.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 1.0,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 7.0,
+            },
+        ];
+        let op = SoftShortestPath::new(0.5);
+        assert_eq!(
+            op.compute(5, &edges),
+            soft_shortest_path_value(5, &edges, 0.5)
+        );
+        assert_eq!(
+            op.edge_marginals(5, &edges),
+            soft_shortest_path_edge_marginals(5, &edges, 0.5)
+        );
+
+        let g = Graph::new(5, edges.clone(), 1, 3).unwrap();
+        let inner = op.source(1).sink(3);
+        assert_eq!(inner.compute(5, &edges), g.value(0.5));
+        assert_eq!(inner.edge_marginals(5, &edges), g.edge_marginals(0.5));
+        assert_eq!(
+            op.source(4).compute(5, &edges),
+            Err(Error::InvalidEndpoints {
+                source_node: 4,
+                sink_node: 4,
+                n: 5
+            })
+        );
+    }
+
+    #[test]
+    fn workspace_reuse_matches_free_functions() {
+        let big = [
+            Edge {
+                from: 0,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 0.5,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 1.0,
+            },
+            Edge {
+                from: 2,
+                to: 5,
+                cost: 0.1,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 0.3,
+            },
+            Edge {
+                from: 3,
+                to: 5,
+                cost: 0.7,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 0.2,
+            },
+        ];
+        let small = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 2.0,
+            },
+        ];
+        let mut ws = Workspace::new();
+        for (n, edges) in [(6, &big[..]), (2, &small[..]), (6, &big[..])] {
+            assert_eq!(
+                ws.value(n, edges, 0.4),
+                soft_shortest_path_value(n, edges, 0.4)
+            );
+            let (v, p) = ws.edge_marginals(n, edges, 0.4).unwrap();
+            assert_eq!(
+                (v, p.to_vec()),
+                soft_shortest_path_edge_marginals(n, edges, 0.4).unwrap()
+            );
+        }
+        assert_eq!(ws.value(6, &big[..3], 0.4), Err(Error::NoPath));
+    }
+
+    #[test]
+    fn warm_start_reuses_untouched_potentials() {
+        let mut edges = vec![
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 0.5,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 0.3,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 0.1,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 0.8,
+            },
+            Edge {
+                from: 3,
+                to: 5,
+                cost: 0.2,
+            },
+            Edge {
+                from: 4,
+                to: 5,
+                cost: 0.4,
+            },
+        ];
+        let mut ws = Workspace::new();
+        assert!(ws.warm_start(&edges).is_none());
+        let (_, before) = ws.edge_marginals(6, &edges, 0.5).unwrap();
+        let before = before.to_vec();
+        let warm = ws.warm_start(&edges).unwrap();
+
+        // Exact with zero tolerance, whichever side of the graph moved.
+        for (k, delta) in [(0, 0.4), (6, -0.3), (3, 1e-9)] {
+            let mut moved = edges.clone();
+            moved[k].cost += delta;
+            let (v, p) = ws.warm_edge_marginals(6, &moved, 0.5, &warm, 0.0).unwrap();
+            let cold = soft_shortest_path_edge_marginals(6, &moved, 0.5).unwrap();
+            assert_eq!((v, p.to_vec()), cold);
+        }
+        // Changes within the tolerance reuse every potential.
+        edges[2].cost += 1e-9;
+        let (_, p) = ws.warm_edge_marginals(6, &edges, 0.5, &warm, 1e-6).unwrap();
+        assert!(p.iter().zip(&before).all(|(a, b)| (a - b).abs() < 1e-8));
+
+        assert_eq!(
+            ws.warm_edge_marginals(6, &edges, 0.4, &warm, 0.0),
+            Err(Error::WarmStartMismatch { n: 6, edges: 7 })
+        );
+        assert_eq!(
+            ws.warm_edge_marginals(6, &edges, 0.5, &warm, -1.0),
+            Err(Error::InvalidTolerance(-1.0))
+        );
+        ws.value(6, &edges, 0.5).unwrap();
+        assert!(ws.warm_start(&edges).is_none());
+    }
+
+    #[test]
+    fn non_finite_edge_costs_report_the_edge() {
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 2,
+                cost: f64::NAN,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: f64::INFINITY,
+            },
+        ];
+        let err = soft_shortest_path_value(3, &edges, 1.0).unwrap_err();
+        assert!(matches!(err, Error::NonFiniteCost { edge_idx: 1, cost } if cost.is_nan()));
+        let mut edges = edges;
+        edges[1].cost = 0.5;
+        assert_eq!(
+            soft_shortest_path_edge_marginals(3, &edges, 1.0),
+            Err(Error::NonFiniteCost {
+                edge_idx: 2,
+                cost: f64::INFINITY
+            })
+        );
+    }
+
+    #[test]
+    fn tiny_gamma_falls_back_to_hard_path() {
+        let edges = [
+            Edge {
+                from: 0,
+                to: 1,
+                cost: 1.0,
+            },
+            Edge {
+                from: 1,
+                to: 3,
+                cost: 2.0,
+            },
+            Edge {
+                from: 0,
+                to: 2,
+                cost: 0.5,
+            },
+            Edge {
+                from: 2,
+                to: 3,
+                cost: 3.0,
+            },
+            Edge {
+                from: 3,
+                to: 4,
+                cost: 1.0,
+            },
+        ];
+        let op = SoftShortestPath::new(1e-310);
+        assert_eq!(op.edge_marginals(5, &edges), Err(Error::NoPath));
+        let op = op.tiny_gamma(TinyGamma::Detect);
+        assert_eq!(op.compute(5, &edges), Ok(4.0));
+        assert_eq!(
+            op.edge_marginals(5, &edges),
+            Ok((4.0, vec![1.0, 1.0, 0.0, 0.0, 1.0]))
+        );
+        assert_eq!(
+            op.sink(3).edge_marginals(5, &edges),
+            Ok((3.0, vec![1.0, 1.0, 0.0, 0.0, 0.0]))
+        );
+
+        let below = SoftShortestPath::new(0.01).tiny_gamma(TinyGamma::Below(0.1));
+        assert_eq!(below.compute(5, &edges), Ok(4.0));
+        assert_eq!(below.source(2).compute(5, &edges), Ok(4.0));
+        let unreachable = [Edge {
+            from: 1,
+            to: 2,
+            cost: 1.0,
+        }];
+        assert_eq!(op.compute(3, &unreachable), Err(Error::NoPath));
+    }
+
+    #[test]
+    fn double_double_keeps_deep_marginals_a_unit_flow() {
+        // A chain of 50 diamonds with costs ~1e5 and gamma 1e-3.
+        let c = |k: usize| 1e5 * (1.0 + (k * 7919 % 1009) as f64 / 1009.0);
+        let mut edges = Vec::new();
+        for d in 0..50 {
+            let (u, a, b, v) = (3 * d, 3 * d + 1, 3 * d + 2, 3 * d + 3);
+            edges.push(Edge {
+                from: u,
+                to: a,
+                cost: c(4 * d),
+            });
+            edges.push(Edge {
+                from: u,
+                to: b,
+                cost: c(4 * d + 1),
+            });
+            edges.push(Edge {
+                from: a,
+                to: v,
+                cost: c(4 * d + 2),
+            });
+            edges.push(Edge {
+                from: b,
+                to: v,
+                cost: c(4 * d + 2) - c(4 * d + 1) + c(4 * d),
+            });
+        }
+        let op = SoftShortestPath::new(1e-3);
+        let (v, p) = op.edge_marginals(151, &edges).unwrap();
+        assert!((p[0] + p[1] - 1.0).abs() > 1e-7);
+        let op = op.precision(Precision::DoubleDouble);
+        let (v_dd, p_dd) = op.edge_marginals(151, &edges).unwrap();
+        assert_eq!(
+            crate::invariants::check_unit_flow(151, &edges, &p_dd, 1e-12),
+            Ok(())
+        );
+        assert!((v - v_dd).abs() < 1e-8 * v.abs());
+        assert_eq!(op.compute(151, &edges), Ok(v_dd));
+
+        // Endpoints still apply.
+        let (v, p) = op.source(3).sink(6).edge_marginals(151, &edges).unwrap();
+        let plain = SoftShortestPath::new(1e-3).source(3).sink(6);
+        assert!((v - plain.compute(151, &edges).unwrap()).abs() < 1e-9);
+        assert!((p[4] + p[5] - 1.0).abs() < 1e-12 && p[0] == 0.0);
+    }
+
+    #[test]
+    fn many_tiny_incoming_terms_are_not_lost() {
+        // One free edge into the sink plus 10^5 parallel edges each carrying ~1e-16 of the
+        // mass: naive summation rounds every one of them away.
+        let gamma = 0.5;
+        let c = 36.84 * gamma;
+        let mut edges = vec![Edge {
+            from: 0,
+            to: 1,
+            cost: 0.0,
+        }];
+        edges.extend(
+            std::iter::repeat(Edge {
+                from: 0,
+                to: 1,
+                cost: c,
+            })
+            .take(100_000),
+        );
+        let (v, p) = soft_shortest_path_edge_marginals(2, &edges, gamma).unwrap();
+        let expected = -gamma * (100_000.0 * (-c / gamma).exp()).ln_1p();
+        // Naive summation returns exactly 0 here (off by 5e-12).
+        assert!(
+            (v - expected).abs() < 1e-15,
+            "v={} expected={}",
+            v,
+            expected
+        );
+        let total: f64 = p.iter().sum();
+        assert!((total - 1.0).abs() < 1e-12, "total={}", total);
+    }
+
     proptest! {
         #[test]
         fn edge_marginals_are_probabilities_on_diamond(
@@ -314,7 +1853,7 @@ mod tests {
             ];
             let (_v, p) = soft_shortest_path_edge_marginals(n, &edges, gamma).unwrap();
             for &pe in &p {
-                prop_assert!(pe >= -1e-12 && pe <= 1.0 + 1e-12);
+                prop_assert!((-1e-12..=1.0 + 1e-12).contains(&pe));
             }
             // Outgoing from source should sum to 1 on this graph.
             let s = p[0] + p[2];
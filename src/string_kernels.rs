@@ -0,0 +1,596 @@
+//! String kernels computed by dynamic programming (Lodhi et al. 2002; Leslie et al. 2004).
+//!
+//! Sequences are symbol indices `0..alphabet`. Both kernels are parameterized by a
+//! learnable substitution matrix `sub` (row-major, `alphabet × alphabet`), and both
+//! return gradients w.r.t. that matrix.
+//!
+//! **Gap-weighted subsequence kernel** (soft-matching variant). For subsequence length
+//! `p` and decay \(\lambda\in(0,1]\):
+//! \[
+//! K_p(s,t) = \sum_{\mathbf i, \mathbf j}
+//!   \lambda^{\ell(\mathbf i)+\ell(\mathbf j)} \prod_{k=1}^{p} A_{s_{i_k}, t_{j_k}},
+//! \]
+//! where \(\mathbf i,\mathbf j\) range over increasing index tuples of length `p` and
+//! \(\ell(\mathbf i)=i_p-i_1+1\) is the spanned length. With \(A=I\) this is the classic
+//! string subsequence kernel.
+//!
+//! **Mismatch kernel**. For k-mers \(\alpha\) of `s` and \(\alpha'\) of `t`:
+//! \[
+//! K(s,t) = \sum_{\alpha,\alpha'} \sum_{\beta \in N_m(\alpha)\cap N_m(\alpha')}
+//!   \prod_{l=1}^{k} B_{\alpha_l,\beta_l} B_{\alpha'_l,\beta_l},
+//! \]
+//! where \(N_m(\alpha)\) is the set of k-mers within Hamming distance `m` of \(\alpha\).
+//! With \(B\) all ones this is the (k,m)-mismatch kernel: the number of shared
+//! mismatch neighbours. The inner sum over \(\beta\) is a small DP over positions whose
+//! state is the pair of mismatch counts.
+
+/// Errors for string kernels.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Decay \(\lambda\) must lie in `(0, 1]`.
+    #[error("decay lambda must lie in (0, 1], got {0}")]
+    InvalidDecay(f64),
+    /// Subsequence / k-mer length must be at least 1.
+    #[error("subsequence length must be at least 1")]
+    ZeroLength,
+    /// Substitution matrix shape mismatch.
+    #[error("substitution matrix has length {len}, expected {alphabet}*{alphabet}={expected}")]
+    InvalidSubstitutionShape {
+        /// The provided `sub` slice length.
+        len: usize,
+        /// Alphabet size.
+        alphabet: usize,
+        /// `alphabet*alphabet`, included explicitly for readability.
+        expected: usize,
+    },
+    /// Substitution matrix entries must be finite.
+    #[error("substitution matrix entry {idx} is not finite: {value}")]
+    NonFiniteSubstitution {
+        /// Flat index of the offending entry.
+        idx: usize,
+        /// The offending value.
+        value: f64,
+    },
+    /// A symbol is outside `0..alphabet`.
+    #[error("symbol {symbol} at position {position} is out of range for alphabet size {alphabet}")]
+    SymbolOutOfRange {
+        /// Position in the offending sequence.
+        position: usize,
+        /// The offending symbol.
+        symbol: usize,
+        /// Alphabet size.
+        alphabet: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn validate_sub(sub: &[f64], alphabet: usize) -> Result<()> {
+    if sub.len() != alphabet * alphabet {
+        return Err(Error::InvalidSubstitutionShape {
+            len: sub.len(),
+            alphabet,
+            expected: alphabet * alphabet,
+        });
+    }
+    if let Some((idx, &value)) = sub.iter().enumerate().find(|(_, v)| !v.is_finite()) {
+        return Err(Error::NonFiniteSubstitution { idx, value });
+    }
+    Ok(())
+}
+
+fn validate_symbols(s: &[usize], alphabet: usize) -> Result<()> {
+    for (position, &symbol) in s.iter().enumerate() {
+        if symbol >= alphabet {
+            return Err(Error::SymbolOutOfRange {
+                position,
+                symbol,
+                alphabet,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Forward tables for the gap-weighted kernel.
+///
+/// `kp[q]` holds \(K'_q\) over prefix lengths, shape `(n+1, m+1)` row-major. The
+/// \(K''_q\) tables are not needed by the reverse sweep and are not kept.
+struct GapTables {
+    kp: Vec<Vec<f64>>,
+    value: f64,
+}
+
+fn gap_weighted_forward(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    p: usize,
+    lambda: f64,
+) -> GapTables {
+    let n = s.len();
+    let m = t.len();
+    let w = m + 1;
+    let l2 = lambda * lambda;
+
+    let mut kp = vec![vec![0.0; (n + 1) * w]; p];
+    let mut kpp = vec![vec![0.0; (n + 1) * w]; p];
+    kp[0].iter_mut().for_each(|v| *v = 1.0);
+
+    for q in 1..p {
+        for i in 1..=n {
+            for j in 1..=m {
+                let a = sub[s[i - 1] * alphabet + t[j - 1]];
+                let v =
+                    lambda * kpp[q][i * w + (j - 1)] + l2 * a * kp[q - 1][(i - 1) * w + (j - 1)];
+                kpp[q][i * w + j] = v;
+                kp[q][i * w + j] = lambda * kp[q][(i - 1) * w + j] + v;
+            }
+        }
+    }
+
+    let mut value = 0.0;
+    for i in 1..=n {
+        for j in 1..=m {
+            let a = sub[s[i - 1] * alphabet + t[j - 1]];
+            value += l2 * a * kp[p - 1][(i - 1) * w + (j - 1)];
+        }
+    }
+    GapTables { kp, value }
+}
+
+fn validate_gap_weighted(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    p: usize,
+    lambda: f64,
+) -> Result<()> {
+    if !(lambda > 0.0 && lambda <= 1.0) {
+        return Err(Error::InvalidDecay(lambda));
+    }
+    if p == 0 {
+        return Err(Error::ZeroLength);
+    }
+    validate_sub(sub, alphabet)?;
+    validate_symbols(s, alphabet)?;
+    validate_symbols(t, alphabet)
+}
+
+/// Gap-weighted subsequence kernel \(K_p(s,t)\) with soft symbol matching.
+///
+/// `sub` is the `alphabet × alphabet` substitution (similarity) matrix, row-major;
+/// pass the identity for exact matching. Runs in \(O(p\,|s|\,|t|)\).
+pub fn gap_weighted_kernel(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    p: usize,
+    lambda: f64,
+) -> Result<f64> {
+    validate_gap_weighted(s, t, sub, alphabet, p, lambda)?;
+    Ok(gap_weighted_forward(s, t, sub, alphabet, p, lambda).value)
+}
+
+/// Gap-weighted subsequence kernel and its gradient w.r.t. the substitution matrix.
+///
+/// Returns `(value, grad_sub)` where `grad_sub.len() == alphabet * alphabet`. The gradient
+/// is obtained by a reverse sweep over the forward tables (same asymptotic cost).
+pub fn gap_weighted_kernel_grad(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    p: usize,
+    lambda: f64,
+) -> Result<(f64, Vec<f64>)> {
+    validate_gap_weighted(s, t, sub, alphabet, p, lambda)?;
+    let tables = gap_weighted_forward(s, t, sub, alphabet, p, lambda);
+
+    let n = s.len();
+    let m = t.len();
+    let w = m + 1;
+    let l2 = lambda * lambda;
+
+    let mut grad = vec![0.0; alphabet * alphabet];
+    // Adjoints of kp / kpp.
+    let mut gp = vec![vec![0.0; (n + 1) * w]; p];
+    let mut gpp = vec![0.0; (n + 1) * w];
+
+    for i in 1..=n {
+        for j in 1..=m {
+            let ab = s[i - 1] * alphabet + t[j - 1];
+            grad[ab] += l2 * tables.kp[p - 1][(i - 1) * w + (j - 1)];
+            gp[p - 1][(i - 1) * w + (j - 1)] += l2 * sub[ab];
+        }
+    }
+
+    for q in (1..p).rev() {
+        gpp.iter_mut().for_each(|v| *v = 0.0);
+        let (lower, upper) = gp.split_at_mut(q);
+        let gq = &mut upper[0];
+        let gq1 = &mut lower[q - 1];
+        for i in (1..=n).rev() {
+            for j in (1..=m).rev() {
+                let g = gq[i * w + j];
+                gq[(i - 1) * w + j] += lambda * g;
+                gpp[i * w + j] += g;
+
+                let gv = gpp[i * w + j];
+                let ab = s[i - 1] * alphabet + t[j - 1];
+                gpp[i * w + (j - 1)] += lambda * gv;
+                grad[ab] += gv * l2 * tables.kp[q - 1][(i - 1) * w + (j - 1)];
+                gq1[(i - 1) * w + (j - 1)] += gv * l2 * sub[ab];
+            }
+        }
+    }
+
+    Ok((tables.value, grad))
+}
+
+/// Normalized gap-weighted kernel \(K(s,t)/\sqrt{K(s,s)K(t,t)}\).
+///
+/// Returns `0.0` when either self-similarity is zero (e.g. a string shorter than `p`).
+pub fn gap_weighted_kernel_normalized(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    p: usize,
+    lambda: f64,
+) -> Result<f64> {
+    let st = gap_weighted_kernel(s, t, sub, alphabet, p, lambda)?;
+    let ss = gap_weighted_kernel(s, s, sub, alphabet, p, lambda)?;
+    let tt = gap_weighted_kernel(t, t, sub, alphabet, p, lambda)?;
+    let denom = (ss * tt).sqrt();
+    if denom > 0.0 {
+        Ok(st / denom)
+    } else {
+        Ok(0.0)
+    }
+}
+
+fn validate_mismatch(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    k: usize,
+) -> Result<()> {
+    if k == 0 {
+        return Err(Error::ZeroLength);
+    }
+    validate_sub(sub, alphabet)?;
+    validate_symbols(s, alphabet)?;
+    validate_symbols(t, alphabet)
+}
+
+/// Sum over \(\beta\) for a single k-mer pair, optionally accumulating the gradient.
+///
+/// State is `(a, b)`: mismatches of \(\beta\) so far vs `x` and vs `y`, each capped at `mm`.
+fn mismatch_pair(
+    x: &[usize],
+    y: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    mm: usize,
+    grad: Option<&mut [f64]>,
+) -> f64 {
+    let k = x.len();
+    let side = mm + 1;
+    let states = side * side;
+
+    // fwd[l] is the state distribution before position l.
+    let mut fwd = vec![vec![0.0; states]; k + 1];
+    fwd[0][0] = 1.0;
+    for l in 0..k {
+        let (cur, next) = fwd.split_at_mut(l + 1);
+        let (cur, next) = (&cur[l], &mut next[0]);
+        for a in 0..side {
+            for b in 0..side {
+                let f = cur[a * side + b];
+                if f == 0.0 {
+                    continue;
+                }
+                for c in 0..alphabet {
+                    let a2 = a + usize::from(c != x[l]);
+                    let b2 = b + usize::from(c != y[l]);
+                    if a2 > mm || b2 > mm {
+                        continue;
+                    }
+                    let wgt = sub[x[l] * alphabet + c] * sub[y[l] * alphabet + c];
+                    next[a2 * side + b2] += f * wgt;
+                }
+            }
+        }
+    }
+    let value: f64 = fwd[k].iter().sum();
+
+    if let Some(grad) = grad {
+        // bwd[s] is the total weight of completions from state s after position l.
+        let mut bwd = vec![1.0; states];
+        for l in (0..k).rev() {
+            let mut prev = vec![0.0; states];
+            for a in 0..side {
+                for b in 0..side {
+                    let f = fwd[l][a * side + b];
+                    for c in 0..alphabet {
+                        let a2 = a + usize::from(c != x[l]);
+                        let b2 = b + usize::from(c != y[l]);
+                        if a2 > mm || b2 > mm {
+                            continue;
+                        }
+                        let bx = sub[x[l] * alphabet + c];
+                        let by = sub[y[l] * alphabet + c];
+                        let g = bwd[a2 * side + b2];
+                        prev[a * side + b] += bx * by * g;
+                        grad[x[l] * alphabet + c] += f * g * by;
+                        grad[y[l] * alphabet + c] += f * g * bx;
+                    }
+                }
+            }
+            bwd = prev;
+        }
+    }
+    value
+}
+
+/// (k, m)-mismatch kernel with a learnable substitution matrix.
+///
+/// Counts (weighted) shared k-mer neighbours within `mismatches` substitutions. With
+/// `sub` set to all ones this is the classic mismatch kernel. Sequences shorter than `k`
+/// contribute no k-mers (value `0.0`).
+pub fn mismatch_kernel(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    k: usize,
+    mismatches: usize,
+) -> Result<f64> {
+    validate_mismatch(s, t, sub, alphabet, k)?;
+    let mm = mismatches.min(k);
+    let mut value = 0.0;
+    for x in s.windows(k) {
+        for y in t.windows(k) {
+            value += mismatch_pair(x, y, sub, alphabet, mm, None);
+        }
+    }
+    Ok(value)
+}
+
+/// Mismatch kernel and its gradient w.r.t. the substitution matrix.
+///
+/// Returns `(value, grad_sub)` where `grad_sub.len() == alphabet * alphabet`.
+pub fn mismatch_kernel_grad(
+    s: &[usize],
+    t: &[usize],
+    sub: &[f64],
+    alphabet: usize,
+    k: usize,
+    mismatches: usize,
+) -> Result<(f64, Vec<f64>)> {
+    validate_mismatch(s, t, sub, alphabet, k)?;
+    let mm = mismatches.min(k);
+    let mut grad = vec![0.0; alphabet * alphabet];
+    let mut value = 0.0;
+    for x in s.windows(k) {
+        for y in t.windows(k) {
+            value += mismatch_pair(x, y, sub, alphabet, mm, Some(&mut grad));
+        }
+    }
+    Ok((value, grad))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn identity(k: usize) -> Vec<f64> {
+        let mut a = vec![0.0; k * k];
+        for i in 0..k {
+            a[i * k + i] = 1.0;
+        }
+        a
+    }
+
+    fn index_tuples(n: usize, p: usize) -> Vec<Vec<usize>> {
+        fn rec(start: usize, n: usize, p: usize, cur: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+            if cur.len() == p {
+                out.push(cur.clone());
+                return;
+            }
+            for i in start..n {
+                cur.push(i);
+                rec(i + 1, n, p, cur, out);
+                cur.pop();
+            }
+        }
+        let mut out = Vec::new();
+        rec(0, n, p, &mut Vec::new(), &mut out);
+        out
+    }
+
+    fn brute_gap_weighted(
+        s: &[usize],
+        t: &[usize],
+        sub: &[f64],
+        k: usize,
+        p: usize,
+        lambda: f64,
+    ) -> f64 {
+        let mut total = 0.0;
+        for is in index_tuples(s.len(), p) {
+            for js in index_tuples(t.len(), p) {
+                let span = (is[p - 1] - is[0] + 1) + (js[p - 1] - js[0] + 1);
+                let mut w = lambda.powi(span as i32);
+                for q in 0..p {
+                    w *= sub[s[is[q]] * k + t[js[q]]];
+                }
+                total += w;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn gap_weighted_matches_brute_force_enumeration() {
+        let s = [0, 1, 2, 1];
+        let t = [1, 0, 2, 2, 1];
+        let sub = [
+            1.0, 0.3, 0.1, //
+            0.3, 1.0, 0.2, //
+            0.1, 0.2, 1.0, //
+        ];
+        for p in 1..=3 {
+            let v = gap_weighted_kernel(&s, &t, &sub, 3, p, 0.7).unwrap();
+            let b = brute_gap_weighted(&s, &t, &sub, 3, p, 0.7);
+            assert!((v - b).abs() < 1e-12, "p={} dp={} brute={}", p, v, b);
+        }
+    }
+
+    #[test]
+    fn classic_lodhi_example_cat_car() {
+        // Lodhi et al.: K_2("cat","car") = λ^4 (only the "ca" subsequence is shared).
+        let (c, a, t, r) = (0, 1, 2, 3);
+        let lambda = 0.5;
+        let v = gap_weighted_kernel(&[c, a, t], &[c, a, r], &identity(4), 4, 2, lambda).unwrap();
+        assert!((v - lambda.powi(4)).abs() < 1e-15, "v={}", v);
+    }
+
+    #[test]
+    fn gap_weighted_grad_matches_finite_differences() {
+        let s = [0, 2, 1, 1, 0];
+        let t = [2, 1, 0, 1];
+        let mut sub = vec![
+            0.9, 0.2, 0.4, //
+            0.1, 1.1, 0.3, //
+            0.5, 0.2, 0.8, //
+        ];
+        let (v, g) = gap_weighted_kernel_grad(&s, &t, &sub, 3, 3, 0.6).unwrap();
+        assert!((v - gap_weighted_kernel(&s, &t, &sub, 3, 3, 0.6).unwrap()).abs() < 1e-15);
+        let h = 1e-6;
+        for idx in 0..sub.len() {
+            let orig = sub[idx];
+            sub[idx] = orig + h;
+            let up = gap_weighted_kernel(&s, &t, &sub, 3, 3, 0.6).unwrap();
+            sub[idx] = orig - h;
+            let dn = gap_weighted_kernel(&s, &t, &sub, 3, 3, 0.6).unwrap();
+            sub[idx] = orig;
+            let fd = (up - dn) / (2.0 * h);
+            assert!(
+                (g[idx] - fd).abs() < 1e-7,
+                "idx={} analytic={} fd={}",
+                idx,
+                g[idx],
+                fd
+            );
+        }
+    }
+
+    fn brute_mismatch(s: &[usize], t: &[usize], alphabet: usize, k: usize, mm: usize) -> f64 {
+        let hamming = |a: &[usize], b: &[usize]| a.iter().zip(b).filter(|(x, y)| x != y).count();
+        let mut total = 0.0;
+        let mut beta = vec![0usize; k];
+        let count = alphabet.pow(k as u32);
+        for code in 0..count {
+            let mut c = code;
+            for slot in beta.iter_mut() {
+                *slot = c % alphabet;
+                c /= alphabet;
+            }
+            let fs = s.windows(k).filter(|x| hamming(x, &beta) <= mm).count();
+            let ft = t.windows(k).filter(|y| hamming(y, &beta) <= mm).count();
+            total += (fs * ft) as f64;
+        }
+        total
+    }
+
+    #[test]
+    fn mismatch_kernel_with_unit_weights_counts_shared_neighbours() {
+        let s = [0, 1, 2, 3, 1, 0];
+        let t = [3, 1, 1, 0, 2];
+        let ones = vec![1.0; 16];
+        for k in 1..=3 {
+            for mm in 0..=k {
+                let v = mismatch_kernel(&s, &t, &ones, 4, k, mm).unwrap();
+                let b = brute_mismatch(&s, &t, 4, k, mm);
+                assert!(
+                    (v - b).abs() < 1e-9,
+                    "k={} m={} dp={} brute={}",
+                    k,
+                    mm,
+                    v,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mismatch_grad_matches_finite_differences() {
+        let s = [0, 1, 2, 2];
+        let t = [2, 1, 0];
+        let mut sub = vec![
+            1.0, 0.4, 0.2, //
+            0.3, 0.9, 0.5, //
+            0.1, 0.6, 1.2, //
+        ];
+        let (_, g) = mismatch_kernel_grad(&s, &t, &sub, 3, 2, 1).unwrap();
+        let h = 1e-6;
+        for idx in 0..sub.len() {
+            let orig = sub[idx];
+            sub[idx] = orig + h;
+            let up = mismatch_kernel(&s, &t, &sub, 3, 2, 1).unwrap();
+            sub[idx] = orig - h;
+            let dn = mismatch_kernel(&s, &t, &sub, 3, 2, 1).unwrap();
+            sub[idx] = orig;
+            let fd = (up - dn) / (2.0 * h);
+            assert!(
+                (g[idx] - fd).abs() < 1e-7,
+                "idx={} analytic={} fd={}",
+                idx,
+                g[idx],
+                fd
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_inputs() {
+        let sub = identity(2);
+        assert_eq!(
+            gap_weighted_kernel(&[0], &[1], &sub, 2, 1, 0.0),
+            Err(Error::InvalidDecay(0.0))
+        );
+        assert_eq!(
+            gap_weighted_kernel(&[0, 2], &[1], &sub, 2, 1, 0.5),
+            Err(Error::SymbolOutOfRange {
+                position: 1,
+                symbol: 2,
+                alphabet: 2
+            })
+        );
+        assert!(matches!(
+            mismatch_kernel(&[0], &[1], &sub[..3], 2, 1, 0),
+            Err(Error::InvalidSubstitutionShape { .. })
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn normalized_gap_weighted_is_bounded_by_one(
+            s in prop::collection::vec(0usize..3, 1..8),
+            t in prop::collection::vec(0usize..3, 1..8),
+            lambda in 0.1f64..1.0,
+        ) {
+            let v = gap_weighted_kernel_normalized(&s, &t, &identity(3), 3, 2, lambda).unwrap();
+            prop_assert!((-1e-12..=1.0 + 1e-12).contains(&v), "v={}", v);
+        }
+    }
+}
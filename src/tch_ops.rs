@@ -0,0 +1,192 @@
+//! `tch` (libtorch) forward/backward functions (feature `tch`).
+//!
+//! The core slice-based kernels are unchanged; these functions move tensor data to host
+//! `f64` buffers, run the kernels, and return tensors with the input's kind and device.
+//!
+//! Two layers are provided:
+//!
+//! - explicit `*_forward` / `*_backward` pairs, for wiring into a custom autograd
+//!   function or a hand-written training loop (the forward returns the saved tensor the
+//!   backward needs);
+//! - `soft_dtw_cost` / `soft_shortest_path`, which return a value that participates in
+//!   torch autograd directly. They use the surrogate
+//!   \(v + \langle x, g\rangle - \langle \operatorname{sg}(x), g\rangle\), whose value is
+//!   exactly \(v\) and whose gradient w.r.t. \(x\) is the analytic gradient \(g\).
+//!
+//! A tensor of the wrong rank (a non-2D cost matrix, a non-1D edge-cost vector) is a
+//! `TensorRank` error of the operator's module.
+
+use tch::{Device, Kind, Tensor};
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::{self, Edge};
+
+fn host_values(t: &Tensor) -> Vec<f64> {
+    let flat = t
+        .detach()
+        .to_device(Device::Cpu)
+        .to_kind(Kind::Double)
+        .contiguous()
+        .reshape([-1]);
+    Vec::<f64>::try_from(&flat).expect("flat f64 CPU tensor converts to Vec<f64>")
+}
+
+fn tensor_like(values: &[f64], shape: &[i64], like: &Tensor) -> Tensor {
+    Tensor::from_slice(values)
+        .reshape(shape)
+        .to_kind(like.kind())
+        .to_device(like.device())
+}
+
+fn with_costs(edges: &[(usize, usize)], costs: &[f64]) -> Vec<Edge> {
+    edges
+        .iter()
+        .zip(costs)
+        .map(|(&(from, to), &cost)| Edge { from, to, cost })
+        .collect()
+}
+
+/// Soft-DTW forward on a `[n, m]` cost tensor.
+///
+/// Returns `(value, alignment)`: a scalar value tensor and the `[n, m]` expected alignment
+/// to save for [`soft_dtw_backward`]. A tensor that is not 2D is a
+/// [`soft_dtw::Error::TensorRank`] error.
+pub fn soft_dtw_forward(cost: &Tensor, gamma: f64) -> soft_dtw::Result<(Tensor, Tensor)> {
+    let size = cost.size();
+    let [n, m] = size[..] else {
+        return Err(soft_dtw::Error::TensorRank {
+            rank: size.len(),
+            expected: 2,
+        });
+    };
+    let (n, m) = (n as usize, m as usize);
+    let (v, e) = soft_dtw::soft_dtw_alignment(&host_values(cost), n, m, gamma)?;
+    let value = tensor_like(&[v], &[], cost);
+    let alignment = tensor_like(&e, &[n as i64, m as i64], cost);
+    Ok((value, alignment))
+}
+
+/// Soft-DTW backward: `grad_cost = alignment * grad_output`.
+pub fn soft_dtw_backward(alignment: &Tensor, grad_output: &Tensor) -> Tensor {
+    alignment * grad_output
+}
+
+/// Soft-DTW value of a `[n, m]` cost tensor that participates in torch autograd.
+pub fn soft_dtw_cost(cost: &Tensor, gamma: f64) -> soft_dtw::Result<Tensor> {
+    let (value, alignment) = soft_dtw_forward(cost, gamma)?;
+    Ok(surrogate(value, cost, &alignment))
+}
+
+/// Soft shortest-path forward for `[edges.len()]` edge costs.
+///
+/// Returns `(value, marginals)`: a scalar value tensor and the `[edges.len()]` edge
+/// marginals to save for [`soft_shortest_path_backward`]. A vector whose length is not
+/// `edges.len()` is a [`soft_shortest_path::Error::EdgeCostLength`] error, a tensor that is
+/// not 1D a [`soft_shortest_path::Error::TensorRank`] error.
+pub fn soft_shortest_path_forward(
+    n: usize,
+    edges: &[(usize, usize)],
+    costs: &Tensor,
+    gamma: f64,
+) -> soft_shortest_path::Result<(Tensor, Tensor)> {
+    let size = costs.size();
+    let [len] = size[..] else {
+        return Err(soft_shortest_path::Error::TensorRank {
+            rank: size.len(),
+            expected: 1,
+        });
+    };
+    let len = len as usize;
+    if len != edges.len() {
+        return Err(soft_shortest_path::Error::EdgeCostLength {
+            len,
+            expected: edges.len(),
+        });
+    }
+    let es = with_costs(edges, &host_values(costs));
+    let (v, p) = soft_shortest_path::soft_shortest_path_edge_marginals(n, &es, gamma)?;
+    let value = tensor_like(&[v], &[], costs);
+    let marginals = tensor_like(&p, &[edges.len() as i64], costs);
+    Ok((value, marginals))
+}
+
+/// Soft shortest-path backward: `grad_costs = marginals * grad_output`.
+pub fn soft_shortest_path_backward(marginals: &Tensor, grad_output: &Tensor) -> Tensor {
+    marginals * grad_output
+}
+
+/// Soft shortest-path value that participates in torch autograd.
+pub fn soft_shortest_path(
+    n: usize,
+    edges: &[(usize, usize)],
+    costs: &Tensor,
+    gamma: f64,
+) -> soft_shortest_path::Result<Tensor> {
+    let (value, marginals) = soft_shortest_path_forward(n, edges, costs, gamma)?;
+    Ok(surrogate(value, costs, &marginals))
+}
+
+/// `value + <x, g> - <sg(x), g>`: value `value`, gradient `g` w.r.t. `x`.
+fn surrogate(value: Tensor, x: &Tensor, g: &Tensor) -> Tensor {
+    let live = (x * g).sum(None::<Kind>);
+    let frozen = (x.detach() * g).sum(None::<Kind>);
+    value + live - frozen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn soft_dtw_autograd_gradient_is_the_alignment() {
+        let flat = [0.3, 1.2, 0.4, 0.8, 0.1, 0.7];
+        let cost = Tensor::from_slice(&flat)
+            .reshape([2, 3])
+            .set_requires_grad(true);
+        let v = soft_dtw_cost(&cost, 0.5).unwrap();
+        v.backward();
+
+        let (expected, e) = soft_dtw::soft_dtw_alignment(&flat, 2, 3, 0.5).unwrap();
+        assert!((v.double_value(&[]) - expected).abs() < 1e-12);
+        for (g, e) in host_values(&cost.grad()).iter().zip(&e) {
+            assert!((g - e).abs() < 1e-12, "grad={} expected={}", g, e);
+        }
+    }
+
+    #[test]
+    fn explicit_backward_scales_by_grad_output() {
+        let edges = [(0, 1), (1, 3), (0, 2), (2, 3)];
+        let costs = Tensor::from_slice(&[1.0f64, 2.0, 3.0, 4.0]);
+        let (_, p) = soft_shortest_path_forward(4, &edges, &costs, 0.5).unwrap();
+        let g = soft_shortest_path_backward(&p, &Tensor::from(2.0f64));
+        for (g, p) in host_values(&g).iter().zip(host_values(&p)) {
+            assert!((g - 2.0 * p).abs() < 1e-12);
+        }
+        assert_eq!(
+            soft_shortest_path_forward(3, &[(0, 1)], &Tensor::from_slice(&[1.0f64]), 0.5)
+                .unwrap_err(),
+            soft_shortest_path::Error::NoPath
+        );
+        assert_eq!(
+            soft_shortest_path_forward(4, &edges, &Tensor::from_slice(&[1.0f64]), 0.5).unwrap_err(),
+            soft_shortest_path::Error::EdgeCostLength {
+                len: 1,
+                expected: 4
+            }
+        );
+        assert_eq!(
+            soft_shortest_path_forward(4, &edges, &costs.reshape([2, 2]), 0.5).unwrap_err(),
+            soft_shortest_path::Error::TensorRank {
+                rank: 2,
+                expected: 1
+            }
+        );
+        assert_eq!(
+            soft_dtw_forward(&costs, 0.5).unwrap_err(),
+            soft_dtw::Error::TensorRank {
+                rank: 1,
+                expected: 2
+            }
+        );
+    }
+}
@@ -0,0 +1,372 @@
+//! Soft shortest paths with time-dependent edge costs, via a time-expanded DAG.
+//!
+//! Schedules and routes often have costs that depend on *when* an edge is taken (rush
+//! hours, timetabled services, tariffs). With time discretized into slots `0..horizon`, a
+//! [`TimedEdge`] `u -> v` taking `duration` slots has one cost per departure slot
+//! (`+inf` where it cannot be taken). The time-expanded graph has a node `(v, t)` per base
+//! node and slot, and:
+//!
+//! - a move `(u, t) -> (v, t + duration)` per edge and departure slot that arrives within
+//!   the horizon, costing that slot's cost;
+//! - optionally, a wait `(v, t) -> (v, t + 1)` at every node but the target;
+//! - an arrival `(target, t) -> sink` of cost 0 per slot.
+//!
+//! Node `(v, t)` is numbered `t * n + v`, so every edge goes forward in time and the result
+//! is a DAG even when the base graph has cycles. Paths start at `(source, 0)`.
+//! [`TimeExpandedGraph::marginals`] runs the soft shortest path on it and aggregates the
+//! marginals back: per base edge, per departure slot, per wait, and the arrival-time
+//! distribution at the target.
+//!
+//! ```
+//! use structop::time_expanded::{TimeExpandedGraph, TimedEdge};
+//!
+//! // 0 -> 1 directly (slow at first, cheap later), or via 2.
+//! let edges = vec![
+//!     TimedEdge { from: 0, to: 1, duration: 1, costs: vec![5.0, 5.0, 1.0, 1.0] },
+//!     TimedEdge { from: 0, to: 2, duration: 1, costs: vec![1.0; 4] },
+//!     TimedEdge { from: 2, to: 1, duration: 2, costs: vec![1.0; 4] },
+//! ];
+//! let graph = TimeExpandedGraph::new(3, 4, edges).unwrap().wait_cost(0.5);
+//! let m = graph.marginals(0, 1, 0.05).unwrap();
+//! // Waiting two slots for the cheap direct edge ties with the detour via 2 (cost 2 each).
+//! assert!((m.edges[0] - 0.5).abs() < 1e-6 && (m.edges[2] - 0.5).abs() < 1e-6);
+//! assert!((m.arrivals.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+//! ```
+
+use crate::soft_shortest_path::{self, Edge, Graph};
+
+/// Errors for time-expanded graphs.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// There must be at least one node and one time slot.
+    #[error("graph needs at least one node and one time slot, got n={n}, horizon={horizon}")]
+    Empty {
+        /// Number of base nodes.
+        n: usize,
+        /// Number of time slots.
+        horizon: usize,
+    },
+    /// A node index is out of bounds.
+    #[error("node {node} is out of bounds for n={n}")]
+    NodeOutOfBounds {
+        /// The offending node.
+        node: usize,
+        /// Number of base nodes.
+        n: usize,
+    },
+    /// An edge takes no time.
+    #[error("edge {edge} has zero duration")]
+    ZeroDuration {
+        /// Index of the edge.
+        edge: usize,
+    },
+    /// An edge does not have one cost per time slot.
+    #[error("edge {edge} has {len} costs, expected {expected}")]
+    CostLength {
+        /// Index of the edge.
+        edge: usize,
+        /// Number of costs given.
+        len: usize,
+        /// The horizon.
+        expected: usize,
+    },
+    /// A cost is NaN or `-inf` (`+inf` marks a slot where the edge is unavailable).
+    #[error("edge {edge} has invalid cost {cost} at time {time}")]
+    InvalidCost {
+        /// Index of the edge.
+        edge: usize,
+        /// Departure slot.
+        time: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// The waiting cost must be finite.
+    #[error("wait cost must be finite, got {0}")]
+    InvalidWaitCost(f64),
+    /// Error from the soft shortest path on the expanded graph.
+    #[error(transparent)]
+    SoftShortestPath(#[from] soft_shortest_path::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Base edge with one cost per departure slot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedEdge {
+    /// Source node.
+    pub from: usize,
+    /// Destination node.
+    pub to: usize,
+    /// Travel time in slots (at least 1).
+    pub duration: usize,
+    /// Cost of departing at each slot `0..horizon` (`+inf` if unavailable).
+    pub costs: Vec<f64>,
+}
+
+/// What an edge of the expanded graph stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Base edge `edge` taken at slot `depart`.
+    Move {
+        /// Index of the base edge.
+        edge: usize,
+        /// Departure slot.
+        depart: usize,
+    },
+    /// Waiting at `node` from slot `time` to `time + 1`.
+    Wait {
+        /// Base node.
+        node: usize,
+        /// Slot the wait starts at.
+        time: usize,
+    },
+    /// Reaching the target at slot `time`.
+    Arrive {
+        /// Arrival slot.
+        time: usize,
+    },
+}
+
+/// Soft shortest-path value and marginals aggregated back to the base graph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeExpandedMarginals {
+    /// Soft shortest-path value.
+    pub value: f64,
+    /// Expected number of times each base edge is taken.
+    pub edges: Vec<f64>,
+    /// Probability of taking each base edge at each departure slot, row-major
+    /// `edges × horizon`.
+    pub departures: Vec<f64>,
+    /// Probability of waiting at each node at each slot, row-major `n × horizon`.
+    pub waits: Vec<f64>,
+    /// Probability of reaching the target at each slot.
+    pub arrivals: Vec<f64>,
+}
+
+/// Base graph with time-dependent edge costs over a discrete horizon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeExpandedGraph {
+    n: usize,
+    horizon: usize,
+    edges: Vec<TimedEdge>,
+    wait_cost: Option<f64>,
+}
+
+impl TimeExpandedGraph {
+    /// Validates `edges` over `n` base nodes and `horizon` slots; waiting is not allowed
+    /// until [`wait_cost`](Self::wait_cost) is set.
+    pub fn new(n: usize, horizon: usize, edges: Vec<TimedEdge>) -> Result<Self> {
+        if n == 0 || horizon == 0 {
+            return Err(Error::Empty { n, horizon });
+        }
+        for (k, e) in edges.iter().enumerate() {
+            for node in [e.from, e.to] {
+                check_node(node, n)?;
+            }
+            if e.duration == 0 {
+                return Err(Error::ZeroDuration { edge: k });
+            }
+            if e.costs.len() != horizon {
+                return Err(Error::CostLength {
+                    edge: k,
+                    len: e.costs.len(),
+                    expected: horizon,
+                });
+            }
+            if let Some((time, &cost)) = e
+                .costs
+                .iter()
+                .enumerate()
+                .find(|(_, c)| c.is_nan() || **c == f64::NEG_INFINITY)
+            {
+                return Err(Error::InvalidCost {
+                    edge: k,
+                    time,
+                    cost,
+                });
+            }
+        }
+        Ok(Self {
+            n,
+            horizon,
+            edges,
+            wait_cost: None,
+        })
+    }
+
+    /// Allows waiting one slot at any node for `cost` (validated when expanding).
+    pub fn wait_cost(mut self, cost: f64) -> Self {
+        self.wait_cost = Some(cost);
+        self
+    }
+
+    /// Number of time slots.
+    pub fn horizon(&self) -> usize {
+        self.horizon
+    }
+
+    /// The expanded DAG from `(source, 0)` to a sink reached from the target at any slot,
+    /// and what each of its edges stands for.
+    pub fn expand(&self, source: usize, target: usize) -> Result<(Graph, Vec<Origin>)> {
+        check_node(source, self.n)?;
+        check_node(target, self.n)?;
+        if let Some(c) = self.wait_cost.filter(|c| !c.is_finite()) {
+            return Err(Error::InvalidWaitCost(c));
+        }
+        let (n, h) = (self.n, self.horizon);
+        let id = |v: usize, t: usize| t * n + v;
+        let sink = n * h;
+        let mut edges = Vec::new();
+        let mut origins = Vec::new();
+        for (k, e) in self.edges.iter().enumerate() {
+            for t in 0..h.saturating_sub(e.duration) {
+                if e.costs[t].is_finite() {
+                    edges.push(Edge {
+                        from: id(e.from, t),
+                        to: id(e.to, t + e.duration),
+                        cost: e.costs[t],
+                    });
+                    origins.push(Origin::Move { edge: k, depart: t });
+                }
+            }
+        }
+        if let Some(cost) = self.wait_cost {
+            for v in (0..n).filter(|&v| v != target) {
+                for t in 0..h - 1 {
+                    edges.push(Edge {
+                        from: id(v, t),
+                        to: id(v, t + 1),
+                        cost,
+                    });
+                    origins.push(Origin::Wait { node: v, time: t });
+                }
+            }
+        }
+        for t in 0..h {
+            edges.push(Edge {
+                from: id(target, t),
+                to: sink,
+                cost: 0.0,
+            });
+            origins.push(Origin::Arrive { time: t });
+        }
+        Ok((Graph::new(sink + 1, edges, id(source, 0), sink)?, origins))
+    }
+
+    /// Soft shortest path from `source` at slot 0 to `target` at any slot, with marginals
+    /// aggregated back to the base graph.
+    pub fn marginals(
+        &self,
+        source: usize,
+        target: usize,
+        gamma: f64,
+    ) -> Result<TimeExpandedMarginals> {
+        let (graph, origins) = self.expand(source, target)?;
+        let (value, p) = graph.edge_marginals(gamma)?;
+        let h = self.horizon;
+        let mut out = TimeExpandedMarginals {
+            value,
+            edges: vec![0.0; self.edges.len()],
+            departures: vec![0.0; self.edges.len() * h],
+            waits: vec![0.0; self.n * h],
+            arrivals: vec![0.0; h],
+        };
+        for (origin, p) in origins.into_iter().zip(p) {
+            match origin {
+                Origin::Move { edge, depart } => {
+                    out.edges[edge] += p;
+                    out.departures[edge * h + depart] += p;
+                }
+                Origin::Wait { node, time } => out.waits[node * h + time] += p,
+                Origin::Arrive { time } => out.arrivals[time] += p,
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn check_node(node: usize, n: usize) -> Result<()> {
+    if node >= n {
+        return Err(Error::NodeOutOfBounds { node, n });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(from: usize, to: usize, duration: usize, costs: Vec<f64>) -> TimedEdge {
+        TimedEdge {
+            from,
+            to,
+            duration,
+            costs,
+        }
+    }
+
+    #[test]
+    fn waiting_for_a_cheaper_departure_wins_at_small_gamma() {
+        let inf = f64::INFINITY;
+        // 0 -> 1 is expensive at slot 0, unavailable at 1, cheap at 2.
+        let edges = vec![
+            edge(0, 1, 1, vec![10.0, inf, 1.0, 1.0, 1.0]),
+            edge(1, 0, 1, vec![1.0; 5]),
+        ];
+        let graph = TimeExpandedGraph::new(2, 5, edges).unwrap().wait_cost(0.5);
+        let m = graph.marginals(0, 1, 0.01).unwrap();
+        assert!((m.value - 2.0).abs() < 1e-6, "{m:?}");
+        assert!((m.departures[2] - 1.0).abs() < 1e-6);
+        assert!((m.waits[0] + m.waits[1] - 2.0).abs() < 1e-6);
+        assert!((m.arrivals[3] - 1.0).abs() < 1e-6);
+
+        // Without waiting only the slot-0 departure reaches the target.
+        let no_wait = TimeExpandedGraph::new(2, 5, graph.edges.clone()).unwrap();
+        let m = no_wait.marginals(0, 1, 0.01).unwrap();
+        assert!((m.value - 10.0).abs() < 1e-6, "{m:?}");
+        assert!(m.waits.iter().all(|&w| w == 0.0));
+    }
+
+    #[test]
+    fn marginals_are_the_expanded_graph_marginals_aggregated() {
+        // A cycle in the base graph unrolls into a DAG.
+        let edges = vec![
+            edge(0, 1, 1, vec![1.0, 2.0, 0.5, 1.5]),
+            edge(1, 0, 1, vec![0.2, 0.4, 0.1, 0.3]),
+            edge(1, 2, 2, vec![1.0, 0.5, 2.0, 1.0]),
+            edge(0, 2, 3, vec![3.0, 3.0, 3.0, 3.0]),
+        ];
+        let graph = TimeExpandedGraph::new(3, 4, edges).unwrap().wait_cost(0.3);
+        let (g, origins) = graph.expand(0, 2).unwrap();
+        assert_eq!(g.edges().len(), origins.len());
+        let (value, p) = g.edge_marginals(0.7).unwrap();
+        let m = graph.marginals(0, 2, 0.7).unwrap();
+        assert_eq!(m.value, value);
+        let total: f64 = origins
+            .iter()
+            .zip(&p)
+            .filter(|(o, _)| matches!(o, Origin::Move { edge: 2, .. }))
+            .map(|(_, p)| p)
+            .sum();
+        assert!((m.edges[2] - total).abs() < 1e-12);
+        assert!((m.arrivals.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        assert_eq!(
+            TimeExpandedGraph::new(3, 4, vec![edge(0, 3, 1, vec![1.0; 4])]),
+            Err(Error::NodeOutOfBounds { node: 3, n: 3 })
+        );
+        assert_eq!(
+            TimeExpandedGraph::new(3, 4, vec![edge(0, 1, 1, vec![1.0; 3])]),
+            Err(Error::CostLength {
+                edge: 0,
+                len: 3,
+                expected: 4
+            })
+        );
+        assert!(matches!(
+            graph.clone().wait_cost(f64::NAN).expand(0, 2),
+            Err(Error::InvalidWaitCost(c)) if c.is_nan()
+        ));
+    }
+}
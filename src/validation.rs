@@ -0,0 +1,251 @@
+//! Structured input validation with full diagnostics.
+//!
+//! The operators fail fast on the first invalid input. Ingestion layers usually want every
+//! problem at once (to report all bad rows of an upload, say), so the functions here run the
+//! same checks but collect each [`Violation`] instead of stopping. An empty result means the
+//! operators accept the input (gamma is checked separately, at call time).
+//!
+//! Violations are plain data: match on the variant for machine handling, or use `Display`
+//! for a message.
+
+use crate::soft_dtw;
+use crate::soft_shortest_path::Edge;
+
+/// One problem found in a graph or cost matrix.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// A graph needs at least 2 nodes (source and sink).
+    #[error("graph must have at least 2 nodes, got {n}")]
+    TooFewNodes {
+        /// Number of nodes.
+        n: usize,
+    },
+    /// An edge endpoint is not a node of the graph.
+    #[error("edge {edge_idx} ({from}->{to}) has an endpoint out of bounds for n={n}")]
+    EdgeOutOfBounds {
+        /// Index of the offending edge.
+        edge_idx: usize,
+        /// Source endpoint.
+        from: usize,
+        /// Destination endpoint.
+        to: usize,
+        /// Number of nodes.
+        n: usize,
+    },
+    /// An edge does not go forward in topological order (`from < to`).
+    #[error("edge {edge_idx} ({from}->{to}) violates topological order")]
+    NotDagOrder {
+        /// Index of the offending edge.
+        edge_idx: usize,
+        /// Source endpoint.
+        from: usize,
+        /// Destination endpoint.
+        to: usize,
+    },
+    /// An edge cost is NaN or infinite.
+    #[error("edge {edge_idx} has non-finite cost {cost}")]
+    NonFiniteEdgeCost {
+        /// Index of the offending edge.
+        edge_idx: usize,
+        /// The cost.
+        cost: f64,
+    },
+    /// No path from node 0 to node `n-1` uses only valid edges.
+    #[error("no path exists from source to sink")]
+    NoPath,
+    /// A cost matrix dimension is zero.
+    #[error("cost matrix must be non-empty, got {n}x{m}")]
+    EmptyCost {
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+    /// The DP table for an `n × m` cost matrix would not fit in the address space.
+    #[error("a {n}x{m} cost matrix is too large")]
+    DimensionOverflow {
+        /// Row count.
+        n: usize,
+        /// Column count.
+        m: usize,
+    },
+    /// The cost slice length is not `n * m`.
+    #[error("cost matrix has length {len}, expected {expected}")]
+    CostShape {
+        /// Provided length.
+        len: usize,
+        /// `n * m`.
+        expected: usize,
+    },
+    /// A cost matrix entry is NaN (`+inf` is allowed: it forbids the cell).
+    #[error("cost[{i}][{j}] is NaN")]
+    NanCost {
+        /// Row index.
+        i: usize,
+        /// Column index.
+        j: usize,
+    },
+}
+
+/// All problems with a DAG given as `n` nodes and `edges`, in the order the operators in
+/// [`crate::soft_shortest_path`] would check them.
+///
+/// `NoPath` is reported only when the edges that passed their own checks do not connect
+/// node 0 to node `n-1`.
+pub fn validate_graph(n: usize, edges: &[Edge]) -> Vec<Violation> {
+    let mut out = Vec::new();
+    if n < 2 {
+        out.push(Violation::TooFewNodes { n });
+    }
+    let mut reachable = vec![false; n];
+    if let Some(first) = reachable.first_mut() {
+        *first = true;
+    }
+    let mut valid = Vec::with_capacity(edges.len());
+    for (k, e) in edges.iter().enumerate() {
+        let before = out.len();
+        if e.from >= n || e.to >= n {
+            out.push(Violation::EdgeOutOfBounds {
+                edge_idx: k,
+                from: e.from,
+                to: e.to,
+                n,
+            });
+        } else if e.from >= e.to {
+            out.push(Violation::NotDagOrder {
+                edge_idx: k,
+                from: e.from,
+                to: e.to,
+            });
+        }
+        if !e.cost.is_finite() {
+            out.push(Violation::NonFiniteEdgeCost {
+                edge_idx: k,
+                cost: e.cost,
+            });
+        }
+        if out.len() == before {
+            valid.push(e);
+        }
+    }
+    if n >= 2 {
+        // Valid edges satisfy from < to, so a pass in `from` order propagates reachability.
+        valid.sort_by_key(|e| e.from);
+        for e in valid {
+            if reachable[e.from] {
+                reachable[e.to] = true;
+            }
+        }
+        if !reachable[n - 1] {
+            out.push(Violation::NoPath);
+        }
+    }
+    out
+}
+
+/// All problems with a row-major `n × m` cost matrix for the Soft-DTW operators.
+///
+/// The length is compared with `n * m` only when the table fits, and NaN entries are
+/// reported only when the shape is valid; there is one violation per NaN.
+pub fn validate_cost(cost: &[f64], n: usize, m: usize) -> Vec<Violation> {
+    let mut out = Vec::new();
+    if n == 0 || m == 0 {
+        out.push(Violation::EmptyCost { n, m });
+    }
+    let expected = n
+        .checked_mul(m)
+        .filter(|_| soft_dtw::table_len(n, m).is_ok());
+    match expected {
+        None => out.push(Violation::DimensionOverflow { n, m }),
+        Some(expected) if cost.len() != expected => out.push(Violation::CostShape {
+            len: cost.len(),
+            expected,
+        }),
+        Some(_) => out.extend(
+            cost.iter()
+                .enumerate()
+                .filter(|(_, c)| c.is_nan())
+                .map(|(k, _)| Violation::NanCost { i: k / m, j: k % m }),
+        ),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::soft_shortest_path::soft_shortest_path_value;
+
+    fn edge(from: usize, to: usize, cost: f64) -> Edge {
+        Edge { from, to, cost }
+    }
+
+    #[test]
+    fn graph_reports_every_violation() {
+        let edges = [
+            edge(0, 1, 1.0),
+            edge(2, 1, 1.0),
+            edge(1, 7, 1.0),
+            edge(1, 3, f64::INFINITY),
+        ];
+        assert_eq!(
+            validate_graph(4, &edges),
+            vec![
+                Violation::NotDagOrder {
+                    edge_idx: 1,
+                    from: 2,
+                    to: 1
+                },
+                Violation::EdgeOutOfBounds {
+                    edge_idx: 2,
+                    from: 1,
+                    to: 7,
+                    n: 4
+                },
+                Violation::NonFiniteEdgeCost {
+                    edge_idx: 3,
+                    cost: f64::INFINITY
+                },
+                Violation::NoPath,
+            ]
+        );
+        assert_eq!(
+            validate_graph(1, &[]),
+            vec![Violation::TooFewNodes { n: 1 }]
+        );
+    }
+
+    #[test]
+    fn valid_inputs_agree_with_operators() {
+        let edges = [edge(0, 2, 1.0), edge(0, 1, 0.5), edge(1, 2, 0.5)];
+        assert!(validate_graph(3, &edges).is_empty());
+        assert!(soft_shortest_path_value(3, &edges, 1.0).is_ok());
+
+        assert!(validate_cost(&[0.0, f64::INFINITY], 1, 2).is_empty());
+        assert_eq!(
+            validate_cost(&[0.0, f64::NAN, 1.0, f64::NAN], 2, 2),
+            vec![
+                Violation::NanCost { i: 0, j: 1 },
+                Violation::NanCost { i: 1, j: 1 }
+            ]
+        );
+        assert_eq!(
+            validate_cost(&[1.0], 0, 3),
+            vec![
+                Violation::EmptyCost { n: 0, m: 3 },
+                Violation::CostShape {
+                    len: 1,
+                    expected: 0
+                }
+            ]
+        );
+        assert_eq!(
+            validate_cost(&[1.0], usize::MAX, 3),
+            vec![Violation::DimensionOverflow {
+                n: usize::MAX,
+                m: 3
+            }]
+        );
+        assert!(soft_dtw::soft_dtw_cost(&[1.0], usize::MAX, 3, 1.0).is_err());
+    }
+}
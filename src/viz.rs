@@ -0,0 +1,158 @@
+//! Text renderings of alignment matrices.
+//!
+//! Soft-DTW alignments and similar marginal matrices are row-major `n × m` slices of
+//! probabilities. The helpers here turn them into something a terminal or spreadsheet can
+//! show: an ASCII heatmap (one character per cell), CSV, and a listing of the cells whose
+//! mass is above a threshold, which is the soft path read as aligned pairs.
+//!
+//! ```
+//! use structop::soft_dtw::soft_dtw_alignment;
+//! use structop::viz::{aligned_pairs, ascii_heatmap};
+//!
+//! let cost = [0.0, 1.0, 4.0, 1.0, 0.0, 1.0];
+//! let (_, e) = soft_dtw_alignment(&cost, 2, 3, 0.1).unwrap();
+//! println!("{}", ascii_heatmap(&e, 2, 3).unwrap());
+//! let pairs = aligned_pairs(&e, 2, 3, 0.5).unwrap();
+//! assert_eq!((pairs[0].i, pairs[0].j), (0, 0));
+//! ```
+
+use std::fmt::Write;
+
+/// Errors for the rendering helpers.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Matrix shape mismatch.
+    #[error("matrix has length {len}, expected {n}*{m}={expected}")]
+    InvalidShape {
+        /// The provided slice length.
+        len: usize,
+        /// Expected row count.
+        n: usize,
+        /// Expected column count.
+        m: usize,
+        /// `n*m`, included explicitly for readability.
+        expected: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Shades from empty to full, in ninths of the maximum.
+const SHADES: &[u8] = b" .:-=+*#%@";
+
+fn check_shape(matrix: &[f64], n: usize, m: usize) -> Result<()> {
+    match n.checked_mul(m) {
+        Some(expected) if expected == matrix.len() => Ok(()),
+        expected => Err(Error::InvalidShape {
+            len: matrix.len(),
+            n,
+            m,
+            expected: expected.unwrap_or(usize::MAX),
+        }),
+    }
+}
+
+/// One character per cell, one line per row, shaded relative to the largest finite entry
+/// (so a one-hot path renders as `@` on blank). Non-finite entries render as `?`.
+pub fn ascii_heatmap(matrix: &[f64], n: usize, m: usize) -> Result<String> {
+    check_shape(matrix, n, m)?;
+    let max = matrix
+        .iter()
+        .copied()
+        .filter(|v| v.is_finite())
+        .fold(0.0, f64::max);
+    let mut out = String::with_capacity(n * (m + 1));
+    for row in matrix.chunks(m.max(1)).take(n) {
+        for &v in row {
+            let c = if !v.is_finite() {
+                '?'
+            } else if max <= 0.0 || v <= 0.0 {
+                ' '
+            } else {
+                let k = (v / max * (SHADES.len() - 1) as f64).round() as usize;
+                SHADES[k.min(SHADES.len() - 1)] as char
+            };
+            out.push(c);
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The matrix as CSV, one line per row, with values in Rust's shortest round-trip format.
+pub fn to_csv(matrix: &[f64], n: usize, m: usize) -> Result<String> {
+    check_shape(matrix, n, m)?;
+    let mut out = String::new();
+    for row in matrix.chunks(m.max(1)).take(n) {
+        for (j, v) in row.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            write!(out, "{v}").expect("writing to a String cannot fail");
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// A cell of an alignment matrix with its mass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlignedPair {
+    /// Row (position in the first sequence).
+    pub i: usize,
+    /// Column (position in the second sequence).
+    pub j: usize,
+    /// Alignment mass of the cell.
+    pub weight: f64,
+}
+
+/// Cells with mass at least `threshold`, in row-major order.
+///
+/// A warping path crosses each anti-diagonal `i + j = k` at most once, so the mass of an
+/// expected alignment on an anti-diagonal is at most one: a threshold above one half keeps
+/// at most one cell per anti-diagonal, and the result reads as the confident part of the
+/// soft path.
+pub fn aligned_pairs(
+    matrix: &[f64],
+    n: usize,
+    m: usize,
+    threshold: f64,
+) -> Result<Vec<AlignedPair>> {
+    check_shape(matrix, n, m)?;
+    Ok(matrix
+        .iter()
+        .enumerate()
+        .filter(|&(_, &w)| w >= threshold)
+        .map(|(k, &weight)| AlignedPair {
+            i: k / m,
+            j: k % m,
+            weight,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_a_one_hot_path() {
+        let e = [1.0, 0.0, 0.0, 0.0, 0.5, 1.0];
+        assert_eq!(ascii_heatmap(&e, 2, 3).unwrap(), "@  \n +@\n");
+        assert_eq!(to_csv(&e, 2, 3).unwrap(), "1,0,0\n0,0.5,1\n");
+        let pairs = aligned_pairs(&e, 2, 3, 0.6).unwrap();
+        let cells: Vec<_> = pairs.iter().map(|p| (p.i, p.j)).collect();
+        assert_eq!(cells, vec![(0, 0), (1, 2)]);
+        assert_eq!(ascii_heatmap(&[f64::NAN, 0.0], 1, 2).unwrap(), "? \n");
+        assert_eq!(
+            to_csv(&e, 3, 3),
+            Err(Error::InvalidShape {
+                len: 6,
+                n: 3,
+                m: 3,
+                expected: 9
+            })
+        );
+    }
+}
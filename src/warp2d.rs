@@ -0,0 +1,145 @@
+//! Constrained 2D warping between matrices (grid-to-grid alignment).
+//!
+//! Unconstrained 2D warping (monotone and continuous in both axes at once) is NP-hard, so
+//! this module uses the usual restriction for spectrogram-like data: rows are aligned by a
+//! monotone warping, and each pair of aligned rows is compared by its own monotone warping
+//! of the columns. Concretely, with `A` of shape `na × wa` and `B` of shape `nb × wb`,
+//!
+//! - `D[i,k] = sdtw_γ(A[i,:], B[k,:])` (squared distance between entries), and
+//! - the value is `sdtw_γ` over the `na × nb` cost matrix `D`.
+//!
+//! The column warpings are chosen independently per row pair, so they need not agree from
+//! one row to the next. The row-wise alignment marginals are the expected alignment of the
+//! outer DP, i.e. the gradient of the value w.r.t. `D`. The cost is
+//! `O(na nb wa wb)` time and `O(wa wb + na nb)` memory.
+
+use crate::soft_dtw::{self, soft_dtw, soft_dtw_alignment};
+
+/// Errors for 2D warping.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Matrix shape mismatch.
+    #[error("matrix has length {len}, expected {rows}*{cols}={expected}")]
+    InvalidShape {
+        /// The provided slice length.
+        len: usize,
+        /// Expected row count.
+        rows: usize,
+        /// Expected column count.
+        cols: usize,
+        /// `rows*cols`, included explicitly for readability.
+        expected: usize,
+    },
+    /// Error from the row or column Soft-DTW.
+    #[error(transparent)]
+    SoftDtw(#[from] soft_dtw::Error),
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn check_shape(matrix: &[f64], rows: usize, cols: usize) -> Result<()> {
+    match rows.checked_mul(cols) {
+        Some(expected) if expected == matrix.len() => Ok(()),
+        expected => Err(Error::InvalidShape {
+            len: matrix.len(),
+            rows,
+            cols,
+            expected: expected.unwrap_or(usize::MAX),
+        }),
+    }
+}
+
+/// Row-to-row cost matrix `D[i,k] = sdtw_γ(A[i,:], B[k,:])`, row-major `na × nb`.
+///
+/// `a` is row-major `na × wa` and `b` row-major `nb × wb`; all dimensions must be nonzero.
+pub fn row_costs(
+    a: &[f64],
+    na: usize,
+    wa: usize,
+    b: &[f64],
+    nb: usize,
+    wb: usize,
+    gamma: f64,
+) -> Result<Vec<f64>> {
+    check_shape(a, na, wa)?;
+    check_shape(b, nb, wb)?;
+    if na == 0 || nb == 0 || wa == 0 || wb == 0 {
+        return Err(soft_dtw::Error::EmptyInput.into());
+    }
+    let mut d = Vec::with_capacity(na * nb);
+    for row_a in a.chunks(wa) {
+        for row_b in b.chunks(wb) {
+            d.push(soft_dtw(row_a, row_b, gamma)?);
+        }
+    }
+    Ok(d)
+}
+
+/// 2D warping value between `a` (`na × wa`) and `b` (`nb × wb`), both row-major.
+pub fn soft_warp_2d(
+    a: &[f64],
+    na: usize,
+    wa: usize,
+    b: &[f64],
+    nb: usize,
+    wb: usize,
+    gamma: f64,
+) -> Result<f64> {
+    let d = row_costs(a, na, wa, b, nb, wb, gamma)?;
+    Ok(soft_dtw::soft_dtw_cost(&d, na, nb, gamma)?)
+}
+
+/// 2D warping value and row-wise alignment marginals (row-major `na × nb`): entry `(i, k)` is
+/// the probability that row `i` of `a` is aligned with row `k` of `b`.
+pub fn soft_warp_2d_alignment(
+    a: &[f64],
+    na: usize,
+    wa: usize,
+    b: &[f64],
+    nb: usize,
+    wb: usize,
+    gamma: f64,
+) -> Result<(f64, Vec<f64>)> {
+    let d = row_costs(a, na, wa, b, nb, wb, gamma)?;
+    Ok(soft_dtw_alignment(&d, na, nb, gamma)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_align_through_a_vertical_shift() {
+        // Three distinct row profiles; `b` repeats the middle one and stretches columns.
+        let a = [0.0, 1.0, 0.0, 2.0, 2.0, 2.0, 1.0, 0.0, 1.0];
+        let b = [
+            0.0, 1.0, 1.0, 0.0, //
+            2.0, 2.0, 2.0, 2.0, //
+            2.0, 2.0, 2.0, 2.0, //
+            1.0, 0.0, 0.0, 1.0,
+        ];
+        let (v, e) = soft_warp_2d_alignment(&a, 3, 3, &b, 4, 4, 0.05).unwrap();
+        assert_eq!(soft_warp_2d(&a, 3, 3, &b, 4, 4, 0.05), Ok(v));
+        // Row 1 of `a` covers rows 1 and 2 of `b`; the ends are pinned.
+        for (i, k) in [(0, 0), (1, 1), (1, 2), (2, 3)] {
+            assert!(e[i * 4 + k] > 0.99, "{:?}", e);
+        }
+        let d = row_costs(&a, 3, 3, &b, 4, 4, 0.05).unwrap();
+        assert_eq!(soft_dtw::soft_dtw_cost(&d, 3, 4, 0.05), Ok(v));
+
+        assert_eq!(
+            soft_warp_2d(&a, 3, 4, &b, 4, 4, 0.05),
+            Err(Error::InvalidShape {
+                len: 9,
+                rows: 3,
+                cols: 4,
+                expected: 12
+            })
+        );
+        assert_eq!(
+            soft_warp_2d(&[], 0, 3, &b, 4, 4, 0.05),
+            Err(Error::SoftDtw(soft_dtw::Error::EmptyInput))
+        );
+    }
+}
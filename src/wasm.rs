@@ -0,0 +1,138 @@
+//! JavaScript bindings via `wasm-bindgen` (feature `wasm`).
+//!
+//! The core operators are single-threaded, allocation-only `std` code with no clocks, file
+//! I/O or RNG, so the whole crate builds for `wasm32-unknown-unknown` as is; this module adds
+//! a thin JS-facing layer on top. Arrays cross the boundary as `Float64Array` (copied in and
+//! out), and crate errors become thrown JS `Error`s carrying the same message.
+//!
+//! See `examples/wasm/` for a browser demo that renders Soft-DTW alignment marginals.
+
+use wasm_bindgen::prelude::*;
+
+use crate::soft_shortest_path::Edge;
+use crate::{soft_dtw as sdtw, soft_shortest_path as ssp};
+
+/// Soft-DTW value of two 1D sequences.
+#[wasm_bindgen(js_name = softDtw)]
+pub fn soft_dtw(x: &[f64], y: &[f64], gamma: f64) -> Result<f64, JsError> {
+    Ok(sdtw::soft_dtw(x, y, gamma)?)
+}
+
+/// Soft-DTW divergence of two 1D sequences.
+#[wasm_bindgen(js_name = softDtwDivergence)]
+pub fn soft_dtw_divergence(x: &[f64], y: &[f64], gamma: f64) -> Result<f64, JsError> {
+    Ok(sdtw::soft_dtw_divergence(x, y, gamma)?)
+}
+
+/// A value together with its gradient (alignment or edge marginals).
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct ValueAndGradient {
+    value: f64,
+    gradient: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl ValueAndGradient {
+    /// Operator value.
+    #[wasm_bindgen(getter)]
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Gradient, row-major for matrices (copied into a new `Float64Array`).
+    #[wasm_bindgen(getter)]
+    pub fn gradient(&self) -> Vec<f64> {
+        self.gradient.clone()
+    }
+}
+
+/// Soft-DTW value and gradient w.r.t. `x` for two 1D sequences.
+#[wasm_bindgen(js_name = softDtwGrad)]
+pub fn soft_dtw_grad(x: &[f64], y: &[f64], gamma: f64) -> Result<ValueAndGradient, JsError> {
+    let (value, gradient) = sdtw::soft_dtw_grad(x, y, gamma)?;
+    Ok(ValueAndGradient { value, gradient })
+}
+
+/// Soft-DTW value and row-major `n × m` expected alignment for a cost matrix.
+#[wasm_bindgen(js_name = softDtwAlignment)]
+pub fn soft_dtw_alignment(
+    cost: &[f64],
+    n: usize,
+    m: usize,
+    gamma: f64,
+) -> Result<ValueAndGradient, JsError> {
+    let (value, gradient) = sdtw::soft_dtw_alignment(cost, n, m, gamma)?;
+    Ok(ValueAndGradient { value, gradient })
+}
+
+/// Soft shortest-path value from node 0 to `n - 1` and edge marginals.
+///
+/// Edge `k` goes from `from[k]` to `to[k]` with cost `costs[k]`.
+#[wasm_bindgen(js_name = softShortestPathMarginals)]
+pub fn soft_shortest_path_marginals(
+    n: usize,
+    from: &[u32],
+    to: &[u32],
+    costs: &[f64],
+    gamma: f64,
+) -> Result<ValueAndGradient, JsError> {
+    let edges = edges(from, to, costs).ok_or_else(|| JsError::new(EDGE_LENGTHS))?;
+    let (value, gradient) = ssp::soft_shortest_path_edge_marginals(n, &edges, gamma)?;
+    Ok(ValueAndGradient { value, gradient })
+}
+
+const EDGE_LENGTHS: &str = "from, to and costs must have the same length";
+
+/// Edges from parallel `from`, `to` and `costs` arrays, or `None` if their lengths differ.
+fn edges(from: &[u32], to: &[u32], costs: &[f64]) -> Option<Vec<Edge>> {
+    if from.len() != costs.len() || to.len() != costs.len() {
+        return None;
+    }
+    let edges = from
+        .iter()
+        .zip(to)
+        .zip(costs)
+        .map(|((&from, &to), &cost)| Edge {
+            from: from as usize,
+            to: to as usize,
+            cost,
+        })
+        .collect();
+    Some(edges)
+}
+
+// Only the success paths run natively: building a `JsError` calls into JS.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrappers_return_gradients_of_the_input_shape() {
+        let (x, y) = ([0.0, 1.0, 2.0], [0.5, 1.5]);
+        let r = soft_dtw_grad(&x, &y, 0.5).unwrap();
+        assert_eq!(r.value(), soft_dtw(&x, &y, 0.5).unwrap());
+        assert_eq!(r.gradient().len(), 3);
+
+        let cost = [0.0, 1.0, 2.0, 1.0, 0.0, 1.0];
+        let r = soft_dtw_alignment(&cost, 2, 3, 0.5).unwrap();
+        assert_eq!(
+            (r.value(), r.gradient()),
+            sdtw::soft_dtw_alignment(&cost, 2, 3, 0.5).unwrap()
+        );
+
+        let r =
+            soft_shortest_path_marginals(3, &[0, 1, 0], &[1, 2, 2], &[1.0, 2.0, 4.0], 1.0).unwrap();
+        assert_eq!(r.gradient().len(), 3);
+        assert_eq!(
+            edges(&[0, 1], &[1, 2], &[1.0, 2.0]).unwrap()[1],
+            Edge {
+                from: 1,
+                to: 2,
+                cost: 2.0
+            }
+        );
+        assert!(edges(&[0, 1], &[1], &[1.0, 2.0]).is_none());
+        assert!(edges(&[0, 1], &[1, 2], &[1.0]).is_none());
+    }
+}
@@ -0,0 +1,288 @@
+//! Differentiable z-normalization.
+//!
+//! DTW workloads usually compare z-normalized series, `z = (x - mean(x)) / std(x)`, either
+//! globally or per sliding window (subsequence search). Training through Soft-DTW then
+//! needs the normalization's vector-Jacobian product to turn `∂L/∂z` into `∂L/∂x`; the
+//! types here keep what that needs and expose it as `vjp`.
+//!
+//! The standard deviation is \(s = \sqrt{\operatorname{var}(x) + \varepsilon}\) (population
+//! variance), so `eps > 0` keeps flat series and windows finite and differentiable, as in
+//! layer normalization; `eps = 0` is the exact z-normalization. With \(\hat x = z\), the
+//! product is \(\partial L/\partial x = (g - \bar g - \hat x\,\overline{g \hat x}) / s\).
+//!
+//! ```
+//! use structop::soft_dtw::soft_dtw_grad;
+//! use structop::znorm::znorm;
+//!
+//! let x = [1.0, 3.0, 2.0, 5.0];
+//! let y = [-1.0, 0.0, 1.0];
+//! let z = znorm(&x, 1e-8).unwrap();
+//! let (_, dz) = soft_dtw_grad(z.values(), &y, 0.1).unwrap();
+//! let dx = z.vjp(&dz).unwrap();
+//! assert_eq!(dx.len(), 4);
+//! ```
+
+/// Errors for z-normalization.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    /// Inputs must be non-empty.
+    #[error("inputs must be non-empty")]
+    EmptyInput,
+    /// `eps` must be finite and nonnegative.
+    #[error("eps must be finite and nonnegative, got {0}")]
+    InvalidEps(f64),
+    /// Window width must be between 1 and the series length.
+    #[error("window width {width} is invalid for a series of length {len}")]
+    InvalidWidth {
+        /// Requested width.
+        width: usize,
+        /// Series length.
+        len: usize,
+    },
+    /// A series or window has zero variance and `eps` is zero.
+    #[error("window {window} has zero variance; use eps > 0")]
+    ZeroVariance {
+        /// Index of the window (0 for the global normalization).
+        window: usize,
+    },
+    /// An upstream gradient does not match the normalized values.
+    #[error("gradient has length {len}, expected {expected}")]
+    GradientLengthMismatch {
+        /// The provided length.
+        len: usize,
+        /// Number of normalized values.
+        expected: usize,
+    },
+}
+
+/// Convenience result type for this module.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Appends the normalized `x` to `out`, returning `(mean, s)`.
+fn normalize_into(out: &mut Vec<f64>, x: &[f64], eps: f64, window: usize) -> Result<(f64, f64)> {
+    let len = x.len() as f64;
+    let mean = x.iter().sum::<f64>() / len;
+    let var = x.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / len;
+    let s = (var + eps).sqrt();
+    if s == 0.0 {
+        return Err(Error::ZeroVariance { window });
+    }
+    out.extend(x.iter().map(|v| (v - mean) / s));
+    Ok((mean, s))
+}
+
+/// Adds the normalization's vector-Jacobian product for one series or window to `out`.
+fn vjp_into(out: &mut [f64], z: &[f64], s: f64, g: &[f64]) {
+    let len = z.len() as f64;
+    let g_mean = g.iter().sum::<f64>() / len;
+    let gz_mean = g.iter().zip(z).map(|(g, z)| g * z).sum::<f64>() / len;
+    for ((o, &g), &z) in out.iter_mut().zip(g).zip(z) {
+        *o += (g - g_mean - z * gz_mean) / s;
+    }
+}
+
+fn check_eps(eps: f64) -> Result<()> {
+    if !(eps >= 0.0 && eps.is_finite()) {
+        return Err(Error::InvalidEps(eps));
+    }
+    Ok(())
+}
+
+/// A globally z-normalized series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZNorm {
+    values: Vec<f64>,
+    mean: f64,
+    std: f64,
+}
+
+impl ZNorm {
+    /// The normalized values.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Mean of the input.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Standard deviation used, \(\sqrt{\operatorname{var} + \varepsilon}\).
+    pub fn std(&self) -> f64 {
+        self.std
+    }
+
+    /// `∂L/∂x` from `grad = ∂L/∂z`.
+    pub fn vjp(&self, grad: &[f64]) -> Result<Vec<f64>> {
+        check_grad(grad, self.values.len())?;
+        let mut out = vec![0.0; grad.len()];
+        vjp_into(&mut out, &self.values, self.std, grad);
+        Ok(out)
+    }
+}
+
+fn check_grad(grad: &[f64], expected: usize) -> Result<()> {
+    if grad.len() != expected {
+        return Err(Error::GradientLengthMismatch {
+            len: grad.len(),
+            expected,
+        });
+    }
+    Ok(())
+}
+
+/// Z-normalizes `x` as a whole.
+pub fn znorm(x: &[f64], eps: f64) -> Result<ZNorm> {
+    check_eps(eps)?;
+    if x.is_empty() {
+        return Err(Error::EmptyInput);
+    }
+    let mut values = Vec::with_capacity(x.len());
+    let (mean, std) = normalize_into(&mut values, x, eps, 0)?;
+    Ok(ZNorm { values, mean, std })
+}
+
+/// Every sliding window of a series, each z-normalized on its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZNormWindows {
+    values: Vec<f64>,
+    means: Vec<f64>,
+    stds: Vec<f64>,
+    width: usize,
+    len: usize,
+}
+
+impl ZNormWindows {
+    /// Window width.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Number of windows, `len - width + 1`.
+    pub fn count(&self) -> usize {
+        self.means.len()
+    }
+
+    /// Normalized window `k`, starting at position `k` of the series.
+    pub fn window(&self, k: usize) -> &[f64] {
+        &self.values[k * self.width..(k + 1) * self.width]
+    }
+
+    /// All normalized windows, row-major `count() × width()`.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Mean of each window.
+    pub fn means(&self) -> &[f64] {
+        &self.means
+    }
+
+    /// Standard deviation used for each window.
+    pub fn stds(&self) -> &[f64] {
+        &self.stds
+    }
+
+    /// `∂L/∂x` (length of the series) from `grad`, the gradient w.r.t. all normalized
+    /// windows (row-major `count() × width()`); overlapping windows add up.
+    pub fn vjp(&self, grad: &[f64]) -> Result<Vec<f64>> {
+        check_grad(grad, self.values.len())?;
+        let mut out = vec![0.0; self.len];
+        for (k, g) in grad.chunks(self.width).enumerate() {
+            vjp_into(&mut out[k..k + self.width], self.window(k), self.stds[k], g);
+        }
+        Ok(out)
+    }
+}
+
+/// Z-normalizes every window `x[k..k + width]` of `x`.
+pub fn znorm_windows(x: &[f64], width: usize, eps: f64) -> Result<ZNormWindows> {
+    check_eps(eps)?;
+    if width == 0 || width > x.len() {
+        return Err(Error::InvalidWidth {
+            width,
+            len: x.len(),
+        });
+    }
+    let count = x.len() - width + 1;
+    let mut values = Vec::with_capacity(count * width);
+    let mut means = Vec::with_capacity(count);
+    let mut stds = Vec::with_capacity(count);
+    for (k, w) in x.windows(width).enumerate() {
+        let (mean, std) = normalize_into(&mut values, w, eps, k)?;
+        means.push(mean);
+        stds.push(std);
+    }
+    Ok(ZNormWindows {
+        values,
+        means,
+        stds,
+        width,
+        len: x.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{check_gradient, Tolerance};
+    use crate::soft_dtw::{soft_dtw, soft_dtw_grad};
+
+    #[test]
+    fn global_vjp_backpropagates_soft_dtw() {
+        let (x, y) = ([1.0, 3.0, 2.0, 5.0, 4.5], [-1.0, 0.5, 1.0, -0.2]);
+        let z = znorm(&x, 0.0).unwrap();
+        let mean = z.values().iter().sum::<f64>() / 5.0;
+        let var = z.values().iter().map(|v| v * v).sum::<f64>() / 5.0;
+        assert!(mean.abs() < 1e-12 && (var - 1.0).abs() < 1e-12);
+
+        let (_, dz) = soft_dtw_grad(z.values(), &y, 0.3).unwrap();
+        let dx = z.vjp(&dz).unwrap();
+        let f = |x: &[f64]| Ok(soft_dtw(znorm(x, 0.0).unwrap().values(), &y, 0.3)?);
+        assert!(check_gradient(f, &x, &dx, Tolerance::default())
+            .unwrap()
+            .passed());
+
+        assert_eq!(
+            znorm(&[2.0; 3], 0.0),
+            Err(Error::ZeroVariance { window: 0 })
+        );
+        assert_eq!(znorm(&[2.0; 3], 1e-6).unwrap().values(), &[0.0; 3]);
+        assert_eq!(
+            z.vjp(&dz[..3]),
+            Err(Error::GradientLengthMismatch {
+                len: 3,
+                expected: 5
+            })
+        );
+    }
+
+    #[test]
+    fn windowed_vjp_sums_overlapping_windows() {
+        let (x, y) = ([1.0, 3.0, 2.0, 5.0, 4.5, 4.5, 0.0], [-1.0, 0.5, 1.0]);
+        let w = znorm_windows(&x, 3, 1e-3).unwrap();
+        assert_eq!((w.count(), w.width()), (5, 3));
+        assert_eq!(w.window(1), znorm(&x[1..4], 1e-3).unwrap().values());
+
+        // L = sum over windows of their soft-DTW value against `y`.
+        let loss = |x: &[f64]| {
+            let w = znorm_windows(x, 3, 1e-3).unwrap();
+            (0..w.count())
+                .map(|k| soft_dtw(w.window(k), &y, 0.3))
+                .sum::<std::result::Result<f64, _>>()
+        };
+        let mut grad = Vec::new();
+        for k in 0..w.count() {
+            grad.extend(soft_dtw_grad(w.window(k), &y, 0.3).unwrap().1);
+        }
+        let dx = w.vjp(&grad).unwrap();
+        let f = |x: &[f64]| Ok(loss(x)?);
+        assert!(check_gradient(f, &x, &dx, Tolerance::default())
+            .unwrap()
+            .passed());
+        assert_eq!(
+            znorm_windows(&x, 8, 0.0),
+            Err(Error::InvalidWidth { width: 8, len: 7 })
+        );
+    }
+}